@@ -2,9 +2,19 @@ use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::BufferSize;
 use rtrb::Consumer;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::master::{MasterMeter, MasterSettings, MasterStage};
+use crate::metronome::{ClickSounds, Metronome, MetronomeSettings};
+use crate::mix::PadMix;
+use crate::record;
+use crate::convolution::{Convolver, ImpulseResponse};
+use crate::inserts::{Effect, InsertChain};
+use crate::reverb::{Reverb, ReverbSettings};
+
 /// An audio output device descriptor
 #[derive(Debug, Clone)]
 pub struct AudioDevice {
@@ -44,6 +54,15 @@ fn get_device(device_index: Option<usize>) -> Result<cpal::Device> {
     }
 }
 
+/// Return the sample rate the output device prefers (its default output config).
+pub fn preferred_sample_rate(device_index: Option<usize>) -> Result<u32> {
+    let device = get_device(device_index)?;
+    let config = device
+        .default_output_config()
+        .context("Failed to query default output config")?;
+    Ok(config.sample_rate().0)
+}
+
 /// Play pre-decoded f32 PCM samples through a cpal output device.
 /// Blocks until playback completes.
 pub fn play_sample(
@@ -114,18 +133,47 @@ pub fn play_sample(
     Ok(())
 }
 
+/// Voice tag of the main kit; kits assigned to MIDI channels use 1 and up.
+pub const MAIN_KIT: u8 = 0;
+
 /// Command sent from the MIDI thread to the audio thread via rtrb.
 pub enum AudioCommand {
-    /// Trigger a new voice with the given samples, gain, and source note.
+    /// Trigger a new voice with the given samples, gain, and source note of a kit.
     Trigger {
         samples: Arc<Vec<f32>>,
         gain: f32,
         note: u8,
+        kit: u8,
     },
-    /// Choke (fade out) all playing voices for the given note.
+    /// Choke (fade out) all playing voices for the given note of a kit.
+    /// The fade is in seconds so it lasts as long at any stream rate.
     Choke {
         note: u8,
-        fade_frames: usize,
+        kit: u8,
+        fade_secs: f64,
+    },
+    /// Start copying the mixed output into this tap (see `record::start`).
+    StartRecording(record::Tap),
+    /// Drop the recording tap; the writer thread then finalizes the file.
+    StopRecording,
+    /// Replace the metronome settings (tempo, meter, volume, on/off).
+    SetMetronome(MetronomeSettings),
+    /// Set the volume, pan, sends, mute, solo and inserts of one main-kit sample
+    /// note, applied to ringing voices too.
+    SetPadMix { note: u8, mix: PadMix },
+    /// Put every main-kit pad back at unity, centred and unmuted, without inserts.
+    ResetPadMix,
+    /// Replace the size, damping and wet level of the reverb bus.
+    SetReverb(ReverbSettings),
+    /// Replace the IR bus's impulse response (none silences it) and wet level.
+    SetConvolution {
+        impulse: Option<Arc<ImpulseResponse>>,
+        wet: f32,
+    },
+    /// Replace the master stage's gain and limiter, publishing levels to `meter`.
+    SetMaster {
+        settings: MasterSettings,
+        meter: Arc<MasterMeter>,
     },
 }
 
@@ -141,10 +189,24 @@ pub struct Voice {
     pub position: usize,
     pub gain: f32,
     pub note: u8,
+    /// Which loaded kit the voice belongs to (`MAIN_KIT` or a channel kit).
+    pub kit: u8,
     fade: Option<Fade>,
 }
 
 impl Voice {
+    /// Create a voice at the start of its sample data.
+    fn new(samples: Arc<Vec<f32>>, gain: f32, note: u8, kit: u8) -> Self {
+        Self {
+            samples,
+            position: 0,
+            gain,
+            note,
+            kit,
+            fade: None,
+        }
+    }
+
     /// Returns true when this voice has finished playing all its samples
     /// or has completed its fade-out.
     pub fn is_done(&self) -> bool {
@@ -152,6 +214,11 @@ impl Voice {
             || self.fade.as_ref().is_some_and(|f| f.remaining_frames == 0)
     }
 
+    /// Returns true while the voice plays normally (not choked or stolen).
+    fn is_active(&self) -> bool {
+        self.fade.is_none()
+    }
+
     /// Start a fade-out over the given number of frames.
     /// If already fading, keeps the shorter remaining fade.
     fn start_fade(&mut self, fade_frames: usize) {
@@ -181,17 +248,424 @@ impl Voice {
     }
 }
 
-const MAX_POLYPHONY: usize = 16;
+/// Default number of simultaneously sounding voices.
+pub const DEFAULT_MAX_VOICES: usize = 32;
+
+/// Fade applied to a voice stolen to make room for a new hit (5 ms).
+const STEAL_FADE_SECS: f64 = 0.005;
+
+/// Frames the send buffers hold; longer buffers are mixed in pieces.
+const SEND_BLOCK_FRAMES: usize = 4096;
+
+/// Which voice to steal when the polyphony limit is reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StealPolicy {
+    /// Steal the voice that started first.
+    #[default]
+    Oldest,
+    /// Steal the voice with the lowest gain.
+    Quietest,
+    /// Steal the oldest voice of the incoming note, falling back to the oldest overall.
+    SameNoteFirst,
+}
+
+/// Polyphony limits applied by the mixer.
+#[derive(Debug, Clone)]
+pub struct Polyphony {
+    /// Maximum number of voices playing at once (stolen voices still fading don't count).
+    pub max_voices: usize,
+    pub policy: StealPolicy,
+    /// Per-note voice limits keyed by sample note (e.g. at most 4 ride voices).
+    pub note_limits: HashMap<u8, usize>,
+}
+
+impl Default for Polyphony {
+    fn default() -> Self {
+        Self {
+            max_voices: DEFAULT_MAX_VOICES,
+            policy: StealPolicy::default(),
+            note_limits: HashMap::new(),
+        }
+    }
+}
+
+/// A pad's mix as the audio thread applies it.
+#[derive(Clone, Copy)]
+struct PadGains {
+    left: f32,
+    right: f32,
+    /// Gain for mono output and channels past the first two.
+    gain: f32,
+    /// Reverb and IR sends, after the volume.
+    send: f32,
+    ir_send: f32,
+    mute: bool,
+    solo: bool,
+}
+
+impl Default for PadGains {
+    fn default() -> Self {
+        Self::from(PadMix::default())
+    }
+}
+
+impl PadGains {
+    /// Whether the pad is heard: not muted, and soloed if any pad is.
+    fn is_audible(&self, any_solo: bool) -> bool {
+        !self.mute && (self.solo || !any_solo)
+    }
+
+    /// Gain of output channel `c` of `channels`.
+    fn channel_gain(&self, audible: bool, channels: usize, c: usize) -> f32 {
+        match (audible, channels, c) {
+            (false, _, _) => 0.0,
+            (true, 1, _) => self.gain,
+            (true, _, 0) => self.left,
+            (true, _, 1) => self.right,
+            (true, _, _) => self.gain,
+        }
+    }
+}
+
+impl From<PadMix> for PadGains {
+    fn from(mix: PadMix) -> Self {
+        let (left, right) = mix.channel_gains();
+        Self {
+            left,
+            right,
+            gain: mix.gain(),
+            send: mix.gain() * mix.reverb,
+            ir_send: mix.gain() * mix.convolution,
+            mute: mix.mute,
+            solo: mix.solo,
+        }
+    }
+}
+
+/// Voice mixer driven by `AudioCommand`s. Owned by the audio thread.
+///
+/// All storage is allocated up front so that triggering, stealing and
+/// rendering never allocate.
+pub struct Mixer {
+    voices: Vec<Voice>,
+    sample_rate: u32,
+    channels: usize,
+    max_voices: usize,
+    policy: StealPolicy,
+    /// Per-note voice limit indexed by note number (0 = unlimited).
+    note_limits: [usize; 128],
+    steal_fade: usize,
+    metronome: Metronome,
+    /// Main-kit pad mix indexed by sample note.
+    pads: [PadGains; 128],
+    /// Whether any pad is soloed.
+    solo: bool,
+    /// Main-kit insert chains indexed by sample note, the buffer a pad's voices
+    /// are summed into for them, and the pads using it this block.
+    inserts: Vec<InsertChain>,
+    insert_buffer: Vec<f32>,
+    insert_notes: Vec<u8>,
+    /// Reverb bus fed by the pads' sends, and the mono send mixed for it.
+    reverb: Reverb,
+    send: Vec<f32>,
+    /// IR bus and its send.
+    convolver: Convolver,
+    ir_send: Vec<f32>,
+    /// Output gain and limiter, applied last.
+    master: MasterStage,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32, channels: u16, polyphony: &Polyphony) -> Self {
+        let max_voices = polyphony.max_voices.max(1);
+        let mut note_limits = [0; 128];
+        for (&note, &limit) in &polyphony.note_limits {
+            if let Some(slot) = note_limits.get_mut(note as usize) {
+                *slot = limit;
+            }
+        }
+        Self {
+            // Headroom so stolen voices can finish their fade alongside new ones
+            voices: Vec::with_capacity(max_voices * 2),
+            sample_rate,
+            channels: channels.max(1) as usize,
+            max_voices,
+            policy: polyphony.policy,
+            note_limits,
+            steal_fade: ((sample_rate as f64 * STEAL_FADE_SECS) as usize).max(1),
+            metronome: Metronome::new(sample_rate, ClickSounds::synth(sample_rate, channels)),
+            pads: [PadGains::default(); 128],
+            solo: false,
+            inserts: (0..128).map(|_| InsertChain::new(sample_rate, channels.max(1) as usize)).collect(),
+            insert_buffer: vec![0.0; SEND_BLOCK_FRAMES * channels.max(1) as usize],
+            insert_notes: Vec::with_capacity(128),
+            reverb: Reverb::new(sample_rate),
+            send: vec![0.0; SEND_BLOCK_FRAMES],
+            convolver: Convolver::new(sample_rate),
+            ir_send: vec![0.0; SEND_BLOCK_FRAMES],
+            master: MasterStage::new(sample_rate),
+        }
+    }
+
+    /// Replace the metronome's click sounds (call before moving the mixer to the audio thread).
+    pub fn set_clicks(&mut self, clicks: ClickSounds) {
+        self.metronome.set_clicks(clicks);
+    }
+
+    /// Apply a single command from the MIDI thread.
+    pub fn handle_command(&mut self, cmd: AudioCommand) {
+        match cmd {
+            AudioCommand::Trigger { samples, gain, note, kit } => {
+                self.trigger(Voice::new(samples, gain, note, kit));
+            }
+            AudioCommand::Choke { note, kit, fade_secs } => {
+                let fade_frames = ((self.sample_rate as f64 * fade_secs) as usize).max(1);
+                for voice in self.voices.iter_mut() {
+                    if voice.note == note && voice.kit == kit {
+                        voice.start_fade(fade_frames);
+                    }
+                }
+            }
+            // The recording tap lives in the output stream callback, not the mixer
+            AudioCommand::StartRecording(_) | AudioCommand::StopRecording => {}
+            AudioCommand::SetMetronome(settings) => self.metronome.set(settings),
+            AudioCommand::SetPadMix { note, mix } => {
+                if let (Some(slot), Some(chain)) =
+                    (self.pads.get_mut(note as usize), self.inserts.get_mut(note as usize))
+                {
+                    let mix = mix.sanitized();
+                    *slot = PadGains::from(mix);
+                    chain.set(mix.inserts);
+                    self.solo = self.pads.iter().any(|p| p.solo);
+                }
+            }
+            AudioCommand::ResetPadMix => {
+                self.pads = [PadGains::default(); 128];
+                self.solo = false;
+                for chain in &mut self.inserts {
+                    chain.set(Default::default());
+                }
+            }
+            AudioCommand::SetReverb(settings) => self.reverb.set(settings),
+            AudioCommand::SetConvolution { impulse, wet } => self.convolver.set(impulse, wet),
+            AudioCommand::SetMaster { settings, meter } => self.master.set(settings, Some(meter)),
+        }
+    }
+
+    /// Start a new voice, stealing existing ones if a limit is reached.
+    fn trigger(&mut self, voice: Voice) {
+        let pad = (voice.kit, voice.note);
+        let limit = self.note_limits.get(voice.note as usize).copied().unwrap_or(0);
+        // Per-note limits always steal within the same note of the same kit
+        if limit > 0
+            && self.active_count(Some(pad)) >= limit
+            && let Some(idx) = self.oldest_active(Some(pad))
+        {
+            self.voices[idx].start_fade(self.steal_fade);
+        }
+
+        if self.active_count(None) >= self.max_voices
+            && let Some(idx) = self.steal_candidate(pad)
+        {
+            self.voices[idx].start_fade(self.steal_fade);
+        }
+
+        // Out of headroom: drop the fading voice closest to silence outright
+        if self.voices.len() == self.voices.capacity() {
+            let idx = self
+                .voices
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, v)| v.fade.as_ref().map_or(usize::MAX, |f| f.remaining_frames))
+                .map(|(i, _)| i);
+            if let Some(idx) = idx {
+                self.voices.remove(idx);
+            }
+        }
+
+        // A pad's inserts start afresh once its last hit has finished
+        if voice.kit == MAIN_KIT
+            && !self.voices.iter().any(|v| v.kit == MAIN_KIT && v.note == voice.note)
+            && let Some(chain) = self.inserts.get_mut(voice.note as usize)
+        {
+            chain.reset();
+        }
+
+        self.voices.push(voice);
+    }
+
+    /// Count active voices, optionally restricted to one `(kit, note)` pad.
+    fn active_count(&self, pad: Option<(u8, u8)>) -> usize {
+        self.voices
+            .iter()
+            .filter(|v| v.is_active() && pad.is_none_or(|p| (v.kit, v.note) == p))
+            .count()
+    }
+
+    /// Index of the oldest active voice, optionally restricted to one `(kit, note)` pad.
+    /// Voices are kept in trigger order, so the first match is the oldest.
+    fn oldest_active(&self, pad: Option<(u8, u8)>) -> Option<usize> {
+        self.voices
+            .iter()
+            .position(|v| v.is_active() && pad.is_none_or(|p| (v.kit, v.note) == p))
+    }
+
+    /// Pick the voice to steal for an incoming `(kit, note)` pad according to the policy.
+    fn steal_candidate(&self, incoming: (u8, u8)) -> Option<usize> {
+        match self.policy {
+            StealPolicy::Oldest => self.oldest_active(None),
+            StealPolicy::Quietest => self
+                .voices
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| v.is_active())
+                .min_by(|(_, a), (_, b)| a.gain.total_cmp(&b.gain))
+                .map(|(i, _)| i),
+            StealPolicy::SameNoteFirst => self
+                .oldest_active(Some(incoming))
+                .or_else(|| self.oldest_active(None)),
+        }
+    }
+
+    /// True when no voices or reverb tails are sounding (the metronome is not counted).
+    pub fn is_idle(&self) -> bool {
+        self.voices.is_empty() && self.reverb.is_idle() && self.convolver.is_idle()
+    }
+
+    /// Mix all voices into an interleaved output buffer, overwriting its contents.
+    pub fn render(&mut self, output: &mut [f32]) {
+        for block in output.chunks_mut(SEND_BLOCK_FRAMES * self.channels) {
+            self.render_block(block);
+        }
+    }
+
+    fn render_block(&mut self, output: &mut [f32]) {
+        // Zero the output buffer
+        for sample in output.iter_mut() {
+            *sample = 0.0;
+        }
+
+        // Mix all active voices into the output
+        let ch = self.channels;
+        let frames = output.len() / ch;
+        let send = &mut self.send[..frames];
+        send.fill(0.0);
+        let ir_send = &mut self.ir_send[..frames];
+        ir_send.fill(0.0);
+        self.insert_notes.clear();
+        for voice in &mut self.voices {
+            // Notes past 127 (e.g. a `200.wav`) have no pad and so no chain
+            let has_inserts = self.inserts.get(voice.note as usize).is_some_and(|c| c.is_active());
+            if voice.kit == MAIN_KIT && has_inserts {
+                // Mixed below, through the pad's inserts
+                if !self.insert_notes.contains(&voice.note) {
+                    self.insert_notes.push(voice.note);
+                }
+                continue;
+            }
+            let pad = match self.pads.get(voice.note as usize) {
+                Some(pad) if voice.kit == MAIN_KIT => *pad,
+                _ => PadGains::default(),
+            };
+            // Muted voices keep playing silently, so unmuting picks them up mid-sample
+            let audible = pad.is_audible(self.solo);
+            for frame in 0..frames {
+                if voice.is_done() {
+                    break;
+                }
+                let gain = voice.frame_gain();
+                let mut mono = 0.0;
+                for c in 0..ch {
+                    if voice.position + c < voice.samples.len() {
+                        let sample = voice.samples[voice.position + c] * gain;
+                        output[frame * ch + c] += sample * pad.channel_gain(audible, ch, c);
+                        mono += sample;
+                    }
+                }
+                if audible && pad.send > 0.0 {
+                    send[frame] += mono * pad.send / ch as f32;
+                }
+                if audible && pad.ir_send > 0.0 {
+                    ir_send[frame] += mono * pad.ir_send / ch as f32;
+                }
+                voice.position += ch;
+            }
+        }
+
+        // Pads with inserts: their voices summed, processed, then mixed like the rest
+        for &note in &self.insert_notes {
+            let buffer = &mut self.insert_buffer[..frames * ch];
+            buffer.fill(0.0);
+            for voice in self.voices.iter_mut().filter(|v| v.kit == MAIN_KIT && v.note == note) {
+                for frame in 0..frames {
+                    if voice.is_done() {
+                        break;
+                    }
+                    let gain = voice.frame_gain();
+                    for c in 0..ch {
+                        if voice.position + c < voice.samples.len() {
+                            buffer[frame * ch + c] += voice.samples[voice.position + c] * gain;
+                        }
+                    }
+                    voice.position += ch;
+                }
+            }
+            self.inserts[note as usize].process(buffer, ch);
+
+            let pad = self.pads[note as usize];
+            let audible = pad.is_audible(self.solo);
+            for (frame, samples) in buffer.chunks(ch).enumerate() {
+                let mut mono = 0.0;
+                for (c, &sample) in samples.iter().enumerate() {
+                    output[frame * ch + c] += sample * pad.channel_gain(audible, ch, c);
+                    mono += sample;
+                }
+                if audible && pad.send > 0.0 {
+                    send[frame] += mono * pad.send / ch as f32;
+                }
+                if audible && pad.ir_send > 0.0 {
+                    ir_send[frame] += mono * pad.ir_send / ch as f32;
+                }
+            }
+        }
+
+        // Remove finished voices
+        self.voices.retain(|v| !v.is_done());
+
+        self.reverb.process(send, output, ch);
+        self.convolver.process(ir_send, output, ch);
+
+        self.metronome.render(output, ch);
+
+        self.master.process(output, ch);
+    }
+}
+
+/// A running output stream. Dropping it stops audio.
+pub struct OutputStream {
+    _stream: cpal::Stream,
+    failed: Arc<AtomicBool>,
+}
+
+impl OutputStream {
+    /// True once the stream has reported an error, e.g. because the device was unplugged.
+    pub fn has_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
 
 /// Start a persistent audio output stream that mixes voices triggered via rtrb.
 ///
-/// The returned `cpal::Stream` must be kept alive — dropping it stops audio.
+/// The returned stream must be kept alive — dropping it stops audio.
 pub fn run_output_stream(
     device_index: Option<usize>,
     mut consumer: Consumer<AudioCommand>,
     sample_rate: u32,
     channels: u16,
-) -> Result<cpal::Stream> {
+    polyphony: &Polyphony,
+    metronome: &MetronomeSettings,
+) -> Result<OutputStream> {
     let device = get_device(device_index)?;
     let device_name = device.name().unwrap_or_else(|_| "Unknown".to_string());
 
@@ -201,8 +675,14 @@ pub fn run_output_stream(
         buffer_size: BufferSize::Fixed(64),
     };
 
-    let ch = channels as usize;
-    let mut voices: Vec<Voice> = Vec::with_capacity(MAX_POLYPHONY);
+    let failed = Arc::new(AtomicBool::new(false));
+    let failed_flag = Arc::clone(&failed);
+    let mut mixer = Mixer::new(sample_rate, channels, polyphony);
+    mixer.set_clicks(ClickSounds::load(sample_rate, channels));
+    mixer.handle_command(AudioCommand::SetMetronome(*metronome));
+    // Recording tap. Dropping the producer here never frees the ring buffer:
+    // the writer thread still holds the consumer until it sees the tap abandoned.
+    let mut tap: Option<record::Tap> = None;
 
     let stream = device
         .build_output_stream(
@@ -211,60 +691,23 @@ pub fn run_output_stream(
                 // Drain all pending commands from the ring buffer
                 while let Ok(cmd) = consumer.pop() {
                     match cmd {
-                        AudioCommand::Trigger {
-                            samples,
-                            gain,
-                            note,
-                        } => {
-                            if voices.len() < MAX_POLYPHONY {
-                                voices.push(Voice {
-                                    samples,
-                                    position: 0,
-                                    gain,
-                                    note,
-                                    fade: None,
-                                });
-                            }
-                            // Excess triggers silently dropped
-                        }
-                        AudioCommand::Choke { note, fade_frames } => {
-                            for voice in voices.iter_mut() {
-                                if voice.note == note {
-                                    voice.start_fade(fade_frames);
-                                }
-                            }
-                        }
+                        AudioCommand::StartRecording(producer) => tap = Some(producer),
+                        AudioCommand::StopRecording => tap = None,
+                        cmd => mixer.handle_command(cmd),
                     }
                 }
+                mixer.render(output);
 
-                // Zero the output buffer
-                for sample in output.iter_mut() {
-                    *sample = 0.0;
-                }
-
-                // Mix all active voices into the output
-                let frames = output.len() / ch;
-                for voice in &mut voices {
-                    for frame in 0..frames {
-                        if voice.is_done() {
-                            break;
-                        }
-                        let gain = voice.frame_gain();
-                        for c in 0..ch {
-                            if voice.position + c < voice.samples.len() {
-                                output[frame * ch + c] +=
-                                    voice.samples[voice.position + c] * gain;
-                            }
-                        }
-                        voice.position += ch;
-                    }
+                // If the writer falls behind, whole buffers are dropped rather than blocking
+                if let Some(tap) = tap.as_mut() {
+                    tap.push_buffer(output);
                 }
-
-                // Remove finished voices
-                voices.retain(|v| !v.is_done());
             },
             move |err| {
-                eprintln!("Audio stream error: {}", err);
+                // A lost device keeps erroring until the stream is dropped; report it once
+                if !failed_flag.swap(true, Ordering::Relaxed) {
+                    eprintln!("Audio stream error: {}", err);
+                }
             },
             None,
         )
@@ -274,12 +717,13 @@ pub fn run_output_stream(
         .play()
         .with_context(|| format!("Failed to start audio stream on {}", device_name))?;
 
-    Ok(stream)
+    Ok(OutputStream { _stream: stream, failed })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::inserts::CompressorSettings;
 
     #[test]
     fn list_output_devices_does_not_error() {
@@ -296,6 +740,7 @@ mod tests {
             position: 100,
             gain: 1.0,
             note: 36,
+            kit: MAIN_KIT,
             fade: None,
         };
         assert!(voice.is_done());
@@ -309,6 +754,7 @@ mod tests {
             position: 50,
             gain: 1.0,
             note: 36,
+            kit: MAIN_KIT,
             fade: None,
         };
         assert!(!voice.is_done());
@@ -322,6 +768,7 @@ mod tests {
             position: 0,
             gain: 1.0,
             note: 46,
+            kit: MAIN_KIT,
             fade: Some(Fade {
                 remaining_frames: 0,
                 total_frames: 100,
@@ -338,6 +785,7 @@ mod tests {
             position: 0,
             gain: 1.0,
             note: 46,
+            kit: MAIN_KIT,
             fade: None,
         };
 
@@ -376,12 +824,13 @@ mod tests {
                 samples: Arc::clone(&samples),
                 gain: 0.5,
                 note: 38,
+                kit: MAIN_KIT,
             })
             .unwrap();
 
         let cmd = consumer.pop().unwrap();
         match cmd {
-            AudioCommand::Trigger { samples: s, gain, note } => {
+            AudioCommand::Trigger { samples: s, gain, note, .. } => {
                 assert_eq!(s.len(), 3);
                 assert!((gain - 0.5).abs() < f32::EPSILON);
                 assert_eq!(note, 38);
@@ -396,17 +845,366 @@ mod tests {
         producer
             .push(AudioCommand::Choke {
                 note: 46,
-                fade_frames: 3000,
+                kit: 1,
+                fade_secs: 0.068,
             })
             .unwrap();
 
         let cmd = consumer.pop().unwrap();
         match cmd {
-            AudioCommand::Choke { note, fade_frames } => {
+            AudioCommand::Choke { note, kit, fade_secs } => {
                 assert_eq!(note, 46);
-                assert_eq!(fade_frames, 3000);
+                assert_eq!(kit, 1);
+                assert_eq!(fade_secs, 0.068);
             }
             _ => panic!("Expected Choke command"),
         }
     }
+
+    fn trigger(mixer: &mut Mixer, note: u8, gain: f32) {
+        trigger_on(mixer, MAIN_KIT, note, gain);
+    }
+
+    fn trigger_on(mixer: &mut Mixer, kit: u8, note: u8, gain: f32) {
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![1.0_f32; 10_000]),
+            gain,
+            note,
+            kit,
+        });
+    }
+
+    fn polyphony(max_voices: usize, policy: StealPolicy) -> Polyphony {
+        Polyphony {
+            max_voices,
+            policy,
+            note_limits: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn mixer_steals_oldest_instead_of_dropping() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(2, StealPolicy::Oldest));
+        trigger(&mut mixer, 36, 1.0);
+        trigger(&mut mixer, 38, 1.0);
+        trigger(&mut mixer, 42, 1.0);
+
+        // The new hit plays; the oldest voice fades out instead
+        assert_eq!(mixer.voices.len(), 3);
+        assert!(!mixer.voices[0].is_active());
+        assert_eq!(mixer.voices[0].note, 36);
+        assert_eq!(mixer.active_count(None), 2);
+        assert_eq!(mixer.voices[2].note, 42);
+    }
+
+    #[test]
+    fn mixer_steals_quietest() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(3, StealPolicy::Quietest));
+        trigger(&mut mixer, 36, 0.9);
+        trigger(&mut mixer, 38, 0.2);
+        trigger(&mut mixer, 42, 0.5);
+        trigger(&mut mixer, 49, 1.0);
+
+        let stolen: Vec<u8> = mixer.voices.iter().filter(|v| !v.is_active()).map(|v| v.note).collect();
+        assert_eq!(stolen, vec![38]);
+    }
+
+    #[test]
+    fn mixer_steals_same_note_first() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(3, StealPolicy::SameNoteFirst));
+        trigger(&mut mixer, 36, 1.0);
+        trigger(&mut mixer, 38, 1.0);
+        trigger(&mut mixer, 51, 1.0);
+        trigger(&mut mixer, 51, 1.0);
+
+        let stolen: Vec<u8> = mixer.voices.iter().filter(|v| !v.is_active()).map(|v| v.note).collect();
+        assert_eq!(stolen, vec![51]);
+        // Kick and snare keep ringing
+        assert!(mixer.voices[0].is_active());
+        assert!(mixer.voices[1].is_active());
+    }
+
+    #[test]
+    fn mixer_same_note_first_falls_back_to_oldest() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(2, StealPolicy::SameNoteFirst));
+        trigger(&mut mixer, 36, 1.0);
+        trigger(&mut mixer, 38, 1.0);
+        trigger(&mut mixer, 42, 1.0);
+        assert!(!mixer.voices[0].is_active());
+        assert_eq!(mixer.voices[0].note, 36);
+    }
+
+    #[test]
+    fn mixer_enforces_per_note_limit() {
+        let mut poly = polyphony(16, StealPolicy::Oldest);
+        poly.note_limits.insert(51, 2);
+        let mut mixer = Mixer::new(48000, 2, &poly);
+        trigger(&mut mixer, 36, 1.0);
+        trigger(&mut mixer, 51, 1.0);
+        trigger(&mut mixer, 51, 1.0);
+        trigger(&mut mixer, 51, 1.0);
+
+        assert_eq!(mixer.active_count(Some((MAIN_KIT, 51))), 2);
+        assert!(mixer.voices[0].is_active()); // kick untouched
+        assert!(!mixer.voices[1].is_active()); // first ride stolen
+    }
+
+    #[test]
+    fn choke_fade_follows_the_mixer_rate() {
+        for sample_rate in [44100, 96000] {
+            let mut mixer = Mixer::new(sample_rate, 1, &Polyphony::default());
+            trigger(&mut mixer, 46, 1.0);
+            mixer.handle_command(AudioCommand::Choke { note: 46, kit: MAIN_KIT, fade_secs: 0.01 });
+
+            let fade_frames = sample_rate as usize / 100;
+            let mut out = vec![0.0_f32; fade_frames - 1];
+            mixer.render(&mut out);
+            assert_eq!(mixer.voices.len(), 1);
+            let mut out = vec![0.0_f32; 2];
+            mixer.render(&mut out);
+            assert!(mixer.voices.is_empty());
+        }
+    }
+
+    #[test]
+    fn mixer_stolen_voice_fades_over_a_few_ms() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(1, StealPolicy::Oldest));
+        trigger(&mut mixer, 36, 1.0);
+        trigger(&mut mixer, 38, 1.0);
+
+        // 5 ms at 48 kHz = 240 frames
+        let mut out = vec![0.0_f32; 2 * 239];
+        mixer.render(&mut out);
+        assert_eq!(mixer.voices.len(), 2);
+        let mut out = vec![0.0_f32; 2 * 2];
+        mixer.render(&mut out);
+        assert_eq!(mixer.voices.len(), 1);
+        assert_eq!(mixer.voices[0].note, 38);
+    }
+
+    #[test]
+    fn mixer_never_exceeds_preallocated_capacity() {
+        let mut mixer = Mixer::new(48000, 2, &polyphony(2, StealPolicy::Oldest));
+        let capacity = mixer.voices.capacity();
+        for _ in 0..50 {
+            trigger(&mut mixer, 38, 1.0);
+        }
+        assert_eq!(mixer.voices.capacity(), capacity);
+        assert!(mixer.voices.len() <= capacity);
+        assert_eq!(mixer.active_count(None), 2);
+    }
+
+    #[test]
+    fn mixer_renders_voices_into_output() {
+        let mut mixer = Mixer::new(48000, 2, &Polyphony::default());
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![0.5, 0.25, 0.5, 0.25]),
+            gain: 0.5,
+            note: 36,
+            kit: MAIN_KIT,
+        });
+        let mut out = vec![1.0_f32; 6];
+        mixer.render(&mut out);
+        assert_eq!(out, vec![0.25, 0.125, 0.25, 0.125, 0.0, 0.0]);
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn mixer_applies_pad_mix_to_ringing_voices() {
+        let mut mixer = Mixer::new(48000, 1, &Polyphony::default());
+        for note in [36, 38] {
+            mixer.handle_command(AudioCommand::Trigger {
+                samples: Arc::new(vec![0.5; 8]),
+                gain: 1.0,
+                note,
+                kit: MAIN_KIT,
+            });
+        }
+        let mut out = vec![0.0_f32; 2];
+        mixer.render(&mut out);
+        assert_eq!(out, vec![1.0, 1.0]);
+
+        let mute = PadMix { mute: true, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix: mute });
+        mixer.render(&mut out);
+        assert_eq!(out, vec![0.5, 0.5]);
+
+        // Soloing the muted pad still leaves it muted, and silences the kick
+        let solo = PadMix { mute: true, solo: true, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix: solo });
+        mixer.render(&mut out);
+        assert_eq!(out, vec![0.0, 0.0]);
+
+        mixer.handle_command(AudioCommand::ResetPadMix);
+        mixer.render(&mut out);
+        assert_eq!(out, vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn mixer_pans_stereo_voices() {
+        let mut mixer = Mixer::new(48000, 2, &Polyphony::default());
+        let mix = PadMix { pan: 1.0, volume_db: -6.0, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix });
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![0.5; 4]),
+            gain: 1.0,
+            note: 38,
+            kit: MAIN_KIT,
+        });
+        let mut out = vec![0.0_f32; 2];
+        mixer.render(&mut out);
+        let (left, right) = mix.channel_gains();
+        assert!(left.abs() < 1e-6);
+        assert_eq!(out, vec![0.5 * left, 0.5 * right]);
+    }
+
+    #[test]
+    fn mixer_rings_out_the_reverb_of_pads_that_send() {
+        let mut mixer = Mixer::new(48000, 2, &Polyphony::default());
+        let hit = |mixer: &mut Mixer, note| {
+            mixer.handle_command(AudioCommand::Trigger {
+                samples: Arc::new(vec![0.5; 64]),
+                gain: 1.0,
+                note,
+                kit: MAIN_KIT,
+            })
+        };
+        // Without a send the output stays dry
+        hit(&mut mixer, 36);
+        let mut out = vec![0.0_f32; 4800 * 2];
+        mixer.render(&mut out);
+        assert!(out[64..].iter().all(|&s| s == 0.0));
+        assert!(mixer.is_idle());
+
+        let mix = PadMix { reverb: 0.5, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix });
+        hit(&mut mixer, 38);
+        mixer.render(&mut out);
+        assert!(out[64 * 2..].iter().any(|&s| s != 0.0));
+        // The voice is done but its tail keeps the mixer busy
+        assert!(!mixer.is_idle());
+    }
+
+    #[test]
+    fn mixer_convolves_the_ir_send_one_block_late() {
+        let mut mixer = Mixer::new(48000, 1, &Polyphony::default());
+        let impulse = ImpulseResponse::from_channels("echo.wav".into(), 48000, &[vec![0.0, 0.0, 1.0]]);
+        mixer.handle_command(AudioCommand::SetConvolution { impulse: Some(Arc::new(impulse)), wet: 1.0 });
+        let mix = PadMix { convolution: 0.5, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix });
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![1.0]),
+            gain: 1.0,
+            note: 38,
+            kit: MAIN_KIT,
+        });
+        let mut out = vec![0.0_f32; 256];
+        mixer.render(&mut out);
+        // The dry hit, then the echo two frames into the response, one 64-frame block late
+        assert_eq!(out[0], 1.0);
+        assert!((out[66] - 0.5).abs() < 1e-5);
+        assert!(out[1..66].iter().chain(&out[67..]).all(|s| s.abs() < 1e-5));
+        assert!(!mixer.is_idle());
+    }
+
+    #[test]
+    fn mixer_runs_pads_with_inserts_through_them() {
+        let mut mixer = Mixer::new(48000, 2, &Polyphony::default());
+        let mut mix = PadMix { pan: -1.0, ..PadMix::default() };
+        mix.inserts.compressor = CompressorSettings { enabled: true, makeup_db: 6.0, ..CompressorSettings::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix });
+        for note in [36, 38] {
+            mixer.handle_command(AudioCommand::Trigger {
+                samples: Arc::new(vec![0.01; 16]),
+                gain: 1.0,
+                note,
+                kit: MAIN_KIT,
+            });
+        }
+        let mut out = vec![0.0_f32; 8];
+        mixer.render(&mut out);
+        // The snare's makeup gain, panned hard left, on top of the dry kick
+        let (left, _) = mix.channel_gains();
+        let snare = 0.01 * 10.0_f32.powf(6.0 / 20.0) * left;
+        assert!((out[0] - (0.01 + snare)).abs() < 1e-6);
+        assert!((out[1] - 0.01).abs() < 1e-6);
+
+        // Muted, the chain keeps running but nothing is heard
+        mixer.handle_command(AudioCommand::SetPadMix { note: 38, mix: PadMix { mute: true, ..mix } });
+        mixer.render(&mut out);
+        assert!((out[0] - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mixer_plays_notes_without_a_pad_dry() {
+        let mut mixer = Mixer::new(48000, 1, &Polyphony::default());
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![0.5; 4]),
+            gain: 1.0,
+            note: 200,
+            kit: MAIN_KIT,
+        });
+        let mut out = vec![0.0_f32; 4];
+        mixer.render(&mut out);
+        assert_eq!(out, vec![0.5; 4]);
+    }
+
+    #[test]
+    fn choke_only_affects_voices_of_the_same_kit() {
+        let mut mixer = Mixer::new(48000, 1, &Polyphony::default());
+        for kit in [MAIN_KIT, 1] {
+            mixer.handle_command(AudioCommand::Trigger {
+                samples: Arc::new(vec![0.5; 100]),
+                gain: 1.0,
+                note: 46,
+                kit,
+            });
+        }
+        mixer.handle_command(AudioCommand::Choke { note: 46, kit: 1, fade_secs: 0.001 });
+        assert!(mixer.voices[0].is_active());
+        assert!(!mixer.voices[1].is_active());
+
+        // The pad mix belongs to the main kit
+        let mute = PadMix { mute: true, ..PadMix::default() };
+        mixer.handle_command(AudioCommand::SetPadMix { note: 46, mix: mute });
+        mixer.voices.remove(1);
+        mixer.handle_command(AudioCommand::Trigger {
+            samples: Arc::new(vec![0.5; 100]),
+            gain: 1.0,
+            note: 46,
+            kit: 1,
+        });
+        let mut out = vec![0.0_f32; 1];
+        mixer.render(&mut out);
+        assert_eq!(out, vec![0.5]);
+    }
+
+    #[test]
+    fn limits_and_stealing_match_voices_by_kit_and_note() {
+        let mut poly = polyphony(3, StealPolicy::SameNoteFirst);
+        poly.note_limits.insert(51, 1);
+        let mut mixer = Mixer::new(48000, 2, &poly);
+        trigger_on(&mut mixer, MAIN_KIT, 51, 1.0);
+        trigger_on(&mut mixer, 1, 51, 1.0);
+
+        // The channel kit's ride doesn't count against the main kit's limit
+        assert!(mixer.voices[0].is_active());
+        assert_eq!(mixer.active_count(Some((MAIN_KIT, 51))), 1);
+        assert_eq!(mixer.active_count(Some((1, 51))), 1);
+
+        trigger_on(&mut mixer, 1, 51, 1.0);
+        assert!(mixer.voices[0].is_active());
+        assert!(!mixer.voices[1].is_active());
+
+        // Full pool: same-note-first steals the same note of the incoming kit,
+        // even when the main kit's voice on that note is older
+        let mut mixer = Mixer::new(48000, 2, &polyphony(3, StealPolicy::SameNoteFirst));
+        trigger_on(&mut mixer, MAIN_KIT, 51, 1.0);
+        trigger_on(&mut mixer, 1, 51, 1.0);
+        trigger_on(&mut mixer, MAIN_KIT, 36, 1.0);
+        trigger_on(&mut mixer, 1, 51, 1.0);
+        let stolen: Vec<(u8, u8)> = mixer.voices.iter().filter(|v| !v.is_active()).map(|v| (v.kit, v.note)).collect();
+        assert_eq!(stolen, vec![(1, 51)]);
+        assert!(mixer.voices[0].is_active());
+    }
 }
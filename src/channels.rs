@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::control;
+use crate::kit::{self, NoteGroup};
+use crate::mapping::{self, NoteMapping};
+use crate::settings::MidiSettings;
+
+/// A kit that plays on its own MIDI channel alongside the main kit.
+/// Its voices are tagged with the channel number (the main kit is `audio::MAIN_KIT`).
+pub struct ChannelKit {
+    /// MIDI channel, 1-16.
+    pub channel: u8,
+    pub name: String,
+    pub notes: HashMap<u8, Arc<NoteGroup>>,
+    pub mapping: NoteMapping,
+}
+
+/// A set of MIDI channels (1-16).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelFilter(u16);
+
+impl ChannelFilter {
+    /// The given channels; out-of-range ones are ignored and an empty list means all.
+    pub fn new(channels: &[u8]) -> Self {
+        if channels.is_empty() {
+            return Self(u16::MAX);
+        }
+        Self(
+            channels
+                .iter()
+                .filter(|c| (1..=16).contains(*c))
+                .fold(0, |mask, c| mask | 1 << (c - 1)),
+        )
+    }
+
+    pub fn contains(&self, channel: u8) -> bool {
+        (1..=16).contains(&channel) && self.0 & (1 << (channel - 1)) != 0
+    }
+
+    /// Human-readable list, e.g. "10", "10, 12" or "all".
+    pub fn label(&self) -> String {
+        if self.0 == u16::MAX {
+            return "all".to_string();
+        }
+        let channels: Vec<String> = (1..=16u8)
+            .filter(|&c| self.contains(c))
+            .map(|c| c.to_string())
+            .collect();
+        if channels.is_empty() {
+            "none".to_string()
+        } else {
+            channels.join(", ")
+        }
+    }
+}
+
+/// Which MIDI channels play the main kit, and which have kits of their own.
+pub struct ChannelRouting {
+    main_channels: ChannelFilter,
+    kits: Vec<ChannelKit>,
+}
+
+impl ChannelRouting {
+    /// Route `channels` (1-16) to the main kit; an empty list means every channel.
+    pub fn new(channels: &[u8]) -> Self {
+        Self {
+            main_channels: ChannelFilter::new(channels),
+            kits: Vec::new(),
+        }
+    }
+
+    /// Whether a message on `channel` (1-16) should play the main kit, using
+    /// `filter` (a port's own channels) in place of the configured ones if given.
+    /// Channels with a kit of their own play only that kit.
+    pub fn plays_main(&self, channel: u8, filter: Option<&ChannelFilter>) -> bool {
+        filter.unwrap_or(&self.main_channels).contains(channel) && self.kit(channel).is_none()
+    }
+
+    /// The kit assigned to `channel` (1-16), if any.
+    pub fn kit(&self, channel: u8) -> Option<&ChannelKit> {
+        self.kits.iter().find(|k| k.channel == channel)
+    }
+
+    pub fn kits(&self) -> &[ChannelKit] {
+        &self.kits
+    }
+
+    /// Log lines describing the routing (for the TUI log viewer / headless log).
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("MIDI channels: {}", self.main_channels.label())];
+        for kit in &self.kits {
+            lines.push(format!(
+                "Channel {}: kit \"{}\" ({} notes)",
+                kit.channel,
+                kit.name,
+                kit.notes.len()
+            ));
+        }
+        lines
+    }
+}
+
+/// Build the routing from settings, loading each channel kit for a stream at
+/// `sample_rate` with `channels` channels.
+///
+/// Kits that can't be found or loaded are skipped; the reasons are returned
+/// alongside so they can be shown in the log.
+pub fn load_routing(
+    settings: &MidiSettings,
+    kits_dirs: &[PathBuf],
+    sample_rate: u32,
+    channels: u16,
+) -> (ChannelRouting, Vec<String>) {
+    let mut errors = Vec::new();
+    for &channel in &settings.channels {
+        if !(1..=16).contains(&channel) {
+            errors.push(format!("Ignoring MIDI channel {} (must be 1-16)", channel));
+        }
+    }
+    let mut routing = ChannelRouting::new(&settings.channels);
+
+    for (key, query) in &settings.channel_kits {
+        let channel = match key.parse::<u8>() {
+            Ok(c) if (1..=16).contains(&c) => c,
+            _ => {
+                errors.push(format!("Ignoring channel kit for '{}' (channel must be 1-16)", key));
+                continue;
+            }
+        };
+        match load_channel_kit(channel, query, kits_dirs, sample_rate, channels) {
+            Ok(kit) => routing.kits.push(kit),
+            Err(e) => errors.push(format!("Channel {} kit: {:#}", channel, e)),
+        }
+    }
+    routing.kits.sort_by_key(|k| k.channel);
+    (routing, errors)
+}
+
+fn load_channel_kit(
+    channel: u8,
+    query: &str,
+    kits_dirs: &[PathBuf],
+    sample_rate: u32,
+    channels: u16,
+) -> anyhow::Result<ChannelKit> {
+    let (path, name) = control::resolve_kit(query, kits_dirs)?;
+    let loaded = kit::load_kit(&path, Some(sample_rate))?;
+    if loaded.channels != channels {
+        anyhow::bail!(
+            "\"{}\" has {} channels but the output has {}",
+            name,
+            loaded.channels,
+            channels
+        );
+    }
+    let mapping = mapping::load_kit_mapping(&path).unwrap_or_else(mapping::default_mapping);
+    Ok(ChannelKit {
+        channel,
+        name,
+        notes: loaded.notes,
+        mapping,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::Path;
+
+    fn write_kit(dir: &Path) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.join("38.wav"), spec).unwrap();
+        for _ in 0..32 {
+            writer.write_sample(1000_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn main_channels_filter() {
+        let routing = ChannelRouting::new(&[10, 12, 0, 17]);
+        assert!(routing.plays_main(10, None));
+        assert!(routing.plays_main(12, None));
+        assert!(!routing.plays_main(1, None));
+        assert!(!routing.plays_main(11, None));
+        assert_eq!(routing.main_channels.label(), "10, 12");
+
+        // A port's own channels replace the configured ones
+        let port = ChannelFilter::new(&[1]);
+        assert!(routing.plays_main(1, Some(&port)));
+        assert!(!routing.plays_main(10, Some(&port)));
+
+        let omni = ChannelFilter::new(&[]);
+        assert!((1..=16).all(|c| omni.contains(c)));
+        assert_eq!(omni.label(), "all");
+    }
+
+    #[test]
+    fn load_routing_assigns_kits_and_reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write_kit(dir.path());
+        let settings = MidiSettings {
+            channels: vec![10, 11, 99],
+            channel_kits: BTreeMap::from([
+                ("11".to_string(), dir.path().display().to_string()),
+                ("12".to_string(), "/nonexistent/drumkit-kit".to_string()),
+                ("x".to_string(), dir.path().display().to_string()),
+            ]),
+        };
+
+        let (routing, errors) = load_routing(&settings, &[], 48000, 2);
+        assert_eq!(routing.kits().len(), 1);
+        let kit = routing.kit(11).unwrap();
+        assert!(kit.notes.contains_key(&38));
+        // A channel with its own kit doesn't also play the main kit
+        assert!(!routing.plays_main(11, None));
+        assert!(routing.plays_main(10, None));
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+    }
+
+    #[test]
+    fn load_routing_rejects_channel_count_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        write_kit(dir.path());
+        let settings = MidiSettings {
+            channels: vec![10],
+            channel_kits: BTreeMap::from([("11".to_string(), dir.path().display().to_string())]),
+        };
+        let (routing, errors) = load_routing(&settings, &[], 48000, 6);
+        assert!(routing.kits().is_empty());
+        assert!(errors[0].contains("channels"), "{:?}", errors);
+    }
+}
@@ -4,7 +4,7 @@ use std::io::{self, Write};
 use std::path::PathBuf;
 use std::sync::{mpsc, Arc};
 
-use crate::{audio, mapping, midi, sample};
+use crate::{audio, mapping, metronome, midi, sample};
 
 pub fn cmd_devices() -> Result<()> {
     let devices = midi::list_devices()?;
@@ -35,6 +35,16 @@ pub fn cmd_devices() -> Result<()> {
     println!("Use: drumkit monitor --port <number>");
     println!("  e.g. drumkit monitor --port 1");
 
+    let outputs = midi::list_output_devices().unwrap_or_default();
+    if !outputs.is_empty() {
+        println!();
+        println!("MIDI output devices (for [midi_thru] in settings.toml):");
+        println!();
+        for device in &outputs {
+            println!("  {}", device.name);
+        }
+    }
+
     Ok(())
 }
 
@@ -159,7 +169,7 @@ pub fn cmd_monitor(port: Option<usize>) -> Result<()> {
                     midi::MidiMessage::PolyAftertouch { .. } => {
                         println!("{}", line.with(style::Color::Yellow));
                     }
-                    midi::MidiMessage::ControlChange { .. } => {
+                    midi::MidiMessage::ControlChange { .. } | midi::MidiMessage::ProgramChange { .. } => {
                         println!("{}", line.with(style::Color::Magenta));
                     }
                     _ => {
@@ -230,7 +240,7 @@ pub fn cmd_test_trigger(file: PathBuf, note: u8, port: Option<usize>, device: Op
 
     let (mut producer, consumer) = rtrb::RingBuffer::new(64);
 
-    let _stream = audio::run_output_stream(device, consumer, data.sample_rate, data.channels)?;
+    let _stream = audio::run_output_stream(device, consumer, data.sample_rate, data.channels, &audio::Polyphony::default(), &metronome::MetronomeSettings::default())?;
 
     let devices = midi::list_devices()?;
     if devices.is_empty() {
@@ -266,6 +276,7 @@ pub fn cmd_test_trigger(file: PathBuf, note: u8, port: Option<usize>, device: Op
                     samples: Arc::clone(&trigger_samples),
                     gain,
                     note: target_note,
+                    kit: audio::MAIN_KIT,
                 });
             }
         }
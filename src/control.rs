@@ -0,0 +1,405 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::tui::TuiEvent;
+use crate::{kit, mapping};
+
+/// How long a client waits for the player to act on a command (kit loads can be slow).
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Velocity used by `trigger` when the command doesn't give one.
+const DEFAULT_TRIGGER_VELOCITY: u8 = 100;
+
+/// A command read from the control socket, one JSON object per line,
+/// e.g. `{"cmd": "trigger", "note": 38, "velocity": 100}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Switch kits, by name as shown in the kit picker or by directory path.
+    Kit { kit: String },
+    /// Switch note mappings by name.
+    Mapping { mapping: String },
+    /// Set the master output gain from a linear volume (1.0 = unity).
+    Volume { volume: f32 },
+    /// Play a note as if it came from the MIDI input.
+    Trigger {
+        note: u8,
+        #[serde(default = "default_trigger_velocity")]
+        velocity: u8,
+    },
+    StartRecording,
+    StopRecording,
+    /// Report the player state.
+    State,
+}
+
+fn default_trigger_velocity() -> u8 {
+    DEFAULT_TRIGGER_VELOCITY
+}
+
+/// Reply to a command, written back as one JSON line.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ControlResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<PlayerState>,
+}
+
+impl ControlResponse {
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            message: Some(message.into()),
+            error: None,
+            state: None,
+        }
+    }
+
+    pub fn error(error: impl std::fmt::Display) -> Self {
+        Self {
+            ok: false,
+            message: None,
+            error: Some(error.to_string()),
+            state: None,
+        }
+    }
+
+    pub fn state(state: PlayerState) -> Self {
+        Self {
+            ok: true,
+            message: None,
+            error: None,
+            state: Some(state),
+        }
+    }
+}
+
+impl From<Result<String>> for ControlResponse {
+    fn from(result: Result<String>) -> Self {
+        match result {
+            Ok(message) => Self::ok(message),
+            Err(e) => Self::error(e),
+        }
+    }
+}
+
+/// Snapshot of the running player, returned by the `state` command.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerState {
+    pub kit: String,
+    pub kit_path: PathBuf,
+    pub mapping: String,
+    pub midi_device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Master output gain as a linear volume (1.0 = unity).
+    pub volume: f32,
+    /// Path of the recording in progress.
+    pub recording: Option<PathBuf>,
+    pub midi_capture: bool,
+    /// Metronome summary (e.g. `120 BPM 4/4`) while it is running.
+    pub metronome: Option<String>,
+    /// Audio buffers that clipped since the count was last reset.
+    pub clips: u32,
+}
+
+/// A parsed command waiting for the player's main loop, delivered as `TuiEvent::Control`.
+pub struct ControlRequest {
+    pub command: ControlCommand,
+    pub reply: mpsc::Sender<ControlResponse>,
+}
+
+impl ControlRequest {
+    pub fn respond(self, response: ControlResponse) {
+        let _ = self.reply.send(response);
+    }
+}
+
+/// Default socket path: `$XDG_RUNTIME_DIR/drumkit.sock`, or a per-user file in `/tmp`.
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("drumkit.sock"),
+        _ => {
+            #[cfg(unix)]
+            let user = unsafe { libc::getuid() }.to_string();
+            #[cfg(not(unix))]
+            let user = std::env::var("USERNAME").unwrap_or_default();
+            std::env::temp_dir().join(format!("drumkit-{}.sock", user))
+        }
+    }
+}
+
+/// Find a kit by name (as discovered in the kit directories, case-insensitive) or by path.
+pub fn resolve_kit(query: &str, extra_dirs: &[PathBuf]) -> Result<(PathBuf, String)> {
+    if let Some(found) = kit::discover_kits(extra_dirs)
+        .into_iter()
+        .find(|k| k.name.eq_ignore_ascii_case(query))
+    {
+        return Ok((found.path, found.name));
+    }
+    let path = PathBuf::from(query);
+    if path.is_dir() {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| query.to_string());
+        return Ok((path, name));
+    }
+    anyhow::bail!("Kit not found: {}", query)
+}
+
+/// Find a mapping by name among the built-in, user and extra-directory mappings.
+pub fn resolve_mapping(query: &str, extra_dirs: &[PathBuf]) -> Result<mapping::NoteMapping> {
+    mapping::discover_all_mappings(extra_dirs)
+        .into_iter()
+        .find(|m| m.name.eq_ignore_ascii_case(query))
+        .with_context(|| format!("Mapping not found: {}", query))
+}
+
+/// Validate a `trigger` command and build the MIDI note-on it stands for (channel 10).
+pub fn trigger_message(note: u8, velocity: u8) -> Result<[u8; 3]> {
+    if note > 127 {
+        anyhow::bail!("Note {} out of range (0-127)", note);
+    }
+    if !(1..=127).contains(&velocity) {
+        anyhow::bail!("Velocity {} out of range (1-127)", velocity);
+    }
+    Ok([0x99, note, velocity])
+}
+
+/// Listening control socket. The socket file is removed when this is dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Listen for line-delimited JSON commands on a Unix socket at `path`.
+///
+/// Each connection gets its own thread; commands are forwarded to the player's
+/// main loop as `TuiEvent::Control` and answered in order, one JSON line each.
+#[cfg(unix)]
+pub fn listen(path: &Path, events: mpsc::Sender<TuiEvent>) -> Result<ControlSocket> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            anyhow::bail!("Not a socket, refusing to replace: {}", path.display());
+        }
+        if UnixStream::connect(path).is_ok() {
+            anyhow::bail!("Another drumkit is already listening on {}", path.display());
+        }
+        // Left over from a crashed run
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket: {}", path.display()))?;
+    }
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create socket dir: {}", parent.display()))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let events = events.clone();
+            std::thread::spawn(move || serve_connection(stream, &events));
+        }
+    });
+
+    Ok(ControlSocket {
+        path: path.to_path_buf(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn listen(_path: &Path, _events: mpsc::Sender<TuiEvent>) -> Result<ControlSocket> {
+    anyhow::bail!("The control socket is only available on Unix-like systems")
+}
+
+/// Answer commands on one connection until the client hangs up.
+#[cfg(unix)]
+fn serve_connection(stream: std::os::unix::net::UnixStream, events: &mpsc::Sender<TuiEvent>) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let Ok(mut writer) = stream.try_clone() else { return };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = dispatch(&line, events);
+        let Ok(json) = serde_json::to_string(&response) else { break };
+        if writeln!(writer, "{}", json).is_err() {
+            break;
+        }
+    }
+}
+
+/// Parse one command line, hand it to the player and wait for the reply.
+#[cfg(unix)]
+fn dispatch(line: &str, events: &mpsc::Sender<TuiEvent>) -> ControlResponse {
+    let command = match serde_json::from_str::<ControlCommand>(line) {
+        Ok(command) => command,
+        Err(e) => return ControlResponse::error(format!("Invalid command: {}", e)),
+    };
+    let (reply, response) = mpsc::channel();
+    if events
+        .send(TuiEvent::Control(ControlRequest { command, reply }))
+        .is_err()
+    {
+        return ControlResponse::error("Player is shutting down");
+    }
+    response
+        .recv_timeout(REPLY_TIMEOUT)
+        .unwrap_or_else(|_| ControlResponse::error("No reply from the player"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let parse = |s: &str| serde_json::from_str::<ControlCommand>(s).unwrap();
+        assert_eq!(
+            parse(r#"{"cmd": "kit", "kit": "Acoustic"}"#),
+            ControlCommand::Kit { kit: "Acoustic".to_string() }
+        );
+        assert_eq!(
+            parse(r#"{"cmd": "trigger", "note": 38}"#),
+            ControlCommand::Trigger { note: 38, velocity: DEFAULT_TRIGGER_VELOCITY }
+        );
+        assert_eq!(parse(r#"{"cmd": "volume", "volume": 0.5}"#), ControlCommand::Volume { volume: 0.5 });
+        assert_eq!(parse(r#"{"cmd": "start_recording"}"#), ControlCommand::StartRecording);
+        assert_eq!(parse(r#"{"cmd":"state"}"#), ControlCommand::State);
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"cmd": "explode"}"#).is_err());
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"cmd": "trigger", "note": 300}"#).is_err());
+    }
+
+    #[test]
+    fn responses_omit_empty_fields() {
+        assert_eq!(
+            serde_json::to_string(&ControlResponse::ok("Volume 50%")).unwrap(),
+            r#"{"ok":true,"message":"Volume 50%"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ControlResponse::error("Kit not found: x")).unwrap(),
+            r#"{"ok":false,"error":"Kit not found: x"}"#
+        );
+    }
+
+    #[test]
+    fn trigger_message_validates_ranges() {
+        assert_eq!(trigger_message(38, 100).unwrap(), [0x99, 38, 100]);
+        assert!(trigger_message(128, 100).is_err());
+        assert!(trigger_message(38, 0).is_err());
+    }
+
+    #[test]
+    fn resolve_kit_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let kit_dir = dir.path().join("My Kit");
+        std::fs::create_dir(&kit_dir).unwrap();
+        let (path, name) = resolve_kit(kit_dir.to_str().unwrap(), &[]).unwrap();
+        assert_eq!(path, kit_dir);
+        assert_eq!(name, "My Kit");
+        assert!(resolve_kit("/nonexistent/kit", &[]).is_err());
+    }
+
+    #[test]
+    fn resolve_builtin_mapping_ignores_case() {
+        let found = resolve_mapping("general midi", &[]).unwrap();
+        assert_eq!(found.name, "General MIDI");
+        assert!(resolve_mapping("No Such Mapping", &[]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_roundtrip() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::os::unix::net::UnixStream;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("drumkit.sock");
+        let (tx, rx) = mpsc::channel();
+        let socket = listen(&path, tx).unwrap();
+
+        // Stand-in for the player's main loop
+        std::thread::spawn(move || {
+            while let Ok(TuiEvent::Control(request)) = rx.recv() {
+                let response = match request.command {
+                    ControlCommand::Volume { volume } => ControlResponse::ok(format!("Volume {}", volume)),
+                    _ => ControlResponse::error("unsupported"),
+                };
+                request.respond(response);
+            }
+        });
+
+        let stream = UnixStream::connect(socket.path()).unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+
+        writeln!(writer, r#"{{"cmd": "volume", "volume": 0.5}}"#).unwrap();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line.trim(), r#"{"ok":true,"message":"Volume 0.5"}"#);
+
+        line.clear();
+        writeln!(writer, "not json").unwrap();
+        reader.read_line(&mut line).unwrap();
+        assert!(line.starts_with(r#"{"ok":false,"error":"Invalid command"#));
+
+        // A second player can't take over a live socket; a stale one is replaced
+        let (tx2, _rx2) = mpsc::channel();
+        assert!(listen(&path, tx2).is_err());
+        drop(writer);
+        drop(reader);
+        drop(socket);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_socket_file_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("drumkit.sock");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let (tx, _rx) = mpsc::channel();
+        let socket = listen(&path, tx).unwrap();
+        assert!(std::os::unix::net::UnixStream::connect(socket.path()).is_ok());
+
+        // Regular files are never deleted
+        let file = dir.path().join("notes.txt");
+        std::fs::write(&file, "keep me").unwrap();
+        let (tx, _rx) = mpsc::channel();
+        assert!(listen(&file, tx).is_err());
+        assert!(file.exists());
+    }
+}
@@ -0,0 +1,631 @@
+use anyhow::Result;
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::{kit, sample};
+
+/// Partition size of the head of the response, and the latency of the IR bus.
+const HEAD_BLOCK: usize = 64;
+
+/// Partition size of the tail, past the first `HEAD_LEN` frames.
+const TAIL_BLOCK: usize = 2048;
+
+/// Frames of the response convolved in small partitions. The tail starts a whole
+/// tail block later than it could, which leaves one block's time to compute it.
+const HEAD_LEN: usize = 2 * TAIL_BLOCK;
+
+/// Head blocks per tail block: the tail's work is spread over this many callbacks.
+const STEPS: usize = TAIL_BLOCK / HEAD_BLOCK;
+
+/// Longer responses are cut short.
+pub const MAX_IR_SECS: f32 = 4.0;
+
+/// Folder inside a kit directory holding its impulse responses.
+const KIT_IMPULSES_DIR: &str = "impulses";
+
+/// The convolution (IR) bus of a kit's mix: a recorded room, plate or spring,
+/// applied to whatever the pads send it. Stored with the kit's mix as
+/// `[mixes.convolution]` in settings.toml.
+///
+/// ```toml
+/// [mixes.convolution]
+/// ir = "plate.wav"   # from the kit's impulses/ folder or the user's, or a path
+/// wet = 0.3          # return level
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConvolutionSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ir: Option<String>,
+    pub wet: f32,
+}
+
+impl Default for ConvolutionSettings {
+    fn default() -> Self {
+        Self { ir: None, wet: 0.3 }
+    }
+}
+
+impl ConvolutionSettings {
+    /// Clamp the wet level to 0.0..=1.0.
+    pub fn sanitized(mut self) -> Self {
+        self.wet = if self.wet.is_finite() { self.wet.clamp(0.0, 1.0) } else { 0.3 };
+        self
+    }
+}
+
+/// Directory for impulse responses shared by every kit: `$XDG_DATA_HOME/drumkit/impulses`
+/// (usually `~/.local/share/drumkit/impulses`).
+pub fn user_impulses_dir() -> PathBuf {
+    if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
+        PathBuf::from(xdg).join("drumkit/impulses")
+    } else {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(home).join(".local/share/drumkit/impulses")
+    }
+}
+
+/// Impulse responses on offer for the kit at `kit`: its own first, then the user's,
+/// each sorted by file name.
+pub fn list_impulses(kit: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for dir in [kit.join(KIT_IMPULSES_DIR), user_impulses_dir()] {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(kit::is_audio_file))
+            .collect();
+        files.sort();
+        found.extend(files);
+    }
+    found
+}
+
+/// Find an impulse response by the name the settings give it: a file in the kit's
+/// `impulses/` folder, then in the user's, or a path.
+pub fn find_impulse(name: &str, kit: &Path) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    [kit.join(KIT_IMPULSES_DIR), user_impulses_dir()]
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// The name to save for an impulse response: its file name when it's in the kit's
+/// own folder, so the kit can move, or else its full path.
+pub fn impulse_name(path: &Path, kit: &Path) -> String {
+    match path.strip_prefix(kit.join(KIT_IMPULSES_DIR)) {
+        Ok(name) => name.to_string_lossy().into_owned(),
+        Err(_) => path.to_string_lossy().into_owned(),
+    }
+}
+
+/// Short display name, e.g. "plate" for `.../impulses/plate.wav`.
+pub fn display_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// One output channel's response as partition spectra, scaled for the unnormalized
+/// inverse transform.
+struct Spectra {
+    head: Vec<Complex<f32>>,
+    tail: Vec<Complex<f32>>,
+}
+
+/// An impulse response prepared for convolution at one sample rate. Immutable and
+/// shared with the audio thread.
+pub struct ImpulseResponse {
+    pub path: PathBuf,
+    pub sample_rate: u32,
+    /// Length in frames, after trimming to `MAX_IR_SECS`.
+    pub frames: usize,
+    /// One or two (left and right) channels.
+    channels: Vec<Spectra>,
+    head_parts: usize,
+    tail_parts: usize,
+}
+
+impl std::fmt::Debug for ImpulseResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImpulseResponse")
+            .field("path", &self.path)
+            .field("sample_rate", &self.sample_rate)
+            .field("frames", &self.frames)
+            .field("channels", &self.channels.len())
+            .finish()
+    }
+}
+
+/// The same file at the same rate is the same response.
+impl PartialEq for ImpulseResponse {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.sample_rate == other.sample_rate && self.frames == other.frames
+    }
+}
+
+impl ImpulseResponse {
+    /// Decode an audio file as a response at `sample_rate`, normalized to unit
+    /// energy so wet levels compare across files.
+    pub fn load(path: &Path, sample_rate: u32) -> Result<Self> {
+        let data = sample::load_audio(path)?.resampled(sample_rate);
+        let ch = data.channels.max(1) as usize;
+        let max_frames = (MAX_IR_SECS * sample_rate as f32) as usize;
+        let frames = (data.samples.len() / ch).min(max_frames);
+        let outputs = ch.min(2);
+        let channels: Vec<Vec<f32>> = (0..outputs)
+            .map(|c| (0..frames).map(|f| data.samples[f * ch + c]).collect())
+            .collect();
+
+        let energy = channels.iter().flatten().map(|s| s * s).sum::<f32>() / outputs as f32;
+        if frames == 0 || energy <= 0.0 {
+            anyhow::bail!("Impulse response is silent: {}", path.display());
+        }
+        let scale = energy.sqrt().recip();
+        let channels: Vec<Vec<f32>> = channels
+            .into_iter()
+            .map(|c| c.into_iter().map(|s| s * scale).collect())
+            .collect();
+        Ok(Self::from_channels(path.to_path_buf(), sample_rate, &channels))
+    }
+
+    /// Prepare one or two channels of response samples as they are.
+    pub(crate) fn from_channels(path: PathBuf, sample_rate: u32, channels: &[Vec<f32>]) -> Self {
+        let frames = channels.iter().map(Vec::len).max().unwrap_or(0);
+        let mut planner = RealFftPlanner::<f32>::new();
+        let head_fft = planner.plan_fft_forward(2 * HEAD_BLOCK);
+        let tail_fft = planner.plan_fft_forward(2 * TAIL_BLOCK);
+        let spectra = channels
+            .iter()
+            .take(2)
+            .map(|samples| {
+                let split = samples.len().min(HEAD_LEN);
+                Spectra {
+                    head: partition(&samples[..split], HEAD_BLOCK, &*head_fft),
+                    tail: partition(&samples[split..], TAIL_BLOCK, &*tail_fft),
+                }
+            })
+            .collect();
+        Self {
+            path,
+            sample_rate,
+            frames,
+            channels: spectra,
+            head_parts: frames.min(HEAD_LEN).div_ceil(HEAD_BLOCK),
+            tail_parts: frames.saturating_sub(HEAD_LEN).div_ceil(TAIL_BLOCK),
+        }
+    }
+}
+
+/// Spectra of `samples` cut into `block`-frame partitions, each zero-padded to
+/// twice that and scaled by the inverse transform's 1/N.
+fn partition(samples: &[f32], block: usize, fft: &dyn RealToComplex<f32>) -> Vec<Complex<f32>> {
+    let bins = block + 1;
+    let mut spectra = vec![Complex::default(); samples.len().div_ceil(block) * bins];
+    let mut time = fft.make_input_vec();
+    let mut scratch = fft.make_scratch_vec();
+    for (part, chunk) in samples.chunks(block).enumerate() {
+        time.fill(0.0);
+        time[..chunk.len()].copy_from_slice(chunk);
+        let _ = fft.process_with_scratch(&mut time, &mut spectra[part * bins..(part + 1) * bins], &mut scratch);
+    }
+    let norm = 1.0 / (2 * block) as f32;
+    for bin in spectra.iter_mut() {
+        *bin *= norm;
+    }
+    spectra
+}
+
+/// Responses handed to the audio thread, held here until it lets go of them so
+/// their memory is freed on this side rather than in the audio callback.
+static SENT: Mutex<Vec<Arc<ImpulseResponse>>> = Mutex::new(Vec::new());
+
+/// Keep `impulse` alive until the audio thread has replaced it.
+pub fn keep_until_released(impulse: &Arc<ImpulseResponse>) {
+    let mut sent = SENT.lock().unwrap();
+    sent.retain(|ir| Arc::strong_count(ir) > 1);
+    if !sent.iter().any(|ir| Arc::ptr_eq(ir, impulse)) {
+        sent.push(Arc::clone(impulse));
+    }
+}
+
+/// One uniformly partitioned overlap-save stage.
+struct Stage {
+    block: usize,
+    /// The previous input block, then the one being filled.
+    input: Vec<f32>,
+    /// Buffer the transforms work in.
+    time: Vec<f32>,
+    /// Spectra of recent input blocks, a ring with the newest at `newest`.
+    history: Vec<Complex<f32>>,
+    newest: usize,
+    /// Output spectrum being summed, per channel.
+    sum: [Vec<Complex<f32>>; 2],
+}
+
+impl Stage {
+    fn new(block: usize, parts: usize) -> Self {
+        let bins = block + 1;
+        Self {
+            block,
+            input: vec![0.0; 2 * block],
+            time: vec![0.0; 2 * block],
+            history: vec![Complex::default(); parts.max(1) * bins],
+            newest: 0,
+            sum: [vec![Complex::default(); bins], vec![Complex::default(); bins]],
+        }
+    }
+
+    fn bins(&self) -> usize {
+        self.block + 1
+    }
+
+    /// Transform the input and make it the newest block of the history.
+    fn push_block(&mut self, fft: &dyn RealToComplex<f32>, scratch: &mut [Complex<f32>]) {
+        let bins = self.bins();
+        self.newest = (self.newest + 1) % (self.history.len() / bins);
+        self.time.copy_from_slice(&self.input);
+        let slot = &mut self.history[self.newest * bins..(self.newest + 1) * bins];
+        let _ = fft.process_with_scratch(&mut self.time, slot, scratch);
+        self.input.copy_within(self.block.., 0);
+    }
+
+    /// Add partitions `parts` of `spectra` times the matching input blocks to `channel`'s sum.
+    fn accumulate(&mut self, spectra: &[Complex<f32>], parts: Range<usize>, channel: usize) {
+        let bins = self.bins();
+        let slots = self.history.len() / bins;
+        let sum = &mut self.sum[channel];
+        for part in parts {
+            let slot = (self.newest + slots - part % slots) % slots;
+            let input = &self.history[slot * bins..(slot + 1) * bins];
+            let response = &spectra[part * bins..(part + 1) * bins];
+            for ((acc, x), h) in sum.iter_mut().zip(input).zip(response) {
+                *acc += x * h;
+            }
+        }
+    }
+
+    /// Transform `channel`'s sum back into `out` (one block) and clear it.
+    fn finish(&mut self, ifft: &dyn ComplexToReal<f32>, scratch: &mut [Complex<f32>], channel: usize, out: &mut [f32]) {
+        let _ = ifft.process_with_scratch(&mut self.sum[channel], &mut self.time, scratch);
+        out.copy_from_slice(&self.time[self.block..]);
+        self.sum[channel].fill(Complex::default());
+    }
+
+    /// Forget all input, including the `parts` newest blocks of history.
+    fn clear(&mut self, parts: usize) {
+        let bins = self.bins();
+        let slots = self.history.len() / bins;
+        for part in 0..parts.min(slots) {
+            let slot = (self.newest + slots - part) % slots;
+            self.history[slot * bins..(slot + 1) * bins].fill(Complex::default());
+        }
+        self.input.fill(0.0);
+        for sum in self.sum.iter_mut() {
+            sum.fill(Complex::default());
+        }
+    }
+}
+
+/// Partitioned FFT convolution on the audio thread. The head of the response
+/// runs in 64-frame partitions, so the bus adds one 64-frame block of latency;
+/// the tail runs in larger ones whose work is spread evenly over the callbacks,
+/// so no buffer costs much more than another. Everything is allocated up front
+/// for responses up to `MAX_IR_SECS`.
+pub struct Convolver {
+    impulse: Option<Arc<ImpulseResponse>>,
+    wet: f32,
+    head_fft: Arc<dyn RealToComplex<f32>>,
+    head_ifft: Arc<dyn ComplexToReal<f32>>,
+    tail_fft: Arc<dyn RealToComplex<f32>>,
+    tail_ifft: Arc<dyn ComplexToReal<f32>>,
+    scratch: Vec<Complex<f32>>,
+    head: Stage,
+    tail: Stage,
+    /// Output of the last head block, played during the one being filled.
+    head_out: [Vec<f32>; 2],
+    /// The tail's output being played, and the one being summed.
+    tail_out: [Vec<f32>; 2],
+    tail_next: [Vec<f32>; 2],
+    /// Frames into the head block being filled.
+    fill: usize,
+    /// Head blocks into the tail block being filled.
+    step: usize,
+    /// Frames until the output has died away since the last input.
+    ringing: usize,
+}
+
+impl Convolver {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let head_fft = planner.plan_fft_forward(2 * HEAD_BLOCK);
+        let head_ifft = planner.plan_fft_inverse(2 * HEAD_BLOCK);
+        let tail_fft = planner.plan_fft_forward(2 * TAIL_BLOCK);
+        let tail_ifft = planner.plan_fft_inverse(2 * TAIL_BLOCK);
+        let scratch_len = [
+            head_fft.get_scratch_len(),
+            head_ifft.get_scratch_len(),
+            tail_fft.get_scratch_len(),
+            tail_ifft.get_scratch_len(),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0);
+        let max_frames = (MAX_IR_SECS * sample_rate as f32) as usize;
+        let tail_parts = max_frames.saturating_sub(HEAD_LEN).div_ceil(TAIL_BLOCK);
+        let block = |len: usize| [vec![0.0; len], vec![0.0; len]];
+        Self {
+            impulse: None,
+            wet: ConvolutionSettings::default().wet,
+            head_fft,
+            head_ifft,
+            tail_fft,
+            tail_ifft,
+            scratch: vec![Complex::default(); scratch_len],
+            head: Stage::new(HEAD_BLOCK, HEAD_LEN / HEAD_BLOCK),
+            tail: Stage::new(TAIL_BLOCK, tail_parts),
+            head_out: block(HEAD_BLOCK),
+            tail_out: block(TAIL_BLOCK),
+            tail_next: block(TAIL_BLOCK),
+            fill: 0,
+            step: 0,
+            ringing: 0,
+        }
+    }
+
+    /// Switch responses (starting from silence) and set the wet level. A response
+    /// longer than was allocated for is ignored.
+    pub fn set(&mut self, impulse: Option<Arc<ImpulseResponse>>, wet: f32) {
+        self.wet = wet.clamp(0.0, 1.0);
+        let same = match (&self.impulse, &impulse) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        if same {
+            return;
+        }
+        let fits = |ir: &ImpulseResponse| {
+            ir.tail_parts <= self.tail.history.len() / self.tail.bins() && ir.head_parts <= HEAD_LEN / HEAD_BLOCK
+        };
+        self.impulse = impulse.filter(|ir| fits(ir));
+        let (head_parts, tail_parts) = self.impulse.as_ref().map_or((0, 0), |ir| (ir.head_parts, ir.tail_parts));
+        self.head.clear(head_parts);
+        self.tail.clear(tail_parts);
+        for buffer in self.head_out.iter_mut().chain(&mut self.tail_out).chain(&mut self.tail_next) {
+            buffer.fill(0.0);
+        }
+        self.fill = 0;
+        self.step = 0;
+        self.ringing = 0;
+    }
+
+    /// True once the output has died away (or there is no response).
+    pub fn is_idle(&self) -> bool {
+        self.ringing == 0
+    }
+
+    /// Convolve the mono `send` with the response and add the wet signal to the
+    /// interleaved `output`, one head block late: a stereo response's channels go
+    /// to the first two channels, a mono one to both. Does nothing while idle.
+    pub fn process(&mut self, send: &[f32], output: &mut [f32], channels: usize) {
+        let Some(frames) = self.impulse.as_ref().map(|ir| ir.frames) else {
+            return;
+        };
+        if send.iter().any(|&s| s != 0.0) {
+            // Long enough for the last input to leave every stage
+            self.ringing = frames + 4 * TAIL_BLOCK;
+        } else if self.ringing == 0 {
+            return;
+        } else {
+            self.ringing = self.ringing.saturating_sub(send.len());
+        }
+
+        let channels = channels.max(1);
+        for (&input, frame) in send.iter().zip(output.chunks_mut(channels)) {
+            self.head.input[HEAD_BLOCK + self.fill] = input;
+            self.tail.input[TAIL_BLOCK + self.step * HEAD_BLOCK + self.fill] = input;
+            let left = self.head_out[0][self.fill] * self.wet;
+            let right = self.head_out[1][self.fill] * self.wet;
+            match frame {
+                [mono] => *mono += (left + right) * 0.5,
+                [l, r, ..] => {
+                    *l += left;
+                    *r += right;
+                }
+                [] => {}
+            }
+            self.fill += 1;
+            if self.fill == HEAD_BLOCK {
+                self.fill = 0;
+                self.end_block();
+            }
+        }
+    }
+
+    /// At the end of each head block: work out the head's output for it, add the
+    /// tail's, and do this block's share of the tail block in progress.
+    fn end_block(&mut self) {
+        let Some(ir) = self.impulse.as_deref() else {
+            return;
+        };
+        let outputs = ir.channels.len();
+
+        self.head.push_block(&*self.head_fft, &mut self.scratch);
+        for c in 0..outputs {
+            self.head.accumulate(&ir.channels[c].head, 0..ir.head_parts, c);
+            self.head.finish(&*self.head_ifft, &mut self.scratch, c, &mut self.head_out[c]);
+            let tail = &self.tail_out[c][self.step * HEAD_BLOCK..(self.step + 1) * HEAD_BLOCK];
+            for (out, t) in self.head_out[c].iter_mut().zip(tail) {
+                *out += t;
+            }
+        }
+
+        if ir.tail_parts > 0 {
+            let share = self.step * ir.tail_parts / STEPS..(self.step + 1) * ir.tail_parts / STEPS;
+            for c in 0..outputs {
+                self.tail.accumulate(&ir.channels[c].tail, share.clone(), c);
+            }
+            if self.step == STEPS - 1 {
+                // The sum is complete just in time to be played from the next block on
+                for c in 0..outputs {
+                    self.tail.finish(&*self.tail_ifft, &mut self.scratch, c, &mut self.tail_next[c]);
+                    std::mem::swap(&mut self.tail_out[c], &mut self.tail_next[c]);
+                }
+                self.tail.push_block(&*self.tail_fft, &mut self.scratch);
+            }
+        } else if self.step == STEPS - 1 {
+            self.tail.input.copy_within(TAIL_BLOCK.., 0);
+        }
+
+        if outputs == 1 {
+            let (left, right) = self.head_out.split_at_mut(1);
+            right[0].copy_from_slice(&left[0]);
+        }
+        self.step = (self.step + 1) % STEPS;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in -1.0..1.0.
+    fn noise(len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    fn convolve(input: &[f32], ir: &[f32], len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|t| (0..=t.min(ir.len() - 1)).filter(|&k| t - k < input.len()).map(|k| input[t - k] * ir[k]).sum())
+            .collect()
+    }
+
+    #[test]
+    fn matches_direct_convolution_one_block_late() {
+        // Long enough to need the tail stage, decaying like a room
+        let ir: Vec<f32> = noise(HEAD_LEN + 3 * TAIL_BLOCK + 100, 1)
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| s * (-(i as f32) / 3000.0).exp())
+            .collect();
+        let input = noise(6000, 2);
+        let frames = input.len() + ir.len() + 2 * TAIL_BLOCK;
+
+        let mut convolver = Convolver::new(48000);
+        let impulse = Arc::new(ImpulseResponse::from_channels(PathBuf::from("test.wav"), 48000, std::slice::from_ref(&ir)));
+        convolver.set(Some(impulse), 1.0);
+        let mut send = input.clone();
+        send.resize(frames, 0.0);
+        let mut output = vec![0.0; frames];
+        // Odd callback sizes, so blocks straddle callbacks
+        let mut start = 0;
+        for size in [37, 64, 1, 500].into_iter().cycle() {
+            if start >= frames {
+                break;
+            }
+            let end = (start + size).min(frames);
+            convolver.process(&send[start..end], &mut output[start..end], 1);
+            start = end;
+        }
+
+        let expected = convolve(&input, &ir, frames - HEAD_BLOCK);
+        for (t, want) in expected.iter().enumerate() {
+            let got = output[t + HEAD_BLOCK];
+            assert!((got - want).abs() < 1e-3, "frame {}: {} vs {}", t, got, want);
+        }
+        assert!(output[..HEAD_BLOCK].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn stereo_responses_feed_both_channels_and_ring_out() {
+        let left = vec![1.0, 0.0, 0.5];
+        let right = vec![0.0, 1.0];
+        let mut convolver = Convolver::new(44100);
+        let impulse = Arc::new(ImpulseResponse::from_channels(PathBuf::from("s.wav"), 44100, &[left, right]));
+        convolver.set(Some(impulse), 0.5);
+
+        let mut send = vec![0.0; 256];
+        send[0] = 1.0;
+        let mut output = vec![0.0; 512];
+        convolver.process(&send, &mut output, 2);
+        let frame = |f: usize| (output[2 * f], output[2 * f + 1]);
+        assert!((frame(HEAD_BLOCK).0 - 0.5).abs() < 1e-5);
+        assert!(frame(HEAD_BLOCK).1.abs() < 1e-5);
+        assert!((frame(HEAD_BLOCK + 1).1 - 0.5).abs() < 1e-5);
+        assert!((frame(HEAD_BLOCK + 2).0 - 0.25).abs() < 1e-5);
+        assert!(!convolver.is_idle());
+
+        // Silence runs it down to idle, after which it is skipped
+        let silence = vec![0.0; 4096];
+        let mut scratch = vec![0.0; 4096 * 2];
+        while !convolver.is_idle() {
+            convolver.process(&silence, &mut scratch, 2);
+        }
+        assert!(scratch.iter().all(|&s| s.abs() < 1e-6));
+        let mut untouched = vec![0.25; 8];
+        convolver.process(&[0.0; 4], &mut untouched, 2);
+        assert!(untouched.iter().all(|&s| s == 0.25));
+    }
+
+    #[test]
+    fn impulses_are_found_in_the_kit_then_the_user_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let kit = dir.path().join("Kit");
+        std::fs::create_dir_all(kit.join(KIT_IMPULSES_DIR)).unwrap();
+        let plate = kit.join(KIT_IMPULSES_DIR).join("plate.wav");
+        std::fs::write(&plate, b"").unwrap();
+        std::fs::write(kit.join(KIT_IMPULSES_DIR).join("notes.txt"), b"").unwrap();
+
+        assert_eq!(list_impulses(&kit).first(), Some(&plate));
+        assert!(list_impulses(&kit).iter().all(|p| p.extension().unwrap() != "txt"));
+        assert_eq!(find_impulse("plate.wav", &kit), Some(plate.clone()));
+        assert_eq!(find_impulse(plate.to_str().unwrap(), &kit), Some(plate.clone()));
+        assert_eq!(find_impulse("missing.wav", &kit), None);
+        assert_eq!(impulse_name(&plate, &kit), "plate.wav");
+        assert_eq!(impulse_name(Path::new("/irs/hall.wav"), &kit), "/irs/hall.wav");
+        assert_eq!(display_name(&plate), "plate");
+    }
+
+    #[test]
+    fn loaded_responses_are_normalized_and_trimmed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("room.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..(8000.0 * (MAX_IR_SECS + 1.0)) as usize {
+            writer.write_sample(if i % 1000 == 0 { 0.5_f32 } else { 0.0 }).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let ir = ImpulseResponse::load(&path, 8000).unwrap();
+        assert_eq!(ir.frames, (8000.0 * MAX_IR_SECS) as usize);
+        // Mono files load as two identical channels
+        assert_eq!(ir.channels.len(), 2);
+        assert_eq!(ir.head_parts, HEAD_LEN / HEAD_BLOCK);
+        // 32 taps of 0.5, scaled to unit energy
+        let first_head_bin = ir.channels[0].head[0].re * (2 * HEAD_BLOCK) as f32;
+        assert!((first_head_bin - 1.0 / 32.0_f32.sqrt()).abs() < 1e-4);
+    }
+}
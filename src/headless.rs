@@ -0,0 +1,562 @@
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use notify::Watcher;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::play::PlayOptions;
+use crate::{audio, control, hotplug, kit, kitswitch, mapping, master, metronome, midi, midifile, mix, pipeline, record, settings, tui};
+
+/// How often the main loop checks the signal flags while idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Quiet period after the last file change before the kit is hot-reloaded.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Set by SIGTERM / SIGINT.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Set by SIGHUP.
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Options for `drumkit play --headless`.
+pub struct HeadlessOptions {
+    /// Append the event log to this file instead of stdout.
+    pub log_file: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    // Only async-signal-safe work here: flip a flag for the main loop
+    if signal == libc::SIGHUP {
+        RELOAD.store(true, Ordering::Relaxed);
+    } else {
+        SHUTDOWN.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Route SIGTERM / SIGINT to a clean shutdown and SIGHUP to a kit reload.
+#[cfg(unix)]
+fn install_signal_handlers() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        unsafe {
+            libc::signal(signal, handler);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn install_signal_handlers() {}
+
+/// Line-oriented event log, written to stdout (e.g. the systemd journal) or a file.
+struct EventLog {
+    out: Box<dyn Write + Send>,
+    started: Instant,
+}
+
+impl EventLog {
+    fn open(path: Option<&Path>) -> Result<Self> {
+        let out: Box<dyn Write + Send> = match path {
+            Some(path) => {
+                if let Some(parent) = path.parent()
+                    && !parent.as_os_str().is_empty()
+                {
+                    std::fs::create_dir_all(parent).with_context(|| {
+                        format!("Failed to create log dir: {}", parent.display())
+                    })?;
+                }
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open log file: {}", path.display()))?;
+                Box::new(file)
+            }
+            None => Box::new(std::io::stdout()),
+        };
+        Ok(Self {
+            out,
+            started: Instant::now(),
+        })
+    }
+
+    /// Write one line, prefixed with seconds since startup.
+    fn line(&mut self, message: impl Display) {
+        let secs = self.started.elapsed().as_secs_f64();
+        let _ = writeln!(self.out, "[{:>10.3}] {}", secs, message);
+        let _ = self.out.flush();
+    }
+}
+
+/// The running pipeline, owned by the headless main loop.
+struct Player {
+    log: EventLog,
+    kit_name: String,
+    kit_path: PathBuf,
+    midi_device: String,
+    sample_rate: u32,
+    channels: u16,
+    producer: Arc<Mutex<Option<rtrb::Producer<audio::AudioCommand>>>>,
+    shared_notes: Arc<ArcSwap<HashMap<u8, Arc<kit::NoteGroup>>>>,
+    shared_mapping: Arc<ArcSwap<mapping::NoteMapping>>,
+    /// Routes control-socket triggers; kept so the hi-hat pedal and chokes carry over.
+    router: midi::MidiRouter,
+    /// Feeds triggered hits back into the loop so they are logged like MIDI hits.
+    event_tx: mpsc::Sender<tui::TuiEvent>,
+    watcher: notify::RecommendedWatcher,
+    midi_capture: Arc<Mutex<Option<midifile::MidiCapture>>>,
+    recording: Option<record::Recording>,
+    recordings_dir: PathBuf,
+    kits_dirs: Vec<PathBuf>,
+    extra_mapping_dirs: Vec<PathBuf>,
+    /// The output stream; `None` while its device is unplugged.
+    stream: Option<audio::OutputStream>,
+    /// Output device to reopen when it comes back (`None` for the default device).
+    audio_device_name: Option<String>,
+    polyphony: audio::Polyphony,
+    metronome: metronome::MetronomeSettings,
+    midi_inputs: Vec<midi::InputPort>,
+    missing_midi: hotplug::MissingInputs,
+    midi_targets: midi::MidiTargets,
+    device_watcher: hotplug::DeviceWatcher,
+    /// The kit's pad mix, resent when the stream restarts.
+    mix: mix::KitMix,
+    /// Output gain and limiter, resent when the stream restarts.
+    master: master::MasterBus,
+}
+
+/// Run play mode without the TUI until SIGTERM / SIGINT.
+///
+/// Uses the same audio and MIDI pipeline as the TUI. Hits, chokes and reloads are
+/// logged; SIGHUP (or a change in the kit folder) reloads the kit.
+pub fn run(
+    kit_path: PathBuf,
+    port_index: Option<usize>,
+    audio_device: Option<usize>,
+    options: PlayOptions,
+    headless: HeadlessOptions,
+) -> Result<()> {
+    let mut log = EventLog::open(headless.log_file.as_deref())?;
+    install_signal_handlers();
+    log.line(format!("drumkit {} starting headless at {}", env!("CARGO_PKG_VERSION"), record::timestamp()));
+
+    // Run the stream at the device's native rate; samples are resampled to match
+    let device_rate = audio::preferred_sample_rate(audio_device).ok();
+    let loaded_kit = kit::load_kit(&kit_path, device_rate)
+        .with_context(|| format!("Failed to load kit: {}", kit_path.display()))?;
+
+    let (event_tx, event_rx) = mpsc::channel::<tui::TuiEvent>();
+    let pipeline::Pipeline {
+        stream,
+        producer,
+        targets,
+        midi_inputs,
+        missing_midi,
+        device_watcher,
+        audio_device_name,
+        metronome,
+        mix,
+        master,
+        control_socket: socket,
+        osc_server,
+        log: setup_log,
+    } = pipeline::start(&kit_path, &loaded_kit, port_index, audio_device, &options, &event_tx)?;
+    for line in setup_log {
+        log.line(line);
+    }
+    let midi_device = midi::inputs_label(&midi_inputs);
+    let sample_rate = loaded_kit.sample_rate;
+    let channels = loaded_kit.channels;
+
+    let (watch_tx, watch_rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+        if res.is_ok() {
+            let _ = watch_tx.send(());
+        }
+    })?;
+    watcher.watch(kit_path.as_ref(), notify::RecursiveMode::NonRecursive)?;
+
+    log.line(format!(
+        "Playing \"{}\" from {} at {} Hz, {} ch",
+        loaded_kit.name, midi_device, sample_rate, channels
+    ));
+    if options.capture.start {
+        log.line("Capturing MIDI input");
+    }
+
+    let mut player = Player {
+        log,
+        kit_name: loaded_kit.name,
+        kit_path,
+        midi_device,
+        sample_rate,
+        channels,
+        producer,
+        shared_notes: Arc::clone(&targets.shared_notes),
+        shared_mapping: Arc::clone(&targets.shared_mapping),
+        router: midi::MidiRouter::default(),
+        event_tx,
+        watcher,
+        midi_capture: Arc::clone(&targets.midi_capture),
+        recording: None,
+        recordings_dir: settings::load_settings().recordings_dir(),
+        kits_dirs: options.kits_dirs,
+        extra_mapping_dirs: options.extra_mapping_dirs,
+        stream: Some(stream),
+        audio_device_name,
+        polyphony: options.polyphony,
+        metronome,
+        midi_inputs,
+        missing_midi,
+        midi_targets: targets,
+        device_watcher,
+        mix,
+        master,
+    };
+
+    let mut last_change: Option<Instant> = None;
+    while !SHUTDOWN.load(Ordering::Relaxed) {
+        if RELOAD.swap(false, Ordering::Relaxed) {
+            player.log.line("SIGHUP: reloading kit");
+            player.reload_kit();
+        }
+
+        while watch_rx.try_recv().is_ok() {
+            last_change = Some(Instant::now());
+        }
+        if last_change.is_some_and(|t| t.elapsed() >= RELOAD_DEBOUNCE) {
+            last_change = None;
+            player.log.line("Kit folder changed: reloading kit");
+            player.reload_kit();
+        }
+
+        player.check_audio();
+        if player.recording.as_ref().is_some_and(|r| r.is_finished()) {
+            let _ = player.stop_recording();
+        }
+
+        match event_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(tui::TuiEvent::Hit { note, velocity }) => {
+                let name = player.shared_mapping.load().drum_name(note).to_string();
+                player.log.line(format!("hit   {:>3} {:<16} vel {:>3}", note, name, velocity));
+            }
+            Ok(tui::TuiEvent::Choke { note }) => {
+                let name = player.shared_mapping.load().drum_name(note).to_string();
+                player.log.line(format!("choke {:>3} {}", note, name));
+            }
+            Ok(tui::TuiEvent::Control(request)) => player.handle_control(request),
+            Ok(tui::TuiEvent::Devices(change)) => player.handle_device_change(change),
+            Ok(tui::TuiEvent::KitSwitch(selection)) => player.switch_kit_by_program(selection),
+            Ok(tui::TuiEvent::PadVolume { note, db }) => player.set_pad_volume(note, db),
+            Ok(tui::TuiEvent::OscError(msg)) => player.log.line(format!("OSC: {}", msg)),
+            Ok(_) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    player.log.line("Shutting down");
+    drop(socket);
+    drop(osc_server);
+    player.midi_inputs.clear();
+    let (retriggers, crosstalk) = player.midi_targets.hit_stats.rejected();
+    if retriggers + crosstalk > 0 {
+        player.log.line(format!("Suppressed {} double triggers and {} crosstalk hits", retriggers, crosstalk));
+    }
+    if let Some(capture) = player.midi_capture.lock().unwrap().take() {
+        match capture.save(&player.recordings_dir) {
+            Ok(path) => player.log.line(format!("MIDI capture saved to {}", path.display())),
+            Err(e) => player.log.line(format!("MIDI capture failed: {}", e)),
+        }
+    }
+    // Dropping the stream drops the recording tap, which lets the writer finalize
+    player.stream = None;
+    if player.recording.is_some() {
+        let _ = player.stop_recording();
+    }
+    Ok(())
+}
+
+impl Player {
+    /// Reload the current kit (and its `mapping.toml`, if any) into the running pipeline.
+    fn reload_kit(&mut self) {
+        match kit::load_kit(&self.kit_path, Some(self.sample_rate)) {
+            Ok(new_kit) if new_kit.channels != self.channels => {
+                self.log.line(format!(
+                    "Kit reload failed: channel count mismatch (expected {}, got {})",
+                    self.channels, new_kit.channels
+                ));
+            }
+            Ok(new_kit) => {
+                if let Some(new_mapping) = mapping::load_kit_mapping(&self.kit_path) {
+                    self.shared_mapping.store(Arc::new(new_mapping));
+                }
+                for line in kit::summary_lines(&new_kit, &self.shared_mapping.load()) {
+                    self.log.line(line);
+                }
+                self.shared_notes.store(Arc::new(new_kit.notes));
+            }
+            Err(e) => self.log.line(format!("Kit reload failed: {}", e)),
+        }
+    }
+
+    /// Switch to another kit at the stream's rate. The stream is never rebuilt
+    /// headless, so the new kit must have the same channel count.
+    fn switch_kit(&mut self, path: PathBuf, name: String) -> Result<String> {
+        let new_kit = kit::load_kit(&path, Some(self.sample_rate))
+            .with_context(|| format!("Failed to load kit: {}", path.display()))?;
+        if new_kit.channels != self.channels {
+            anyhow::bail!(
+                "Channel count mismatch (expected {}, got {})",
+                self.channels,
+                new_kit.channels
+            );
+        }
+        let new_mapping = mapping::load_kit_mapping(&path).unwrap_or_else(mapping::default_mapping);
+        for line in kit::summary_lines(&new_kit, &new_mapping) {
+            self.log.line(line);
+        }
+        self.shared_notes.store(Arc::new(new_kit.notes));
+        self.shared_mapping.store(Arc::new(new_mapping));
+        self.mix = settings::load_settings().kit_mix(&path);
+        if let Err(e) = self.mix.load_impulse(&path, self.sample_rate) {
+            self.log.line(format!("IR bus: {:#}", e));
+        }
+        self.mix.send(&self.producer);
+
+        let _ = self.watcher.unwatch(&self.kit_path);
+        let _ = self.watcher.watch(path.as_ref(), notify::RecursiveMode::NonRecursive);
+        self.kit_path = path;
+        self.kit_name = name;
+        Ok(format!("Kit: {}", self.kit_name))
+    }
+
+    /// Switch to the kit the drum module picked, unless it's already playing.
+    fn switch_kit_by_program(&mut self, selection: kitswitch::KitSelection) {
+        let result = control::resolve_kit(&selection.kit, &self.kits_dirs).and_then(|(path, name)| {
+            if path == self.kit_path {
+                return Ok(format!("Kit: {} (already playing)", name));
+            }
+            self.switch_kit(path, name)
+        });
+        match result {
+            Ok(message) => self.log.line(format!("{}: {}", selection.label(), message)),
+            Err(e) => self.log.line(format!("{}: {:#}", selection.label(), e)),
+        }
+    }
+
+    /// Release the output stream once it fails (e.g. its device was unplugged) and
+    /// have the watcher look for the device coming back.
+    fn check_audio(&mut self) {
+        if self.stream.as_ref().is_some_and(|s| s.has_failed()) {
+            *self.producer.lock().unwrap() = None;
+            self.stream = None;
+            self.device_watcher.set_audio_lost(true);
+            self.log.line(format!("Audio output lost: {}", self.audio_device_label()));
+        }
+    }
+
+    /// Reconnect MIDI inputs and the audio output as their devices come back.
+    fn handle_device_change(&mut self, change: hotplug::DeviceChange) {
+        match change {
+            hotplug::DeviceChange::Midi(devices) => {
+                let lines = self.missing_midi.sync(
+                    &mut self.midi_inputs,
+                    &devices,
+                    &self.midi_targets,
+                    &self.extra_mapping_dirs,
+                );
+                for line in lines {
+                    self.log.line(line);
+                }
+                self.midi_device = midi::inputs_label(&self.midi_inputs);
+            }
+            hotplug::DeviceChange::Audio(devices) => {
+                if self.stream.is_some() {
+                    return;
+                }
+                let Some(device_index) = hotplug::find_audio_device(&devices, self.audio_device_name.as_deref())
+                else {
+                    return;
+                };
+                // On failure it's tried again on the next poll
+                if let Ok(stream) = self.stream_setup().open(&self.producer, device_index) {
+                    self.stream = Some(stream);
+                    self.device_watcher.set_audio_lost(false);
+                    self.log.line(format!("Audio output reconnected: {}", self.audio_device_label()));
+                }
+            }
+        }
+    }
+
+    /// How the output stream is opened; the rate and channels never change headless.
+    fn stream_setup(&self) -> pipeline::StreamSetup<'_> {
+        pipeline::StreamSetup {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            polyphony: &self.polyphony,
+            metronome: &self.metronome,
+            mix: &self.mix,
+            master: &self.master,
+        }
+    }
+
+    fn audio_device_label(&self) -> &str {
+        self.audio_device_name.as_deref().unwrap_or("default device")
+    }
+
+    fn push(&self, cmd: audio::AudioCommand) -> Result<()> {
+        let mut guard = self.producer.lock().unwrap();
+        let prod = guard.as_mut().context("Audio stream is not running")?;
+        prod.push(cmd).map_err(|_| anyhow::anyhow!("Audio queue full"))
+    }
+
+    /// Set the master output gain from a linear volume and persist it.
+    fn set_volume(&mut self, volume: f32) -> Result<String> {
+        if !volume.is_finite() || volume < 0.0 {
+            anyhow::bail!("Volume must be 0 or more");
+        }
+        self.master.settings.set_volume(volume);
+        self.master.send(&self.producer);
+        let mut s = settings::load_settings();
+        s.master = self.master.settings;
+        let _ = settings::save_settings(&s);
+        Ok(self.master.settings.label())
+    }
+
+    /// Set a pad's mixer volume (from OSC) until the kit changes.
+    fn set_pad_volume(&mut self, note: u8, db: f32) {
+        let pad = mix::PadMix { volume_db: db, ..self.mix.for_note(note) }.sanitized();
+        self.mix.set(note, pad);
+        let _ = self.push(audio::AudioCommand::SetPadMix { note, mix: pad });
+    }
+
+    /// Play a note through the same routing as the MIDI input (remapping, chokes).
+    fn trigger(&mut self, note: u8, velocity: u8) -> Result<String> {
+        let data = control::trigger_message(note, velocity)?;
+        let mut guard = self.producer.lock().unwrap();
+        let prod = guard.as_mut().context("Audio stream is not running")?;
+        self.router.route(
+            &data,
+            &self.shared_mapping.load(),
+            &self.shared_notes.load(),
+            &self.midi_targets.velocity.load(),
+            |cmd| {
+                let _ = prod.push(cmd);
+            },
+            |event| {
+                let _ = self.event_tx.send(event);
+            },
+        );
+        Ok(format!("Triggered note {} at velocity {}", note, velocity))
+    }
+
+    fn start_recording(&mut self) -> Result<String> {
+        if self.recording.is_some() {
+            anyhow::bail!("Already recording");
+        }
+        let (recording, tap) = record::start(&self.recordings_dir, self.sample_rate, self.channels)?;
+        self.push(audio::AudioCommand::StartRecording(tap))?;
+        let message = format!("Recording to {}", recording.path.display());
+        self.recording = Some(recording);
+        Ok(message)
+    }
+
+    /// Stop the recording in progress (if the stream is still running) and finalize it.
+    fn stop_recording(&mut self) -> Result<String> {
+        if self.recording.is_none() {
+            anyhow::bail!("Not recording");
+        }
+        // Fails harmlessly once the stream is gone; the tap was dropped with it
+        let _ = self.push(audio::AudioCommand::StopRecording);
+        let recording = self.recording.take().context("Not recording")?;
+        let stats = Arc::clone(&recording.stats);
+        let result = recording.finish().map(|path| record::saved_message(&path, &stats));
+        match &result {
+            Ok(message) => self.log.line(message),
+            Err(e) => self.log.line(format!("Recording failed: {}", e)),
+        }
+        result
+    }
+
+    fn state(&self) -> control::PlayerState {
+        control::PlayerState {
+            kit: self.kit_name.clone(),
+            kit_path: self.kit_path.clone(),
+            mapping: self.shared_mapping.load().name.clone(),
+            midi_device: self.midi_device.clone(),
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            volume: self.master.settings.volume(),
+            recording: self.recording.as_ref().map(|r| r.path.clone()),
+            midi_capture: self.midi_capture.lock().unwrap().is_some(),
+            metronome: self.metronome.enabled.then(|| self.metronome.label()),
+            clips: self.master.meter.clips(),
+        }
+    }
+
+    fn handle_control(&mut self, request: control::ControlRequest) {
+        let result = match &request.command {
+            control::ControlCommand::Kit { kit } => control::resolve_kit(kit, &self.kits_dirs)
+                .and_then(|(path, name)| self.switch_kit(path, name)),
+            control::ControlCommand::Mapping { mapping } => {
+                control::resolve_mapping(mapping, &self.extra_mapping_dirs).map(|found| {
+                    let message = format!("Mapping: {}", found.name);
+                    self.shared_mapping.store(Arc::new(found));
+                    message
+                })
+            }
+            control::ControlCommand::Volume { volume } => self.set_volume(*volume),
+            control::ControlCommand::Trigger { note, velocity } => self.trigger(*note, *velocity),
+            control::ControlCommand::StartRecording => self.start_recording(),
+            control::ControlCommand::StopRecording => self.stop_recording(),
+            control::ControlCommand::State => {
+                let state = self.state();
+                request.respond(control::ControlResponse::state(state));
+                return;
+            }
+        };
+        match &result {
+            Ok(message) => self.log.line(format!("control: {}", message)),
+            Err(e) => self.log.line(format!("control error: {}", e)),
+        }
+        request.respond(result.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_log_appends_to_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs/drumkit.log");
+        EventLog::open(Some(&path)).unwrap().line("first");
+        EventLog::open(Some(&path)).unwrap().line("second");
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with('[') && lines[0].ends_with("] first"));
+        assert!(lines[1].ends_with("] second"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sighup_requests_reload_without_shutdown() {
+        install_signal_handlers();
+        RELOAD.store(false, Ordering::Relaxed);
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+        assert!(RELOAD.swap(false, Ordering::Relaxed));
+        assert!(!SHUTDOWN.load(Ordering::Relaxed));
+    }
+}
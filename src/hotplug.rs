@@ -0,0 +1,267 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::audio::{self, AudioDevice};
+use crate::midi::{self, InputPort, MidiDevice};
+use crate::settings::MidiInputSettings;
+
+/// How often the watcher lists the devices.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the watcher thread checks whether it should stop.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the device watcher noticed.
+pub enum DeviceChange {
+    /// The set of MIDI input ports changed; these are the ones present now.
+    Midi(Vec<MidiDevice>),
+    /// The audio output devices present while the output is lost (sent on every poll).
+    Audio(Vec<AudioDevice>),
+}
+
+/// Background thread polling for MIDI ports and audio devices coming and going.
+/// Stops when dropped.
+pub struct DeviceWatcher {
+    stop: Arc<AtomicBool>,
+    audio_lost: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DeviceWatcher {
+    /// Start polling; `notify` is called from the watcher thread.
+    pub fn spawn(notify: impl Fn(DeviceChange) + Send + 'static) -> Self {
+        Self::spawn_with(
+            POLL_INTERVAL,
+            || midi::list_devices().ok(),
+            || audio::list_output_devices().ok(),
+            notify,
+        )
+    }
+
+    fn spawn_with<M, A, N>(interval: Duration, mut list_midi: M, mut list_audio: A, notify: N) -> Self
+    where
+        M: FnMut() -> Option<Vec<MidiDevice>> + Send + 'static,
+        A: FnMut() -> Option<Vec<AudioDevice>> + Send + 'static,
+        N: Fn(DeviceChange) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let audio_lost = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let audio_lost = Arc::clone(&audio_lost);
+            std::thread::spawn(move || {
+                let mut last_midi: Option<Vec<String>> = None;
+                while !stop.load(Ordering::Relaxed) {
+                    if let Some(devices) = list_midi() {
+                        let names: Vec<String> = devices.iter().map(|d| d.name.clone()).collect();
+                        if last_midi.as_ref() != Some(&names) {
+                            last_midi = Some(names);
+                            notify(DeviceChange::Midi(devices));
+                        }
+                    }
+                    // While the output is running its device is busy and may not be
+                    // listed at all, so audio devices are only polled once it's lost
+                    if audio_lost.load(Ordering::Relaxed)
+                        && let Some(devices) = list_audio()
+                    {
+                        notify(DeviceChange::Audio(devices));
+                    }
+
+                    let started = Instant::now();
+                    while started.elapsed() < interval && !stop.load(Ordering::Relaxed) {
+                        std::thread::sleep(STOP_CHECK_INTERVAL.min(interval));
+                    }
+                }
+            })
+        };
+        Self {
+            stop,
+            audio_lost,
+            thread: Some(thread),
+        }
+    }
+
+    /// Start (or stop) polling the audio devices for the lost output to come back.
+    pub fn set_audio_lost(&self, lost: bool) {
+        self.audio_lost.store(lost, Ordering::Relaxed);
+    }
+}
+
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// MIDI inputs that should be connected but whose port is unplugged.
+#[derive(Default)]
+pub struct MissingInputs(Vec<MidiInputSettings>);
+
+impl MissingInputs {
+    /// The inputs in `wanted` that aren't among `connected`.
+    pub fn new(wanted: Vec<MidiInputSettings>, connected: &[InputPort]) -> Self {
+        Self(
+            wanted
+                .into_iter()
+                .filter(|w| !connected.iter().any(|c| c.name == w.port))
+                .collect(),
+        )
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.0.iter().map(|i| i.port.clone()).collect()
+    }
+
+    /// Stop waiting for `port`, e.g. because it was connected by hand.
+    pub fn remove(&mut self, port: &str) {
+        self.0.retain(|i| i.port != port);
+    }
+
+    /// Drop the hardware inputs whose port has gone (remembering them) and reconnect
+    /// the missing ones whose port is back. Returns a log line for each change.
+    pub fn sync(
+        &mut self,
+        inputs: &mut Vec<InputPort>,
+        present: &[MidiDevice],
+        targets: &midi::MidiTargets,
+        extra_mapping_dirs: &[PathBuf],
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+        let (gone, kept): (Vec<InputPort>, Vec<InputPort>) = std::mem::take(inputs)
+            .into_iter()
+            .partition(|input| !input.virtual_port && !present.iter().any(|d| d.name == input.name));
+        *inputs = kept;
+        for input in gone {
+            lines.push(format!("MIDI input unplugged: {}", input.name));
+            self.0.push(input.settings.clone());
+        }
+
+        let mut still_missing = Vec::new();
+        for wanted in std::mem::take(&mut self.0) {
+            let Some(device) = present.iter().find(|d| d.name == wanted.port) else {
+                still_missing.push(wanted);
+                continue;
+            };
+            match midi::connect_input(device.port_index, wanted.clone(), targets, extra_mapping_dirs) {
+                Ok(input) => {
+                    lines.push(format!("MIDI input reconnected: {}", input.name));
+                    inputs.push(input);
+                }
+                Err(e) => {
+                    // Tried again the next time the ports change
+                    lines.push(format!("MIDI input \"{}\": {:#}", wanted.port, e));
+                    still_missing.push(wanted);
+                }
+            }
+        }
+        self.0 = still_missing;
+        lines
+    }
+}
+
+/// Where to reopen the audio output among `devices`: the device called `name`, or
+/// the default device when `name` is `None`. `None` if it isn't plugged in.
+pub fn find_audio_device(devices: &[AudioDevice], name: Option<&str>) -> Option<Option<usize>> {
+    match name {
+        Some(name) => devices.iter().find(|d| d.name == name).map(|d| Some(d.index)),
+        None => Some(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Mutex};
+
+    fn midi_device(name: &str, port_index: usize) -> MidiDevice {
+        MidiDevice {
+            name: name.to_string(),
+            port_index,
+        }
+    }
+
+    #[test]
+    fn watcher_reports_midi_changes_and_polls_audio_only_while_lost() {
+        // The drum module is unplugged on the third poll
+        let mut polls = 0;
+        let list_midi = move || {
+            polls += 1;
+            let mut devices = vec![midi_device("Through", 0)];
+            if !(3..5).contains(&polls) {
+                devices.push(midi_device("TD-17", 1));
+            }
+            Some(devices)
+        };
+        let list_audio = || {
+            Some(vec![AudioDevice {
+                name: "USB Audio".to_string(),
+                index: 2,
+            }])
+        };
+        let (tx, rx) = mpsc::channel();
+        let watcher = DeviceWatcher::spawn_with(Duration::from_millis(5), list_midi, list_audio, move |change| {
+            let _ = tx.send(change);
+        });
+
+        let timeout = Duration::from_secs(5);
+        let midi_names = |change: DeviceChange| match change {
+            DeviceChange::Midi(devices) => devices.into_iter().map(|d| d.name).collect::<Vec<_>>(),
+            DeviceChange::Audio(_) => panic!("audio polled while the output is running"),
+        };
+        assert_eq!(midi_names(rx.recv_timeout(timeout).unwrap()), ["Through", "TD-17"]);
+        assert_eq!(midi_names(rx.recv_timeout(timeout).unwrap()), ["Through"]);
+        assert_eq!(midi_names(rx.recv_timeout(timeout).unwrap()), ["Through", "TD-17"]);
+
+        watcher.set_audio_lost(true);
+        let found = loop {
+            if let DeviceChange::Audio(devices) = rx.recv_timeout(timeout).unwrap() {
+                break devices;
+            }
+        };
+        assert_eq!(find_audio_device(&found, Some("USB Audio")), Some(Some(2)));
+        assert_eq!(find_audio_device(&found, Some("HDA Intel PCH")), None);
+        assert_eq!(find_audio_device(&found, None), Some(None));
+
+        drop(watcher);
+        // The thread has stopped, so nothing more arrives once the queue is drained
+        while rx.try_recv().is_ok() {}
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn missing_inputs_wait_for_their_port() {
+        let wanted = vec![MidiInputSettings::new("TD-17".to_string()), MidiInputSettings::new("Pad".to_string())];
+        let mut missing = MissingInputs::new(wanted, &[]);
+        assert_eq!(missing.names(), ["TD-17", "Pad"]);
+
+        missing.remove("Pad");
+        assert_eq!(missing.names(), ["TD-17"]);
+
+        // Nothing to reconnect while the port is absent
+        let (tx, _rx) = mpsc::channel();
+        let targets = midi::MidiTargets {
+            producer: Arc::new(Mutex::new(None)),
+            shared_notes: Arc::new(arc_swap::ArcSwap::from_pointee(Default::default())),
+            shared_mapping: Arc::new(arc_swap::ArcSwap::from_pointee(crate::mapping::default_mapping())),
+            routing: Arc::new(arc_swap::ArcSwap::from_pointee(crate::channels::ChannelRouting::new(&[]))),
+            velocity: Arc::new(arc_swap::ArcSwap::from_pointee(Default::default())),
+            suppression: Arc::new(arc_swap::ArcSwap::from_pointee(Default::default())),
+            hit_stats: Arc::new(Default::default()),
+            kit_switch: Arc::new(Default::default()),
+            tui_tx: tx,
+            midi_capture: Arc::new(Mutex::new(None)),
+            thru: None,
+        };
+        let mut inputs = Vec::new();
+        let lines = missing.sync(&mut inputs, &[midi_device("Through", 0)], &targets, &[]);
+        assert!(lines.is_empty());
+        assert!(inputs.is_empty());
+        assert_eq!(missing.names(), ["TD-17"]);
+    }
+}
@@ -0,0 +1,768 @@
+use serde::{Deserialize, Serialize};
+
+/// Gain changes of the EQ bands, in dB either way.
+pub const MAX_EQ_DB: f32 = 18.0;
+
+/// Lowest and highest EQ band frequency, in Hz.
+pub const MIN_FREQ: f32 = 20.0;
+pub const MAX_FREQ: f32 = 20000.0;
+
+/// Range of the EQ band widths (Q).
+pub const MIN_Q: f32 = 0.1;
+pub const MAX_Q: f32 = 10.0;
+
+/// Largest boost or cut the transient shaper applies, in dB.
+const MAX_SHAPE_DB: f32 = 24.0;
+
+/// Level treated as silence by the envelope followers.
+const FLOOR: f32 = 1e-6;
+
+/// An insert effect: processes one pad's audio in place on the audio thread, so
+/// it must never allocate or block.
+pub trait Effect {
+    /// Process the interleaved `buffer`, `channels` wide, in place.
+    fn process(&mut self, buffer: &mut [f32], channels: usize);
+
+    /// Forget everything heard so far, as if freshly made.
+    fn reset(&mut self);
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(FLOOR).log10()
+}
+
+/// Clamp `value` to `min..=max`, or use `fallback` if it isn't a number.
+fn clamped(value: f32, min: f32, max: f32, fallback: f32) -> f32 {
+    if value.is_finite() { value.clamp(min, max) } else { fallback }
+}
+
+/// Coefficient of a one-pole smoother that covers most of the way in `ms`.
+fn smoothing(ms: f32, sample_rate: u32) -> f32 {
+    let frames = ms * 0.001 * sample_rate.max(1) as f32;
+    if frames <= 0.0 { 0.0 } else { (-1.0 / frames).exp() }
+}
+
+/// One band of the EQ: a shelf for the low and high bands, a bell for the mid.
+/// Missing fields take the band's own defaults, so `low = { gain_db = 4.0 }` is
+/// a 4 dB shelf at 100 Hz.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqBand {
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl Default for EqBand {
+    fn default() -> Self {
+        Self { freq: 1000.0, gain_db: 0.0, q: 0.707 }
+    }
+}
+
+impl EqBand {
+    pub const LOW: EqBand = EqBand { freq: 100.0, gain_db: 0.0, q: 0.707 };
+    pub const MID: EqBand = EqBand { freq: 1000.0, gain_db: 0.0, q: 1.0 };
+    pub const HIGH: EqBand = EqBand { freq: 8000.0, gain_db: 0.0, q: 0.707 };
+
+    fn sanitized(self, default: EqBand) -> Self {
+        Self {
+            freq: clamped(self.freq, MIN_FREQ, MAX_FREQ, default.freq),
+            gain_db: clamped(self.gain_db, -MAX_EQ_DB, MAX_EQ_DB, 0.0),
+            q: clamped(self.q, MIN_Q, MAX_Q, default.q),
+        }
+    }
+}
+
+/// 3-band parametric EQ: low shelf, mid bell and high shelf.
+///
+/// ```toml
+/// [mixes.pads.36.inserts.eq]
+/// enabled = true
+/// low = { freq = 60.0, gain_db = 4.0, q = 0.707 }
+/// mid = { freq = 400.0, gain_db = -6.0, q = 2.0 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqSettings {
+    pub enabled: bool,
+    #[serde(deserialize_with = "low_band")]
+    pub low: EqBand,
+    #[serde(deserialize_with = "mid_band")]
+    pub mid: EqBand,
+    #[serde(deserialize_with = "high_band")]
+    pub high: EqBand,
+}
+
+/// A band as written in settings.toml, before the gaps are filled.
+#[derive(Deserialize)]
+struct PartialBand {
+    freq: Option<f32>,
+    gain_db: Option<f32>,
+    q: Option<f32>,
+}
+
+impl PartialBand {
+    fn or(self, default: EqBand) -> EqBand {
+        EqBand {
+            freq: self.freq.unwrap_or(default.freq),
+            gain_db: self.gain_db.unwrap_or(default.gain_db),
+            q: self.q.unwrap_or(default.q),
+        }
+    }
+}
+
+fn low_band<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<EqBand, D::Error> {
+    Ok(PartialBand::deserialize(deserializer)?.or(EqBand::LOW))
+}
+
+fn mid_band<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<EqBand, D::Error> {
+    Ok(PartialBand::deserialize(deserializer)?.or(EqBand::MID))
+}
+
+fn high_band<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<EqBand, D::Error> {
+    Ok(PartialBand::deserialize(deserializer)?.or(EqBand::HIGH))
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low: EqBand::LOW,
+            mid: EqBand::MID,
+            high: EqBand::HIGH,
+        }
+    }
+}
+
+impl EqSettings {
+    pub fn sanitized(self) -> Self {
+        let default = Self::default();
+        Self {
+            enabled: self.enabled,
+            low: self.low.sanitized(default.low),
+            mid: self.mid.sanitized(default.mid),
+            high: self.high.sanitized(default.high),
+        }
+    }
+}
+
+/// Feed-forward compressor with a hard knee, linked across channels.
+///
+/// ```toml
+/// [mixes.pads.38.inserts.compressor]
+/// enabled = true
+/// threshold_db = -18.0
+/// ratio = 4.0
+/// attack_ms = 10.0
+/// release_ms = 100.0
+/// makeup_db = 3.0
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressorSettings {
+    pub enabled: bool,
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -18.0,
+            ratio: 4.0,
+            attack_ms: 10.0,
+            release_ms: 100.0,
+            makeup_db: 0.0,
+        }
+    }
+}
+
+impl CompressorSettings {
+    pub fn sanitized(self) -> Self {
+        let default = Self::default();
+        Self {
+            enabled: self.enabled,
+            threshold_db: clamped(self.threshold_db, -60.0, 0.0, default.threshold_db),
+            ratio: clamped(self.ratio, 1.0, 20.0, default.ratio),
+            attack_ms: clamped(self.attack_ms, 0.1, 100.0, default.attack_ms),
+            release_ms: clamped(self.release_ms, 10.0, 2000.0, default.release_ms),
+            makeup_db: clamped(self.makeup_db, 0.0, 24.0, default.makeup_db),
+        }
+    }
+}
+
+/// Transient shaper: `attack` brings out (above 0) or softens (below) the start
+/// of each hit, `sustain` lengthens or shortens its ring. Both -1.0 to 1.0.
+///
+/// ```toml
+/// [mixes.pads.38.inserts.transient]
+/// enabled = true
+/// attack = 0.5
+/// sustain = -0.3
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransientSettings {
+    pub enabled: bool,
+    pub attack: f32,
+    pub sustain: f32,
+}
+
+impl TransientSettings {
+    pub fn sanitized(self) -> Self {
+        Self {
+            enabled: self.enabled,
+            attack: clamped(self.attack, -1.0, 1.0, 0.0),
+            sustain: clamped(self.sustain, -1.0, 1.0, 0.0),
+        }
+    }
+}
+
+/// A pad's insert chain: EQ, then compressor, then transient shaper. Each is
+/// bypassed until enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PadInserts {
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub eq: EqSettings,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub compressor: CompressorSettings,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub transient: TransientSettings,
+}
+
+impl PadInserts {
+    pub fn sanitized(self) -> Self {
+        Self {
+            eq: self.eq.sanitized(),
+            compressor: self.compressor.sanitized(),
+            transient: self.transient.sanitized(),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        is_default(self)
+    }
+
+    /// Whether any effect is enabled.
+    pub fn is_active(&self) -> bool {
+        self.eq.enabled || self.compressor.enabled || self.transient.enabled
+    }
+
+    /// The enabled effects, e.g. "EQ, comp, shaper", or `None` if there are none.
+    pub fn label(&self) -> Option<String> {
+        let names: Vec<&str> = [
+            (self.eq.enabled, "EQ"),
+            (self.compressor.enabled, "comp"),
+            (self.transient.enabled, "shaper"),
+        ]
+        .into_iter()
+        .filter_map(|(on, name)| on.then_some(name))
+        .collect();
+        (!names.is_empty()).then(|| names.join(", "))
+    }
+}
+
+/// Biquad filter coefficients, normalized so a0 is 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+/// The shape of an EQ band's filter.
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+impl Coefficients {
+    const IDENTITY: Coefficients = Coefficients { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 };
+
+    /// The band's filter, from the Audio EQ Cookbook.
+    fn band(shape: Shape, band: EqBand, sample_rate: u32) -> Self {
+        if band.gain_db == 0.0 {
+            return Self::IDENTITY;
+        }
+        let sample_rate = sample_rate.max(1) as f64;
+        let freq = (band.freq as f64).min(sample_rate * 0.45);
+        let a = 10.0_f64.powf(band.gain_db as f64 / 40.0);
+        let w0 = std::f64::consts::TAU * freq / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(MIN_Q) as f64);
+        let root = 2.0 * a.sqrt() * alpha;
+        let [b0, b1, b2, a0, a1, a2] = match shape {
+            Shape::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + root),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - root),
+                (a + 1.0) + (a - 1.0) * cos + root,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - root,
+            ],
+            Shape::Peak => [
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ],
+            Shape::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + root),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - root),
+                (a + 1.0) - (a - 1.0) * cos + root,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - root,
+            ],
+        };
+        Self {
+            b0: (b0 / a0) as f32,
+            b1: (b1 / a0) as f32,
+            b2: (b2 / a0) as f32,
+            a1: (a1 / a0) as f32,
+            a2: (a2 / a0) as f32,
+        }
+    }
+}
+
+/// A biquad in transposed direct form II, with state for every channel.
+struct Biquad {
+    coefficients: Coefficients,
+    state: Vec<[f32; 2]>,
+}
+
+impl Biquad {
+    fn new(channels: usize) -> Self {
+        Self {
+            coefficients: Coefficients::IDENTITY,
+            state: vec![[0.0; 2]; channels.max(1)],
+        }
+    }
+
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        if self.coefficients == Coefficients::IDENTITY {
+            return;
+        }
+        let Coefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+        for frame in buffer.chunks_mut(channels.max(1)) {
+            for (x, [z1, z2]) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let y = b0 * *x + *z1;
+                *z1 = b1 * *x - a1 * y + *z2;
+                *z2 = b2 * *x - a2 * y;
+                *x = y;
+            }
+        }
+    }
+}
+
+/// The 3-band EQ on the audio thread.
+pub struct Equalizer {
+    sample_rate: u32,
+    bands: [Biquad; 3],
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            sample_rate,
+            bands: [Biquad::new(channels), Biquad::new(channels), Biquad::new(channels)],
+        }
+    }
+
+    pub fn set(&mut self, settings: EqSettings) {
+        let settings = settings.sanitized();
+        let shapes = [
+            (Shape::LowShelf, settings.low),
+            (Shape::Peak, settings.mid),
+            (Shape::HighShelf, settings.high),
+        ];
+        for (filter, (shape, band)) in self.bands.iter_mut().zip(shapes) {
+            filter.coefficients = Coefficients::band(shape, band, self.sample_rate);
+        }
+    }
+}
+
+impl Effect for Equalizer {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        for band in &mut self.bands {
+            band.process(buffer, channels);
+        }
+    }
+
+    fn reset(&mut self) {
+        for band in &mut self.bands {
+            band.state.fill([0.0; 2]);
+        }
+    }
+}
+
+/// The compressor on the audio thread.
+pub struct Compressor {
+    sample_rate: u32,
+    settings: CompressorSettings,
+    attack: f32,
+    release: f32,
+    /// Smoothed gain reduction in dB.
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut compressor = Self {
+            sample_rate,
+            settings: CompressorSettings::default(),
+            attack: 0.0,
+            release: 0.0,
+            reduction_db: 0.0,
+        };
+        compressor.set(CompressorSettings::default());
+        compressor
+    }
+
+    pub fn set(&mut self, settings: CompressorSettings) {
+        self.settings = settings.sanitized();
+        self.attack = smoothing(self.settings.attack_ms, self.sample_rate);
+        self.release = smoothing(self.settings.release_ms, self.sample_rate);
+    }
+}
+
+impl Effect for Compressor {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let CompressorSettings { threshold_db, ratio, makeup_db, .. } = self.settings;
+        let slope = 1.0 - 1.0 / ratio;
+        for frame in buffer.chunks_mut(channels.max(1)) {
+            let peak = frame.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let target = (gain_to_db(peak) - threshold_db).max(0.0) * slope;
+            let coeff = if target > self.reduction_db { self.attack } else { self.release };
+            self.reduction_db = target + (self.reduction_db - target) * coeff;
+            let gain = db_to_gain(makeup_db - self.reduction_db);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+}
+
+/// Peak follower with separate attack and release times.
+#[derive(Debug, Clone, Copy)]
+struct Follower {
+    attack: f32,
+    release: f32,
+    level: f32,
+}
+
+impl Follower {
+    fn new(attack_ms: f32, release_ms: f32, sample_rate: u32) -> Self {
+        Self {
+            attack: smoothing(attack_ms, sample_rate),
+            release: smoothing(release_ms, sample_rate),
+            level: 0.0,
+        }
+    }
+
+    fn follow(&mut self, input: f32) -> f32 {
+        let coeff = if input > self.level { self.attack } else { self.release };
+        self.level = input + (self.level - input) * coeff;
+        if self.level < FLOOR {
+            self.level = 0.0;
+        }
+        self.level
+    }
+}
+
+/// The transient shaper on the audio thread. Two pairs of envelope followers
+/// tell the parts of a hit apart: a fast-attack one runs ahead of a slow-attack
+/// one while a hit starts, and a slow-release one stays above a fast-release
+/// one while it rings. The gaps between them, in dB, set the gain.
+pub struct TransientShaper {
+    settings: TransientSettings,
+    fast_attack: Follower,
+    slow_attack: Follower,
+    fast_release: Follower,
+    slow_release: Follower,
+}
+
+impl TransientShaper {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            settings: TransientSettings::default(),
+            fast_attack: Follower::new(0.5, 60.0, sample_rate),
+            slow_attack: Follower::new(15.0, 60.0, sample_rate),
+            fast_release: Follower::new(0.5, 20.0, sample_rate),
+            slow_release: Follower::new(0.5, 250.0, sample_rate),
+        }
+    }
+
+    pub fn set(&mut self, settings: TransientSettings) {
+        self.settings = settings.sanitized();
+    }
+}
+
+impl Effect for TransientShaper {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        let TransientSettings { attack, sustain, .. } = self.settings;
+        for frame in buffer.chunks_mut(channels.max(1)) {
+            let peak = frame.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+            let onset = gain_to_db(self.fast_attack.follow(peak)) - gain_to_db(self.slow_attack.follow(peak));
+            let ring = gain_to_db(self.slow_release.follow(peak)) - gain_to_db(self.fast_release.follow(peak));
+            let shape_db = (attack * onset + sustain * ring).clamp(-MAX_SHAPE_DB, MAX_SHAPE_DB);
+            if shape_db != 0.0 {
+                let gain = db_to_gain(shape_db);
+                for sample in frame.iter_mut() {
+                    *sample *= gain;
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for follower in [&mut self.fast_attack, &mut self.slow_attack, &mut self.fast_release, &mut self.slow_release] {
+            follower.level = 0.0;
+        }
+    }
+}
+
+/// A pad's insert chain on the audio thread, allocated up front.
+pub struct InsertChain {
+    settings: PadInserts,
+    eq: Equalizer,
+    compressor: Compressor,
+    transient: TransientShaper,
+}
+
+impl InsertChain {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            settings: PadInserts::default(),
+            eq: Equalizer::new(sample_rate, channels),
+            compressor: Compressor::new(sample_rate),
+            transient: TransientShaper::new(sample_rate),
+        }
+    }
+
+    pub fn set(&mut self, settings: PadInserts) {
+        self.settings = settings.sanitized();
+        self.eq.set(self.settings.eq);
+        self.compressor.set(self.settings.compressor);
+        self.transient.set(self.settings.transient);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.settings.is_active()
+    }
+
+    /// The effects in processing order, with whether each is enabled.
+    fn effects(&mut self) -> [(bool, &mut dyn Effect); 3] {
+        [
+            (self.settings.eq.enabled, &mut self.eq),
+            (self.settings.compressor.enabled, &mut self.compressor),
+            (self.settings.transient.enabled, &mut self.transient),
+        ]
+    }
+}
+
+impl Effect for InsertChain {
+    fn process(&mut self, buffer: &mut [f32], channels: usize) {
+        for (enabled, effect) in self.effects() {
+            if enabled {
+                effect.process(buffer, channels);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for (_, effect) in self.effects() {
+            effect.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    fn sine(freq: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (std::f32::consts::TAU * freq * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// Peak of the second half, once the filters have settled.
+    fn settled_peak(samples: &[f32]) -> f32 {
+        samples[samples.len() / 2..].iter().fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    fn eq_gain(settings: EqSettings, freq: f32) -> f32 {
+        let mut eq = Equalizer::new(RATE, 1);
+        eq.set(settings);
+        let mut samples = sine(freq, RATE as usize / 5);
+        eq.process(&mut samples, 1);
+        settled_peak(&samples)
+    }
+
+    #[test]
+    fn eq_bands_boost_and_cut_their_own_range() {
+        let mut settings = EqSettings { enabled: true, ..EqSettings::default() };
+        settings.mid.gain_db = 6.0;
+        assert!((eq_gain(settings, 1000.0) - 2.0).abs() < 0.05);
+        assert!((eq_gain(settings, 60.0) - 1.0).abs() < 0.05);
+
+        settings.mid.gain_db = 0.0;
+        settings.low.gain_db = -12.0;
+        settings.high.gain_db = 6.0;
+        assert!((eq_gain(settings, 30.0) - 0.25).abs() < 0.03);
+        assert!((eq_gain(settings, 15000.0) - 2.0).abs() < 0.1);
+        assert!((eq_gain(settings, 1000.0) - 1.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn flat_eq_leaves_the_signal_untouched() {
+        let mut eq = Equalizer::new(RATE, 2);
+        eq.set(EqSettings { enabled: true, ..EqSettings::default() });
+        let input = sine(440.0, 256);
+        let mut output = input.clone();
+        eq.process(&mut output, 2);
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn compressor_reduces_levels_above_the_threshold() {
+        let mut compressor = Compressor::new(RATE);
+        compressor.set(CompressorSettings { enabled: true, threshold_db: -20.0, ratio: 4.0, ..CompressorSettings::default() });
+
+        // Quiet signals pass unchanged
+        let mut quiet = vec![0.05; 1000];
+        compressor.process(&mut quiet, 1);
+        assert!(quiet.iter().all(|&s| (s - 0.05).abs() < 1e-6));
+
+        // At 0 dB, 20 dB over the threshold comes out 5 dB over once settled
+        let mut loud = vec![1.0; RATE as usize / 2];
+        compressor.process(&mut loud, 1);
+        assert!(loud[0] > 0.9, "the attack lets the start through");
+        assert!((gain_to_db(*loud.last().unwrap()) + 15.0).abs() < 0.1);
+
+        // Makeup gain lifts the result, and reset forgets the reduction
+        compressor.set(CompressorSettings { makeup_db: 6.0, ..compressor.settings });
+        compressor.reset();
+        let mut again = vec![1.0; 1];
+        compressor.process(&mut again, 1);
+        assert!(again[0] > 1.5);
+    }
+
+    /// A hit: a sharp start and an exponential ring.
+    fn hit() -> Vec<f32> {
+        (0..RATE as usize / 2)
+            .map(|i| 0.8 * (-(i as f32) / (RATE as f32 * 0.05)).exp())
+            .collect()
+    }
+
+    fn shaped(attack: f32, sustain: f32) -> Vec<f32> {
+        let mut shaper = TransientShaper::new(RATE);
+        shaper.set(TransientSettings { enabled: true, attack, sustain });
+        let mut samples = hit();
+        shaper.process(&mut samples, 1);
+        samples
+    }
+
+    #[test]
+    fn transient_shaper_moves_attack_and_sustain() {
+        let dry = hit();
+        let energy = |s: &[f32]| s.iter().map(|x| x * x).sum::<f32>();
+        let start = |s: &[f32]| energy(&s[..RATE as usize / 200]);
+        let tail = |s: &[f32]| energy(&s[RATE as usize / 10..]);
+
+        assert_eq!(shaped(0.0, 0.0), dry);
+        assert!(start(&shaped(1.0, 0.0)) > start(&dry) * 2.0);
+        assert!(start(&shaped(-1.0, 0.0)) < start(&dry) * 0.5);
+        assert!(tail(&shaped(0.0, 1.0)) > tail(&dry) * 2.0);
+        assert!(tail(&shaped(0.0, -1.0)) < tail(&dry) * 0.5);
+    }
+
+    #[test]
+    fn chain_runs_only_enabled_effects() {
+        let mut chain = InsertChain::new(RATE, 1);
+        let mut samples = vec![1.0; 64];
+        chain.process(&mut samples, 1);
+        assert!(!chain.is_active());
+        assert!(samples.iter().all(|&s| s == 1.0));
+
+        let mut inserts = PadInserts {
+            compressor: CompressorSettings { enabled: true, makeup_db: 6.0, ..CompressorSettings::default() },
+            ..PadInserts::default()
+        };
+        inserts.eq.mid.gain_db = 12.0;
+        chain.set(inserts);
+        assert!(chain.is_active());
+        assert_eq!(inserts.label().as_deref(), Some("comp"));
+        let mut quiet = vec![0.01; 64];
+        chain.process(&mut quiet, 1);
+        // Only the makeup gain: the EQ is off and nothing reaches the threshold
+        assert!(quiet.iter().all(|&s| (s - 0.01 * db_to_gain(6.0)).abs() < 1e-6));
+    }
+
+    #[test]
+    fn settings_are_clamped_and_parsed() {
+        let eq = EqSettings { mid: EqBand { freq: 1e6, gain_db: -40.0, q: f32::NAN }, ..EqSettings::default() }.sanitized();
+        assert_eq!(eq.mid, EqBand { freq: MAX_FREQ, gain_db: -MAX_EQ_DB, q: 1.0 });
+        let compressor = CompressorSettings { ratio: 0.5, attack_ms: 0.0, ..CompressorSettings::default() }.sanitized();
+        assert_eq!((compressor.ratio, compressor.attack_ms), (1.0, 0.1));
+
+        let inserts: PadInserts = toml::from_str(
+            r#"
+            [eq]
+            enabled = true
+            low = { freq = 80.0, gain_db = 3.0, q = 0.707 }
+
+            [transient]
+            attack = 0.5
+            "#,
+        )
+        .unwrap();
+        assert!(inserts.eq.enabled && !inserts.transient.enabled);
+        assert_eq!(inserts.eq.low.gain_db, 3.0);
+        assert_eq!(inserts.transient.attack, 0.5);
+        assert_eq!(inserts.compressor, CompressorSettings::default());
+        assert_eq!(toml::to_string(&PadInserts::default()).unwrap(), "");
+    }
+
+    #[test]
+    fn partial_bands_take_their_own_defaults() {
+        let eq: EqSettings = toml::from_str(
+            r#"
+            low = { gain_db = 4.0 }
+            high = { freq = 10000.0 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(eq.low, EqBand { gain_db: 4.0, ..EqBand::LOW });
+        assert_eq!(eq.mid, EqBand::MID);
+        assert_eq!(eq.high, EqBand { freq: 10000.0, ..EqBand::HIGH });
+
+        let band: EqBand = toml::from_str("q = 2.0").unwrap();
+        assert_eq!(band, EqBand { q: 2.0, ..EqBand::default() });
+    }
+}
@@ -1,17 +1,21 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use crate::sample;
+use crate::sample::{self, SampleData};
+
+/// Optional manifest filename inside a kit directory.
+pub const MANIFEST_FILENAME: &str = "kit.toml";
 
 /// Supported audio file extensions (Symphonia-backed).
 const AUDIO_EXTENSIONS: &[&str] = &["wav", "mp3", "flac", "ogg", "aac", "m4a"];
 
 /// Check whether the given file extension is a supported audio format.
-fn is_audio_file(ext: &OsStr) -> bool {
+pub(crate) fn is_audio_file(ext: &OsStr) -> bool {
     AUDIO_EXTENSIONS
         .iter()
         .any(|&e| ext.eq_ignore_ascii_case(e))
@@ -61,12 +65,126 @@ pub fn parse_sample_filename(filename: &str) -> Option<SampleFileInfo> {
     })
 }
 
+/// Kit manifest (`kit.toml`) — optional, overrides the filename scheme per note.
+///
+/// ```toml
+/// name = "Studio Maple"
+/// author = "Jane Doe"
+/// license = "CC-BY-4.0"
+///
+/// [notes.38]
+/// gain_db = -3.0
+/// pan = 0.2
+/// samples = [
+///     { file = "snare soft.wav", velocity = [1, 63] },
+///     { file = "snare hard a.wav", velocity = [64, 127], offset_ms = 1.5 },
+///     { file = "snare hard b.wav", velocity = [64, 127] },
+/// ]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct KitManifest {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    /// Keyed by note number as a string (TOML keys must be strings).
+    #[serde(default)]
+    pub notes: HashMap<String, NoteManifest>,
+}
+
+/// Per-note manifest entry. With `samples` empty, the note still comes from the
+/// filename scheme and only the gain/pan/pitch settings apply.
+#[derive(Debug, Default, Deserialize)]
+pub struct NoteManifest {
+    /// Gain in decibels.
+    #[serde(default)]
+    pub gain_db: f32,
+    /// Stereo balance, -1.0 (left) to 1.0 (right).
+    #[serde(default)]
+    pub pan: f32,
+    /// Pitch shift in semitones (changes duration along with pitch).
+    #[serde(default)]
+    pub pitch: f32,
+    #[serde(default)]
+    pub samples: Vec<SampleManifest>,
+}
+
+/// One sample file in a manifest note entry.
+#[derive(Debug, Deserialize)]
+pub struct SampleManifest {
+    /// Path relative to the kit directory; it may not leave it.
+    pub file: String,
+    /// Inclusive velocity range `[low, high]`; defaults to the full range.
+    /// Samples sharing a range are played round-robin.
+    pub velocity: Option<[u8; 2]>,
+    /// Skip this many milliseconds from the start of the file.
+    #[serde(default)]
+    pub offset_ms: f32,
+}
+
+/// Parse a kit manifest from a TOML string.
+pub fn parse_manifest(toml_str: &str) -> Result<KitManifest> {
+    let manifest: KitManifest = toml::from_str(toml_str).context("Failed to parse kit manifest")?;
+    for (key, note) in &manifest.notes {
+        key.parse::<u8>()
+            .ok()
+            .filter(|&n| n < 128)
+            .with_context(|| format!("Invalid note number in kit manifest: {}", key))?;
+        for s in &note.samples {
+            let file = Path::new(&s.file);
+            if file.as_os_str().is_empty()
+                || !file.components().all(|c| matches!(c, Component::Normal(_)))
+            {
+                anyhow::bail!("Sample path must stay inside the kit directory: {}", s.file);
+            }
+            if let Some([lo, hi]) = s.velocity
+                && (lo > hi || hi > 127)
+            {
+                anyhow::bail!("Invalid velocity range [{}, {}] for {}", lo, hi, s.file);
+            }
+        }
+    }
+    Ok(manifest)
+}
+
+/// Load `kit.toml` from a kit directory, if present.
+fn read_manifest(dir: &Path) -> Result<Option<KitManifest>> {
+    let path = dir.join(MANIFEST_FILENAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_manifest(&contents)
+        .with_context(|| format!("Invalid kit manifest: {}", path.display()))
+        .map(Some)
+}
+
+/// Kit metadata from the manifest (all optional).
+#[derive(Debug, Default, Clone)]
+pub struct KitMetadata {
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+}
+
 /// A single sample variant with its velocity layer and round-robin index.
 #[derive(Debug)]
 pub struct SampleVariant {
     pub samples: Arc<Vec<f32>>,
     pub velocity_layer: u8,
     pub round_robin: u8,
+    /// Explicit inclusive velocity range from the manifest; overrides `velocity_layer`.
+    pub velocity_range: Option<(u8, u8)>,
+}
+
+impl SampleVariant {
+    fn plays_at(&self, velocity: u8, layer: u8) -> bool {
+        match self.velocity_range {
+            Some((lo, hi)) => (lo..=hi).contains(&velocity),
+            None => self.velocity_layer == layer,
+        }
+    }
 }
 
 /// All variants for a single MIDI note, supporting velocity layers and round-robin.
@@ -92,8 +210,9 @@ impl std::fmt::Debug for NoteGroup {
 impl NoteGroup {
     /// Select a sample based on MIDI velocity (1-127).
     ///
-    /// Maps velocity to the appropriate velocity layer, then cycles through
-    /// round-robin variants to avoid the machine-gun effect.
+    /// Maps velocity to the appropriate velocity layer (or the manifest's explicit
+    /// velocity ranges), then cycles through round-robin variants to avoid the
+    /// machine-gun effect.
     pub fn select(&self, velocity: u8) -> Option<&Arc<Vec<f32>>> {
         let velocity = velocity.max(1);
 
@@ -106,7 +225,7 @@ impl NoteGroup {
         let rr_count = self
             .variants
             .iter()
-            .filter(|v| v.velocity_layer == layer)
+            .filter(|v| v.plays_at(velocity, layer))
             .count();
 
         if rr_count == 0 {
@@ -119,7 +238,7 @@ impl NoteGroup {
         // Find the nth variant matching this layer
         self.variants
             .iter()
-            .filter(|v| v.velocity_layer == layer)
+            .filter(|v| v.plays_at(velocity, layer))
             .nth(rr_index)
             .map(|v| &v.samples)
     }
@@ -133,6 +252,7 @@ pub struct Kit {
     pub notes: HashMap<u8, Arc<NoteGroup>>,
     pub sample_rate: u32,
     pub channels: u16,
+    pub metadata: KitMetadata,
 }
 
 /// Return sorted note keys for a kit's note map.
@@ -153,6 +273,15 @@ pub fn summary_lines(kit: &Kit, mapping: &crate::mapping::NoteMapping) -> Vec<St
         kit.sample_rate,
         kit.channels
     ));
+    if let Some(description) = &kit.metadata.description {
+        lines.push(format!("  {}", description));
+    }
+    if let Some(author) = &kit.metadata.author {
+        lines.push(format!("  Author: {}", author));
+    }
+    if let Some(license) = &kit.metadata.license {
+        lines.push(format!("  License: {}", license));
+    }
     for &n in &keys {
         let group = &kit.notes[&n];
         let variant_info = if group.max_velocity_layer > 1 || group.max_round_robin > 1 {
@@ -175,19 +304,108 @@ pub fn summary_lines(kit: &Kit, mapping: &crate::mapping::NoteMapping) -> Vec<St
     lines
 }
 
+/// A sample file queued for loading, from the manifest or the filename scheme.
+struct SampleSource {
+    path: PathBuf,
+    note: u8,
+    velocity_layer: u8,
+    round_robin: u8,
+    velocity_range: Option<(u8, u8)>,
+    offset_ms: f32,
+}
+
+/// Queue every sample listed in the manifest. Velocity layers are numbered by
+/// ascending range so the kit summary stays meaningful.
+fn manifest_sources(dir: &Path, manifest: &KitManifest) -> Vec<SampleSource> {
+    let mut sources = Vec::new();
+    for (key, entry) in &manifest.notes {
+        // Keys were validated by parse_manifest
+        let Ok(note) = key.parse::<u8>() else { continue };
+
+        let ranges: Vec<(u8, u8)> = entry
+            .samples
+            .iter()
+            .map(|s| s.velocity.map_or((1, 127), |[lo, hi]| (lo, hi)))
+            .collect();
+        let mut distinct = ranges.clone();
+        distinct.sort();
+        distinct.dedup();
+
+        let mut rr_counts: HashMap<(u8, u8), u8> = HashMap::new();
+        for (s, &range) in entry.samples.iter().zip(&ranges) {
+            let rr = rr_counts.entry(range).or_insert(0);
+            *rr += 1;
+            sources.push(SampleSource {
+                path: dir.join(&s.file),
+                note,
+                velocity_layer: distinct.iter().position(|&r| r == range).unwrap_or(0) as u8 + 1,
+                round_robin: *rr,
+                velocity_range: Some(range),
+                offset_ms: s.offset_ms,
+            });
+        }
+    }
+    sources
+}
+
+/// Bake the manifest's per-sample offset and per-note pitch, gain and pan into the data.
+fn apply_note_settings(data: &mut SampleData, settings: Option<&NoteManifest>, offset_ms: f32) {
+    let ch = data.channels.max(1) as usize;
+
+    if offset_ms > 0.0 {
+        let skip = (offset_ms as f64 * data.sample_rate as f64 / 1000.0) as usize * ch;
+        data.samples.drain(..skip.min(data.samples.len()));
+    }
+
+    let Some(settings) = settings else { return };
+
+    if settings.pitch != 0.0 {
+        data.samples = sample::pitch_shift(&data.samples, data.channels, settings.pitch);
+    }
+
+    let gain = 10.0_f32.powf(settings.gain_db / 20.0);
+    // Constant-power balance: unity at center, +3 dB on the favored side at the extremes
+    let (left, right) = if ch == 2 && settings.pan != 0.0 {
+        let angle = (settings.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        (angle.cos() * std::f32::consts::SQRT_2, angle.sin() * std::f32::consts::SQRT_2)
+    } else {
+        (1.0, 1.0)
+    };
+    if gain == 1.0 && left == 1.0 && right == 1.0 {
+        return;
+    }
+    for frame in data.samples.chunks_mut(ch) {
+        for (c, s) in frame.iter_mut().enumerate() {
+            let pan_gain = match (ch, c) {
+                (2, 0) => left,
+                (2, _) => right,
+                _ => 1.0,
+            };
+            *s *= gain * pan_gain;
+        }
+    }
+}
+
 /// Shared loading logic for `load_kit` and `load_kit_with_progress`.
 ///
+/// Every sample is converted to `target_rate`; if `None`, the first file's rate is used.
+/// If the directory has a `kit.toml`, notes it lists samples for come from the manifest
+/// and the rest still come from the filename scheme.
 /// `on_entries_counted` is called once with the total file count after reading the directory.
 /// `on_file_done` is called after each file is processed (whether loaded or skipped).
 fn load_kit_inner(
     path: &Path,
+    target_rate: Option<u32>,
     on_entries_counted: &mut dyn FnMut(usize),
     on_file_done: &mut dyn FnMut(),
 ) -> Result<Kit> {
-    let name = path
-        .file_name()
-        .map(|n| n.to_string_lossy().into_owned())
-        .unwrap_or_else(|| "unnamed".to_string());
+    let manifest = read_manifest(path)?.unwrap_or_default();
+
+    let name = manifest.name.clone().unwrap_or_else(|| {
+        path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "unnamed".to_string())
+    });
 
     let mut entries: Vec<_> = std::fs::read_dir(path)
         .with_context(|| format!("Failed to read kit directory: {}", path.display()))?
@@ -199,25 +417,32 @@ fn load_kit_inner(
         })
         .collect();
 
-    if entries.is_empty() {
+    let mut sources = manifest_sources(path, &manifest);
+
+    // Files and notes claimed by the manifest are not parsed from filenames
+    let claimed_files: HashSet<PathBuf> = sources.iter().map(|s| s.path.clone()).collect();
+    let claimed_notes: HashSet<u8> = sources.iter().map(|s| s.note).collect();
+    entries.retain(|e| !claimed_files.contains(&e.path()));
+
+    if entries.is_empty() && sources.is_empty() {
         anyhow::bail!("No audio files found in {}", path.display());
     }
 
     // Sort alphabetically for deterministic ordering
     entries.sort_by_key(|e| e.file_name());
-    on_entries_counted(entries.len());
-
-    let mut variants_map: HashMap<u8, Vec<SampleVariant>> = HashMap::new();
-    let mut kit_sample_rate: Option<u32> = None;
-    let mut kit_channels: Option<u16> = None;
+    on_entries_counted(entries.len() + sources.len());
 
     for entry in &entries {
-        let file_path = entry.path();
         let filename = entry.file_name();
         let filename_str = filename.to_string_lossy();
 
         let info = match parse_sample_filename(&filename_str) {
-            Some(info) => info,
+            Some(info) if !claimed_notes.contains(&info.note) => info,
+            Some(_) => {
+                eprintln!("  Skipping {} (note is defined in {})", filename_str, MANIFEST_FILENAME);
+                on_file_done();
+                continue;
+            }
             None => {
                 eprintln!("  Skipping {} (cannot parse note number)", filename_str);
                 on_file_done();
@@ -225,30 +450,36 @@ fn load_kit_inner(
             }
         };
 
-        let data = sample::load_audio(&file_path)
+        sources.push(SampleSource {
+            path: entry.path(),
+            note: info.note,
+            velocity_layer: info.velocity_layer.unwrap_or(1),
+            round_robin: info.round_robin.unwrap_or(1),
+            velocity_range: None,
+            offset_ms: 0.0,
+        });
+    }
+
+    let mut variants_map: HashMap<u8, Vec<SampleVariant>> = HashMap::new();
+    let mut kit_sample_rate: Option<u32> = None;
+    let mut kit_channels: Option<u16> = None;
+
+    for source in &sources {
+        let file_path = &source.path;
+        let data = sample::load_audio(file_path)
             .with_context(|| format!("Failed to load {}", file_path.display()))?;
 
         on_file_done();
 
-        match kit_sample_rate {
-            None => kit_sample_rate = Some(data.sample_rate),
-            Some(sr) if sr != data.sample_rate => {
-                anyhow::bail!(
-                    "Sample rate mismatch in {}: expected {} Hz, got {} Hz",
-                    filename_str,
-                    sr,
-                    data.sample_rate
-                );
-            }
-            _ => {}
-        }
+        let sample_rate = *kit_sample_rate.get_or_insert(target_rate.unwrap_or(data.sample_rate));
+        let mut data = data.resampled(sample_rate);
 
         match kit_channels {
             None => kit_channels = Some(data.channels),
             Some(ch) if ch != data.channels => {
                 anyhow::bail!(
                     "Channel count mismatch in {}: expected {}, got {}",
-                    filename_str,
+                    file_path.display(),
                     ch,
                     data.channels
                 );
@@ -256,13 +487,17 @@ fn load_kit_inner(
             _ => {}
         }
 
+        let settings = manifest.notes.get(&source.note.to_string());
+        apply_note_settings(&mut data, settings, source.offset_ms);
+
         let variant = SampleVariant {
             samples: Arc::new(data.samples),
-            velocity_layer: info.velocity_layer.unwrap_or(1),
-            round_robin: info.round_robin.unwrap_or(1),
+            velocity_layer: source.velocity_layer,
+            round_robin: source.round_robin,
+            velocity_range: source.velocity_range,
         };
 
-        variants_map.entry(info.note).or_default().push(variant);
+        variants_map.entry(source.note).or_default().push(variant);
     }
 
     if variants_map.is_empty() {
@@ -271,7 +506,6 @@ fn load_kit_inner(
             path.display()
         );
     }
-
     let sample_rate = kit_sample_rate.unwrap();
     let channels = kit_channels.unwrap();
 
@@ -304,26 +538,35 @@ fn load_kit_inner(
         notes,
         sample_rate,
         channels,
+        metadata: KitMetadata {
+            author: manifest.author,
+            description: manifest.description,
+            license: manifest.license,
+        },
     })
 }
 
 /// Load all audio files from a directory and map them by MIDI note number.
 ///
-/// All samples must share the same sample rate and channel count.
+/// Samples are resampled to `target_rate` (the output device's rate), or to the
+/// first file's rate if `None`. All samples must share the same channel count.
 /// Files with the same note number are grouped by velocity layer and round-robin.
-pub fn load_kit(path: &Path) -> Result<Kit> {
-    load_kit_inner(path, &mut |_| {}, &mut || {})
+/// An optional `kit.toml` manifest can name files freely and adjust each note.
+pub fn load_kit(path: &Path, target_rate: Option<u32>) -> Result<Kit> {
+    load_kit_inner(path, target_rate, &mut |_| {}, &mut || {})
 }
 
 /// Like `load_kit`, but reports progress via atomic counters so a UI thread
 /// can display a progress bar while loading runs in the background.
 pub fn load_kit_with_progress(
     path: &Path,
+    target_rate: Option<u32>,
     progress: &Arc<AtomicUsize>,
     total: &Arc<AtomicUsize>,
 ) -> Result<Kit> {
     load_kit_inner(
         path,
+        target_rate,
         &mut |count| { total.store(count, Ordering::Relaxed); },
         &mut || { progress.fetch_add(1, Ordering::Relaxed); },
     )
@@ -501,7 +744,7 @@ mod tests {
         write_test_wav(&dir.path().join("38.wav"), 44100, 2);
         write_test_wav(&dir.path().join("42.wav"), 44100, 2);
 
-        let kit = load_kit(dir.path()).unwrap();
+        let kit = load_kit(dir.path(), None).unwrap();
         assert_eq!(kit.notes.len(), 3);
         assert!(kit.notes.contains_key(&36));
         assert!(kit.notes.contains_key(&38));
@@ -513,20 +756,34 @@ mod tests {
     #[test]
     fn load_kit_empty_dir() {
         let dir = tempfile::tempdir().unwrap();
-        let result = load_kit(dir.path());
+        let result = load_kit(dir.path(), None);
         assert!(result.is_err());
     }
 
     #[test]
-    fn load_kit_mismatched_sample_rates() {
+    fn load_kit_mixed_sample_rates_are_resampled() {
         let dir = tempfile::tempdir().unwrap();
         write_test_wav(&dir.path().join("36.wav"), 44100, 2);
         write_test_wav(&dir.path().join("38.wav"), 48000, 2);
 
-        let result = load_kit(dir.path());
-        assert!(result.is_err());
-        let err = result.unwrap_err().to_string();
-        assert!(err.contains("Sample rate mismatch"));
+        // Without a target, everything follows the first file (36.wav at 44.1 kHz)
+        let kit = load_kit(dir.path(), None).unwrap();
+        assert_eq!(kit.sample_rate, 44100);
+        // 50 stereo frames at 48 kHz → 46 frames at 44.1 kHz
+        assert_eq!(kit.notes[&38].variants[0].samples.len(), 46 * 2);
+        assert_eq!(kit.notes[&36].variants[0].samples.len(), 50 * 2);
+    }
+
+    #[test]
+    fn load_kit_resamples_to_target_rate() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_wav(&dir.path().join("36.wav"), 44100, 2);
+        write_test_wav(&dir.path().join("38.wav"), 96000, 2);
+
+        let kit = load_kit(dir.path(), Some(48000)).unwrap();
+        assert_eq!(kit.sample_rate, 48000);
+        assert_eq!(kit.notes[&36].variants[0].samples.len(), 55 * 2);
+        assert_eq!(kit.notes[&38].variants[0].samples.len(), 25 * 2);
     }
 
     // --- Stage 5: velocity layer + round-robin tests ---
@@ -538,6 +795,7 @@ mod tests {
                 samples: Arc::new(vec![1.0; 10]),
                 velocity_layer: 1,
                 round_robin: 1,
+                velocity_range: None,
             }],
             max_velocity_layer: 1,
             max_round_robin: 1,
@@ -561,11 +819,13 @@ mod tests {
                     samples: Arc::clone(&soft),
                     velocity_layer: 1,
                     round_robin: 1,
+                    velocity_range: None,
                 },
                 SampleVariant {
                     samples: Arc::clone(&loud),
                     velocity_layer: 2,
                     round_robin: 1,
+                    velocity_range: None,
                 },
             ],
             max_velocity_layer: 2,
@@ -602,16 +862,19 @@ mod tests {
                     samples: Arc::clone(&rr1),
                     velocity_layer: 1,
                     round_robin: 1,
+                    velocity_range: None,
                 },
                 SampleVariant {
                     samples: Arc::clone(&rr2),
                     velocity_layer: 1,
                     round_robin: 2,
+                    velocity_range: None,
                 },
                 SampleVariant {
                     samples: Arc::clone(&rr3),
                     velocity_layer: 1,
                     round_robin: 3,
+                    velocity_range: None,
                 },
             ],
             max_velocity_layer: 1,
@@ -638,7 +901,7 @@ mod tests {
         // Note 36 as a single sample
         write_test_wav(&dir.path().join("36.wav"), 44100, 2);
 
-        let kit = load_kit(dir.path()).unwrap();
+        let kit = load_kit(dir.path(), None).unwrap();
         assert_eq!(kit.notes.len(), 2);
 
         let group38 = &kit.notes[&38];
@@ -664,16 +927,19 @@ mod tests {
                     samples: Arc::clone(&l1),
                     velocity_layer: 1,
                     round_robin: 1,
+                    velocity_range: None,
                 },
                 SampleVariant {
                     samples: Arc::clone(&l2),
                     velocity_layer: 2,
                     round_robin: 1,
+                    velocity_range: None,
                 },
                 SampleVariant {
                     samples: Arc::clone(&l3),
                     velocity_layer: 3,
                     round_robin: 1,
+                    velocity_range: None,
                 },
             ],
             max_velocity_layer: 3,
@@ -689,4 +955,164 @@ mod tests {
         assert_eq!(group.select(85).unwrap()[0], 3.0);
         assert_eq!(group.select(127).unwrap()[0], 3.0);
     }
+
+    // --- kit.toml manifest ---
+
+    #[test]
+    fn parse_manifest_metadata_and_notes() {
+        let manifest = parse_manifest(
+            r#"
+name = "Studio Maple"
+author = "Jane Doe"
+license = "CC-BY-4.0"
+
+[notes.38]
+gain_db = -6.0
+pan = 0.5
+pitch = -2.0
+samples = [
+    { file = "snare soft.wav", velocity = [1, 63] },
+    { file = "snare hard.wav", velocity = [64, 127], offset_ms = 2.5 },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("Studio Maple"));
+        assert_eq!(manifest.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(manifest.description, None);
+        let snare = &manifest.notes["38"];
+        assert_eq!(snare.gain_db, -6.0);
+        assert_eq!(snare.samples.len(), 2);
+        assert_eq!(snare.samples[0].velocity, Some([1, 63]));
+        assert_eq!(snare.samples[1].offset_ms, 2.5);
+    }
+
+    #[test]
+    fn parse_manifest_rejects_bad_notes_and_ranges() {
+        assert!(parse_manifest("[notes.200]\ngain_db = 1.0").is_err());
+        assert!(parse_manifest("[notes.snare]\ngain_db = 1.0").is_err());
+        assert!(parse_manifest(
+            "[notes.38]\nsamples = [{ file = \"a.wav\", velocity = [90, 10] }]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_manifest_rejects_paths_outside_the_kit() {
+        for file in ["/etc/passwd", "../other/snare.wav", "samples/../../snare.wav", "./snare.wav", ""] {
+            let toml = format!("[notes.38]\nsamples = [{{ file = {:?} }}]", file);
+            assert!(parse_manifest(&toml).is_err(), "{:?} was accepted", file);
+        }
+        assert!(parse_manifest("[notes.38]\nsamples = [{ file = \"snares/hard.wav\" }]").is_ok());
+    }
+
+    #[test]
+    fn load_kit_with_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_wav_value(&dir.path().join("snare soft.wav"), 44100, 2, 1000);
+        write_test_wav_value(&dir.path().join("snare hard a.wav"), 44100, 2, 2000);
+        write_test_wav_value(&dir.path().join("snare hard b.wav"), 44100, 2, 3000);
+        // Claimed by the manifest, so the filename is not parsed
+        write_test_wav(&dir.path().join("38.wav"), 44100, 2);
+        // Not in the manifest: falls back to the filename scheme
+        write_test_wav(&dir.path().join("36.wav"), 44100, 2);
+        std::fs::write(
+            dir.path().join(MANIFEST_FILENAME),
+            r#"
+name = "Custom"
+description = "Test kit"
+
+[notes.38]
+samples = [
+    { file = "snare soft.wav", velocity = [1, 63], offset_ms = 0.5 },
+    { file = "snare hard a.wav", velocity = [64, 127] },
+    { file = "snare hard b.wav", velocity = [64, 127] },
+]
+"#,
+        )
+        .unwrap();
+
+        let kit = load_kit(dir.path(), None).unwrap();
+        assert_eq!(kit.name, "Custom");
+        assert_eq!(kit.metadata.description.as_deref(), Some("Test kit"));
+        assert_eq!(note_keys(&kit.notes), vec![36, 38]);
+
+        let snare = &kit.notes[&38];
+        assert_eq!(snare.variants.len(), 3);
+        assert_eq!(snare.max_velocity_layer, 2);
+        assert_eq!(snare.max_round_robin, 2);
+
+        let soft = 1000.0 / 32768.0;
+        let hard_a = 2000.0 / 32768.0;
+        let hard_b = 3000.0 / 32768.0;
+        assert!((snare.select(63).unwrap()[0] - soft).abs() < 1e-6);
+        assert!((snare.select(64).unwrap()[0] - hard_b).abs() < 1e-6);
+        assert!((snare.select(127).unwrap()[0] - hard_a).abs() < 1e-6);
+
+        // 0.5 ms at 44.1 kHz = 22 frames trimmed from the 50-frame file
+        assert_eq!(snare.variants[0].samples.len(), 28 * 2);
+    }
+
+    #[test]
+    fn load_kit_manifest_note_settings_apply_to_filename_samples() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_wav_value(&dir.path().join("36.wav"), 44100, 2, 16384);
+        write_test_wav_value(&dir.path().join("38.wav"), 44100, 2, 16384);
+        std::fs::write(
+            dir.path().join(MANIFEST_FILENAME),
+            "[notes.36]\ngain_db = -6.0206\n\n[notes.38]\npan = 1.0\npitch = 12.0\n",
+        )
+        .unwrap();
+
+        let kit = load_kit(dir.path(), None).unwrap();
+
+        let kick = &kit.notes[&36].variants[0].samples;
+        assert!((kick[0] - 0.25).abs() < 1e-4);
+        assert!((kick[1] - 0.25).abs() < 1e-4);
+
+        // Hard right: left silent, right boosted by +3 dB; an octave up halves the length
+        let snare = &kit.notes[&38].variants[0].samples;
+        assert_eq!(snare.len(), 25 * 2);
+        assert!(snare[20].abs() < 1e-4);
+        assert!((snare[21] - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-2);
+    }
+
+    #[test]
+    fn load_kit_manifest_missing_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_wav(&dir.path().join("36.wav"), 44100, 2);
+        std::fs::write(
+            dir.path().join(MANIFEST_FILENAME),
+            "[notes.38]\nsamples = [{ file = \"missing.wav\" }]\n",
+        )
+        .unwrap();
+        assert!(load_kit(dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn select_explicit_velocity_ranges() {
+        let group = NoteGroup {
+            variants: vec![
+                SampleVariant {
+                    samples: Arc::new(vec![1.0; 10]),
+                    velocity_layer: 1,
+                    round_robin: 1,
+                    velocity_range: Some((1, 20)),
+                },
+                SampleVariant {
+                    samples: Arc::new(vec![2.0; 10]),
+                    velocity_layer: 2,
+                    round_robin: 1,
+                    velocity_range: Some((21, 127)),
+                },
+            ],
+            max_velocity_layer: 2,
+            max_round_robin: 1,
+            rr_counter: AtomicUsize::new(0),
+        };
+
+        assert_eq!(group.select(20).unwrap()[0], 1.0);
+        assert_eq!(group.select(21).unwrap()[0], 2.0);
+        assert_eq!(group.select(100).unwrap()[0], 2.0);
+    }
 }
@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Bank Select (MSB) controller number.
+const BANK_SELECT_CC: u8 = 0;
+
+/// Switching kits from the drum module: Program Change (and optionally a CC)
+/// picks a kit from a table, persisted under `[kit_switch]` in settings.toml.
+///
+/// ```toml
+/// [kit_switch]
+/// channel = 10          # only listen on this channel (1-16); all channels if unset
+/// cc = 21               # also take program numbers from this CC's value
+/// bank_select = true    # Bank Select (CC 0) picks a bank, for "bank:program" keys
+///
+/// [kit_switch.programs] # program numbers count from 1, as modules show them
+/// 1 = "Acoustic"        # kit name from the kit picker, or a directory path
+/// 2 = "/home/me/kits/808"
+/// "2:1" = "Jazz"        # bank 2, program 1
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KitSwitchSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cc: Option<u8>,
+    #[serde(default)]
+    pub bank_select: bool,
+    /// Kits by program. Keys are strings because TOML only supports string keys.
+    #[serde(default)]
+    pub programs: BTreeMap<String, String>,
+}
+
+impl KitSwitchSettings {
+    /// Convert to the lookup table, skipping unparseable program keys.
+    pub fn to_table(&self) -> KitSwitchTable {
+        let number = |s: &str| s.trim().parse::<u8>().ok().filter(|n| (1..=128).contains(n));
+        let programs = self
+            .programs
+            .iter()
+            .filter_map(|(key, kit)| {
+                let (bank, program) = match key.split_once(':') {
+                    Some((bank, program)) => (Some(number(bank)?), number(program)?),
+                    None => (None, number(key)?),
+                };
+                Some(((bank, program), kit.clone()))
+            })
+            .collect();
+        KitSwitchTable {
+            channel: self.channel,
+            cc: self.cc,
+            bank_select: self.bank_select,
+            programs,
+        }
+    }
+}
+
+/// The program table in effect, checked against every incoming message.
+#[derive(Debug, Clone, Default)]
+pub struct KitSwitchTable {
+    channel: Option<u8>,
+    cc: Option<u8>,
+    bank_select: bool,
+    /// Kits by (bank, program), counting from 1; `None` banks match any bank.
+    programs: HashMap<(Option<u8>, u8), String>,
+}
+
+impl KitSwitchTable {
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// The kit for `program` in `bank`: an entry for that bank, or one for any bank.
+    fn kit(&self, bank: Option<u8>, program: u8) -> Option<&String> {
+        bank.and_then(|bank| self.programs.get(&(Some(bank), program)))
+            .or_else(|| self.programs.get(&(None, program)))
+    }
+
+    /// Log line describing the table, e.g. "Kit switching: 3 programs (ch 10, CC 21)".
+    pub fn label(&self) -> String {
+        let mut sources = Vec::new();
+        if let Some(channel) = self.channel {
+            sources.push(format!("ch {}", channel));
+        }
+        if let Some(cc) = self.cc {
+            sources.push(format!("CC {}", cc));
+        }
+        if self.bank_select {
+            sources.push("bank select".to_string());
+        }
+        let programs = match self.programs.len() {
+            1 => "1 program".to_string(),
+            n => format!("{} programs", n),
+        };
+        if sources.is_empty() {
+            format!("Kit switching: {}", programs)
+        } else {
+            format!("Kit switching: {} ({})", programs, sources.join(", "))
+        }
+    }
+}
+
+/// A kit picked from the module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KitSelection {
+    /// Bank and program (from 1) the kit was found under.
+    pub bank: Option<u8>,
+    pub program: u8,
+    /// Kit name or path, as written in the table.
+    pub kit: String,
+}
+
+impl KitSelection {
+    /// e.g. "Program 5" or "Bank 2 program 5".
+    pub fn label(&self) -> String {
+        match self.bank {
+            Some(bank) => format!("Bank {} program {}", bank, self.program),
+            None => format!("Program {}", self.program),
+        }
+    }
+}
+
+/// Follows one input's Program Change, Bank Select and kit CC messages.
+#[derive(Default)]
+pub struct KitSwitcher {
+    /// Last Bank Select value per channel, counting from 1.
+    banks: [Option<u8>; 16],
+}
+
+impl KitSwitcher {
+    /// The kit `data` selects, if it is a Program Change (or the table's CC) for a
+    /// program in the table. Bank Select is remembered for the programs after it.
+    pub fn check(&mut self, data: &[u8], table: &KitSwitchTable) -> Option<KitSelection> {
+        if table.is_empty() || data.is_empty() || data[0] >= 0xF0 {
+            return None;
+        }
+        let channel = (data[0] & 0x0F) + 1;
+        if table.channel.is_some_and(|c| c != channel) {
+            return None;
+        }
+
+        let value = match (data[0] & 0xF0, data) {
+            (0xC0, [_, program]) => *program,
+            (0xB0, [_, BANK_SELECT_CC, bank]) if table.bank_select => {
+                self.banks[channel as usize - 1] = Some((bank & 0x7F) + 1);
+                return None;
+            }
+            (0xB0, [_, controller, value]) if table.cc == Some(*controller) => *value,
+            _ => return None,
+        };
+        let program = (value & 0x7F) + 1;
+        let bank = self.banks[channel as usize - 1].filter(|_| table.bank_select);
+        let kit = table.kit(bank, program)?;
+        Some(KitSelection {
+            bank: bank.filter(|&bank| table.programs.contains_key(&(Some(bank), program))),
+            program,
+            kit: kit.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(toml_str: &str) -> KitSwitchTable {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            kit_switch: KitSwitchSettings,
+        }
+        toml::from_str::<Wrapper>(toml_str).unwrap().kit_switch.to_table()
+    }
+
+    #[test]
+    fn program_change_selects_kits_counting_from_one() {
+        let table = table(
+            r#"
+            [kit_switch]
+            channel = 10
+
+            [kit_switch.programs]
+            1 = "Acoustic"
+            2 = "/kits/808"
+            nope = "Skipped"
+            "#,
+        );
+        let mut switcher = KitSwitcher::default();
+
+        let selection = switcher.check(&[0xC9, 1], &table).unwrap();
+        assert_eq!(selection.kit, "/kits/808");
+        assert_eq!(selection.label(), "Program 2");
+        assert_eq!(switcher.check(&[0xC9, 0], &table).unwrap().kit, "Acoustic");
+        // Not in the table, or on another channel
+        assert_eq!(switcher.check(&[0xC9, 5], &table), None);
+        assert_eq!(switcher.check(&[0xC0, 0], &table), None);
+        // Notes and other CCs aren't kit switches
+        assert_eq!(switcher.check(&[0x99, 1, 100], &table), None);
+        assert_eq!(switcher.check(&[0xB9, 21, 1], &table), None);
+        assert_eq!(table.label(), "Kit switching: 2 programs (ch 10)");
+    }
+
+    #[test]
+    fn cc_and_bank_select() {
+        let table = table(
+            r#"
+            [kit_switch]
+            cc = 21
+            bank_select = true
+
+            [kit_switch.programs]
+            1 = "Acoustic"
+            "2:1" = "Jazz"
+            "#,
+        );
+        let mut switcher = KitSwitcher::default();
+
+        assert_eq!(switcher.check(&[0xB9, 21, 0], &table).unwrap().kit, "Acoustic");
+        // Bank 2 (CC 0 value 1) has its own program 1...
+        assert_eq!(switcher.check(&[0xB9, 0, 1], &table), None);
+        let selection = switcher.check(&[0xC9, 0], &table).unwrap();
+        assert_eq!(selection.kit, "Jazz");
+        assert_eq!(selection.label(), "Bank 2 program 1");
+        // ...but only on the channel that selected it
+        assert_eq!(switcher.check(&[0xC0, 0], &table).unwrap().kit, "Acoustic");
+        // Bank 3 has no entries of its own, so plain program keys apply
+        switcher.check(&[0xB9, 0, 2], &table);
+        let selection = switcher.check(&[0xC9, 0], &table).unwrap();
+        assert_eq!((selection.kit.as_str(), selection.bank), ("Acoustic", None));
+    }
+}
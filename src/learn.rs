@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use crate::mapping::{self, MappingSource, NoteMapping};
+
+/// What a slot is, which decides the choke rules it gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotKind {
+    Drum,
+    /// Closed hi-hat strikes and the pedal chip choke the open hi-hat.
+    ClosedHiHat,
+    OpenHiHat,
+}
+
+/// A drum the learn popup asks for, and the General MIDI sample note it plays.
+pub struct LearnSlot {
+    pub name: &'static str,
+    pub note: u8,
+    pub kind: SlotKind,
+}
+
+/// The slots walked through, in order.
+pub const SLOTS: &[LearnSlot] = &[
+    LearnSlot { name: "Kick", note: 36, kind: SlotKind::Drum },
+    LearnSlot { name: "Snare", note: 38, kind: SlotKind::Drum },
+    LearnSlot { name: "Snare Rim", note: 40, kind: SlotKind::Drum },
+    LearnSlot { name: "Side Stick", note: 37, kind: SlotKind::Drum },
+    LearnSlot { name: "Tom 1", note: 48, kind: SlotKind::Drum },
+    LearnSlot { name: "Tom 2", note: 45, kind: SlotKind::Drum },
+    LearnSlot { name: "Tom 3", note: 43, kind: SlotKind::Drum },
+    LearnSlot { name: "Tom 4", note: 41, kind: SlotKind::Drum },
+    LearnSlot { name: "Hi-Hat Closed", note: 42, kind: SlotKind::ClosedHiHat },
+    LearnSlot { name: "Hi-Hat Open", note: 46, kind: SlotKind::OpenHiHat },
+    LearnSlot { name: "Hi-Hat Pedal", note: 44, kind: SlotKind::ClosedHiHat },
+    LearnSlot { name: "Crash 1", note: 49, kind: SlotKind::Drum },
+    LearnSlot { name: "Crash 2", note: 57, kind: SlotKind::Drum },
+    LearnSlot { name: "Ride", note: 51, kind: SlotKind::Drum },
+    LearnSlot { name: "Ride Bell", note: 53, kind: SlotKind::Drum },
+];
+
+/// Half-open and splash hi-hat sample notes, choked along with the open hi-hat as in
+/// the General MIDI mapping.
+const HALF_OPEN_HIHAT_NOTES: [u8; 2] = [23, 21];
+
+/// Build a mapping from the incoming note learned for each of `SLOTS` (`None` =
+/// skipped): learned notes are remapped to their slot's sample and named after it,
+/// and closed hi-hat notes choke the open hi-hat.
+pub fn build_mapping(name: String, learned: &[Option<u8>]) -> NoteMapping {
+    let mut notes = mapping::default_mapping().notes;
+    let mut remap = HashMap::new();
+    let mut chokes = HashMap::new();
+
+    let learned_slots = || SLOTS.iter().zip(learned).filter_map(|(slot, note)| Some((slot, (*note)?)));
+    let open_hihat: Vec<u8> = SLOTS
+        .iter()
+        .filter(|slot| slot.kind == SlotKind::OpenHiHat)
+        .map(|slot| slot.note)
+        .chain(HALF_OPEN_HIHAT_NOTES)
+        .collect();
+
+    // Samples first, so a learned incoming note keeps its own slot's name
+    for (slot, _) in learned_slots() {
+        notes.insert(slot.note, slot.name.to_string());
+    }
+    for (slot, incoming) in learned_slots() {
+        notes.insert(incoming, slot.name.to_string());
+        if incoming != slot.note {
+            remap.insert(incoming, slot.note);
+        }
+        if slot.kind == SlotKind::ClosedHiHat {
+            chokes.insert(incoming, open_hihat.clone());
+        }
+    }
+
+    NoteMapping {
+        name,
+        notes,
+        chokes,
+        remap,
+        hihat: None,
+        source: MappingSource::UserFile(std::path::PathBuf::from("unsaved")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot_index(name: &str) -> usize {
+        SLOTS.iter().position(|s| s.name == name).unwrap()
+    }
+
+    #[test]
+    fn learned_notes_become_remaps_and_chokes() {
+        let mut learned = vec![None; SLOTS.len()];
+        learned[slot_index("Kick")] = Some(36);
+        learned[slot_index("Snare")] = Some(26);
+        learned[slot_index("Tom 1")] = Some(71);
+        learned[slot_index("Hi-Hat Closed")] = Some(22);
+        learned[slot_index("Hi-Hat Pedal")] = Some(44);
+
+        let mapping = build_mapping("TD-1".to_string(), &learned);
+        assert_eq!(mapping.remap_note(26), 38);
+        assert_eq!(mapping.remap_note(71), 48);
+        assert_eq!(mapping.remap_note(22), 42);
+        // Notes already on their sample aren't remapped
+        assert!(!mapping.remap.contains_key(&36));
+        assert!(!mapping.remap.contains_key(&44));
+        assert_eq!(mapping.remap.len(), 3);
+
+        assert_eq!(mapping.choke_targets(22), &[46, 23, 21]);
+        assert_eq!(mapping.choke_targets(44), &[46, 23, 21]);
+        assert!(mapping.choke_targets(26).is_empty());
+        assert_eq!(mapping.chokes.len(), 2);
+
+        assert_eq!(mapping.drum_name(71), "Tom 1");
+        assert_eq!(mapping.drum_name(48), "Tom 1");
+        // Skipped slots keep their General MIDI name
+        assert_eq!(mapping.drum_name(45), "Low Tom");
+
+        // Survives the round trip through a mapping file
+        let toml = mapping::serialize_mapping(&mapping).unwrap();
+        let parsed = mapping::parse_mapping(&toml, MappingSource::BuiltIn).unwrap();
+        assert_eq!(parsed.name, "TD-1");
+        assert_eq!(parsed.remap, mapping.remap);
+        assert_eq!(parsed.chokes, mapping.chokes);
+    }
+}
@@ -1,15 +1,35 @@
 mod audio;
+mod channels;
 mod commands;
+mod control;
+mod convolution;
 mod download;
+mod headless;
+mod hotplug;
+mod inserts;
 mod kit;
+mod kitswitch;
+mod learn;
 mod mapping;
+mod master;
+mod metronome;
 mod midi;
+mod midifile;
+mod mix;
+mod osc;
+mod pipeline;
 mod play;
+mod record;
+mod render;
+mod reverb;
 mod sample;
 mod settings;
 mod setup;
 mod stderr;
+mod suppress;
+mod thru;
 mod tui;
+mod velocity;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -78,6 +98,49 @@ enum Commands {
         /// Extra directories to search for kits (can be repeated)
         #[arg(long = "kits-dir", value_name = "DIR")]
         kits_dirs: Vec<PathBuf>,
+        /// Capture MIDI input to a .mid file in the recordings directory from the start
+        /// (toggle with `c` in play mode)
+        #[arg(long)]
+        record_midi: bool,
+        /// Tempo written to captured MIDI files, in BPM
+        #[arg(long, default_value_t = midifile::DEFAULT_BPM)]
+        tempo: f64,
+        /// Resolution of captured MIDI files, in ticks per quarter note
+        #[arg(long, default_value_t = midifile::DEFAULT_PPQ, value_parser = clap::value_parser!(u16).range(1..=0x7FFF))]
+        ppq: u16,
+        /// Run without the TUI (e.g. as a service). Kit, port and device default to the
+        /// saved settings. SIGHUP reloads the kit; SIGTERM or Ctrl+C quits.
+        #[arg(long)]
+        headless: bool,
+        /// Append the headless event log to this file instead of stdout
+        #[arg(long, value_name = "PATH", requires = "headless")]
+        log_file: Option<PathBuf>,
+        /// Accept line-delimited JSON commands on a Unix socket
+        /// (default path: $XDG_RUNTIME_DIR/drumkit.sock)
+        #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = "")]
+        control_socket: Option<PathBuf>,
+        /// Listen for OSC messages on UDP ([HOST:]PORT; a bare port binds to 127.0.0.1)
+        #[arg(long, value_name = "[HOST:]PORT", value_parser = osc::parse_bind_addr)]
+        osc: Option<std::net::SocketAddr>,
+        /// Create a virtual MIDI input that sequencers and DAWs can connect to
+        /// (default name: drumkit). A hardware port is then optional.
+        #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = midi::DEFAULT_VIRTUAL_PORT)]
+        virtual_port: Option<String>,
+    },
+    /// Render a MIDI file through a kit to a WAV file, offline (no audio device needed)
+    Render {
+        /// Path to kit directory
+        #[arg(short, long)]
+        kit: PathBuf,
+        /// Standard MIDI File to render (format 0 or 1)
+        #[arg(short, long)]
+        midi: PathBuf,
+        /// Output WAV file (32-bit float)
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Output sample rate in Hz. Defaults to the kit's native rate.
+        #[arg(long)]
+        sample_rate: Option<u32>,
     },
 }
 
@@ -92,6 +155,17 @@ fn main() -> Result<()> {
         Commands::TestTrigger { file, note, port, device } => {
             commands::cmd_test_trigger(file, note, port, device)
         }
-        Commands::Play { kit, port, device, kits_dirs } => play::cmd_play(kit, port, device, kits_dirs),
+        Commands::Play { kit, port, device, kits_dirs, record_midi, tempo, ppq, headless, log_file, control_socket, osc, virtual_port } => {
+            let capture = midifile::CaptureOptions { start: record_midi, bpm: tempo, ppq };
+            let headless = headless.then_some(headless::HeadlessOptions { log_file });
+            let control_socket = control_socket.map(|path| {
+                if path.as_os_str().is_empty() { control::default_socket_path() } else { path }
+            });
+            let remote = play::RemoteOptions { control_socket, osc, virtual_port };
+            play::cmd_play(kit, port, device, kits_dirs, capture, headless, remote)
+        }
+        Commands::Render { kit, midi, out, sample_rate } => {
+            render::cmd_render(kit, midi, out, sample_rate)
+        }
     }
 }
@@ -11,6 +11,63 @@ pub enum MappingSource {
     KitFile(PathBuf),
 }
 
+fn default_hihat_controller() -> u8 {
+    4
+}
+
+/// A range of hi-hat pedal CC values that plays a given sample note.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OpennessZone {
+    pub min: u8,
+    pub max: u8,
+    /// Kit sample note to play while the pedal is in this zone.
+    pub note: u8,
+}
+
+/// Continuous hi-hat: pick the sample for hi-hat strikes from the pedal position.
+///
+/// Pedal CC values grow as the pedal closes (0 = fully open, 127 = fully closed).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HiHatConfig {
+    /// Pedal controller number (CC 4 on most modules).
+    #[serde(default = "default_hihat_controller")]
+    pub controller: u8,
+    /// Incoming MIDI notes (bow, edge) whose sample follows the pedal.
+    pub notes: Vec<u8>,
+    pub zones: Vec<OpennessZone>,
+}
+
+impl HiHatConfig {
+    /// Whether an incoming note is a hi-hat strike whose sample follows the pedal.
+    pub fn follows_pedal(&self, note: u8) -> bool {
+        self.notes.contains(&note)
+    }
+
+    /// Find the zone containing a pedal CC value.
+    pub fn zone_for(&self, value: u8) -> Option<&OpennessZone> {
+        self.zones.iter().find(|z| (z.min..=z.max).contains(&value))
+    }
+
+    /// Sample notes to choke when the pedal moves from `prev` to `value`.
+    ///
+    /// Closing the pedal into a new zone chokes every more-open zone, so ringing
+    /// open and half-open voices are cut off. Opening the pedal chokes nothing.
+    pub fn chokes_on_move(&self, prev: u8, value: u8) -> impl Iterator<Item = u8> + '_ {
+        let entered = if value > prev {
+            self.zone_for(value)
+                .filter(|z| self.zone_for(prev).is_none_or(|p| p.min < z.min))
+        } else {
+            None
+        };
+        entered.into_iter().flat_map(move |new| {
+            self.zones
+                .iter()
+                .filter(move |z| z.max < new.min && z.note != new.note)
+                .map(|z| z.note)
+        })
+    }
+}
+
 /// Raw TOML schema — keys are strings because TOML only supports string keys.
 #[derive(Deserialize, Serialize)]
 struct MappingFile {
@@ -23,6 +80,9 @@ struct MappingFile {
     /// Use this when a kit's sample layout doesn't match General MIDI.
     #[serde(default)]
     remap: HashMap<String, u8>,
+    /// Optional continuous hi-hat zones driven by the pedal controller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hihat: Option<HiHatConfig>,
 }
 
 /// A mapping from MIDI note numbers to human-readable names and choke rules.
@@ -35,6 +95,8 @@ pub struct NoteMapping {
     /// When a kit's sample layout doesn't match GM, this translates note numbers
     /// so the correct sample plays (e.g., GM note 48 "Hi-Mid Tom" → kit sample 45).
     pub remap: HashMap<u8, u8>,
+    /// Continuous hi-hat openness zones, if the mapping declares them.
+    pub hihat: Option<HiHatConfig>,
     pub source: MappingSource,
 }
 
@@ -95,6 +157,7 @@ pub fn parse_mapping(toml_str: &str, source: MappingSource) -> Result<NoteMappin
         notes,
         chokes,
         remap,
+        hihat: file.hihat,
         source,
     })
 }
@@ -121,6 +184,7 @@ pub fn serialize_mapping(mapping: &NoteMapping) -> Result<String> {
         notes,
         chokes,
         remap,
+        hihat: mapping.hihat.clone(),
     };
 
     toml::to_string_pretty(&file).context("Failed to serialize mapping")
@@ -160,6 +224,7 @@ fn fallback_mapping() -> NoteMapping {
         notes,
         chokes,
         remap: HashMap::new(),
+        hihat: None,
         source: MappingSource::BuiltIn,
     }
 }
@@ -315,6 +380,7 @@ name = "Bad"
                 .collect(),
             chokes: [(42, vec![46, 23])].into_iter().collect(),
             remap: [(48, 45)].into_iter().collect(),
+            hihat: None,
             source: MappingSource::BuiltIn,
         };
 
@@ -336,6 +402,7 @@ name = "Bad"
             notes: HashMap::new(),
             chokes: HashMap::new(),
             remap: HashMap::new(),
+            hihat: None,
             source: MappingSource::BuiltIn,
         };
         assert_eq!(m.drum_name(60), "Unknown");
@@ -371,6 +438,7 @@ name = "Bad"
             notes: [(36, "Kick".to_string())].into_iter().collect(),
             chokes: HashMap::new(),
             remap: HashMap::new(),
+            hihat: None,
             source: MappingSource::BuiltIn,
         };
 
@@ -404,4 +472,101 @@ name = "Bad"
         let dir = tempfile::tempdir().unwrap();
         assert!(load_kit_mapping(dir.path()).is_none());
     }
+
+    fn test_hihat() -> HiHatConfig {
+        HiHatConfig {
+            controller: 4,
+            notes: vec![42, 46],
+            zones: vec![
+                OpennessZone { min: 0, max: 39, note: 46 },
+                OpennessZone { min: 40, max: 89, note: 23 },
+                OpennessZone { min: 90, max: 127, note: 42 },
+            ],
+        }
+    }
+
+    #[test]
+    fn parse_hihat_zones() {
+        let toml = r#"
+name = "HH"
+
+[hihat]
+notes = [42, 46]
+zones = [
+  { min = 0, max = 39, note = 46 },
+  { min = 40, max = 89, note = 23 },
+  { min = 90, max = 127, note = 42 },
+]
+"#;
+        let m = parse_mapping(toml, MappingSource::BuiltIn).unwrap();
+        let hh = m.hihat.unwrap();
+        assert_eq!(hh.controller, 4); // default
+        assert_eq!(hh, test_hihat());
+    }
+
+    #[test]
+    fn load_kit_mapping_reads_hihat_zones() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_content = r#"
+name = "Zoned Kit"
+
+[hihat]
+notes = [42, 46]
+zones = [
+  { min = 0, max = 39, note = 46 },
+  { min = 40, max = 89, note = 23 },
+  { min = 90, max = 127, note = 42 },
+]
+"#;
+        std::fs::write(dir.path().join("mapping.toml"), toml_content).unwrap();
+
+        let m = load_kit_mapping(dir.path()).unwrap();
+        assert_eq!(m.hihat, Some(test_hihat()));
+    }
+
+    #[test]
+    fn hihat_zone_lookup() {
+        let hh = test_hihat();
+        assert_eq!(hh.zone_for(0).unwrap().note, 46);
+        assert_eq!(hh.zone_for(39).unwrap().note, 46);
+        assert_eq!(hh.zone_for(40).unwrap().note, 23);
+        assert_eq!(hh.zone_for(127).unwrap().note, 42);
+        assert!(hh.follows_pedal(46));
+        assert!(!hh.follows_pedal(44));
+    }
+
+    #[test]
+    fn hihat_closing_chokes_more_open_zones() {
+        let hh = test_hihat();
+        // Open → half-open chokes open
+        assert_eq!(hh.chokes_on_move(10, 60).collect::<Vec<_>>(), vec![46]);
+        // Open → closed chokes open and half-open
+        assert_eq!(hh.chokes_on_move(10, 127).collect::<Vec<_>>(), vec![46, 23]);
+        // Half-open → closed
+        assert_eq!(hh.chokes_on_move(50, 100).collect::<Vec<_>>(), vec![46, 23]);
+    }
+
+    #[test]
+    fn hihat_moves_within_zone_or_opening_choke_nothing() {
+        let hh = test_hihat();
+        assert_eq!(hh.chokes_on_move(10, 30).count(), 0);
+        assert_eq!(hh.chokes_on_move(127, 0).count(), 0);
+        assert_eq!(hh.chokes_on_move(60, 50).count(), 0);
+    }
+
+    #[test]
+    fn hihat_serialize_roundtrip() {
+        let mut m = default_mapping();
+        m.hihat = Some(test_hihat());
+        let parsed = parse_mapping(&serialize_mapping(&m).unwrap(), MappingSource::BuiltIn).unwrap();
+        assert_eq!(parsed.hihat, Some(test_hihat()));
+    }
+
+    #[test]
+    fn alesis_mapping_declares_hihat_zones() {
+        let alesis = &builtin_mappings()[1];
+        let hh = alesis.hihat.as_ref().unwrap();
+        assert!(hh.follows_pedal(46));
+        assert_eq!(hh.zone_for(127).unwrap().note, 42);
+    }
 }
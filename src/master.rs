@@ -0,0 +1,395 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::AudioCommand;
+
+/// Lowest output gain.
+pub const MIN_GAIN_DB: f32 = -24.0;
+
+/// Highest output gain.
+pub const MAX_GAIN_DB: f32 = 12.0;
+
+/// Width of the limiter's soft knee, centred on the ceiling.
+const KNEE_DB: f32 = 6.0;
+
+/// Time constant of the RMS meter.
+const RMS_SECS: f32 = 0.3;
+
+/// Shown levels fall by this factor per update (about 40 dB/s at the TUI's 30 Hz).
+const PEAK_FALL: f32 = 0.85;
+
+/// Bottom of the meter.
+pub const FLOOR_DB: f32 = -60.0;
+
+/// The master stage, applied to the summed mix: output gain, then a soft-knee
+/// limiter. Persisted under `[master]` in settings.toml.
+///
+/// ```toml
+/// [master]
+/// gain_db = -3.0      # output gain, -24 to +12
+/// limiter = true
+/// ceiling_db = -0.3   # the limiter never lets peaks past this
+/// release_ms = 100
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MasterSettings {
+    pub gain_db: f32,
+    pub limiter: bool,
+    pub ceiling_db: f32,
+    pub release_ms: f32,
+}
+
+impl Default for MasterSettings {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.0,
+            limiter: true,
+            ceiling_db: -0.3,
+            release_ms: 100.0,
+        }
+    }
+}
+
+impl MasterSettings {
+    /// Unity gain with the limiter off: the mix passes through untouched.
+    pub const BYPASS: Self = Self {
+        gain_db: 0.0,
+        limiter: false,
+        ceiling_db: 0.0,
+        release_ms: 100.0,
+    };
+
+    /// Clamp every field to its supported range.
+    pub fn sanitized(mut self) -> Self {
+        let clamp = |value: f32, min: f32, max: f32, fallback: f32| {
+            if value.is_finite() { value.clamp(min, max) } else { fallback }
+        };
+        self.gain_db = clamp(self.gain_db, MIN_GAIN_DB, MAX_GAIN_DB, 0.0);
+        self.ceiling_db = clamp(self.ceiling_db, -20.0, 0.0, -0.3);
+        self.release_ms = clamp(self.release_ms, 1.0, 2000.0, 100.0);
+        self
+    }
+
+    /// The output gain as a linear volume (1.0 = unity), as the control socket sees it.
+    pub fn volume(&self) -> f32 {
+        db_to_gain(self.gain_db)
+    }
+
+    /// Set the output gain from a linear volume. Silence and anything past the
+    /// gain range are clamped to its ends.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.gain_db = if volume > 0.0 { 20.0 * volume.log10() } else { MIN_GAIN_DB };
+        *self = self.sanitized();
+    }
+
+    /// e.g. "Output -3.0 dB, limiter -0.3 dB".
+    pub fn label(&self) -> String {
+        let gain = format!("Output {:+.1} dB", self.gain_db);
+        if self.limiter {
+            format!("{}, limiter {:.1} dB", gain, self.ceiling_db)
+        } else {
+            format!("{}, limiter off", gain)
+        }
+    }
+}
+
+/// Output levels published by the audio thread and read by the TUI.
+///
+/// Levels are stored as `f32` bits: non-negative floats order the same as
+/// their bits, so `fetch_max` keeps the highest since the last read.
+#[derive(Debug, Default)]
+pub struct MasterMeter {
+    peak: AtomicU32,
+    rms: AtomicU32,
+    /// Most gain reduction (dB) since the last read.
+    reduction: AtomicU32,
+    /// Audio buffers in which the output went past full scale.
+    clips: AtomicU32,
+}
+
+/// One read of the meter.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Levels {
+    /// Highest sample since the previous read (linear).
+    pub peak: f32,
+    /// Recent RMS level (linear).
+    pub rms: f32,
+    /// Most limiter gain reduction since the previous read, in dB.
+    pub reduction_db: f32,
+}
+
+impl MasterMeter {
+    fn publish(&self, levels: Levels, clipped: bool) {
+        self.peak.fetch_max(levels.peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(levels.rms.to_bits(), Ordering::Relaxed);
+        self.reduction.fetch_max(levels.reduction_db.to_bits(), Ordering::Relaxed);
+        if clipped {
+            self.clips.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Levels since the previous call.
+    pub fn take(&self) -> Levels {
+        Levels {
+            peak: f32::from_bits(self.peak.swap(0, Ordering::Relaxed)),
+            rms: f32::from_bits(self.rms.load(Ordering::Relaxed)),
+            reduction_db: f32::from_bits(self.reduction.swap(0, Ordering::Relaxed)),
+        }
+    }
+
+    pub fn clips(&self) -> u32 {
+        self.clips.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_clips(&self) {
+        self.clips.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Meter levels as shown: peaks fall back gradually instead of flickering.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeterDisplay {
+    pub levels: Levels,
+    pub clips: u32,
+}
+
+impl MeterDisplay {
+    pub fn update(&mut self, meter: &MasterMeter) {
+        let new = meter.take();
+        self.levels = Levels {
+            peak: new.peak.max(self.levels.peak * PEAK_FALL),
+            rms: new.rms,
+            reduction_db: new.reduction_db.max(self.levels.reduction_db * PEAK_FALL),
+        };
+        self.clips = meter.clips();
+    }
+}
+
+/// Linear level in dB, floored at `FLOOR_DB`.
+pub fn level_db(level: f32) -> f32 {
+    if level > 0.0 {
+        (20.0 * level.log10()).max(FLOOR_DB)
+    } else {
+        FLOOR_DB
+    }
+}
+
+/// The master settings in effect and the meter they publish to, kept across
+/// stream restarts so the clip count survives a device switch.
+#[derive(Debug, Clone, Default)]
+pub struct MasterBus {
+    pub settings: MasterSettings,
+    pub meter: Arc<MasterMeter>,
+}
+
+impl MasterBus {
+    pub fn new(settings: MasterSettings) -> Self {
+        Self {
+            settings: settings.sanitized(),
+            meter: Arc::default(),
+        }
+    }
+
+    /// Put the master stage into effect on the stream behind `producer`.
+    pub fn send(&self, producer: &Mutex<Option<rtrb::Producer<AudioCommand>>>) {
+        if let Some(prod) = producer.lock().unwrap().as_mut() {
+            let _ = prod.push(AudioCommand::SetMaster {
+                settings: self.settings,
+                meter: Arc::clone(&self.meter),
+            });
+        }
+    }
+}
+
+/// The master stage on the audio thread. Starts in bypass, without a meter.
+pub struct MasterStage {
+    sample_rate: u32,
+    settings: MasterSettings,
+    gain: f32,
+    /// Level where the knee starts; quieter signals are left alone.
+    knee_start: f32,
+    /// Per-frame decay of the limiter envelope.
+    release: f32,
+    /// Peak envelope: instant attack, so a frame is never louder than the ceiling.
+    envelope: f32,
+    rms_coeff: f32,
+    mean_square: f32,
+    meter: Option<Arc<MasterMeter>>,
+}
+
+impl MasterStage {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut stage = Self {
+            sample_rate: sample_rate.max(1),
+            settings: MasterSettings::BYPASS,
+            gain: 1.0,
+            knee_start: 1.0,
+            release: 0.0,
+            envelope: 0.0,
+            rms_coeff: (-1.0 / (RMS_SECS * sample_rate.max(1) as f32)).exp(),
+            mean_square: 0.0,
+            meter: None,
+        };
+        stage.set(MasterSettings::BYPASS, None);
+        stage
+    }
+
+    pub fn set(&mut self, settings: MasterSettings, meter: Option<Arc<MasterMeter>>) {
+        let settings = settings.sanitized();
+        self.settings = settings;
+        self.gain = db_to_gain(settings.gain_db);
+        self.knee_start = db_to_gain(settings.ceiling_db - KNEE_DB / 2.0);
+        self.release = (-1.0 / (settings.release_ms / 1000.0 * self.sample_rate as f32)).exp();
+        if meter.is_some() {
+            self.meter = meter;
+        }
+    }
+
+    /// Gain that brings a peak envelope of `envelope` under the ceiling: none
+    /// below the knee, easing in across it, and pinned to the ceiling above.
+    fn limit_gain(&self, envelope: f32) -> f32 {
+        let input_db = 20.0 * envelope.log10();
+        let over = input_db - self.settings.ceiling_db;
+        let output_db = if over >= KNEE_DB / 2.0 {
+            self.settings.ceiling_db
+        } else {
+            input_db - (over + KNEE_DB / 2.0).powi(2) / (2.0 * KNEE_DB)
+        };
+        db_to_gain(output_db - input_db)
+    }
+
+    /// Apply the stage to an interleaved buffer in place and publish its levels.
+    pub fn process(&mut self, output: &mut [f32], channels: usize) {
+        let mut peak = 0.0_f32;
+        let mut reduction = 1.0_f32;
+        for frame in output.chunks_mut(channels.max(1)) {
+            if self.gain != 1.0 {
+                for sample in frame.iter_mut() {
+                    *sample *= self.gain;
+                }
+            }
+            if self.settings.limiter {
+                // Linked across channels so the stereo image doesn't shift
+                let level = frame.iter().fold(0.0_f32, |m, s| m.max(s.abs()));
+                self.envelope = level.max(self.envelope * self.release);
+                if self.envelope > self.knee_start {
+                    let gain = self.limit_gain(self.envelope);
+                    reduction = reduction.min(gain);
+                    for sample in frame.iter_mut() {
+                        *sample *= gain;
+                    }
+                }
+            }
+            if self.meter.is_some() {
+                let mut square = 0.0;
+                for &sample in frame.iter() {
+                    peak = peak.max(sample.abs());
+                    square += sample * sample;
+                }
+                let square = square / frame.len() as f32;
+                self.mean_square = square + self.rms_coeff * (self.mean_square - square);
+            }
+        }
+
+        if let Some(meter) = &self.meter {
+            let levels = Levels {
+                peak,
+                rms: self.mean_square.sqrt(),
+                reduction_db: -20.0 * reduction.log10(),
+            };
+            meter.publish(levels, peak > 1.0);
+        }
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(settings: MasterSettings) -> (MasterStage, Arc<MasterMeter>) {
+        let meter = Arc::new(MasterMeter::default());
+        let mut stage = MasterStage::new(48000);
+        stage.set(settings, Some(Arc::clone(&meter)));
+        (stage, meter)
+    }
+
+    #[test]
+    fn limiter_keeps_peaks_under_the_ceiling() {
+        let (mut stage, meter) = stage(MasterSettings::default());
+        let ceiling = db_to_gain(-0.3);
+
+        // A kick, crash and snare landing together: well past full scale
+        let mut output: Vec<f32> = (0..4800).map(|i| 2.5 * (i as f32 * 0.05).sin()).collect();
+        stage.process(&mut output, 2);
+        assert!(output.iter().all(|s| s.abs() <= ceiling + 1e-6));
+
+        let levels = meter.take();
+        assert!(levels.peak <= ceiling + 1e-6 && levels.peak > 0.9);
+        assert!(levels.reduction_db > 7.0);
+        assert_eq!(meter.clips(), 0);
+        // Taking the levels resets the peak
+        assert_eq!(meter.take().peak, 0.0);
+    }
+
+    #[test]
+    fn quiet_signals_pass_untouched() {
+        let (mut stage, meter) = stage(MasterSettings::default());
+        let input: Vec<f32> = (0..480).map(|i| 0.5 * (i as f32 * 0.05).sin()).collect();
+        let mut output = input.clone();
+        stage.process(&mut output, 1);
+        assert_eq!(output, input);
+        assert_eq!(meter.take().reduction_db, 0.0);
+    }
+
+    #[test]
+    fn clips_are_counted_without_the_limiter() {
+        let settings = MasterSettings { gain_db: 6.0, limiter: false, ..MasterSettings::default() };
+        let (mut stage, meter) = stage(settings);
+
+        let mut output = vec![0.6, -0.6, 0.1, 0.1];
+        stage.process(&mut output, 2);
+        assert!((output[0] - 0.6 * db_to_gain(6.0)).abs() < 1e-6);
+        let mut quiet = vec![0.1; 4];
+        stage.process(&mut quiet, 2);
+        stage.process(&mut output, 2);
+        assert_eq!(meter.clips(), 2);
+        meter.reset_clips();
+        assert_eq!(meter.clips(), 0);
+
+        let mut display = MeterDisplay::default();
+        display.update(&meter);
+        assert!(display.levels.peak > 1.0);
+        // Peaks fall back rather than dropping straight to silence
+        display.update(&meter);
+        assert!(display.levels.peak > 0.8);
+    }
+
+    #[test]
+    fn volume_sets_the_output_gain() {
+        let mut settings = MasterSettings::default();
+        settings.set_volume(0.5);
+        assert!((settings.gain_db + 6.02).abs() < 0.01);
+        assert!((settings.volume() - 0.5).abs() < 1e-6);
+        settings.set_volume(0.0);
+        assert_eq!(settings.gain_db, MIN_GAIN_DB);
+        settings.set_volume(100.0);
+        assert_eq!(settings.gain_db, MAX_GAIN_DB);
+    }
+
+    #[test]
+    fn bypass_is_bit_exact() {
+        let mut stage = MasterStage::new(44100);
+        let input = vec![1.7, -0.2, 0.3];
+        let mut output = input.clone();
+        stage.process(&mut output, 1);
+        assert_eq!(output, input);
+        assert_eq!(level_db(0.0), FLOOR_DB);
+        assert!((level_db(0.5) + 6.02).abs() < 0.01);
+    }
+}
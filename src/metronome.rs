@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::sample;
+
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 300.0;
+pub const MAX_SUBDIVISION: u8 = 4;
+
+/// Time signatures cycled by the play-mode key, as (beats per bar, beat unit).
+pub const TIME_SIGNATURES: &[(u8, u8)] = &[(4, 4), (3, 4), (2, 4), (5, 4), (6, 8), (7, 8), (12, 8)];
+
+/// Accent levels cycled by the play-mode key, in dB above the other beats.
+pub const ACCENT_LEVELS_DB: &[f32] = &[0.0, 3.0, 6.0, 9.0, 12.0];
+
+/// Subdivision clicks sit this far below the beat clicks.
+const SUBDIVISION_DB: f32 = -6.0;
+
+/// Metronome parameters, persisted under `[metronome]` in settings.toml and sent to
+/// the audio thread whole whenever one of them changes.
+///
+/// `bpm` counts beats of the time signature's unit (eighths in 6/8).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetronomeSettings {
+    /// Never persisted — the metronome always starts off.
+    #[serde(skip)]
+    pub enabled: bool,
+    pub bpm: f64,
+    pub beats_per_bar: u8,
+    pub beat_unit: u8,
+    /// Clicks per beat (1 = beats only, 2 = eighths in 4/4, ...).
+    pub subdivision: u8,
+    /// How much louder the first beat of the bar is, in dB.
+    pub accent_db: f32,
+    /// Metronome volume, 0.0 to 1.0, independent of the kit.
+    pub volume: f32,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bpm: 120.0,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            subdivision: 1,
+            accent_db: 6.0,
+            volume: 0.5,
+        }
+    }
+}
+
+impl MetronomeSettings {
+    /// Clamp every field to its supported range.
+    pub fn sanitized(mut self) -> Self {
+        self.bpm = self.bpm.clamp(MIN_BPM, MAX_BPM);
+        self.beats_per_bar = self.beats_per_bar.clamp(1, 16);
+        self.beat_unit = self.beat_unit.clamp(1, 32);
+        self.subdivision = self.subdivision.clamp(1, MAX_SUBDIVISION);
+        self.accent_db = self.accent_db.clamp(0.0, 24.0);
+        self.volume = self.volume.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Frames between consecutive clicks (beats or subdivisions).
+    fn click_interval(&self, sample_rate: u32) -> f64 {
+        sample_rate as f64 * 60.0 / self.bpm.max(MIN_BPM) / self.subdivision.max(1) as f64
+    }
+
+    /// Advance to the next entry of `TIME_SIGNATURES`.
+    pub fn next_time_signature(&mut self) {
+        let current = (self.beats_per_bar, self.beat_unit);
+        let idx = TIME_SIGNATURES.iter().position(|&ts| ts == current);
+        let next = idx.map_or(0, |i| (i + 1) % TIME_SIGNATURES.len());
+        (self.beats_per_bar, self.beat_unit) = TIME_SIGNATURES[next];
+    }
+
+    /// Advance to the next entry of `ACCENT_LEVELS_DB`.
+    pub fn next_accent(&mut self) {
+        let next = ACCENT_LEVELS_DB
+            .iter()
+            .position(|&db| db > self.accent_db + 0.01)
+            .unwrap_or(0);
+        self.accent_db = ACCENT_LEVELS_DB[next];
+    }
+
+    /// Short display form, e.g. `120 BPM 4/4 x2`.
+    pub fn label(&self) -> String {
+        let mut label = format!("{:.0} BPM {}/{}", self.bpm, self.beats_per_bar, self.beat_unit);
+        if self.subdivision > 1 {
+            label.push_str(&format!(" x{}", self.subdivision));
+        }
+        label
+    }
+}
+
+/// Click samples at the stream's rate and channel count.
+#[derive(Clone)]
+pub struct ClickSounds {
+    /// First beat of the bar
+    pub accent: Arc<Vec<f32>>,
+    /// Other beats
+    pub beat: Arc<Vec<f32>>,
+    /// Subdivisions between beats
+    pub sub: Arc<Vec<f32>>,
+}
+
+/// Directory searched for custom click sounds: `$XDG_DATA_HOME/drumkit/metronome`
+/// (usually `~/.local/share/drumkit/metronome`).
+pub fn clicks_dir() -> PathBuf {
+    if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
+        PathBuf::from(xdg).join("drumkit/metronome")
+    } else {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(home).join(".local/share/drumkit/metronome")
+    }
+}
+
+/// A decaying sine blip, the classic electronic metronome click.
+fn synth_click(sample_rate: u32, channels: u16, freq: f32) -> Vec<f32> {
+    let ch = channels.max(1) as usize;
+    let frames = (sample_rate as f32 * 0.04) as usize;
+    let decay = sample_rate as f32 * 0.008;
+    let mut out = Vec::with_capacity(frames * ch);
+    for n in 0..frames {
+        let t = n as f32 / sample_rate as f32;
+        let s = (2.0 * std::f32::consts::PI * freq * t).sin() * (-(n as f32) / decay).exp();
+        out.extend(std::iter::repeat_n(s, ch));
+    }
+    out
+}
+
+/// Load `<stem>.<ext>` from `dir`, converted to the stream's rate and channel count.
+fn load_click(dir: &Path, stem: &str, sample_rate: u32, channels: u16) -> Option<Vec<f32>> {
+    let path = std::fs::read_dir(dir).ok()?.filter_map(|e| e.ok()).map(|e| e.path()).find(|p| {
+        p.file_stem().is_some_and(|s| s == stem)
+            && p.extension().is_some_and(crate::kit::is_audio_file)
+    })?;
+    let data = match sample::load_audio(&path) {
+        Ok(data) => data.resampled(sample_rate),
+        Err(e) => {
+            eprintln!("  Ignoring click sound {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if data.channels == channels {
+        return Some(data.samples);
+    }
+    // Take the first channel and spread it across the stream's channels
+    let src_ch = data.channels.max(1) as usize;
+    Some(
+        data.samples
+            .chunks(src_ch)
+            .flat_map(|frame| std::iter::repeat_n(frame[0], channels.max(1) as usize))
+            .collect(),
+    )
+}
+
+impl ClickSounds {
+    /// Synthesized clicks: high for the accent, lower for beats and subdivisions.
+    pub fn synth(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            accent: Arc::new(synth_click(sample_rate, channels, 1760.0)),
+            beat: Arc::new(synth_click(sample_rate, channels, 1320.0)),
+            sub: Arc::new(synth_click(sample_rate, channels, 990.0)),
+        }
+    }
+
+    /// Use `accent`, `beat` and `sub` audio files from `clicks_dir()` where present,
+    /// synthesizing the rest.
+    pub fn load(sample_rate: u32, channels: u16) -> Self {
+        let dir = clicks_dir();
+        let synth = Self::synth(sample_rate, channels);
+        let pick = |stem: &str, fallback: Arc<Vec<f32>>| {
+            load_click(&dir, stem, sample_rate, channels).map(Arc::new).unwrap_or(fallback)
+        };
+        Self {
+            accent: pick("accent", synth.accent),
+            beat: pick("beat", synth.beat),
+            sub: pick("sub", synth.sub),
+        }
+    }
+}
+
+/// The metronome voice, rendered sample-accurately inside the audio callback.
+pub struct Metronome {
+    settings: MetronomeSettings,
+    clicks: ClickSounds,
+    sample_rate: u32,
+    /// Frames until the next click (fractional, so tempo never drifts)
+    until_next: f64,
+    /// Clicks since the metronome was started
+    tick: u64,
+    /// Currently sounding click: (samples, interleaved position, gain)
+    playing: Option<(Arc<Vec<f32>>, usize, f32)>,
+}
+
+impl Metronome {
+    pub fn new(sample_rate: u32, clicks: ClickSounds) -> Self {
+        Self {
+            settings: MetronomeSettings::default(),
+            clicks,
+            sample_rate,
+            until_next: 0.0,
+            tick: 0,
+            playing: None,
+        }
+    }
+
+    pub fn set_clicks(&mut self, clicks: ClickSounds) {
+        self.playing = None;
+        self.clicks = clicks;
+    }
+
+    /// Apply new settings. Starting the metronome clicks the downbeat immediately;
+    /// tempo changes while running take effect from the next click.
+    pub fn set(&mut self, settings: MetronomeSettings) {
+        let settings = settings.sanitized();
+        if settings.enabled && !self.settings.enabled {
+            self.until_next = 0.0;
+            self.tick = 0;
+        } else if settings.subdivision != self.settings.subdivision {
+            // Keep the beat grid: restart counting at the next beat boundary
+            let per_beat = self.settings.subdivision.max(1) as u64;
+            let remaining = (per_beat - self.tick % per_beat) % per_beat;
+            self.until_next += remaining as f64 * self.settings.click_interval(self.sample_rate);
+            self.tick = (self.tick + remaining) / per_beat * settings.subdivision as u64;
+        } else {
+            self.until_next = self.until_next.min(settings.click_interval(self.sample_rate));
+        }
+        self.settings = settings;
+    }
+
+    fn start_click(&mut self) {
+        let s = &self.settings;
+        let per_beat = s.subdivision.max(1) as u64;
+        let per_bar = per_beat * s.beats_per_bar.max(1) as u64;
+        let (samples, db) = if self.tick.is_multiple_of(per_bar) {
+            (&self.clicks.accent, 0.0)
+        } else if self.tick.is_multiple_of(per_beat) {
+            (&self.clicks.beat, -s.accent_db)
+        } else {
+            (&self.clicks.sub, -s.accent_db + SUBDIVISION_DB)
+        };
+        let gain = s.volume * 10.0_f32.powf(db / 20.0);
+        self.playing = Some((Arc::clone(samples), 0, gain));
+        self.tick += 1;
+    }
+
+    /// Add the metronome into an interleaved output buffer.
+    pub fn render(&mut self, output: &mut [f32], channels: usize) {
+        let ch = channels.max(1);
+        for frame in output.chunks_mut(ch) {
+            if self.settings.enabled {
+                if self.until_next <= 0.0 {
+                    self.start_click();
+                    self.until_next += self.settings.click_interval(self.sample_rate);
+                }
+                self.until_next -= 1.0;
+            }
+
+            let Some((samples, pos, gain)) = self.playing.as_mut() else {
+                if !self.settings.enabled {
+                    return;
+                }
+                continue;
+            };
+            for (c, out) in frame.iter_mut().enumerate() {
+                if let Some(s) = samples.get(*pos + c) {
+                    *out += s * *gain;
+                }
+            }
+            *pos += ch;
+            if *pos >= samples.len() {
+                // The Arc is shared with `self.clicks`, so this never frees on the audio thread
+                self.playing = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit impulses make click onsets easy to find.
+    fn impulse_clicks() -> ClickSounds {
+        ClickSounds {
+            accent: Arc::new(vec![1.0]),
+            beat: Arc::new(vec![0.5]),
+            sub: Arc::new(vec![0.25]),
+        }
+    }
+
+    fn enabled(bpm: f64) -> MetronomeSettings {
+        MetronomeSettings {
+            enabled: true,
+            bpm,
+            accent_db: 0.0,
+            volume: 1.0,
+            ..MetronomeSettings::default()
+        }
+    }
+
+    fn onsets(output: &[f32]) -> Vec<(usize, f32)> {
+        output
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| **s != 0.0)
+            .map(|(i, s)| (i, *s))
+            .collect()
+    }
+
+    #[test]
+    fn clicks_are_sample_accurate_across_buffers() {
+        // 120 BPM at 1000 Hz: a beat every 500 frames
+        let mut m = Metronome::new(1000, impulse_clicks());
+        m.set(enabled(120.0));
+
+        let mut output = vec![0.0_f32; 2000];
+        for chunk in output.chunks_mut(64) {
+            m.render(chunk, 1);
+        }
+        assert_eq!(
+            onsets(&output),
+            vec![(0, 1.0), (500, 0.5), (1000, 0.5), (1500, 0.5)]
+        );
+    }
+
+    #[test]
+    fn accent_marks_the_bar_and_subdivisions_are_quieter() {
+        let mut m = Metronome::new(1000, impulse_clicks());
+        m.set(MetronomeSettings {
+            beats_per_bar: 3,
+            subdivision: 2,
+            accent_db: 6.0,
+            ..enabled(120.0)
+        });
+
+        let mut output = vec![0.0_f32; 1600];
+        m.render(&mut output, 1);
+        let found = onsets(&output);
+        let positions: Vec<usize> = found.iter().map(|&(i, _)| i).collect();
+        assert_eq!(positions, vec![0, 250, 500, 750, 1000, 1250, 1500]);
+
+        let beat = 0.5 * 10.0_f32.powf(-6.0 / 20.0);
+        let sub = 0.25 * 10.0_f32.powf(-12.0 / 20.0);
+        assert_eq!(found[0].1, 1.0); // downbeat
+        assert!((found[1].1 - sub).abs() < 1e-6);
+        assert!((found[2].1 - beat).abs() < 1e-6);
+        assert_eq!(found[6].1, 1.0); // next bar
+    }
+
+    #[test]
+    fn disabled_metronome_is_silent() {
+        let mut m = Metronome::new(1000, impulse_clicks());
+        let mut output = vec![0.0_f32; 1000];
+        m.render(&mut output, 2);
+        assert!(output.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn stereo_clicks_fill_every_channel() {
+        let mut m = Metronome::new(1000, ClickSounds::synth(1000, 2));
+        m.set(enabled(60.0));
+        let mut output = vec![0.0_f32; 200];
+        m.render(&mut output, 2);
+        assert!(output[2] != 0.0);
+        assert_eq!(output[2], output[3]);
+    }
+
+    #[test]
+    fn tempo_change_applies_from_next_click() {
+        let mut m = Metronome::new(1000, impulse_clicks());
+        m.set(enabled(60.0));
+        let mut output = vec![0.0_f32; 100];
+        m.render(&mut output, 1);
+        // Next click was 900 frames away; at 240 BPM it can't be more than 250
+        m.set(enabled(240.0));
+        let mut output = vec![0.0_f32; 300];
+        m.render(&mut output, 1);
+        assert_eq!(onsets(&output), vec![(250, 0.5)]);
+    }
+
+    #[test]
+    fn settings_cycle_and_sanitize() {
+        let mut s = MetronomeSettings::default();
+        s.next_time_signature();
+        assert_eq!((s.beats_per_bar, s.beat_unit), (3, 4));
+        s.beats_per_bar = 9;
+        s.next_time_signature();
+        assert_eq!((s.beats_per_bar, s.beat_unit), (4, 4));
+
+        s.accent_db = 12.0;
+        s.next_accent();
+        assert_eq!(s.accent_db, 0.0);
+        s.next_accent();
+        assert_eq!(s.accent_db, 3.0);
+
+        let wild = MetronomeSettings { bpm: 1000.0, subdivision: 0, volume: 2.0, ..s }.sanitized();
+        assert_eq!(wild.bpm, MAX_BPM);
+        assert_eq!(wild.subdivision, 1);
+        assert_eq!(wild.volume, 1.0);
+        assert_eq!(s.label(), "120 BPM 4/4");
+    }
+}
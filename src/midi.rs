@@ -1,8 +1,9 @@
 use anyhow::{Context, Result};
 use arc_swap::ArcSwap;
-use midir::MidiInput;
+use midir::{MidiInput, MidiOutput};
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
 
 /// MIDI message types relevant to drum kits
@@ -28,6 +29,11 @@ pub enum MidiMessage {
         controller: u8,
         value: u8,
     },
+    /// Sent by most modules when a kit is picked (see `kitswitch`).
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
     /// MIDI system real-time messages (clock, start, stop, etc.) — filtered from display
     SystemRealtime {
         status: u8,
@@ -79,6 +85,10 @@ impl MidiMessage {
                 controller: data[1],
                 value: data[2],
             },
+            (0xC0, 2) => MidiMessage::ProgramChange {
+                channel,
+                program: data[1],
+            },
             _ => MidiMessage::Other { data: data.to_vec() },
         }
     }
@@ -168,6 +178,10 @@ impl fmt::Display for MidiMessage {
                     name = cc_name,
                 )
             }
+            MidiMessage::ProgramChange { channel, program } => {
+                // Counted from 1, as modules show kit numbers
+                write!(f, "PROGRAM   ch:{ch:<2} prog:{prog}", ch = channel, prog = *program as u16 + 1)
+            }
             MidiMessage::SystemRealtime { status } => {
                 let name = match status {
                     0xF8 => "Clock",
@@ -213,6 +227,25 @@ pub fn list_devices() -> Result<Vec<MidiDevice>> {
     Ok(devices)
 }
 
+/// List available MIDI output ports (for `[midi_thru]`).
+pub fn list_output_devices() -> Result<Vec<MidiDevice>> {
+    let midi_out = MidiOutput::new("drumkit-list")
+        .context("Failed to create MIDI output")?;
+
+    let ports = midi_out.ports();
+    let mut devices = Vec::new();
+
+    for (i, port) in ports.iter().enumerate() {
+        let name = midi_out.port_name(port).unwrap_or_else(|_| format!("Unknown port {}", i));
+        devices.push(MidiDevice {
+            name,
+            port_index: i,
+        });
+    }
+
+    Ok(devices)
+}
+
 /// Timestamped MIDI message from the callback
 pub struct TimestampedMessage {
     pub _timestamp_us: u64,
@@ -288,79 +321,589 @@ pub fn connect(
     Ok(connection)
 }
 
-/// Build the MIDI callback closure that pushes AudioCommands via a shared producer.
+/// Hi-hat choke fade when a closing pedal silences open hi-hats, in seconds.
+pub const CHOKE_FADE_SECS: f64 = 0.068;
+
+/// Cymbal grab (poly aftertouch) choke fade, in seconds.
+pub const AFTERTOUCH_FADE_SECS: f64 = 0.085;
+
+/// Turns raw MIDI messages into sampler actions: note remapping, choke groups,
+/// hi-hat pedal zones and cymbal grabs. Shared by live play and offline rendering.
+#[derive(Clone)]
+pub struct MidiRouter {
+    /// Latest hi-hat pedal position (0 = open); the pedal usually rests open
+    hihat_value: u8,
+    /// Voice tag for the kit this router plays (see `audio::MAIN_KIT`)
+    kit: u8,
+}
+
+impl Default for MidiRouter {
+    /// A main-kit router with the pedal open. Chokes carry their fades in seconds
+    /// (`CHOKE_FADE_SECS`, `AFTERTOUCH_FADE_SECS`); the mixer converts them at its rate.
+    fn default() -> Self {
+        Self {
+            hihat_value: 0,
+            kit: crate::audio::MAIN_KIT,
+        }
+    }
+}
+
+impl MidiRouter {
+    /// The same router, tagging its voices and chokes as belonging to `kit`.
+    pub fn for_kit(self, kit: u8) -> Self {
+        Self { kit, ..self }
+    }
+
+    /// Route one raw message. Audio commands go to `send`; hits and chokes are
+    /// reported to `notify` for display. Note-on velocities go through `curves` first.
+    pub fn route(
+        &mut self,
+        data: &[u8],
+        mapping: &crate::mapping::NoteMapping,
+        kit_notes: &HashMap<u8, Arc<crate::kit::NoteGroup>>,
+        curves: &crate::velocity::VelocityCurves,
+        mut send: impl FnMut(crate::audio::AudioCommand),
+        mut notify: impl FnMut(crate::tui::TuiEvent),
+    ) {
+        if data.len() != 3 {
+            return;
+        }
+        let status = data[0] & 0xF0;
+        let note = data[1];
+        let velocity = data[2];
+
+        // Note-on with velocity > 0
+        if status == 0x90 && velocity > 0 {
+            // Below the curve's minimum the hit is ignored altogether
+            let Some(played) = curves.for_note(note).apply(velocity) else {
+                return;
+            };
+
+            // Remap note for sample lookup (e.g., GM 48 → kit sample 45)
+            let mut sample_note = mapping.remap_note(note);
+
+            // Choke targets are sample note numbers (voice tags), not MIDI
+            // input notes, so they must NOT be remapped.
+            for &target in mapping.choke_targets(note) {
+                send(crate::audio::AudioCommand::Choke {
+                    note: target,
+                    kit: self.kit,
+                    fade_secs: CHOKE_FADE_SECS,
+                });
+                notify(crate::tui::TuiEvent::Choke { note: target });
+            }
+
+            // Hi-hat strikes play the sample for the pedal's current openness zone,
+            // falling back to the regular note if the kit lacks that zone's sample.
+            if let Some(hihat) = &mapping.hihat
+                && hihat.follows_pedal(note)
+                && let Some(zone) = hihat.zone_for(self.hihat_value)
+                && kit_notes.contains_key(&zone.note)
+            {
+                sample_note = zone.note;
+            }
+
+            if let Some(group) = kit_notes.get(&sample_note) {
+                if let Some(samples) = group.select(played) {
+                    let gain = played as f32 / 127.0;
+                    send(crate::audio::AudioCommand::Trigger {
+                        samples: Arc::clone(samples),
+                        gain,
+                        note: sample_note,
+                        kit: self.kit,
+                    });
+                }
+            }
+
+            // TUI uses original note for GM name display, and the incoming velocity
+            // so the curve editor can mark it
+            notify(crate::tui::TuiEvent::Hit { note, velocity });
+        }
+
+        // Hi-hat pedal position: closing it chokes the more-open zones
+        if status == 0xB0 {
+            let (controller, value) = (data[1], data[2]);
+            if let Some(hihat) = &mapping.hihat
+                && controller == hihat.controller
+            {
+                let prev = std::mem::replace(&mut self.hihat_value, value);
+                for target in hihat.chokes_on_move(prev, value) {
+                    send(crate::audio::AudioCommand::Choke {
+                        note: target,
+                        kit: self.kit,
+                        fade_secs: CHOKE_FADE_SECS,
+                    });
+                    notify(crate::tui::TuiEvent::Choke { note: target });
+                }
+            }
+        }
+
+        // Polyphonic aftertouch (cymbal grab choke)
+        // Remap so the choke targets the correct voice tag.
+        if status == 0xA0 && velocity == 127 {
+            let choke_note = mapping.remap_note(note);
+            send(crate::audio::AudioCommand::Choke {
+                note: choke_note,
+                kit: self.kit,
+                fade_secs: AFTERTOUCH_FADE_SECS,
+            });
+            notify(crate::tui::TuiEvent::Choke { note: choke_note });
+        }
+    }
+}
+
+/// Shared playback state that every MIDI input's callback plays into.
 ///
 /// The producer is wrapped in `Arc<Mutex<Option<Producer>>>` so it can be swapped
 /// during audio device switches (set to None to silence, then replace with new producer).
+/// `midi_capture` works the same way: while it holds a capture, every message is recorded.
+#[derive(Clone)]
+pub struct MidiTargets {
+    pub producer: Arc<Mutex<Option<rtrb::Producer<crate::audio::AudioCommand>>>>,
+    pub shared_notes: Arc<ArcSwap<HashMap<u8, Arc<crate::kit::NoteGroup>>>>,
+    pub shared_mapping: Arc<ArcSwap<crate::mapping::NoteMapping>>,
+    pub routing: Arc<ArcSwap<crate::channels::ChannelRouting>>,
+    /// Main-kit velocity curves, edited live from the TUI.
+    pub velocity: Arc<ArcSwap<crate::velocity::VelocityCurves>>,
+    /// Double-trigger and crosstalk thresholds for main-kit hits.
+    pub suppression: Arc<ArcSwap<crate::suppress::Suppression>>,
+    /// Played and rejected hits per note, for the stats view.
+    pub hit_stats: Arc<crate::suppress::HitStats>,
+    /// Programs that switch the kit, sent on to the TUI as `TuiEvent::KitSwitch`.
+    pub kit_switch: Arc<crate::kitswitch::KitSwitchTable>,
+    pub tui_tx: mpsc::Sender<crate::tui::TuiEvent>,
+    pub midi_capture: Arc<Mutex<Option<crate::midifile::MidiCapture>>>,
+    /// Output that main-kit events are forwarded to, if `[midi_thru]` is set up.
+    pub thru: Option<Arc<Mutex<crate::thru::MidiThru>>>,
+}
+
+/// A port's own routing, in place of the kit's mapping and `[midi].channels`.
+#[derive(Clone, Default)]
+pub struct PortOverrides {
+    pub mapping: Option<Arc<crate::mapping::NoteMapping>>,
+    pub channels: Option<crate::channels::ChannelFilter>,
+}
+
+/// Tells the captures of different connections apart (each has its own clock).
+static NEXT_INPUT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Build the MIDI callback closure that pushes AudioCommands via the shared producer.
+///
+/// Only channels allowed by `overrides` (or the routing) play the main kit; channels
+/// with a kit of their own play it through a router tagged with the channel number.
+/// Main-kit note-ons are first checked for double triggers and crosstalk, by the
+/// port's timestamps; rejected ones aren't played or forwarded (but are captured).
 pub fn build_midi_callback(
-    producer: Arc<Mutex<Option<rtrb::Producer<crate::audio::AudioCommand>>>>,
-    shared_notes: Arc<ArcSwap<HashMap<u8, Arc<crate::kit::NoteGroup>>>>,
-    shared_mapping: Arc<ArcSwap<crate::mapping::NoteMapping>>,
-    tui_tx: mpsc::Sender<crate::tui::TuiEvent>,
-    choke_fade: usize,
-    aftertouch_fade: usize,
+    targets: MidiTargets,
+    overrides: PortOverrides,
 ) -> impl FnMut(u64, &[u8]) + Send + 'static {
-    move |_timestamp, data: &[u8]| {
-        if data.len() == 3 {
-            let status = data[0] & 0xF0;
-            let note = data[1];
-            let velocity = data[2];
+    let input = NEXT_INPUT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut router = MidiRouter::default();
+    let mut channel_routers: [MidiRouter; 16] =
+        std::array::from_fn(|i| router.clone().for_kit(i as u8 + 1));
+    // Channel kits aren't shown as pads, so they have no curves of their own
+    let linear = crate::velocity::VelocityCurves::default();
+    let mut filter = crate::suppress::HitFilter::default();
+    let mut switcher = crate::kitswitch::KitSwitcher::default();
 
-            // Lock the producer — ~50ns uncontended, negligible for drum events
-            let mut guard = producer.lock().unwrap();
-            let Some(ref mut prod) = *guard else { return };
+    move |timestamp, data: &[u8]| {
+        if let Some(capture) = targets.midi_capture.lock().unwrap().as_mut() {
+            capture.push(input, timestamp, data);
+        }
 
-            // Note-on with velocity > 0
-            if status == 0x90 && velocity > 0 {
-                let current_mapping = shared_mapping.load();
-                // Remap note for sample lookup (e.g., GM 48 → kit sample 45)
-                let sample_note = current_mapping.remap_note(note);
+        if let Some(selection) = switcher.check(data, &targets.kit_switch) {
+            let _ = targets.tui_tx.send(crate::tui::TuiEvent::KitSwitch(selection));
+            return;
+        }
 
-                // Choke targets are sample note numbers (voice tags), not MIDI
-                // input notes, so they must NOT be remapped.
-                for &target in current_mapping.choke_targets(note) {
-                    let _ = prod.push(crate::audio::AudioCommand::Choke {
-                        note: target,
-                        fade_frames: choke_fade,
-                    });
-                    let _ = tui_tx.send(crate::tui::TuiEvent::Choke { note: target });
-                }
+        if data.len() == 3 && data[0] < 0xF0 {
+            let channel = (data[0] & 0x0F) + 1;
+            let routing = targets.routing.load();
+            let kit_mapping = targets.shared_mapping.load();
+            let mapping = overrides.mapping.as_deref().unwrap_or(&kit_mapping);
+            let plays_main = routing.plays_main(channel, overrides.channels.as_ref());
 
-                let kit_notes = shared_notes.load();
-                if let Some(group) = kit_notes.get(&sample_note) {
-                    if let Some(samples) = group.select(velocity) {
-                        let gain = velocity as f32 / 127.0;
-                        let _ = prod.push(crate::audio::AudioCommand::Trigger {
-                            samples: Arc::clone(samples),
-                            gain,
-                            note: sample_note,
-                        });
-                    }
+            if plays_main && data[0] & 0xF0 == 0x90 && data[2] > 0 {
+                let verdict = filter.check(timestamp, data[1], data[2], &targets.suppression.load());
+                targets.hit_stats.record(data[1], verdict);
+                if verdict != crate::suppress::Verdict::Play {
+                    return;
                 }
+            }
 
-                // TUI uses original note for GM name display
-                let _ = tui_tx.send(crate::tui::TuiEvent::Hit { note, velocity });
+            // Forwarded even while the audio output is down
+            if plays_main && let Some(thru) = &targets.thru {
+                thru.lock().unwrap().forward(data, mapping);
             }
 
-            // Polyphonic aftertouch (cymbal grab choke)
-            // Remap so the choke targets the correct voice tag.
-            if status == 0xA0 && velocity == 127 {
-                let current_mapping = shared_mapping.load();
-                let choke_note = current_mapping.remap_note(note);
-                let _ = prod.push(crate::audio::AudioCommand::Choke {
-                    note: choke_note,
-                    fade_frames: aftertouch_fade,
-                });
-                let _ = tui_tx.send(crate::tui::TuiEvent::Choke { note: choke_note });
+            // Lock the producer — ~50ns uncontended, negligible for drum events
+            let mut guard = targets.producer.lock().unwrap();
+            let Some(ref mut prod) = *guard else { return };
+
+            if let Some(kit) = routing.kit(channel) {
+                // Pads and the hit log show the main kit, so channel kits play silently there
+                channel_routers[channel as usize - 1].route(
+                    data,
+                    &kit.mapping,
+                    &kit.notes,
+                    &linear,
+                    |cmd| {
+                        let _ = prod.push(cmd);
+                    },
+                    |_| {},
+                );
+            } else if plays_main {
+                router.route(
+                    data,
+                    mapping,
+                    &targets.shared_notes.load(),
+                    &targets.velocity.load(),
+                    |cmd| {
+                        let _ = prod.push(cmd);
+                    },
+                    |event| {
+                        let _ = targets.tui_tx.send(event);
+                    },
+                );
             }
         }
     }
 }
 
+/// A connected MIDI input. Dropping it disconnects.
+pub struct InputPort {
+    pub name: String,
+    pub settings: crate::settings::MidiInputSettings,
+    /// Created by drumkit for other software to connect to (`--virtual-port`).
+    pub virtual_port: bool,
+    _connection: midir::MidiInputConnection<()>,
+}
+
+impl InputPort {
+    /// One-line description of the port's overrides, e.g. "mapping General MIDI, ch 1",
+    /// or "virtual" for a virtual port.
+    pub fn overrides_label(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.virtual_port {
+            parts.push("virtual".to_string());
+        }
+        if let Some(mapping) = &self.settings.mapping {
+            parts.push(format!("mapping {}", mapping));
+        }
+        if let Some(channels) = &self.settings.channels {
+            parts.push(format!("ch {}", crate::channels::ChannelFilter::new(channels).label()));
+        }
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+/// Connect the port at `port_index` with `settings`' overrides (a mapping is
+/// looked up by name among the known mappings, including `extra_mapping_dirs`).
+pub fn connect_input(
+    port_index: usize,
+    settings: crate::settings::MidiInputSettings,
+    targets: &MidiTargets,
+    extra_mapping_dirs: &[std::path::PathBuf],
+) -> Result<InputPort> {
+    let mapping = match &settings.mapping {
+        Some(name) => Some(Arc::new(crate::control::resolve_mapping(name, extra_mapping_dirs)?)),
+        None => None,
+    };
+    let overrides = PortOverrides {
+        mapping,
+        channels: settings.channels.as_deref().map(crate::channels::ChannelFilter::new),
+    };
+    let connection = connect_callback(port_index, build_midi_callback(targets.clone(), overrides))?;
+    Ok(InputPort {
+        name: settings.port.clone(),
+        settings,
+        virtual_port: false,
+        _connection: connection,
+    })
+}
+
+/// Name of the virtual port when `--virtual-port` is given without one.
+pub const DEFAULT_VIRTUAL_PORT: &str = "drumkit";
+
+/// Create a virtual MIDI input called `name` that other software (a sequencer,
+/// `aplaymidi`, a DAW) can connect to. It plays like a hardware input.
+#[cfg(unix)]
+pub fn create_virtual_input(name: &str, targets: &MidiTargets) -> Result<InputPort> {
+    use midir::os::unix::VirtualInput;
+
+    let midi_in = MidiInput::new(name).context("Failed to create MIDI input")?;
+    let mut callback = build_midi_callback(targets.clone(), PortOverrides::default());
+    let connection = midi_in
+        .create_virtual(name, move |timestamp_us, data, _| callback(timestamp_us, data), ())
+        .map_err(|e| anyhow::anyhow!("Failed to create virtual MIDI port {}: {}", name, e))?;
+    Ok(InputPort {
+        name: name.to_string(),
+        settings: crate::settings::MidiInputSettings::new(name.to_string()),
+        virtual_port: true,
+        _connection: connection,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn create_virtual_input(_name: &str, _targets: &MidiTargets) -> Result<InputPort> {
+    anyhow::bail!("Virtual MIDI ports are not supported on this platform")
+}
+
+/// Connect every input in `inputs` that is currently plugged in, by port name, plus
+/// a virtual port called `virtual_port` if given.
+/// Returns the connected ports and a log line for each one that failed; it's an
+/// error if none could be connected or the virtual port can't be created.
+pub fn connect_inputs(
+    inputs: Vec<crate::settings::MidiInputSettings>,
+    virtual_port: Option<&str>,
+    targets: &MidiTargets,
+    extra_mapping_dirs: &[std::path::PathBuf],
+) -> Result<(Vec<InputPort>, Vec<String>)> {
+    let devices = list_devices()?;
+    let mut ports = Vec::new();
+    let mut errors = Vec::new();
+    for input in inputs {
+        let Some(device) = devices.iter().find(|d| d.name == input.port) else {
+            errors.push(format!("MIDI input \"{}\" not found", input.port));
+            continue;
+        };
+        let name = input.port.clone();
+        match connect_input(device.port_index, input, targets, extra_mapping_dirs) {
+            Ok(port) => ports.push(port),
+            Err(e) => errors.push(format!("MIDI input \"{}\": {:#}", name, e)),
+        }
+    }
+    if let Some(name) = virtual_port {
+        ports.push(create_virtual_input(name, targets)?);
+    }
+    if ports.is_empty() {
+        anyhow::bail!("No MIDI input connected: {}", errors.join("; "));
+    }
+    Ok((ports, errors))
+}
+
+/// Header label for the connected inputs.
+pub fn inputs_label(ports: &[InputPort]) -> String {
+    match ports {
+        [] => "No MIDI input".to_string(),
+        [port] => port.name.clone(),
+        [first, rest @ ..] => format!("{} +{}", first.name, rest.len()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn write_test_kit(dir: &std::path::Path) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.join("38.wav"), spec).unwrap();
+        for _ in 0..32 {
+            writer.write_sample(1000_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn test_notes() -> HashMap<u8, Arc<crate::kit::NoteGroup>> {
+        let dir = tempfile::tempdir().unwrap();
+        write_test_kit(dir.path());
+        crate::kit::load_kit(dir.path(), None).unwrap().notes
+    }
+
+    fn test_targets(
+        producer: rtrb::Producer<crate::audio::AudioCommand>,
+        tui_tx: mpsc::Sender<crate::tui::TuiEvent>,
+        notes: HashMap<u8, Arc<crate::kit::NoteGroup>>,
+        routing: crate::channels::ChannelRouting,
+    ) -> MidiTargets {
+        MidiTargets {
+            producer: Arc::new(Mutex::new(Some(producer))),
+            shared_notes: Arc::new(ArcSwap::from_pointee(notes)),
+            shared_mapping: Arc::new(ArcSwap::from_pointee(crate::mapping::default_mapping())),
+            routing: Arc::new(ArcSwap::from_pointee(routing)),
+            velocity: Arc::new(ArcSwap::from_pointee(Default::default())),
+            suppression: Arc::new(ArcSwap::from_pointee(Default::default())),
+            hit_stats: Arc::new(Default::default()),
+            kit_switch: Arc::new(Default::default()),
+            tui_tx,
+            midi_capture: Arc::new(Mutex::new(None)),
+            thru: None,
+        }
+    }
+
+    #[test]
+    fn callback_only_plays_selected_channels() {
+        use crate::audio::AudioCommand;
+
+        let (producer, mut consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let targets = test_targets(producer, tui_tx, test_notes(), crate::channels::ChannelRouting::new(&[10]));
+        let mut callback = build_midi_callback(targets.clone(), PortOverrides::default());
+
+        // A keyboard on channel 1 is ignored
+        callback(0, &[0x90, 38, 100]);
+        assert!(consumer.pop().is_err());
+
+        callback(0, &[0x99, 38, 100]);
+        assert!(matches!(
+            consumer.pop(),
+            Ok(AudioCommand::Trigger { note: 38, kit: crate::audio::MAIN_KIT, .. })
+        ));
+        assert_eq!(tui_rx.try_iter().count(), 1);
+
+        // A port with its own channels and mapping (GM tom 48 → kit note 38)
+        let mut mapping = crate::mapping::default_mapping();
+        mapping.remap.insert(48, 38);
+        let mut pads = build_midi_callback(
+            targets,
+            PortOverrides {
+                mapping: Some(Arc::new(mapping)),
+                channels: Some(crate::channels::ChannelFilter::new(&[1])),
+            },
+        );
+        pads(0, &[0x99, 38, 100]);
+        assert!(consumer.pop().is_err());
+        pads(0, &[0x90, 48, 100]);
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Trigger { note: 38, .. })));
+    }
+
+    #[test]
+    fn callback_applies_velocity_curves() {
+        use crate::audio::AudioCommand;
+        use crate::velocity::{CurveShape, VelocityCurve, VelocityCurves};
+
+        let (producer, mut consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let targets = test_targets(producer, tui_tx, test_notes(), crate::channels::ChannelRouting::new(&[]));
+        let mut curves = VelocityCurves::default();
+        curves.pads.insert(
+            38,
+            VelocityCurve {
+                curve: CurveShape::Linear,
+                min: 20,
+                max: 64,
+                points: Vec::new(),
+            },
+        );
+        targets.velocity.store(Arc::new(curves));
+        let mut callback = build_midi_callback(targets, PortOverrides::default());
+
+        // Under the pad's minimum: no sound and no hit
+        callback(0, &[0x99, 38, 10]);
+        assert!(consumer.pop().is_err());
+        assert_eq!(tui_rx.try_iter().count(), 0);
+
+        // At the maximum it plays at full gain (127 after the curve); the hit reports the incoming velocity
+        callback(0, &[0x99, 38, 64]);
+        match consumer.pop() {
+            Ok(AudioCommand::Trigger { gain, .. }) => assert_eq!(gain, 1.0),
+            _ => panic!("expected a trigger"),
+        }
+        assert!(matches!(tui_rx.try_recv(), Ok(crate::tui::TuiEvent::Hit { velocity: 64, .. })));
+    }
+
+    #[test]
+    fn callback_suppresses_double_triggers_by_timestamp() {
+        let (producer, consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, _tui_rx) = mpsc::channel();
+        let targets = test_targets(producer, tui_tx, test_notes(), crate::channels::ChannelRouting::new(&[]));
+        let mut suppression = crate::suppress::Suppression::default();
+        suppression.global.retrigger_ms = 20.0;
+        targets.suppression.store(Arc::new(suppression));
+        let stats = Arc::clone(&targets.hit_stats);
+        let mut callback = build_midi_callback(targets, PortOverrides::default());
+
+        callback(1_000_000, &[0x99, 38, 100]);
+        callback(1_005_000, &[0x99, 38, 70]);
+        callback(1_030_000, &[0x99, 38, 90]);
+        // The second hit, 5 ms after the first, is dropped
+        assert_eq!(consumer.slots(), 2);
+        assert_eq!(stats.get(38).played, 2);
+        assert_eq!(stats.get(38).retriggers, 1);
+    }
+
+    #[test]
+    fn callback_sends_program_changes_as_kit_switches() {
+        let (producer, consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let mut targets = test_targets(producer, tui_tx, test_notes(), crate::channels::ChannelRouting::new(&[]));
+        let settings = crate::kitswitch::KitSwitchSettings {
+            programs: [("3".to_string(), "Jazz".to_string())].into(),
+            ..Default::default()
+        };
+        targets.kit_switch = Arc::new(settings.to_table());
+        let mut callback = build_midi_callback(targets, PortOverrides::default());
+
+        callback(0, &[0xC9, 2]);
+        // Programs without a kit are ignored
+        callback(0, &[0xC9, 3]);
+        match tui_rx.try_recv() {
+            Ok(crate::tui::TuiEvent::KitSwitch(selection)) => assert_eq!(selection.kit, "Jazz"),
+            _ => panic!("expected a kit switch"),
+        }
+        assert!(tui_rx.try_recv().is_err());
+        assert_eq!(consumer.slots(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "needs a MIDI sequencer (e.g. the ALSA snd-seq module); run with --include-ignored"]
+    fn virtual_port_plays_notes_from_other_software() {
+        use crate::audio::AudioCommand;
+
+        let output = MidiOutput::new("drumkit-test-sender").expect("a MIDI sequencer is available");
+        let (producer, mut consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let targets = test_targets(producer, tui_tx, test_notes(), crate::channels::ChannelRouting::new(&[10]));
+        let input = create_virtual_input("drumkit-virtual-test", &targets).unwrap();
+        assert!(input.virtual_port);
+        assert_eq!(input.overrides_label().as_deref(), Some("virtual"));
+
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|p| output.port_name(p).is_ok_and(|n| n.contains("drumkit-virtual-test")))
+            .expect("virtual port is listed as a MIDI destination");
+        let mut sender = output.connect(&port, "drumkit-test-out").unwrap();
+        sender.send(&[0x99, 38, 100]).unwrap();
+
+        assert!(matches!(
+            tui_rx.recv_timeout(std::time::Duration::from_secs(2)),
+            Ok(crate::tui::TuiEvent::Hit { note: 38, velocity: 100 })
+        ));
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Trigger { note: 38, .. })));
+    }
+
+    #[test]
+    fn callback_plays_channel_kits_with_their_own_voice_tag() {
+        use crate::audio::AudioCommand;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_test_kit(dir.path());
+        let settings = crate::settings::MidiSettings {
+            channels: vec![10],
+            channel_kits: [("11".to_string(), dir.path().display().to_string())].into(),
+        };
+        let (routing, errors) = crate::channels::load_routing(&settings, &[], 44100, 2);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (producer, mut consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let targets = test_targets(producer, tui_tx, HashMap::new(), routing);
+        let mut callback = build_midi_callback(targets, PortOverrides::default());
+
+        callback(0, &[0x9A, 38, 100]);
+        callback(0, &[0xAA, 38, 127]);
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Trigger { note: 38, kit: 11, .. })));
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Choke { note: 38, kit: 11, fade_secs: AFTERTOUCH_FADE_SECS })));
+        assert_eq!(tui_rx.try_iter().count(), 0);
+    }
+
     #[test]
     fn parse_note_on() {
         let msg = MidiMessage::parse(&[0x99, 38, 100]);
@@ -497,6 +1040,13 @@ mod tests {
         assert!(s.contains("Snare"));
     }
 
+    #[test]
+    fn parse_program_change() {
+        let msg = MidiMessage::parse(&[0xC9, 4]);
+        assert_eq!(msg, MidiMessage::ProgramChange { channel: 10, program: 4 });
+        assert!(format!("{}", msg).contains("prog:5"));
+    }
+
     #[test]
     fn display_choke() {
         let msg = MidiMessage::PolyAftertouch {
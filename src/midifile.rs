@@ -0,0 +1,467 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Default tempo written to captured files, in BPM.
+pub const DEFAULT_BPM: f64 = 120.0;
+
+/// Default resolution of captured files, in ticks per quarter note.
+pub const DEFAULT_PPQ: u16 = 480;
+
+/// A channel message captured from the MIDI input, timed from the start of the capture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapturedEvent {
+    pub time_us: u64,
+    pub data: [u8; 3],
+}
+
+/// How `drumkit play` should capture MIDI (from the `--record-midi`, `--tempo` and `--ppq` flags).
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureOptions {
+    /// Start capturing as soon as play mode starts.
+    pub start: bool,
+    pub bpm: f64,
+    pub ppq: u16,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            start: false,
+            bpm: DEFAULT_BPM,
+            ppq: DEFAULT_PPQ,
+        }
+    }
+}
+
+/// An in-progress capture, shared with the MIDI callback.
+///
+/// midir timestamps count from an arbitrary, per-connection origin, so the first
+/// event from each input is anchored to the wall clock and later events keep the
+/// exact spacing of their midir timestamps.
+pub struct MidiCapture {
+    started: Instant,
+    /// (input id, midir timestamp, capture time) of each input's anchoring event
+    origins: Vec<(usize, u64, u64)>,
+    events: Vec<CapturedEvent>,
+    pub bpm: f64,
+    pub ppq: u16,
+}
+
+impl MidiCapture {
+    pub fn new(bpm: f64, ppq: u16) -> Self {
+        Self {
+            started: Instant::now(),
+            origins: Vec::new(),
+            events: Vec::new(),
+            bpm,
+            ppq,
+        }
+    }
+
+    /// Record a raw message from MIDI connection `input` (see `midi::build_midi_callback`)
+    /// if it is a note-on, note-off, poly aftertouch or CC.
+    pub fn push(&mut self, input: usize, timestamp_us: u64, data: &[u8]) {
+        let &[status, d1, d2] = data else { return };
+        if !matches!(status & 0xF0, 0x80 | 0x90 | 0xA0 | 0xB0) {
+            return;
+        }
+
+        let (origin_ts, origin_time) = match self.origins.iter().find(|o| o.0 == input) {
+            Some(&(_, ts, time)) => (ts, time),
+            None => {
+                let time = self.started.elapsed().as_micros() as u64;
+                self.origins.push((input, timestamp_us, time));
+                (timestamp_us, time)
+            }
+        };
+        // Timestamps should be monotonic; clamp in case a driver disagrees
+        let time_us = origin_time + timestamp_us.saturating_sub(origin_ts);
+        let time_us = self.events.last().map_or(time_us, |e| time_us.max(e.time_us));
+
+        self.events.push(CapturedEvent {
+            time_us,
+            data: [status, d1, d2],
+        });
+    }
+
+    pub fn events(&self) -> &[CapturedEvent] {
+        &self.events
+    }
+
+    /// Write the capture as a timestamped `.mid` file in `dir`.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create recordings dir: {}", dir.display()))?;
+        let path = crate::record::unique_path(dir, "mid");
+        std::fs::write(&path, encode_smf(&self.events, self.bpm, self.ppq))
+            .with_context(|| format!("Failed to write MIDI file: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Append a MIDI variable-length quantity.
+fn write_vlq(out: &mut Vec<u8>, value: u32) {
+    let mut buf = [0u8; 5];
+    let mut i = buf.len() - 1;
+    let mut v = value;
+    buf[i] = (v & 0x7F) as u8;
+    v >>= 7;
+    while v > 0 {
+        i -= 1;
+        buf[i] = (v & 0x7F) as u8 | 0x80;
+        v >>= 7;
+    }
+    out.extend_from_slice(&buf[i..]);
+}
+
+/// Encode events as a format 0 Standard MIDI File with a tempo and 4/4 time signature.
+pub fn encode_smf(events: &[CapturedEvent], bpm: f64, ppq: u16) -> Vec<u8> {
+    let us_per_quarter = (60_000_000.0 / bpm.max(1.0)).round() as u32;
+    let ticks_per_us = ppq as f64 / us_per_quarter as f64;
+
+    let mut track = Vec::with_capacity(events.len() * 5 + 32);
+    // Tempo
+    track.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&us_per_quarter.to_be_bytes()[1..]);
+    // Time signature 4/4, 24 clocks per click, 8 32nds per quarter
+    track.extend_from_slice(&[0x00, 0xFF, 0x58, 0x04, 4, 2, 24, 8]);
+
+    let mut last_tick = 0u64;
+    for event in events {
+        let tick = (event.time_us as f64 * ticks_per_us).round() as u64;
+        let delta = tick.saturating_sub(last_tick);
+        last_tick = last_tick.max(tick);
+        write_vlq(&mut track, delta.min(0x0FFF_FFFF) as u32);
+        track.extend_from_slice(&event.data);
+    }
+    // End of track
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut out = Vec::with_capacity(track.len() + 22);
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // format 0
+    out.extend_from_slice(&1u16.to_be_bytes()); // one track
+    out.extend_from_slice(&ppq.to_be_bytes());
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend_from_slice(&track);
+    out
+}
+
+/// A channel message read from a Standard MIDI File, at its absolute time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfEvent {
+    pub time_secs: f64,
+    pub data: Vec<u8>,
+}
+
+/// Read a variable-length quantity, advancing `pos`.
+fn read_vlq(bytes: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let b = *bytes.get(*pos).context("Truncated variable-length value")?;
+        *pos += 1;
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("Variable-length value longer than 4 bytes")
+}
+
+/// A track event before tempo mapping: tempo changes and channel messages.
+enum TrackEvent {
+    Tempo(u32),
+    Channel(Vec<u8>),
+}
+
+/// Parse one MTrk body into (absolute tick, event) pairs, honouring running status.
+fn parse_track(track: &[u8], out: &mut Vec<(u64, TrackEvent)>) -> Result<()> {
+    let mut pos = 0;
+    let mut tick = 0u64;
+    let mut running: Option<u8> = None;
+    while pos < track.len() {
+        tick += read_vlq(track, &mut pos)? as u64;
+        let mut status = *track.get(pos).context("Truncated track event")?;
+        if status < 0x80 {
+            // Running status: reuse the previous status byte; this byte is data
+            status = running.context("Data byte without running status")?;
+        } else {
+            pos += 1;
+        }
+
+        match status {
+            0xFF => {
+                let kind = *track.get(pos).context("Truncated meta event")?;
+                pos += 1;
+                let len = read_vlq(track, &mut pos)? as usize;
+                let body = track.get(pos..pos + len).context("Truncated meta event")?;
+                pos += len;
+                if kind == 0x51 && len == 3 {
+                    let tempo = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                    out.push((tick, TrackEvent::Tempo(tempo)));
+                } else if kind == 0x2F {
+                    break;
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = read_vlq(track, &mut pos)? as usize;
+                pos += len;
+            }
+            _ => {
+                running = Some(status);
+                let data_len = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let data = track
+                    .get(pos..pos + data_len)
+                    .context("Truncated channel message")?;
+                pos += data_len;
+                let mut msg = Vec::with_capacity(data_len + 1);
+                msg.push(status);
+                msg.extend_from_slice(data);
+                out.push((tick, TrackEvent::Channel(msg)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse a Standard MIDI File (format 0 or 1) into channel messages with absolute
+/// times in seconds, applying the file's tempo map across all tracks.
+pub fn parse_smf(bytes: &[u8]) -> Result<Vec<SmfEvent>> {
+    if bytes.get(0..4) != Some(b"MThd") || bytes.len() < 14 {
+        anyhow::bail!("Not a Standard MIDI File (missing MThd header)");
+    }
+    let header_len = u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize;
+    let format = u16::from_be_bytes([bytes[8], bytes[9]]);
+    let division = u16::from_be_bytes([bytes[12], bytes[13]]);
+    if format > 1 {
+        anyhow::bail!("MIDI file format {} is not supported (only 0 and 1)", format);
+    }
+
+    // Ticks → seconds is either tempo-relative (PPQ) or fixed (SMPTE)
+    let smpte_ticks_per_sec = if division & 0x8000 != 0 {
+        let fps = -((division >> 8) as u8 as i8) as f64;
+        let fps = if fps == 29.0 { 29.97 } else { fps };
+        Some(fps * (division & 0xFF) as f64)
+    } else {
+        None
+    };
+    let ppq = (division & 0x7FFF).max(1) as f64;
+
+    let mut events = Vec::new();
+    let mut pos = 8 + header_len;
+    let mut track_index = 0usize;
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let len = u32::from_be_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body = bytes
+            .get(pos + 8..pos + 8 + len)
+            .context("Truncated MIDI track")?;
+        if id == b"MTrk" {
+            let mut track_events = Vec::new();
+            parse_track(body, &mut track_events)
+                .with_context(|| format!("Invalid MIDI track {}", track_index))?;
+            events.extend(track_events);
+            track_index += 1;
+        }
+        pos += 8 + len;
+    }
+
+    // Stable sort keeps each track's order for simultaneous events
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut us_per_quarter = 500_000.0;
+    let mut last_tick = 0u64;
+    let mut time_secs = 0.0;
+    for (tick, event) in events {
+        let delta = (tick - last_tick) as f64;
+        time_secs += match smpte_ticks_per_sec {
+            Some(rate) => delta / rate,
+            None => delta * us_per_quarter / ppq / 1_000_000.0,
+        };
+        last_tick = tick;
+        match event {
+            TrackEvent::Tempo(tempo) => us_per_quarter = tempo as f64,
+            TrackEvent::Channel(data) => out.push(SmfEvent { time_secs, data }),
+        }
+    }
+    Ok(out)
+}
+
+/// Read and parse a `.mid` file from disk.
+pub fn read_smf(path: &Path) -> Result<Vec<SmfEvent>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read MIDI file: {}", path.display()))?;
+    parse_smf(&bytes).with_context(|| format!("Invalid MIDI file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vlq_encoding() {
+        let cases: &[(u32, &[u8])] = &[
+            (0, &[0x00]),
+            (0x40, &[0x40]),
+            (0x7F, &[0x7F]),
+            (0x80, &[0x81, 0x00]),
+            (0x2000, &[0xC0, 0x00]),
+            (0x3FFF, &[0xFF, 0x7F]),
+            (0x0FFF_FFFF, &[0xFF, 0xFF, 0xFF, 0x7F]),
+        ];
+        for &(value, expected) in cases {
+            let mut out = Vec::new();
+            write_vlq(&mut out, value);
+            assert_eq!(out, expected, "value {:#x}", value);
+        }
+    }
+
+    #[test]
+    fn encode_smf_header_and_timing() {
+        // 120 BPM, 480 PPQ: one quarter = 500 ms = 480 ticks
+        let events = [
+            CapturedEvent { time_us: 0, data: [0x99, 36, 100] },
+            CapturedEvent { time_us: 500_000, data: [0x89, 36, 0] },
+            CapturedEvent { time_us: 750_000, data: [0xB9, 4, 90] },
+        ];
+        let smf = encode_smf(&events, 120.0, 480);
+
+        assert_eq!(&smf[0..4], b"MThd");
+        assert_eq!(&smf[8..14], &[0, 0, 0, 1, 0x01, 0xE0]);
+        assert_eq!(&smf[14..18], b"MTrk");
+        let track_len = u32::from_be_bytes(smf[18..22].try_into().unwrap()) as usize;
+        let track = &smf[22..];
+        assert_eq!(track.len(), track_len);
+
+        // Tempo: 500000 µs per quarter
+        assert_eq!(&track[0..7], &[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20]);
+        // Events after the 8-byte time signature
+        assert_eq!(
+            &track[15..],
+            &[
+                0x00, 0x99, 36, 100, // delta 0
+                0x83, 0x60, 0x89, 36, 0, // delta 480
+                0x81, 0x70, 0xB9, 4, 90, // delta 240
+                0x00, 0xFF, 0x2F, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn capture_filters_and_keeps_timestamp_spacing() {
+        let mut capture = MidiCapture::new(DEFAULT_BPM, DEFAULT_PPQ);
+        capture.push(0, 1_000_000, &[0x99, 38, 90]);
+        capture.push(0, 1_000_100, &[0xF8]); // clock: ignored
+        capture.push(0, 1_250_000, &[0xA9, 49, 127]);
+        capture.push(0, 1_300_000, &[0xC9, 5, 0]); // program change: ignored
+
+        let events = capture.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].time_us - events[0].time_us, 250_000);
+        assert_eq!(events[1].data, [0xA9, 49, 127]);
+    }
+
+    #[test]
+    fn capture_anchors_each_input_separately() {
+        let mut capture = MidiCapture::new(DEFAULT_BPM, DEFAULT_PPQ);
+        capture.push(0, 5_000_000, &[0x99, 36, 100]);
+        // Another port's clock starts near zero; time must not go backwards
+        capture.push(1, 10, &[0x99, 38, 100]);
+        capture.push(1, 100_010, &[0x99, 42, 100]);
+        capture.push(0, 5_200_000, &[0x99, 36, 100]);
+        let events = capture.events();
+        assert!(events[1].time_us >= events[0].time_us);
+        assert_eq!(events[2].time_us - events[1].time_us, 100_000);
+        assert!(events[3].time_us - events[0].time_us >= 200_000);
+    }
+
+    #[test]
+    fn capture_save_writes_mid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut capture = MidiCapture::new(90.0, 96);
+        capture.push(0, 0, &[0x99, 36, 100]);
+        let path = capture.save(dir.path()).unwrap();
+        assert_eq!(path.extension().unwrap(), "mid");
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[0..4], b"MThd");
+        assert_eq!(&bytes[12..14], &96u16.to_be_bytes());
+    }
+
+    #[test]
+    fn parse_smf_roundtrips_encoder() {
+        let events = [
+            CapturedEvent { time_us: 0, data: [0x99, 36, 100] },
+            CapturedEvent { time_us: 250_000, data: [0x99, 42, 80] },
+            CapturedEvent { time_us: 1_000_000, data: [0xA9, 49, 127] },
+        ];
+        let parsed = parse_smf(&encode_smf(&events, 120.0, 480)).unwrap();
+        assert_eq!(parsed.len(), 3);
+        for (p, e) in parsed.iter().zip(&events) {
+            assert!((p.time_secs - e.time_us as f64 / 1e6).abs() < 1e-3);
+            assert_eq!(p.data, e.data);
+        }
+    }
+
+    #[test]
+    fn parse_smf_format1_running_status_and_tempo_map() {
+        // Format 1, two tracks, 96 PPQ. Track 0: tempo 120 BPM, then 60 BPM at tick 96.
+        // Track 1: note at tick 0, note at 96, note at 192 (running status).
+        let mut smf = Vec::new();
+        smf.extend_from_slice(b"MThd");
+        smf.extend_from_slice(&6u32.to_be_bytes());
+        smf.extend_from_slice(&[0, 1, 0, 2, 0, 96]);
+
+        let tempo_track = [
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000 µs
+            0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // +96: 1000000 µs
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(tempo_track.len() as u32).to_be_bytes());
+        smf.extend_from_slice(&tempo_track);
+
+        let note_track = [
+            0x00, 0x99, 36, 100, //
+            0x60, 38, 90, // running status
+            0x00, 0xF0, 0x02, 0x7E, 0xF7, // sysex, skipped
+            0x60, 0x89, 38, 0, //
+            0x00, 0xC9, 0x05, // program change (one data byte)
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(note_track.len() as u32).to_be_bytes());
+        smf.extend_from_slice(&note_track);
+
+        let events = parse_smf(&smf).unwrap();
+        let summary: Vec<(f64, Vec<u8>)> =
+            events.into_iter().map(|e| (e.time_secs, e.data)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0.0, vec![0x99, 36, 100]),
+                (0.5, vec![0x99, 38, 90]),
+                (1.5, vec![0x89, 38, 0]),
+                (1.5, vec![0xC9, 5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_smf_rejects_garbage() {
+        assert!(parse_smf(b"RIFF....").is_err());
+        let mut truncated = encode_smf(
+            &[CapturedEvent { time_us: 0, data: [0x99, 36, 100] }],
+            120.0,
+            480,
+        );
+        truncated.truncate(truncated.len() - 6);
+        assert!(parse_smf(&truncated).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::AudioCommand;
+use crate::convolution::{self, ConvolutionSettings, ImpulseResponse};
+use crate::inserts::PadInserts;
+use crate::reverb::ReverbSettings;
+
+/// Lowest pad volume; anything at or below it is silent.
+pub const MIN_DB: f32 = -60.0;
+
+/// Highest pad volume.
+pub const MAX_DB: f32 = 12.0;
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+/// Volume, pan, reverb and IR sends, mute, solo and insert effects of one pad,
+/// by sample note.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PadMix {
+    /// Level in dB; `MIN_DB` and below mute.
+    #[serde(default)]
+    pub volume_db: f32,
+    /// -1.0 (left) to 1.0 (right).
+    #[serde(default)]
+    pub pan: f32,
+    /// Reverb send level, 0.0 to 1.0, after the volume.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub reverb: f32,
+    /// IR (convolution) send level, 0.0 to 1.0, after the volume.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub convolution: f32,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub mute: bool,
+    /// While any pad is soloed, only soloed pads play.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub solo: bool,
+    /// EQ, compressor and transient shaper, before the volume.
+    #[serde(default, skip_serializing_if = "PadInserts::is_default")]
+    pub inserts: PadInserts,
+}
+
+impl PadMix {
+    /// Clamp every field to its supported range.
+    pub fn sanitized(mut self) -> Self {
+        self.volume_db = if self.volume_db.is_finite() { self.volume_db.clamp(MIN_DB, MAX_DB) } else { 0.0 };
+        self.pan = if self.pan.is_finite() { self.pan.clamp(-1.0, 1.0) } else { 0.0 };
+        self.reverb = if self.reverb.is_finite() { self.reverb.clamp(0.0, 1.0) } else { 0.0 };
+        self.convolution = if self.convolution.is_finite() { self.convolution.clamp(0.0, 1.0) } else { 0.0 };
+        self.inserts = self.inserts.sanitized();
+        self
+    }
+
+    /// Linear gain of the volume alone.
+    pub fn gain(&self) -> f32 {
+        if self.volume_db <= MIN_DB {
+            0.0
+        } else {
+            10.0_f32.powf(self.volume_db / 20.0)
+        }
+    }
+
+    /// Left and right gains: constant-power pan, scaled so a centred pad plays at
+    /// its volume (hard left or right is +3 dB on that side).
+    pub fn channel_gains(&self) -> (f32, f32) {
+        // Exact, so an unpanned pad renders bit-for-bit as before
+        if self.pan == 0.0 {
+            return (self.gain(), self.gain());
+        }
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        let gain = self.gain() * std::f32::consts::SQRT_2;
+        (gain * angle.cos(), gain * angle.sin())
+    }
+
+    /// e.g. "-3.0 dB, L25, reverb 20%, IR 10%, EQ, comp, muted".
+    pub fn label(&self) -> String {
+        let mut parts = vec![volume_label(self.volume_db), pan_label(self.pan)];
+        if self.reverb > 0.0 {
+            parts.push(format!("reverb {}", send_label(self.reverb)));
+        }
+        if self.convolution > 0.0 {
+            parts.push(format!("IR {}", send_label(self.convolution)));
+        }
+        parts.extend(self.inserts.label());
+        if self.mute {
+            parts.push("muted".to_string());
+        }
+        if self.solo {
+            parts.push("solo".to_string());
+        }
+        parts.join(", ")
+    }
+}
+
+/// e.g. "-3.0 dB", or "-inf dB" at the bottom of the range.
+pub fn volume_label(db: f32) -> String {
+    if db <= MIN_DB {
+        "-inf dB".to_string()
+    } else {
+        format!("{:+.1} dB", db)
+    }
+}
+
+/// e.g. "C", "L25" or "R100".
+pub fn pan_label(pan: f32) -> String {
+    let percent = (pan * 100.0).round() as i32;
+    match percent {
+        0 => "C".to_string(),
+        p if p < 0 => format!("L{}", -p),
+        p => format!("R{}", p),
+    }
+}
+
+/// e.g. "20%", or "off" for no send.
+pub fn send_label(send: f32) -> String {
+    if send <= 0.0 {
+        "off".to_string()
+    } else {
+        format!("{:.0}%", send * 100.0)
+    }
+}
+
+/// One kit's mix, persisted as a `[[mixes]]` entry in settings.toml.
+///
+/// ```toml
+/// [[mixes]]
+/// kit = "/home/me/kits/Acoustic"
+///
+/// [mixes.reverb]
+/// size = 0.6
+///
+/// [mixes.convolution]
+/// ir = "plate.wav"
+///
+/// [mixes.pads.38]   # sample note
+/// volume_db = -3.0
+/// pan = -0.25
+/// reverb = 0.2
+/// convolution = 0.1
+///
+/// [mixes.pads.38.inserts.compressor]
+/// enabled = true
+///
+/// [mixes.pads.42]
+/// mute = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KitMixSettings {
+    pub kit: PathBuf,
+    #[serde(default)]
+    pub reverb: ReverbSettings,
+    #[serde(default)]
+    pub convolution: ConvolutionSettings,
+    /// Pads that aren't at unity. Keys are strings because TOML only supports string keys.
+    #[serde(default)]
+    pub pads: BTreeMap<String, PadMix>,
+}
+
+impl KitMixSettings {
+    /// Convert to the mixer's representation, skipping unparseable note keys.
+    pub fn to_mix(&self) -> KitMix {
+        KitMix {
+            pads: self
+                .pads
+                .iter()
+                .filter_map(|(k, v)| Some((k.parse().ok().filter(|&n: &u8| n < 128)?, v.sanitized())))
+                .collect(),
+            reverb: self.reverb.sanitized(),
+            convolution: self.convolution.clone().sanitized(),
+            impulse: None,
+        }
+    }
+}
+
+/// The mix in effect for the main kit, by sample note.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KitMix {
+    pub pads: HashMap<u8, PadMix>,
+    /// The reverb bus the pads send to.
+    pub reverb: ReverbSettings,
+    /// The IR bus the pads send to, and its response once loaded (see `load_impulse`).
+    pub convolution: ConvolutionSettings,
+    pub impulse: Option<Arc<ImpulseResponse>>,
+}
+
+impl KitMix {
+    /// The pad's mix (unity and centred unless set).
+    pub fn for_note(&self, note: u8) -> PadMix {
+        self.pads.get(&note).copied().unwrap_or_default()
+    }
+
+    /// Replace a pad's mix; a pad back at the defaults is dropped.
+    pub fn set(&mut self, note: u8, mix: PadMix) {
+        if mix == PadMix::default() {
+            self.pads.remove(&note);
+        } else {
+            self.pads.insert(note, mix);
+        }
+    }
+
+    pub fn any_solo(&self) -> bool {
+        self.pads.values().any(|pad| pad.solo)
+    }
+
+    /// Whether a pad is heard: not muted, and soloed if any pad is.
+    pub fn is_audible(&self, note: u8) -> bool {
+        let pad = self.for_note(note);
+        !pad.mute && (pad.solo || !self.any_solo())
+    }
+
+    /// Put the whole mix into effect on the stream behind `producer` (after a kit
+    /// switch or a new stream), replacing whatever it had.
+    pub fn send(&self, producer: &Mutex<Option<rtrb::Producer<AudioCommand>>>) {
+        if let Some(prod) = producer.lock().unwrap().as_mut() {
+            let _ = prod.push(AudioCommand::ResetPadMix);
+            for (&note, &mix) in &self.pads {
+                let _ = prod.push(AudioCommand::SetPadMix { note, mix });
+            }
+            let _ = prod.push(AudioCommand::SetReverb(self.reverb));
+        }
+        self.send_convolution(producer);
+    }
+
+    /// Put the IR bus's response and wet level into effect.
+    pub fn send_convolution(&self, producer: &Mutex<Option<rtrb::Producer<AudioCommand>>>) {
+        if let Some(prod) = producer.lock().unwrap().as_mut() {
+            if let Some(impulse) = &self.impulse {
+                convolution::keep_until_released(impulse);
+            }
+            let _ = prod.push(AudioCommand::SetConvolution {
+                impulse: self.impulse.clone(),
+                wet: self.convolution.wet,
+            });
+        }
+    }
+
+    /// e.g. "IR bus: plate, wet 30%", once a response is loaded.
+    pub fn impulse_label(&self) -> Option<String> {
+        let impulse = self.impulse.as_ref()?;
+        Some(format!(
+            "IR bus: {}, wet {}",
+            convolution::display_name(&impulse.path),
+            send_label(self.convolution.wet)
+        ))
+    }
+
+    /// Load the impulse response the IR bus names, for the kit at `kit` and a
+    /// stream at `sample_rate`. Keeps the one already loaded if it matches; on
+    /// error the bus is left without one.
+    pub fn load_impulse(&mut self, kit: &Path, sample_rate: u32) -> Result<()> {
+        let Some(name) = self.convolution.ir.clone() else {
+            self.impulse = None;
+            return Ok(());
+        };
+        let Some(path) = convolution::find_impulse(&name, kit) else {
+            self.impulse = None;
+            anyhow::bail!("Impulse response not found: {}", name);
+        };
+        if self.impulse.as_ref().is_some_and(|ir| ir.path == path && ir.sample_rate == sample_rate) {
+            return Ok(());
+        }
+        self.impulse = None;
+        let impulse = ImpulseResponse::load(&path, sample_rate)
+            .with_context(|| format!("Failed to load impulse response {}", name))?;
+        self.impulse = Some(Arc::new(impulse));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_is_constant_power_and_unity_when_centred() {
+        let centre = PadMix::default();
+        let (l, r) = centre.channel_gains();
+        assert!((l - 1.0).abs() < 1e-6 && (r - 1.0).abs() < 1e-6);
+
+        for pan in [-1.0, -0.5, 0.3, 1.0] {
+            let (l, r) = PadMix { pan, ..PadMix::default() }.channel_gains();
+            assert!((l * l + r * r - 2.0).abs() < 1e-5, "pan {}", pan);
+        }
+        let (l, r) = PadMix { pan: -1.0, volume_db: -6.0, ..PadMix::default() }.channel_gains();
+        assert!((l - 10.0_f32.powf(-6.0 / 20.0) * std::f32::consts::SQRT_2).abs() < 1e-6);
+        assert!(r.abs() < 1e-6);
+
+        let silent = PadMix { volume_db: MIN_DB, ..PadMix::default() };
+        assert_eq!(silent.channel_gains(), (0.0, 0.0));
+        assert!((PadMix { volume_db: -6.0, ..PadMix::default() }.gain() - 0.501).abs() < 1e-3);
+    }
+
+    #[test]
+    fn solo_silences_the_other_pads() {
+        let mut mix = KitMix::default();
+        mix.set(42, PadMix { mute: true, ..PadMix::default() });
+        assert!(mix.is_audible(38));
+        assert!(!mix.is_audible(42));
+
+        mix.set(38, PadMix { solo: true, ..PadMix::default() });
+        assert!(mix.is_audible(38));
+        assert!(!mix.is_audible(36));
+
+        // Back at the defaults, a pad has no entry
+        mix.set(38, PadMix::default());
+        assert_eq!(mix.pads.len(), 1);
+        assert!(mix.is_audible(36));
+    }
+
+    #[test]
+    fn parse_kit_mixes() {
+        let toml_str = r#"
+            [[mixes]]
+            kit = "/kits/Acoustic"
+
+            [mixes.reverb]
+            size = 0.8
+
+            [mixes.pads.38]
+            volume_db = -3.0
+            pan = -2.0
+            reverb = 0.25
+            convolution = 1.5
+
+            [mixes.pads.38.inserts.compressor]
+            enabled = true
+            ratio = 50.0
+
+            [mixes.pads.42]
+            mute = true
+
+            [mixes.pads.x]
+            solo = true
+        "#;
+        #[derive(Deserialize)]
+        struct Wrapper {
+            mixes: Vec<KitMixSettings>,
+        }
+        let mixes = toml::from_str::<Wrapper>(toml_str).unwrap().mixes;
+        assert_eq!(mixes[0].kit, PathBuf::from("/kits/Acoustic"));
+        let mix = mixes[0].to_mix();
+        assert_eq!(mix.pads.len(), 2);
+        assert_eq!(mix.for_note(38).volume_db, -3.0);
+        // Out-of-range values are clamped
+        assert_eq!(mix.for_note(38).pan, -1.0);
+        assert_eq!(mix.for_note(38).reverb, 0.25);
+        assert_eq!(mix.for_note(38).convolution, 1.0);
+        assert!(mix.for_note(38).inserts.compressor.enabled);
+        assert_eq!(mix.for_note(38).inserts.compressor.ratio, 20.0);
+        assert!(!mix.for_note(42).inserts.is_active());
+        assert_eq!(mix.reverb, ReverbSettings { size: 0.8, ..ReverbSettings::default() });
+        assert_eq!(mix.convolution, ConvolutionSettings::default());
+        assert!(mix.for_note(42).mute);
+        assert_eq!(mix.for_note(36), PadMix::default());
+    }
+}
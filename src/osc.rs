@@ -0,0 +1,529 @@
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use std::collections::HashMap;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::audio::AudioCommand;
+use crate::control::{ControlCommand, ControlRequest};
+use crate::midi::MidiRouter;
+use crate::tui::TuiEvent;
+use crate::velocity::VelocityCurves;
+use crate::{kit, mapping, mix};
+
+/// Prefix shared by every address drumkit answers to.
+const ADDRESS_PREFIX: &str = "/drumkit/";
+
+/// Velocity used by `/drumkit/trigger` without a velocity argument.
+const DEFAULT_TRIGGER_VELOCITY: u8 = 100;
+
+/// Largest datagram read (OSC packets from control surfaces are tiny).
+const MAX_PACKET: usize = 8192;
+
+/// How often the receive thread checks whether it should stop.
+const RECV_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A decoded OSC argument. Integer and float widths are widened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+}
+
+impl OscArg {
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            OscArg::Int(i) => Some(*i as f64),
+            OscArg::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+}
+
+/// A single OSC message (bundles are flattened by `decode`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+/// Read a NUL-terminated string padded to a multiple of 4 bytes.
+fn read_str(buf: &[u8], pos: &mut usize) -> Result<String> {
+    let rest = buf.get(*pos..).context("Truncated OSC string")?;
+    let len = rest
+        .iter()
+        .position(|&b| b == 0)
+        .context("Unterminated OSC string")?;
+    let s = std::str::from_utf8(&rest[..len]).context("OSC string is not UTF-8")?;
+    *pos += (len + 4) & !3;
+    Ok(s.to_string())
+}
+
+fn read_bytes<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let bytes = buf.get(*pos..*pos + len).context("Truncated OSC argument")?;
+    *pos += len;
+    Ok(bytes)
+}
+
+fn decode_message(buf: &[u8]) -> Result<OscMessage> {
+    let mut pos = 0;
+    let address = read_str(buf, &mut pos)?;
+    if !address.starts_with('/') {
+        anyhow::bail!("Invalid OSC address: {}", address);
+    }
+    // Very old senders omit the type tag string entirely
+    if pos >= buf.len() {
+        return Ok(OscMessage { address, args: Vec::new() });
+    }
+    let tags = read_str(buf, &mut pos)?;
+    let tags = tags.strip_prefix(',').context("Missing OSC type tags")?;
+
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(read_bytes(buf, &mut pos, 4)?.try_into()?) as i64),
+            'h' => OscArg::Int(i64::from_be_bytes(read_bytes(buf, &mut pos, 8)?.try_into()?)),
+            'f' => OscArg::Float(f32::from_be_bytes(read_bytes(buf, &mut pos, 4)?.try_into()?) as f64),
+            'd' => OscArg::Float(f64::from_be_bytes(read_bytes(buf, &mut pos, 8)?.try_into()?)),
+            's' | 'S' => OscArg::Str(read_str(buf, &mut pos)?),
+            'b' => {
+                let len = i32::from_be_bytes(read_bytes(buf, &mut pos, 4)?.try_into()?);
+                let len = usize::try_from(len).context("Negative OSC blob size")?;
+                let blob = read_bytes(buf, &mut pos, len)?.to_vec();
+                pos = (pos + 3) & !3;
+                OscArg::Blob(blob)
+            }
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            other => anyhow::bail!("Unsupported OSC type tag '{}'", other),
+        };
+        args.push(arg);
+    }
+    Ok(OscMessage { address, args })
+}
+
+/// Decode a UDP packet into messages, flattening (possibly nested) bundles.
+/// Bundle time tags are ignored: everything is applied on arrival.
+pub fn decode(packet: &[u8]) -> Result<Vec<OscMessage>> {
+    let mut messages = Vec::new();
+    decode_into(packet, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(packet: &[u8], out: &mut Vec<OscMessage>) -> Result<()> {
+    if !packet.starts_with(b"#bundle\0") {
+        out.push(decode_message(packet)?);
+        return Ok(());
+    }
+    let mut pos = 16; // "#bundle\0" + 8-byte time tag
+    while pos < packet.len() {
+        let size = i32::from_be_bytes(read_bytes(packet, &mut pos, 4)?.try_into()?);
+        let size = usize::try_from(size).context("Negative OSC bundle element size")?;
+        decode_into(read_bytes(packet, &mut pos, size)?, out)?;
+    }
+    Ok(())
+}
+
+/// What an incoming OSC message asks drumkit to do.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscAction {
+    /// `/drumkit/trigger <note> [velocity]`
+    Trigger { note: u8, velocity: u8 },
+    /// `/drumkit/kit <name or path>`
+    Kit(String),
+    /// `/drumkit/volume <note> <db>`
+    Volume { note: u8, db: f32 },
+    /// `/drumkit/choke <note>`
+    Choke { note: u8 },
+}
+
+fn midi_value(arg: Option<&OscArg>, what: &str) -> Result<u8> {
+    let value = arg
+        .and_then(OscArg::as_f64)
+        .with_context(|| format!("Missing or non-numeric {}", what))?
+        .round();
+    if !(0.0..=127.0).contains(&value) {
+        anyhow::bail!("{} {} out of range (0-127)", what, value);
+    }
+    Ok(value as u8)
+}
+
+impl OscAction {
+    pub fn from_message(msg: &OscMessage) -> Result<Self> {
+        let command = msg
+            .address
+            .strip_prefix(ADDRESS_PREFIX)
+            .with_context(|| format!("Unknown OSC address: {}", msg.address))?;
+        let args = &msg.args;
+        match command {
+            "trigger" => {
+                let note = midi_value(args.first(), "note")?;
+                let velocity = match args.get(1) {
+                    Some(arg) => midi_value(Some(arg), "velocity")?,
+                    None => DEFAULT_TRIGGER_VELOCITY,
+                };
+                if velocity == 0 {
+                    anyhow::bail!("velocity must be at least 1");
+                }
+                Ok(OscAction::Trigger { note, velocity })
+            }
+            "kit" => match args.first() {
+                Some(OscArg::Str(name)) => Ok(OscAction::Kit(name.clone())),
+                _ => anyhow::bail!("/drumkit/kit expects a kit name"),
+            },
+            "volume" => {
+                let note = midi_value(args.first(), "note")?;
+                let db = args
+                    .get(1)
+                    .and_then(OscArg::as_f64)
+                    .context("/drumkit/volume expects a note and a level in dB")?;
+                Ok(OscAction::Volume { note, db: (db as f32).clamp(mix::MIN_DB, mix::MAX_DB) })
+            }
+            "choke" => Ok(OscAction::Choke { note: midi_value(args.first(), "note")? }),
+            _ => anyhow::bail!("Unknown OSC address: {}", msg.address),
+        }
+    }
+}
+
+/// Parse `--osc`: a bare port binds to localhost only, otherwise `host:port`.
+pub fn parse_bind_addr(value: &str) -> Result<SocketAddr> {
+    if let Ok(port) = value.parse::<u16>() {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    value
+        .parse()
+        .with_context(|| format!("Invalid OSC address '{}' (expected PORT or HOST:PORT)", value))
+}
+
+/// Build the handler that applies OSC actions, mirroring `midi::build_midi_callback`.
+///
+/// Triggers and chokes go through the same routing as MIDI notes and feed the same
+/// shared producer; kit switches are handed to the main loop like control commands.
+pub fn build_osc_handler(
+    producer: Arc<Mutex<Option<rtrb::Producer<AudioCommand>>>>,
+    shared_notes: Arc<ArcSwap<HashMap<u8, Arc<kit::NoteGroup>>>>,
+    shared_mapping: Arc<ArcSwap<mapping::NoteMapping>>,
+    velocity: Arc<ArcSwap<VelocityCurves>>,
+    tui_tx: mpsc::Sender<TuiEvent>,
+) -> impl FnMut(OscAction) + Send + 'static {
+    let mut router = MidiRouter::default();
+
+    move |action| {
+        let data = match action {
+            OscAction::Trigger { note, velocity } => [0x99, note, velocity],
+            // A cymbal grab: choke with the aftertouch fade
+            OscAction::Choke { note } => [0xA9, note, 127],
+            OscAction::Volume { note, db } => {
+                // The pad mixer lives with the main loop, which also keeps it across streams
+                let note = shared_mapping.load().remap_note(note);
+                let _ = tui_tx.send(TuiEvent::PadVolume { note, db });
+                return;
+            }
+            OscAction::Kit(kit) => {
+                // Nobody waits for the reply; the switch shows in the TUI / log
+                let (reply, _) = mpsc::channel();
+                let command = ControlCommand::Kit { kit };
+                let _ = tui_tx.send(TuiEvent::Control(ControlRequest { command, reply }));
+                return;
+            }
+        };
+
+        let mut guard = producer.lock().unwrap();
+        let Some(ref mut prod) = *guard else { return };
+        router.route(
+            &data,
+            &shared_mapping.load(),
+            &shared_notes.load(),
+            &velocity.load(),
+            |cmd| {
+                let _ = prod.push(cmd);
+            },
+            |event| {
+                let _ = tui_tx.send(event);
+            },
+        );
+    }
+}
+
+/// Running OSC listener. Dropping it stops the receive thread.
+pub struct OscServer {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl OscServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Listen for OSC on UDP `addr`, passing each valid action to `handler`.
+/// Malformed or unknown messages are reported to the main loop as `TuiEvent::OscError`
+/// and skipped.
+pub fn listen(
+    addr: SocketAddr,
+    events: mpsc::Sender<TuiEvent>,
+    mut handler: impl FnMut(OscAction) + Send + 'static,
+) -> Result<OscServer> {
+    let socket = UdpSocket::bind(addr).with_context(|| format!("Failed to bind OSC port {}", addr))?;
+    socket.set_read_timeout(Some(RECV_TIMEOUT))?;
+    let local_addr = socket.local_addr()?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let thread_stop = Arc::clone(&stop);
+    let thread = std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_PACKET];
+        while !thread_stop.load(Ordering::Relaxed) {
+            let Ok(len) = socket.recv(&mut buf) else { continue };
+            let messages = match decode(&buf[..len]) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    let _ = events.send(TuiEvent::OscError(e.to_string()));
+                    continue;
+                }
+            };
+            for msg in &messages {
+                match OscAction::from_message(msg) {
+                    Ok(action) => handler(action),
+                    Err(e) => {
+                        let _ = events.send(TuiEvent::OscError(e.to_string()));
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(OscServer {
+        local_addr,
+        stop,
+        thread: Some(thread),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a message the way control surfaces do.
+    fn encode(address: &str, args: &[OscArg]) -> Vec<u8> {
+        fn push_str(out: &mut Vec<u8>, s: &str) {
+            out.extend_from_slice(s.as_bytes());
+            out.push(0);
+            while !out.len().is_multiple_of(4) {
+                out.push(0);
+            }
+        }
+        let mut out = Vec::new();
+        push_str(&mut out, address);
+        let mut tags = String::from(",");
+        let mut data = Vec::new();
+        for arg in args {
+            match arg {
+                OscArg::Int(i) => match i32::try_from(*i) {
+                    Ok(i) => {
+                        tags.push('i');
+                        data.extend_from_slice(&i.to_be_bytes());
+                    }
+                    Err(_) => {
+                        tags.push('h');
+                        data.extend_from_slice(&i.to_be_bytes());
+                    }
+                },
+                OscArg::Float(f) => {
+                    tags.push('f');
+                    data.extend_from_slice(&(*f as f32).to_be_bytes());
+                }
+                OscArg::Str(s) => {
+                    tags.push('s');
+                    push_str(&mut data, s);
+                }
+                OscArg::Blob(blob) => {
+                    tags.push('b');
+                    data.extend_from_slice(&(blob.len() as i32).to_be_bytes());
+                    data.extend_from_slice(blob);
+                    while !data.len().is_multiple_of(4) {
+                        data.push(0);
+                    }
+                }
+                OscArg::Bool(true) => tags.push('T'),
+                OscArg::Bool(false) => tags.push('F'),
+                OscArg::Nil => tags.push('N'),
+            }
+        }
+        push_str(&mut out, &tags);
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn decode_message_with_mixed_args() {
+        let args = vec![
+            OscArg::Int(38),
+            OscArg::Float(0.5),
+            OscArg::Str("abc".into()),
+            OscArg::Int(1 << 40),
+            OscArg::Blob(vec![1, 2, 3, 4, 5]),
+            OscArg::Bool(true),
+            OscArg::Bool(false),
+            OscArg::Nil,
+            OscArg::Int(-1),
+        ];
+        let packet = encode("/drumkit/trigger", &args);
+        let messages = decode(&packet).unwrap();
+        assert_eq!(
+            messages,
+            vec![OscMessage {
+                address: "/drumkit/trigger".into(),
+                args,
+            }]
+        );
+    }
+
+    #[test]
+    fn decode_flattens_bundles() {
+        let first = encode("/drumkit/trigger", &[OscArg::Int(36)]);
+        let second = encode("/drumkit/choke", &[OscArg::Int(49)]);
+        let mut packet = b"#bundle\0".to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]); // "immediately"
+        for msg in [&first, &second] {
+            packet.extend_from_slice(&(msg.len() as i32).to_be_bytes());
+            packet.extend_from_slice(msg);
+        }
+        let addresses: Vec<String> = decode(&packet).unwrap().into_iter().map(|m| m.address).collect();
+        assert_eq!(addresses, vec!["/drumkit/trigger", "/drumkit/choke"]);
+    }
+
+    #[test]
+    fn decode_rejects_truncated_packets() {
+        let packet = encode("/drumkit/trigger", &[OscArg::Int(38)]);
+        assert!(decode(&packet[..packet.len() - 2]).is_err());
+        assert!(decode(b"no slash\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn actions_from_messages() {
+        let action = |address: &str, args: Vec<OscArg>| {
+            OscAction::from_message(&OscMessage { address: address.into(), args })
+        };
+        assert_eq!(
+            action("/drumkit/trigger", vec![OscArg::Int(38), OscArg::Float(90.0)]).unwrap(),
+            OscAction::Trigger { note: 38, velocity: 90 }
+        );
+        assert_eq!(
+            action("/drumkit/trigger", vec![OscArg::Float(36.0)]).unwrap(),
+            OscAction::Trigger { note: 36, velocity: DEFAULT_TRIGGER_VELOCITY }
+        );
+        assert_eq!(
+            action("/drumkit/kit", vec![OscArg::Str("Rock".into())]).unwrap(),
+            OscAction::Kit("Rock".into())
+        );
+        assert_eq!(
+            action("/drumkit/volume", vec![OscArg::Int(38), OscArg::Float(-100.0)]).unwrap(),
+            OscAction::Volume { note: 38, db: mix::MIN_DB }
+        );
+        assert_eq!(action("/drumkit/choke", vec![OscArg::Int(49)]).unwrap(), OscAction::Choke { note: 49 });
+
+        assert!(action("/drumkit/trigger", vec![OscArg::Int(200)]).is_err());
+        assert!(action("/drumkit/trigger", vec![OscArg::Int(38), OscArg::Int(0)]).is_err());
+        assert!(action("/drumkit/kit", vec![OscArg::Int(1)]).is_err());
+        assert!(action("/drumkit/volume", vec![OscArg::Int(38)]).is_err());
+        assert!(action("/other/trigger", vec![OscArg::Int(38)]).is_err());
+    }
+
+    #[test]
+    fn parse_bind_addr_forms() {
+        assert_eq!(parse_bind_addr("9000").unwrap(), "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(parse_bind_addr("0.0.0.0:8000").unwrap(), "0.0.0.0:8000".parse().unwrap());
+        assert!(parse_bind_addr("nonsense").is_err());
+    }
+
+    #[test]
+    fn udp_loopback_delivers_actions() {
+        let (tx, rx) = mpsc::channel();
+        let (events_tx, events_rx) = mpsc::channel();
+        let server = listen("127.0.0.1:0".parse().unwrap(), events_tx, move |action| {
+            let _ = tx.send(action);
+        })
+        .unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .send_to(&encode("/drumkit/bogus", &[]), server.local_addr())
+            .unwrap();
+        client
+            .send_to(
+                &encode("/drumkit/volume", &[OscArg::Int(38), OscArg::Float(-6.0)]),
+                server.local_addr(),
+            )
+            .unwrap();
+
+        let action = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(action, OscAction::Volume { note: 38, db: -6.0 });
+        let error = events_rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert!(matches!(error, TuiEvent::OscError(msg) if msg.contains("/drumkit/bogus")));
+        drop(server);
+    }
+
+    #[test]
+    fn handler_feeds_the_audio_producer() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.path().join("38.wav"), spec).unwrap();
+        for _ in 0..64 {
+            writer.write_sample(1000_i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let notes = kit::load_kit(dir.path(), None).unwrap().notes;
+
+        let (producer, mut consumer) = rtrb::RingBuffer::new(16);
+        let (tui_tx, tui_rx) = mpsc::channel();
+        let mut handler = build_osc_handler(
+            Arc::new(Mutex::new(Some(producer))),
+            Arc::new(ArcSwap::from_pointee(notes)),
+            Arc::new(ArcSwap::from_pointee(mapping::default_mapping())),
+            Arc::new(ArcSwap::from_pointee(VelocityCurves::default())),
+            tui_tx,
+        );
+
+        handler(OscAction::Trigger { note: 38, velocity: 127 });
+        handler(OscAction::Choke { note: 49 });
+        handler(OscAction::Volume { note: 38, db: 0.0 });
+        handler(OscAction::Kit("Rock".into()));
+
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Trigger { note: 38, .. })));
+        assert!(matches!(consumer.pop(), Ok(AudioCommand::Choke { note: 49, fade_secs: crate::midi::AFTERTOUCH_FADE_SECS, .. })));
+        assert!(consumer.pop().is_err());
+
+        let events: Vec<TuiEvent> = tui_rx.try_iter().collect();
+        assert!(matches!(events[0], TuiEvent::Hit { note: 38, velocity: 127 }));
+        assert!(matches!(events[1], TuiEvent::Choke { note: 49 }));
+        assert!(matches!(events[2], TuiEvent::PadVolume { note: 38, db } if db == 0.0));
+        assert!(matches!(
+            &events[3],
+            TuiEvent::Control(ControlRequest { command: ControlCommand::Kit { kit }, .. }) if kit == "Rock"
+        ));
+    }
+}
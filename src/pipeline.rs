@@ -0,0 +1,217 @@
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+
+use crate::play::PlayOptions;
+use crate::{audio, channels, control, hotplug, kit, mapping, master, metronome, midi, midifile, mix, osc, settings, suppress, thru, tui};
+
+/// What an output stream is opened with, and the mix and master stage put back
+/// into effect on it. Shared by the TUI and headless modes so a stream rebuilt
+/// for any reason (a new device, a replug, a new sample rate) sounds the same.
+pub struct StreamSetup<'a> {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub polyphony: &'a audio::Polyphony,
+    pub metronome: &'a metronome::MetronomeSettings,
+    pub mix: &'a mix::KitMix,
+    pub master: &'a master::MasterBus,
+}
+
+impl StreamSetup<'_> {
+    /// Open a fresh output stream on `device_index`, hand its command queue to
+    /// `producer` and resend the mix and master stage. The producer is left
+    /// empty if the stream can't be opened.
+    pub fn open(
+        &self,
+        producer: &Mutex<Option<rtrb::Producer<audio::AudioCommand>>>,
+        device_index: Option<usize>,
+    ) -> Result<audio::OutputStream> {
+        // Take the producer first so the MIDI callback drops events meanwhile
+        *producer.lock().unwrap() = None;
+        let (new_producer, consumer) = rtrb::RingBuffer::new(128);
+        let stream = audio::run_output_stream(
+            device_index,
+            consumer,
+            self.sample_rate,
+            self.channels,
+            self.polyphony,
+            self.metronome,
+        )?;
+        *producer.lock().unwrap() = Some(new_producer);
+        self.mix.send(producer);
+        self.master.send(producer);
+        Ok(stream)
+    }
+}
+
+/// The audio and MIDI pipeline both play modes run on, as `start` built it.
+pub struct Pipeline {
+    pub stream: audio::OutputStream,
+    pub producer: Arc<Mutex<Option<rtrb::Producer<audio::AudioCommand>>>>,
+    /// What every MIDI input plays into, including the kit's notes and mapping.
+    pub targets: midi::MidiTargets,
+    pub midi_inputs: Vec<midi::InputPort>,
+    pub missing_midi: hotplug::MissingInputs,
+    pub device_watcher: hotplug::DeviceWatcher,
+    /// Name of the output device, looked up before the stream opened.
+    pub audio_device_name: Option<String>,
+    pub metronome: metronome::MetronomeSettings,
+    pub mix: mix::KitMix,
+    pub master: master::MasterBus,
+    pub control_socket: Option<control::ControlSocket>,
+    pub osc_server: Option<osc::OscServer>,
+    /// What was set up, for the mode's log.
+    pub log: Vec<String>,
+}
+
+/// Open the output stream for `kit`, connect the MIDI inputs to it and start the
+/// device watcher and remote listeners. Events for the mode's loop (hits, device
+/// changes, control requests) are sent to `events`.
+pub fn start(
+    kit_path: &Path,
+    kit: &kit::Kit,
+    port_index: Option<usize>,
+    audio_device: Option<usize>,
+    options: &PlayOptions,
+    events: &mpsc::Sender<tui::TuiEvent>,
+) -> Result<Pipeline> {
+    let shared_mapping = Arc::new(ArcSwap::from_pointee(
+        mapping::load_kit_mapping(kit_path).unwrap_or_else(mapping::default_mapping),
+    ));
+    let mut log = kit::summary_lines(kit, &shared_mapping.load());
+
+    let saved = settings::load_settings();
+    let metronome = saved.metronome.sanitized();
+    let mut mix = saved.kit_mix(kit_path);
+    let impulse_line = match mix.load_impulse(kit_path, kit.sample_rate) {
+        Ok(()) => mix.impulse_label(),
+        Err(e) => Some(format!("IR bus: {:#}", e)),
+    };
+    let master = master::MasterBus::new(saved.master);
+    // Looked up before the stream opens: a busy device may not be listed
+    let audio_device_name = audio_device.and_then(|index| {
+        audio::list_output_devices()
+            .ok()?
+            .into_iter()
+            .find(|d| d.index == index)
+            .map(|d| d.name)
+    });
+    let producer = Arc::new(Mutex::new(None));
+    let stream = StreamSetup {
+        sample_rate: kit.sample_rate,
+        channels: kit.channels,
+        polyphony: &options.polyphony,
+        metronome: &metronome,
+        mix: &mix,
+        master: &master,
+    }
+    .open(&producer, audio_device)?;
+
+    let (routing, routing_errors) =
+        channels::load_routing(&saved.midi, &options.kits_dirs, kit.sample_rate, kit.channels);
+    log.extend(routing.summary_lines());
+    log.extend(routing_errors);
+
+    let thru = match thru::MidiThru::open(&saved.midi_thru) {
+        Ok(thru) => thru,
+        Err(e) => {
+            log.push(format!("MIDI thru: {:#}", e));
+            None
+        }
+    };
+    log.extend(thru.as_ref().map(|t| t.label()));
+    let kit_switch = saved.kit_switch.to_table();
+    if !kit_switch.is_empty() {
+        log.push(kit_switch.label());
+    }
+    log.extend(impulse_line);
+    log.push(master.settings.label());
+
+    let capture = options.capture;
+    let targets = midi::MidiTargets {
+        producer: Arc::clone(&producer),
+        shared_notes: Arc::new(ArcSwap::from_pointee(kit.notes.clone())),
+        shared_mapping,
+        routing: Arc::new(ArcSwap::from_pointee(routing)),
+        velocity: Arc::new(ArcSwap::from_pointee(saved.velocity.to_curves())),
+        suppression: Arc::new(ArcSwap::from_pointee(saved.suppression.to_suppression())),
+        hit_stats: Arc::new(suppress::HitStats::default()),
+        kit_switch: Arc::new(kit_switch),
+        tui_tx: events.clone(),
+        midi_capture: Arc::new(Mutex::new(
+            capture.start.then(|| midifile::MidiCapture::new(capture.bpm, capture.ppq)),
+        )),
+        thru: thru.map(|t| Arc::new(Mutex::new(t))),
+    };
+    let wanted_inputs = match port_index {
+        Some(port_index) => {
+            let name = midi::list_devices()?
+                .into_iter()
+                .find(|d| d.port_index == port_index)
+                .map(|d| d.name)
+                .unwrap_or_else(|| format!("MIDI port {}", port_index));
+            saved.midi_inputs_with(&name)
+        }
+        None => Vec::new(),
+    };
+    let (midi_inputs, input_errors) = midi::connect_inputs(
+        wanted_inputs.clone(),
+        options.remote.virtual_port.as_deref(),
+        &targets,
+        &options.extra_mapping_dirs,
+    )?;
+    let missing_midi = hotplug::MissingInputs::new(wanted_inputs, &midi_inputs);
+    for input in &midi_inputs {
+        match input.overrides_label() {
+            Some(overrides) => log.push(format!("MIDI input: {} ({})", input.name, overrides)),
+            None => log.push(format!("MIDI input: {}", input.name)),
+        }
+    }
+    log.extend(input_errors);
+    let device_tx = events.clone();
+    let device_watcher = hotplug::DeviceWatcher::spawn(move |change| {
+        let _ = device_tx.send(tui::TuiEvent::Devices(change));
+    });
+
+    let control_socket = match &options.remote.control_socket {
+        Some(path) => Some(control::listen(path, events.clone())?),
+        None => None,
+    };
+    let osc_server = match options.remote.osc {
+        Some(addr) => Some(osc::listen(
+            addr,
+            events.clone(),
+            osc::build_osc_handler(
+                Arc::clone(&producer),
+                Arc::clone(&targets.shared_notes),
+                Arc::clone(&targets.shared_mapping),
+                Arc::clone(&targets.velocity),
+                events.clone(),
+            ),
+        )?),
+        None => None,
+    };
+    if let Some(socket) = &control_socket {
+        log.push(format!("Control socket: {}", socket.path().display()));
+    }
+    if let Some(server) = &osc_server {
+        log.push(format!("OSC: listening on udp://{}", server.local_addr()));
+    }
+
+    Ok(Pipeline {
+        stream,
+        producer,
+        targets,
+        midi_inputs,
+        missing_midi,
+        device_watcher,
+        audio_device_name,
+        metronome,
+        mix,
+        master,
+        control_socket,
+        osc_server,
+        log,
+    })
+}
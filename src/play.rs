@@ -1,13 +1,43 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use arc_swap::ArcSwap;
 use notify::Watcher;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use std::sync::{mpsc, Arc, Mutex};
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, Instant};
 
-use crate::{audio, kit, mapping, midi, settings, setup, stderr, tui};
+use crate::{audio, headless, kit, mapping, midi, midifile, pipeline, settings, setup, stderr, tui};
 
-pub fn cmd_play(kit: Option<PathBuf>, port: Option<usize>, device: Option<usize>, kits_dirs: Vec<PathBuf>) -> Result<()> {
+/// Library directories and settings carried from `cmd_play` into play mode.
+pub(crate) struct PlayOptions {
+    pub(crate) kits_dirs: Vec<PathBuf>,
+    pub(crate) extra_mapping_dirs: Vec<PathBuf>,
+    pub(crate) kit_repos: Vec<String>,
+    pub(crate) polyphony: audio::Polyphony,
+    pub(crate) capture: midifile::CaptureOptions,
+    pub(crate) remote: RemoteOptions,
+}
+
+/// Remote control listeners and ports for other software, requested on the command line.
+#[derive(Default)]
+pub(crate) struct RemoteOptions {
+    /// Where to listen for control commands (`--control-socket`), if anywhere.
+    pub(crate) control_socket: Option<PathBuf>,
+    /// UDP address for OSC messages (`--osc`), if any.
+    pub(crate) osc: Option<SocketAddr>,
+    /// Name of a virtual MIDI input to create (`--virtual-port`), if any.
+    pub(crate) virtual_port: Option<String>,
+}
+
+pub fn cmd_play(
+    kit: Option<PathBuf>,
+    port: Option<usize>,
+    device: Option<usize>,
+    kits_dirs: Vec<PathBuf>,
+    capture: midifile::CaptureOptions,
+    headless: Option<headless::HeadlessOptions>,
+    remote: RemoteOptions,
+) -> Result<()> {
     let saved = settings::load_settings();
     let mut all_kit_dirs = saved.extra_kit_dirs.clone();
     for d in &kits_dirs {
@@ -17,7 +47,33 @@ pub fn cmd_play(kit: Option<PathBuf>, port: Option<usize>, device: Option<usize>
     }
     let extra_mapping_dirs = saved.extra_mapping_dirs.clone();
 
-    if let (Some(kit_path), Some(port_idx), Some(dev_idx)) = (&kit, port, device) {
+    if let Some(headless) = headless {
+        let options = PlayOptions {
+            kits_dirs: all_kit_dirs,
+            extra_mapping_dirs,
+            kit_repos: saved.kit_repos.clone(),
+            polyphony: saved.polyphony.to_polyphony(),
+            capture,
+            remote,
+        };
+        return cmd_play_headless(kit, port, device, &saved, options, headless);
+    }
+
+    // A virtual port stands in for the hardware one, so nothing needs to be picked
+    // when the kit and audio device are known from the flags or the saved settings
+    let (kit, port, device) = if remote.virtual_port.is_some() {
+        (
+            kit.or_else(|| saved.kit_path.clone().filter(|p| p.is_dir())),
+            port.or_else(|| saved_midi_port(&saved)),
+            device.or_else(|| saved_audio_device(&saved)),
+        )
+    } else {
+        (kit, port, device)
+    };
+
+    if let (Some(kit_path), Some(dev_idx)) = (&kit, device)
+        && (port.is_some() || remote.virtual_port.is_some())
+    {
         let audio_devices = audio::list_output_devices().unwrap_or_default();
         let midi_devices = midi::list_devices().unwrap_or_default();
         let audio_name = audio_devices
@@ -26,17 +82,27 @@ pub fn cmd_play(kit: Option<PathBuf>, port: Option<usize>, device: Option<usize>
             .map(|d| d.name.clone());
         let midi_name = midi_devices
             .iter()
-            .find(|d| d.port_index == port_idx)
+            .find(|d| Some(d.port_index) == port)
             .map(|d| d.name.clone());
-        let _ = settings::save_settings(&settings::Settings {
-            kit_path: Some(kit_path.clone()),
-            audio_device: audio_name,
-            midi_device: midi_name,
-            extra_kit_dirs: all_kit_dirs.clone(),
-            extra_mapping_dirs: extra_mapping_dirs.clone(),
+        let mut s = settings::load_settings();
+        s.kit_path = Some(kit_path.clone());
+        s.audio_device = audio_name;
+        if let Some(name) = &midi_name {
+            s.set_midi_input_enabled(name, true);
+            s.midi_device = midi_name;
+        }
+        s.extra_kit_dirs = all_kit_dirs.clone();
+        s.extra_mapping_dirs = extra_mapping_dirs.clone();
+        let _ = settings::save_settings(&s);
+        let options = PlayOptions {
+            kits_dirs: all_kit_dirs,
+            extra_mapping_dirs,
             kit_repos: saved.kit_repos.clone(),
-        });
-        return cmd_play_direct(kit_path.clone(), port_idx, dev_idx, all_kit_dirs, extra_mapping_dirs, saved.kit_repos.clone());
+            polyphony: saved.polyphony.to_polyphony(),
+            capture,
+            remote,
+        };
+        return cmd_play_direct(kit_path.clone(), port, dev_idx, options);
     }
 
     match setup::run_setup(kit, device, port, &all_kit_dirs)? {
@@ -47,28 +113,87 @@ pub fn cmd_play(kit: Option<PathBuf>, port: Option<usize>, device: Option<usize>
             midi_port,
             midi_device_name,
         } => {
-            let saved = settings::load_settings();
-            let _ = settings::save_settings(&settings::Settings {
-                kit_path: Some(kit_path.clone()),
-                audio_device: Some(audio_device_name),
-                midi_device: Some(midi_device_name),
-                extra_kit_dirs: all_kit_dirs.clone(),
-                extra_mapping_dirs: extra_mapping_dirs.clone(),
-                kit_repos: saved.kit_repos.clone(),
-            });
-            cmd_play_direct(kit_path, midi_port, audio_device, all_kit_dirs, extra_mapping_dirs, saved.kit_repos)
+            let mut saved = settings::load_settings();
+            saved.kit_path = Some(kit_path.clone());
+            saved.audio_device = Some(audio_device_name);
+            saved.set_midi_input_enabled(&midi_device_name, true);
+            saved.midi_device = Some(midi_device_name);
+            saved.extra_kit_dirs = all_kit_dirs.clone();
+            saved.extra_mapping_dirs = extra_mapping_dirs.clone();
+            let _ = settings::save_settings(&saved);
+            let options = PlayOptions {
+                kits_dirs: all_kit_dirs,
+                extra_mapping_dirs,
+                kit_repos: saved.kit_repos,
+                polyphony: saved.polyphony.to_polyphony(),
+                capture,
+                remote,
+            };
+            cmd_play_direct(kit_path, Some(midi_port), audio_device, options)
         }
         setup::SetupResult::Cancelled => Ok(()),
     }
 }
 
-fn cmd_play_direct(kit_path: PathBuf, port_index: usize, audio_device: usize, kits_dirs: Vec<PathBuf>, extra_mapping_dirs: Vec<PathBuf>, kit_repos: Vec<String>) -> Result<()> {
+/// Resolve kit, MIDI port and audio device without prompting, then run headless.
+///
+/// Anything not given on the command line comes from the saved settings; the audio
+/// device falls back to the system default.
+fn cmd_play_headless(
+    kit: Option<PathBuf>,
+    port: Option<usize>,
+    device: Option<usize>,
+    saved: &settings::Settings,
+    options: PlayOptions,
+    headless: headless::HeadlessOptions,
+) -> Result<()> {
+    let kit_path = kit
+        .or_else(|| saved.kit_path.clone())
+        .context("No kit selected: pass --kit or pick one in interactive mode first")?;
+    if !kit_path.is_dir() {
+        anyhow::bail!("Kit directory not found: {}", kit_path.display());
+    }
+
+    let port_index = port.or_else(|| saved_midi_port(saved));
+    if port_index.is_none() && options.remote.virtual_port.is_none() {
+        anyhow::bail!("No MIDI input selected: pass --port or --virtual-port, or pick one in interactive mode first");
+    }
+    let audio_device = device.or_else(|| saved_audio_device(saved));
+
+    headless::run(kit_path, port_index, audio_device, options, headless)
+}
+
+/// Port index of the saved MIDI input, if it's plugged in.
+fn saved_midi_port(saved: &settings::Settings) -> Option<usize> {
+    let name = saved.midi_device.as_ref()?;
+    midi::list_devices()
+        .ok()?
+        .into_iter()
+        .find(|d| d.name == *name)
+        .map(|d| d.port_index)
+}
+
+/// Index of the saved audio output device, if it's plugged in.
+fn saved_audio_device(saved: &settings::Settings) -> Option<usize> {
+    let name = saved.audio_device.as_ref()?;
+    audio::list_output_devices()
+        .ok()?
+        .into_iter()
+        .find(|d| d.name == *name)
+        .map(|d| d.index)
+}
+
+/// Play in the TUI. `port_index` is the hardware MIDI input, which is optional with a virtual port.
+fn cmd_play_direct(kit_path: PathBuf, port_index: Option<usize>, audio_device: usize, options: PlayOptions) -> Result<()> {
     let mut terminal = tui::init_terminal()?;
     let kit_name_display = kit_path
         .file_name()
         .map(|n| n.to_string_lossy().into_owned())
         .unwrap_or_else(|| "kit".to_string());
 
+    // Run the stream at the device's native rate; samples are resampled to match
+    let device_rate = audio::preferred_sample_rate(Some(audio_device)).ok();
+
     let progress = Arc::new(std::sync::atomic::AtomicUsize::new(0));
     let total = Arc::new(std::sync::atomic::AtomicUsize::new(0));
     let load_path = kit_path.clone();
@@ -76,7 +201,7 @@ fn cmd_play_direct(kit_path: PathBuf, port_index: usize, audio_device: usize, ki
     let tot = Arc::clone(&total);
     let (load_tx, load_rx) = mpsc::channel();
     std::thread::spawn(move || {
-        let result = kit::load_kit_with_progress(&load_path, &prog, &tot);
+        let result = kit::load_kit_with_progress(&load_path, device_rate, &prog, &tot);
         let _ = load_tx.send(result);
     });
 
@@ -154,7 +279,7 @@ fn cmd_play_direct(kit_path: PathBuf, port_index: usize, audio_device: usize, ki
         }
     };
 
-    match cmd_play_direct_inner(terminal, kit_path, loaded_kit, port_index, audio_device, kits_dirs, extra_mapping_dirs, kit_repos) {
+    match cmd_play_direct_inner(terminal, kit_path, loaded_kit, port_index, audio_device, options) {
         Ok(()) => Ok(()),
         Err(e) => {
             tui::restore_terminal();
@@ -167,49 +292,49 @@ fn cmd_play_direct_inner(
     terminal: tui::Term,
     kit_path: PathBuf,
     loaded_kit: kit::Kit,
-    port_index: usize,
+    port_index: Option<usize>,
     audio_device: usize,
-    kits_dirs: Vec<PathBuf>,
-    extra_mapping_dirs: Vec<PathBuf>,
-    kit_repos: Vec<String>,
+    options: PlayOptions,
 ) -> Result<()> {
     let capture = stderr::StderrCapture::start();
 
-    let initial_mapping = mapping::load_kit_mapping(&kit_path)
-        .unwrap_or_else(mapping::default_mapping);
-    let shared_mapping = Arc::new(ArcSwap::from_pointee(initial_mapping));
-
-    let kit_summary = kit::summary_lines(&loaded_kit, &shared_mapping.load());
-
-    let choke_fade = (loaded_kit.sample_rate as f64 * 0.068) as usize;
-    let aftertouch_fade = (loaded_kit.sample_rate as f64 * 0.085) as usize;
-
-    let (producer, consumer) = rtrb::RingBuffer::new(128);
-    let shared_producer = Arc::new(Mutex::new(Some(producer)));
-
-    let stream = audio::run_output_stream(Some(audio_device), consumer, loaded_kit.sample_rate, loaded_kit.channels)?;
-
-    let midi_device_name = midi::list_devices()?
-        .into_iter()
-        .find(|d| d.port_index == port_index)
-        .map(|d| d.name)
-        .unwrap_or_else(|| format!("MIDI port {}", port_index));
-
-    let shared_notes = Arc::new(ArcSwap::from_pointee(loaded_kit.notes));
-
     let (tui_tx, tui_rx) = mpsc::channel::<tui::TuiEvent>();
-
-    let connection = midi::connect_callback(
-        port_index,
-        midi::build_midi_callback(
-            Arc::clone(&shared_producer),
-            Arc::clone(&shared_notes),
-            Arc::clone(&shared_mapping),
-            tui_tx.clone(),
-            choke_fade,
-            aftertouch_fade,
-        ),
-    )?;
+    let pipeline::Pipeline {
+        stream,
+        producer: shared_producer,
+        targets,
+        midi_inputs,
+        missing_midi,
+        device_watcher,
+        audio_device_name,
+        metronome,
+        mix,
+        master,
+        control_socket,
+        osc_server,
+        log: mut initial_log,
+    } = pipeline::start(&kit_path, &loaded_kit, port_index, Some(audio_device), &options, &tui_tx)?;
+    let audio_device_name = audio_device_name.unwrap_or_else(|| format!("Audio device {}", audio_device));
+    let PlayOptions {
+        kits_dirs,
+        extra_mapping_dirs,
+        kit_repos,
+        polyphony,
+        capture: capture_options,
+        ..
+    } = options;
+    let midi::MidiTargets {
+        shared_notes,
+        shared_mapping,
+        routing: channel_routing,
+        velocity,
+        suppression,
+        hit_stats,
+        kit_switch,
+        midi_capture,
+        thru: midi_thru,
+        ..
+    } = targets;
 
     let (watch_tx, watch_rx) = mpsc::channel();
     let mut watcher = notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
@@ -225,7 +350,11 @@ fn cmd_play_direct_inner(
     let shared_kit_path = Arc::new(ArcSwap::from_pointee(kit_path.clone()));
     let suppress_reload = Arc::new(std::sync::atomic::AtomicBool::new(false));
 
+    // Hot reloads follow the stream rate, which changes when the audio device does
+    let shared_sample_rate = Arc::new(std::sync::atomic::AtomicU32::new(stream_sample_rate));
+
     let debounce_shared_notes = Arc::clone(&shared_notes);
+    let debounce_sample_rate = Arc::clone(&shared_sample_rate);
     let debounce_shared_mapping = Arc::clone(&shared_mapping);
     let debounce_kit_path = Arc::clone(&shared_kit_path);
     let debounce_suppress = Arc::clone(&suppress_reload);
@@ -244,16 +373,10 @@ fn cmd_play_direct_inner(
                             continue;
                         }
                         let current_path = debounce_kit_path.load();
-                        match kit::load_kit(&current_path) {
+                        let rate = debounce_sample_rate.load(std::sync::atomic::Ordering::Relaxed);
+                        match kit::load_kit(&current_path, Some(rate)) {
                             Ok(new_kit) => {
-                                if new_kit.sample_rate != stream_sample_rate {
-                                    let _ = debounce_tui_tx.send(tui::TuiEvent::KitReloadError(
-                                        format!(
-                                            "sample rate mismatch (expected {} Hz, got {} Hz)",
-                                            stream_sample_rate, new_kit.sample_rate
-                                        ),
-                                    ));
-                                } else if new_kit.channels != stream_channels {
+                                if new_kit.channels != stream_channels {
                                     let _ = debounce_tui_tx.send(tui::TuiEvent::KitReloadError(
                                         format!(
                                             "channel count mismatch (expected {}, got {})",
@@ -295,44 +418,66 @@ fn cmd_play_direct_inner(
         }
     });
 
-    let mut initial_log = kit_summary;
     if let Some(ref cap) = capture {
         cap.drain_into(&mut initial_log);
     }
 
     let note_keys = kit::note_keys(&shared_notes.load());
     let current_mapping = Arc::clone(&shared_mapping.load());
-    let state = tui::AppState::new(
+    let mut state = tui::AppState::new(
         loaded_kit.name,
-        midi_device_name,
+        midi::inputs_label(&midi_inputs),
         stream_sample_rate,
         stream_channels,
         &note_keys,
         initial_log,
         current_mapping,
     );
+    if capture_options.start {
+        state.midi_capture_started = Some(Instant::now());
+    }
+    state.midi_unplugged = missing_midi.names();
 
     let resources = tui::PlayResources {
-        stream,
-        connection,
+        stream: Some(stream),
+        midi_inputs,
+        missing_midi,
+        device_watcher,
         producer: shared_producer,
         shared_notes,
         kit_path,
         shared_kit_path,
         suppress_reload,
         sample_rate: stream_sample_rate,
+        shared_sample_rate,
         channels: stream_channels,
         audio_device_index: audio_device,
-        midi_port_index: port_index,
+        audio_device_name,
         tui_tx: tui_tx.clone(),
-        choke_fade,
-        aftertouch_fade,
+        control_router: midi::MidiRouter::default(),
         watcher,
         stderr_capture: capture,
         extra_kits_dirs: kits_dirs,
         extra_mapping_dirs,
         shared_mapping,
+        channel_routing,
+        midi_thru,
+        velocity,
+        suppression,
+        hit_stats,
+        kit_switch,
+        mix,
         kit_repos,
+        polyphony,
+        recording: None,
+        recordings_dir: settings::load_settings().recordings_dir(),
+        midi_capture,
+        capture_options,
+        metronome,
+        master,
+        control_socket,
+        osc_server,
+        pending_kit_reply: None,
     };
 
     tui::run(terminal, tui_rx, state, resources)
@@ -0,0 +1,379 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds of audio the tap ring buffer holds before the writer falls behind.
+const TAP_BUFFER_SECS: usize = 2;
+
+/// How long the writer thread sleeps when the tap is empty.
+const WRITER_POLL: Duration = Duration::from_millis(10);
+
+/// Size of the canonical WAV header written by `WavWriter`.
+const WAV_HEADER_LEN: u64 = 44;
+
+/// Streaming 32-bit float WAV writer. Sizes in the header are patched on `finalize`.
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    data_bytes: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut inner: W, sample_rate: u32, channels: u16) -> Result<Self> {
+        let block_align = channels as u32 * 4;
+        let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&0u32.to_le_bytes()); // patched on finalize
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&3u16.to_le_bytes()); // WAVE_FORMAT_IEEE_FLOAT
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align).to_le_bytes());
+        header.extend_from_slice(&(block_align as u16).to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes()); // patched on finalize
+        inner.write_all(&header).context("Failed to write WAV header")?;
+        Ok(Self {
+            inner,
+            data_bytes: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        for s in samples {
+            self.inner.write_all(&s.to_le_bytes())?;
+        }
+        self.data_bytes += samples.len() as u64 * 4;
+        Ok(())
+    }
+
+    /// Total bytes written so far, header included.
+    pub fn bytes_written(&self) -> u64 {
+        WAV_HEADER_LEN + self.data_bytes
+    }
+
+    /// Patch the RIFF and data chunk sizes and flush.
+    pub fn finalize(mut self) -> Result<W> {
+        let data_len = u32::try_from(self.data_bytes).context("Recording exceeds the 4 GB WAV limit")?;
+        self.inner.seek(SeekFrom::Start(4))?;
+        self.inner.write_all(&(data_len + WAV_HEADER_LEN as u32 - 8).to_le_bytes())?;
+        self.inner.seek(SeekFrom::Start(40))?;
+        self.inner.write_all(&data_len.to_le_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Live counters shared between the writer thread and the TUI header.
+pub struct RecordingStats {
+    sample_rate: u32,
+    frames: AtomicU64,
+    bytes: AtomicU64,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RecordingStats {
+    /// Recorded audio duration.
+    pub fn elapsed(&self) -> Duration {
+        let frames = self.frames.load(Ordering::Relaxed);
+        Duration::from_secs_f64(frames as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Current file size in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Output buffers left out of the file because the writer fell behind.
+    pub fn dropped_buffers(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// The audio thread's end of a recording: copies whole output buffers into the
+/// writer's ring. A buffer that doesn't fit is dropped and counted, never split,
+/// so the channels of every frame stay aligned in the file.
+pub struct Tap {
+    producer: rtrb::Producer<f32>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Tap {
+    /// Copy one interleaved output buffer into the ring. Never blocks.
+    pub fn push_buffer(&mut self, samples: &[f32]) {
+        match self.producer.write_chunk_uninit(samples.len()) {
+            Ok(chunk) => {
+                chunk.fill_from_iter(samples.iter().copied());
+            }
+            Err(_) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// An in-progress recording. The audio thread holds the matching tap producer;
+/// dropping it (via `AudioCommand::StopRecording` or a stream rebuild) ends the file.
+pub struct Recording {
+    pub path: PathBuf,
+    pub stats: Arc<RecordingStats>,
+    writer: JoinHandle<Result<()>>,
+}
+
+impl Recording {
+    /// True once the writer has finalized the file (the tap was dropped or writing failed).
+    pub fn is_finished(&self) -> bool {
+        self.writer.is_finished()
+    }
+
+    /// Wait for the writer to drain the tap and finalize the file.
+    /// Check `stats.dropped_buffers()` afterwards for gaps.
+    pub fn finish(self) -> Result<PathBuf> {
+        self.writer
+            .join()
+            .map_err(|_| anyhow::anyhow!("Recording writer thread panicked"))??;
+        Ok(self.path)
+    }
+}
+
+/// Default recordings directory: `$XDG_DATA_HOME/drumkit/recordings`
+/// (usually `~/.local/share/drumkit/recordings`).
+pub fn default_recordings_dir() -> PathBuf {
+    if let Ok(xdg) = std::env::var("XDG_DATA_HOME") {
+        PathBuf::from(xdg).join("drumkit/recordings")
+    } else {
+        let home = std::env::var("HOME").unwrap_or_else(|_| "/tmp".to_string());
+        PathBuf::from(home).join(".local/share/drumkit/recordings")
+    }
+}
+
+/// Wall-clock timestamp for filenames, e.g. `20261016-193329` (local time on Unix, UTC elsewhere).
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    #[cfg(unix)]
+    {
+        let t = secs as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return format!(
+                "{:04}{:02}{:02}-{:02}{:02}{:02}",
+                tm.tm_year + 1900,
+                tm.tm_mon + 1,
+                tm.tm_mday,
+                tm.tm_hour,
+                tm.tm_min,
+                tm.tm_sec
+            );
+        }
+    }
+
+    // Civil-from-days (proleptic Gregorian), UTC
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Pick `drumkit-<timestamp>.<ext>` in `dir`, adding a counter if the name is taken.
+pub fn unique_path(dir: &Path, ext: &str) -> PathBuf {
+    let stamp = timestamp();
+    let mut path = dir.join(format!("drumkit-{}.{}", stamp, ext));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("drumkit-{}-{}.{}", stamp, n, ext));
+        n += 1;
+    }
+    path
+}
+
+/// Create a timestamped WAV in `dir` and spawn its writer thread.
+///
+/// Returns the recording handle and the tap to hand to the audio thread
+/// with `AudioCommand::StartRecording`.
+pub fn start(dir: &Path, sample_rate: u32, channels: u16) -> Result<(Recording, Tap)> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create recordings dir: {}", dir.display()))?;
+    let path = unique_path(dir, "wav");
+    let file = File::create(&path)
+        .with_context(|| format!("Failed to create recording: {}", path.display()))?;
+    let mut writer = WavWriter::new(BufWriter::new(file), sample_rate, channels)?;
+
+    let capacity = sample_rate as usize * channels as usize * TAP_BUFFER_SECS;
+    let (producer, mut consumer) = rtrb::RingBuffer::<f32>::new(capacity);
+
+    let dropped = Arc::new(AtomicU64::new(0));
+    let stats = Arc::new(RecordingStats {
+        sample_rate,
+        frames: AtomicU64::new(0),
+        bytes: AtomicU64::new(writer.bytes_written()),
+        dropped: Arc::clone(&dropped),
+    });
+
+    let thread_stats = Arc::clone(&stats);
+    let handle = std::thread::spawn(move || -> Result<()> {
+        let ch = channels.max(1) as u64;
+        loop {
+            // Check before draining so samples pushed just before the drop are kept
+            let abandoned = consumer.is_abandoned();
+            let available = consumer.slots();
+            if available > 0 {
+                let chunk = consumer
+                    .read_chunk(available)
+                    .map_err(|e| anyhow::anyhow!("Recording tap error: {}", e))?;
+                let (first, second) = chunk.as_slices();
+                writer.write_samples(first)?;
+                writer.write_samples(second)?;
+                chunk.commit_all();
+                thread_stats
+                    .frames
+                    .store((writer.bytes_written() - WAV_HEADER_LEN) / 4 / ch, Ordering::Relaxed);
+                thread_stats.bytes.store(writer.bytes_written(), Ordering::Relaxed);
+            }
+            if abandoned {
+                break;
+            }
+            if available == 0 {
+                std::thread::sleep(WRITER_POLL);
+            }
+        }
+        writer.finalize()?;
+        Ok(())
+    });
+
+    Ok((
+        Recording {
+            path,
+            stats,
+            writer: handle,
+        },
+        Tap { producer, dropped },
+    ))
+}
+
+/// Status line for a finished recording, noting any buffers the writer missed.
+pub fn saved_message(path: &Path, stats: &RecordingStats) -> String {
+    match stats.dropped_buffers() {
+        0 => format!("Saved {}", path.display()),
+        n => format!("Saved {} ({} buffers dropped)", path.display(), n),
+    }
+}
+
+/// Format a byte count for display, e.g. `512 KB`, `4.2 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes < 1024 * 1024 {
+        format!("{} KB", bytes / 1024)
+    } else {
+        format!("{:.1} MB", bytes as f64 / MB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_writer_roundtrips_through_hound() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.wav");
+        let mut writer =
+            WavWriter::new(BufWriter::new(File::create(&path).unwrap()), 48000, 2).unwrap();
+        writer.write_samples(&[0.0, 0.5, -0.5, 1.0]).unwrap();
+        writer.write_samples(&[0.25, -0.25]).unwrap();
+        assert_eq!(writer.bytes_written(), 44 + 6 * 4);
+        writer.finalize().unwrap();
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, 2);
+        assert_eq!(spec.sample_rate, 48000);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.0, 0.5, -0.5, 1.0, 0.25, -0.25]);
+    }
+
+    #[test]
+    fn recording_finalizes_when_tap_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recording, mut tap) = start(dir.path(), 44100, 2).unwrap();
+        assert!(recording.path.starts_with(dir.path()));
+
+        let samples: Vec<f32> = (0..4410 * 2).map(|i| (i % 100) as f32 / 100.0).collect();
+        for buffer in samples.chunks(128) {
+            tap.push_buffer(buffer);
+        }
+        drop(tap);
+
+        let stats = Arc::clone(&recording.stats);
+        let path = recording.finish().unwrap();
+        assert_eq!(stats.elapsed(), Duration::from_millis(100));
+        assert_eq!(stats.bytes(), 44 + 4410 * 2 * 4);
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.len(), 4410 * 2);
+    }
+
+    #[test]
+    fn full_tap_drops_whole_buffers() {
+        let (producer, mut consumer) = rtrb::RingBuffer::<f32>::new(5);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let mut tap = Tap { producer, dropped: Arc::clone(&dropped) };
+
+        tap.push_buffer(&[0.1, 0.2]);
+        tap.push_buffer(&[0.3, 0.4]);
+        // Only one slot left: the stereo frame is dropped rather than split
+        tap.push_buffer(&[0.5, 0.6]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(consumer.slots(), 4);
+
+        consumer.read_chunk(2).unwrap().commit_all();
+        tap.push_buffer(&[0.7, 0.8]);
+        let chunk = consumer.read_chunk(4).unwrap();
+        let (first, second) = chunk.as_slices();
+        assert_eq!([first, second].concat(), vec![0.3, 0.4, 0.7, 0.8]);
+    }
+
+    #[test]
+    fn unique_path_avoids_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = unique_path(dir.path(), "wav");
+        std::fs::write(&first, b"").unwrap();
+        let second = unique_path(dir.path(), "wav");
+        assert_ne!(first, second);
+        assert_eq!(second.extension().unwrap(), "wav");
+    }
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(2048), "2 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 300 * 1024), "5.3 MB");
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::audio::{AudioCommand, Mixer, Polyphony};
+use crate::kit::Kit;
+use crate::mapping::{self, NoteMapping};
+use crate::master::MasterSettings;
+use crate::midi::MidiRouter;
+use crate::midifile::{self, SmfEvent};
+use crate::mix::KitMix;
+use crate::record::WavWriter;
+use crate::velocity::VelocityCurves;
+use crate::{kit, settings};
+
+/// Frames mixed per block, matching a typical audio callback size.
+const RENDER_BLOCK: usize = 1024;
+
+/// Render MIDI events through a kit, offline and faster than real time.
+///
+/// Each message goes through the same routing as live play (remapping, chokes,
+/// hi-hat zones, velocity curves, pad mix, master stage) at its exact frame, and rendering continues until
+/// every voice has finished ringing. Returns interleaved samples at the kit's rate.
+pub fn render_events(
+    kit: &Kit,
+    mapping: &NoteMapping,
+    events: &[SmfEvent],
+    polyphony: &Polyphony,
+    curves: &VelocityCurves,
+    mix: &KitMix,
+    master: &MasterSettings,
+) -> Vec<f32> {
+    let ch = kit.channels.max(1) as usize;
+    let mut mixer = Mixer::new(kit.sample_rate, kit.channels, polyphony);
+    for (&note, &pad) in &mix.pads {
+        mixer.handle_command(AudioCommand::SetPadMix { note, mix: pad });
+    }
+    mixer.handle_command(AudioCommand::SetReverb(mix.reverb));
+    mixer.handle_command(AudioCommand::SetConvolution { impulse: mix.impulse.clone(), wet: mix.convolution.wet });
+    mixer.handle_command(AudioCommand::SetMaster { settings: *master, meter: Arc::default() });
+    let mut router = MidiRouter::default();
+    let mut output = Vec::new();
+
+    for event in events {
+        let frame = (event.time_secs * kit.sample_rate as f64).round() as usize;
+        while output.len() / ch < frame {
+            let frames = (frame - output.len() / ch).min(RENDER_BLOCK);
+            render_block(&mut mixer, &mut output, frames * ch);
+        }
+        router.route(
+            &event.data,
+            mapping,
+            &kit.notes,
+            curves,
+            |cmd| mixer.handle_command(cmd),
+            |_| {},
+        );
+    }
+
+    // Let the last hits ring out
+    while !mixer.is_idle() {
+        render_block(&mut mixer, &mut output, RENDER_BLOCK * ch);
+    }
+    output
+}
+
+/// Append `len` freshly mixed samples to `output`.
+fn render_block(mixer: &mut Mixer, output: &mut Vec<f32>, len: usize) {
+    let start = output.len();
+    output.resize(start + len, 0.0);
+    mixer.render(&mut output[start..]);
+}
+
+pub fn cmd_render(
+    kit_path: PathBuf,
+    midi_path: PathBuf,
+    out_path: PathBuf,
+    sample_rate: Option<u32>,
+) -> Result<()> {
+    let started = Instant::now();
+    let events = midifile::read_smf(&midi_path)?;
+    let loaded_kit = kit::load_kit(&kit_path, sample_rate)
+        .with_context(|| format!("Failed to load kit: {}", kit_path.display()))?;
+    let mapping = mapping::load_kit_mapping(&kit_path).unwrap_or_else(mapping::default_mapping);
+    let saved = settings::load_settings();
+    let polyphony = saved.polyphony.to_polyphony();
+    let curves = saved.velocity.to_curves();
+    let mut mix = saved.kit_mix(&kit_path);
+    mix.load_impulse(&kit_path, loaded_kit.sample_rate)?;
+
+    let samples = render_events(&loaded_kit, &mapping, &events, &polyphony, &curves, &mix, &saved.master);
+
+    let file = File::create(&out_path)
+        .with_context(|| format!("Failed to create output: {}", out_path.display()))?;
+    let mut writer = WavWriter::new(BufWriter::new(file), loaded_kit.sample_rate, loaded_kit.channels)?;
+    writer.write_samples(&samples)?;
+    writer.finalize()?;
+
+    let frames = samples.len() / loaded_kit.channels.max(1) as usize;
+    println!(
+        "Rendered {} MIDI events through '{}' to {}",
+        events.len(),
+        loaded_kit.name,
+        out_path.display()
+    );
+    println!(
+        "  {:.1}s of audio at {} Hz, {} ch in {:.2}s",
+        frames as f64 / loaded_kit.sample_rate as f64,
+        loaded_kit.sample_rate,
+        loaded_kit.channels,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midifile::{encode_smf, parse_smf, CapturedEvent};
+    use std::path::Path;
+
+    const RATE: u32 = 22050;
+
+    /// A decaying sine burst, so overlapping voices and fades are audible in the output.
+    fn write_tone(path: &Path, freq: f32, secs: f32) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        let frames = (RATE as f32 * secs) as usize;
+        for i in 0..frames {
+            let t = i as f32 / RATE as f32;
+            let env = 1.0 - i as f32 / frames as f32;
+            let v = (t * freq * std::f32::consts::TAU).sin() * env * 0.5;
+            writer.write_sample((v * i16::MAX as f32) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn test_kit(dir: &Path) -> Kit {
+        write_tone(&dir.join("36.wav"), 60.0, 0.3);
+        write_tone(&dir.join("38.wav"), 200.0, 0.2);
+        write_tone(&dir.join("46.wav"), 3000.0, 0.5);
+        write_tone(&dir.join("42.wav"), 4000.0, 0.05);
+        write_tone(&dir.join("49.wav"), 2500.0, 0.6);
+        kit::load_kit(dir, None).unwrap()
+    }
+
+    /// A bar with an open hi-hat choked by a closed one and a grabbed crash.
+    fn test_sequence() -> Vec<SmfEvent> {
+        let hits = [
+            (0, [0x99, 36, 127]),
+            (0, [0x99, 46, 90]),
+            (125_000, [0x99, 42, 70]),
+            (250_000, [0x99, 38, 110]),
+            (375_000, [0x99, 36, 64]),
+            (375_000, [0x99, 49, 120]),
+            (500_000, [0xA9, 49, 127]),
+            (500_000, [0x99, 38, 40]),
+        ];
+        let events: Vec<CapturedEvent> = hits
+            .iter()
+            .map(|&(time_us, data)| CapturedEvent { time_us, data })
+            .collect();
+        parse_smf(&encode_smf(&events, 120.0, 480)).unwrap()
+    }
+
+    fn read_golden(path: &Path) -> Vec<f32> {
+        let mut reader = hound::WavReader::open(path).unwrap_or_else(|e| {
+            panic!(
+                "Missing golden file {} ({}). Run with DRUMKIT_BLESS=1 to create it.",
+                path.display(),
+                e
+            )
+        });
+        reader.samples::<f32>().map(|s| s.unwrap()).collect()
+    }
+
+    #[test]
+    fn render_matches_golden_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let kit = test_kit(dir.path());
+        let rendered = render_events(
+            &kit,
+            &mapping::default_mapping(),
+            &test_sequence(),
+            &Polyphony::default(),
+            &VelocityCurves::default(),
+            &KitMix::default(),
+            &MasterSettings::BYPASS,
+        );
+
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/render/choke-and-grab.wav");
+        if std::env::var_os("DRUMKIT_BLESS").is_some() {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            let file = File::create(&golden).unwrap();
+            let mut writer = WavWriter::new(BufWriter::new(file), kit.sample_rate, kit.channels).unwrap();
+            writer.write_samples(&rendered).unwrap();
+            writer.finalize().unwrap();
+        }
+
+        let expected = read_golden(&golden);
+        assert_eq!(rendered.len(), expected.len(), "rendered length differs from golden file");
+        for (i, (a, b)) in rendered.iter().zip(&expected).enumerate() {
+            assert!((a - b).abs() < 1e-6, "sample {} differs: {} vs {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn render_places_hits_at_exact_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let kit = test_kit(dir.path());
+        let events = vec![SmfEvent { time_secs: 0.5, data: vec![0x99, 38, 127] }];
+        let rendered = render_events(&kit, &mapping::default_mapping(), &events, &Polyphony::default(), &VelocityCurves::default(), &KitMix::default(), &MasterSettings::BYPASS);
+
+        let ch = kit.channels as usize;
+        let onset = (RATE / 2) as usize * ch;
+        assert!(rendered[..onset].iter().all(|&s| s == 0.0));
+        // The tone starts at a zero crossing, so look at the next few frames
+        assert!(rendered[onset..onset + 10 * ch].iter().any(|&s| s != 0.0));
+        // Ends once the 0.2 s snare has finished, rounded up to a whole block
+        let end = onset + (RATE as f32 * 0.2) as usize * ch;
+        assert!(rendered.len() >= end && rendered.len() < end + RENDER_BLOCK * ch);
+    }
+
+    #[test]
+    fn render_without_events_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let kit = test_kit(dir.path());
+        let rendered = render_events(&kit, &mapping::default_mapping(), &[], &Polyphony::default(), &VelocityCurves::default(), &KitMix::default(), &MasterSettings::BYPASS);
+        assert!(rendered.is_empty());
+    }
+}
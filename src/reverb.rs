@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+/// Comb filter lengths of the original Freeverb at 44.1 kHz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+
+/// All-pass filter lengths at 44.1 kHz.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+
+/// Extra delay of the right channel's filters, which decorrelates it from the left.
+const STEREO_SPREAD: usize = 23;
+
+/// Freeverb's constants: the send is scaled down before it reaches the eight
+/// parallel combs, and the wet level scaled back up.
+const INPUT_GAIN: f32 = 0.03;
+const WET_SCALE: f32 = 3.0;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// The reverb bus of a kit's mix: an algorithmic room every pad can send to.
+/// Stored with the kit's mix as `[mixes.reverb]` in settings.toml.
+///
+/// ```toml
+/// [mixes.reverb]
+/// size = 0.5      # 0 (small room) to 1 (hall)
+/// damping = 0.5   # how quickly the highs die away
+/// wet = 0.3       # return level
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReverbSettings {
+    pub size: f32,
+    pub damping: f32,
+    pub wet: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        Self {
+            size: 0.5,
+            damping: 0.5,
+            wet: 0.3,
+        }
+    }
+}
+
+impl ReverbSettings {
+    /// Clamp every field to 0.0..=1.0.
+    pub fn sanitized(mut self) -> Self {
+        let unit = |value: f32, fallback: f32| if value.is_finite() { value.clamp(0.0, 1.0) } else { fallback };
+        let default = Self::default();
+        self.size = unit(self.size, default.size);
+        self.damping = unit(self.damping, default.damping);
+        self.wet = unit(self.wet, default.wet);
+        self
+    }
+
+    /// e.g. "size 50%, damping 50%, wet 30%".
+    pub fn label(&self) -> String {
+        format!(
+            "size {:.0}%, damping {:.0}%, wet {:.0}%",
+            self.size * 100.0,
+            self.damping * 100.0,
+            self.wet * 100.0
+        )
+    }
+
+    /// Feedback of the comb filters.
+    fn feedback(&self) -> f32 {
+        self.size * 0.28 + 0.7
+    }
+}
+
+/// Lowpass-filtered feedback comb: the room's repeating reflections.
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            index: 0,
+            filter: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter = flush_denormal(output * (1.0 - damping) + self.filter * damping);
+        self.buffer[self.index] = input + self.filter * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// Schroeder all-pass: diffuses the combs' echoes into a smooth tail.
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+        self.buffer[self.index] = flush_denormal(input + delayed * ALLPASS_FEEDBACK);
+        self.index = (self.index + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// One output channel's filter network.
+struct Tank {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Tank {
+    fn new(scale: f64, spread: usize) -> Self {
+        let len = |tuning: usize| ((tuning + spread) as f64 * scale).round() as usize;
+        Self {
+            combs: COMB_TUNING.iter().map(|&t| Comb::new(len(t))).collect(),
+            allpasses: ALLPASS_TUNING.iter().map(|&t| Allpass::new(len(t))).collect(),
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let mut output = 0.0;
+        for comb in &mut self.combs {
+            output += comb.process(input, feedback, damping);
+        }
+        for allpass in &mut self.allpasses {
+            output = allpass.process(output);
+        }
+        output
+    }
+}
+
+/// Freeverb-style stereo reverb. Its delay lines are allocated up front, so
+/// `process` never allocates and can run on the audio thread.
+pub struct Reverb {
+    left: Tank,
+    right: Tank,
+    settings: ReverbSettings,
+    /// Length of the longest comb, for working out how long the tail rings.
+    longest_comb: usize,
+    /// Frames the tail takes to fall by 60 dB at the current size.
+    tail_frames: usize,
+    /// Frames until the tail has died away since the last input.
+    ringing: usize,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: u32) -> Self {
+        let scale = sample_rate.max(1) as f64 / 44100.0;
+        let mut reverb = Self {
+            left: Tank::new(scale, 0),
+            right: Tank::new(scale, STEREO_SPREAD),
+            settings: ReverbSettings::default(),
+            longest_comb: ((COMB_TUNING[7] + STEREO_SPREAD) as f64 * scale).round() as usize,
+            tail_frames: 0,
+            ringing: 0,
+        };
+        reverb.set(ReverbSettings::default());
+        reverb
+    }
+
+    pub fn set(&mut self, settings: ReverbSettings) {
+        self.settings = settings.sanitized();
+        // Passes round the longest comb for its echoes to fall by 60 dB
+        let passes = 3.0 / -self.settings.feedback().log10();
+        self.tail_frames = (passes * self.longest_comb as f32).ceil() as usize;
+    }
+
+    /// True once the tail has died away.
+    pub fn is_idle(&self) -> bool {
+        self.ringing == 0
+    }
+
+    /// Run the mono `send` through the reverb and add the wet signal to the
+    /// interleaved `output`: left and right on the first two channels, or their
+    /// mix on a mono output. Does nothing while silent and idle.
+    pub fn process(&mut self, send: &[f32], output: &mut [f32], channels: usize) {
+        if send.iter().any(|&s| s != 0.0) {
+            self.ringing = self.tail_frames;
+        } else if self.ringing == 0 {
+            return;
+        } else {
+            self.ringing = self.ringing.saturating_sub(send.len());
+        }
+
+        let feedback = self.settings.feedback();
+        let damping = self.settings.damping * 0.4;
+        let wet = self.settings.wet * WET_SCALE;
+        let channels = channels.max(1);
+        for (&input, frame) in send.iter().zip(output.chunks_mut(channels)) {
+            let input = input * INPUT_GAIN;
+            let left = self.left.process(input, feedback, damping) * wet;
+            let right = self.right.process(input, feedback, damping) * wet;
+            match frame {
+                [mono] => *mono += (left + right) * 0.5,
+                [l, r, ..] => {
+                    *l += left;
+                    *r += right;
+                }
+                [] => {}
+            }
+        }
+    }
+}
+
+/// Zero out values too small to hear, which are very slow on some CPUs.
+fn flush_denormal(value: f32) -> f32 {
+    if value.abs() < 1e-20 { 0.0 } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum()
+    }
+
+    #[test]
+    fn impulse_rings_out_in_stereo() {
+        let mut reverb = Reverb::new(48000);
+        let mut send = vec![0.0; 4800];
+        send[0] = 1.0;
+        let mut output = vec![0.0; 4800 * 2];
+        reverb.process(&send, &mut output, 2);
+
+        let left: Vec<f32> = output.iter().step_by(2).copied().collect();
+        let right: Vec<f32> = output.iter().skip(1).step_by(2).copied().collect();
+        // Nothing comes back before the shortest comb has gone round once
+        assert!(left[..1000].iter().all(|&s| s == 0.0));
+        assert!(energy(&left) > 0.0 && energy(&right) > 0.0);
+        assert_ne!(left, right);
+        assert!(!reverb.is_idle());
+    }
+
+    #[test]
+    fn bigger_rooms_ring_longer() {
+        let tail = |size: f32| {
+            let mut reverb = Reverb::new(44100);
+            reverb.set(ReverbSettings { size, ..ReverbSettings::default() });
+            let mut send = vec![0.0; 44100];
+            send[0] = 1.0;
+            let mut output = vec![0.0; 44100];
+            reverb.process(&send, &mut output, 1);
+            energy(&output[22050..])
+        };
+        assert!(tail(0.9) > tail(0.2) * 10.0);
+    }
+
+    #[test]
+    fn silence_in_idle_reverb_is_skipped() {
+        let mut reverb = Reverb::new(44100);
+        let mut output = vec![0.25; 64];
+        reverb.process(&[0.0; 32], &mut output, 2);
+        assert!(output.iter().all(|&s| s == 0.25));
+        assert!(reverb.is_idle());
+
+        // After a hit, it rings until the tail has died away, then stops
+        reverb.process(&[1.0; 32], &mut output, 2);
+        let silence = vec![0.0; 44100];
+        let mut scratch = vec![0.0; 44100 * 2];
+        let mut frames = 0;
+        while !reverb.is_idle() {
+            reverb.process(&silence, &mut scratch, 2);
+            frames += silence.len();
+        }
+        assert!((44100..44100 * 10).contains(&frames), "{} frames", frames);
+    }
+
+    #[test]
+    fn settings_are_clamped() {
+        let settings = ReverbSettings { size: 2.0, damping: -1.0, wet: f32::NAN }.sanitized();
+        assert_eq!(settings, ReverbSettings { size: 1.0, damping: 0.0, wet: 0.3 });
+        assert_eq!(ReverbSettings::default().label(), "size 50%, damping 50%, wet 30%");
+    }
+}
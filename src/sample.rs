@@ -14,6 +14,135 @@ pub struct SampleData {
     pub channels: u16,
 }
 
+impl SampleData {
+    /// Convert to `target_rate` with a band-limited resampler. No-op if the rate already matches.
+    pub fn resampled(self, target_rate: u32) -> SampleData {
+        if self.sample_rate == target_rate {
+            return self;
+        }
+        SampleData {
+            samples: resample(&self.samples, self.channels, self.sample_rate, target_rate),
+            sample_rate: target_rate,
+            channels: self.channels,
+        }
+    }
+}
+
+/// Half-width of the windowed-sinc kernel, in zero crossings.
+const RESAMPLE_HALF_TAPS: usize = 32;
+/// Kaiser window shape parameter (≈ 90 dB stopband attenuation).
+const KAISER_BETA: f64 = 8.6;
+/// Kernel table resolution: entries per zero crossing (linearly interpolated).
+const KERNEL_OVERSAMPLE: usize = 512;
+
+/// Zeroth-order modified Bessel function of the first kind (power series).
+fn bessel_i0(x: f64) -> f64 {
+    let q = x * x / 4.0;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..64 {
+        term *= q / (k * k) as f64;
+        sum += term;
+        if term < sum * 1e-14 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Tabulate the Kaiser-windowed sinc kernel over `0..=RESAMPLE_HALF_TAPS` zero crossings.
+fn kernel_table() -> Vec<f64> {
+    let len = RESAMPLE_HALF_TAPS * KERNEL_OVERSAMPLE + 1;
+    let norm = bessel_i0(KAISER_BETA);
+    (0..len)
+        .map(|i| {
+            let u = i as f64 / KERNEL_OVERSAMPLE as f64;
+            let sinc = if i == 0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * u).sin() / (std::f64::consts::PI * u)
+            };
+            let r = u / RESAMPLE_HALF_TAPS as f64;
+            let window = bessel_i0(KAISER_BETA * (1.0 - r * r).max(0.0).sqrt()) / norm;
+            sinc * window
+        })
+        .collect()
+}
+
+/// Look up the kernel at `u` zero crossings from the center (linear interpolation).
+fn kernel_at(table: &[f64], u: f64) -> f64 {
+    let pos = u.abs() * KERNEL_OVERSAMPLE as f64;
+    let idx = pos as usize;
+    if idx + 1 >= table.len() {
+        return 0.0;
+    }
+    let frac = pos - idx as f64;
+    table[idx] + (table[idx + 1] - table[idx]) * frac
+}
+
+/// Resample interleaved PCM from `from_rate` to `to_rate`.
+///
+/// Uses a Kaiser-windowed sinc interpolator whose cutoff tracks the lower of the
+/// two Nyquist frequencies, so downsampling doesn't alias.
+pub fn resample(samples: &[f32], channels: u16, from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || from_rate == 0 || to_rate == 0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let ch = channels.max(1) as usize;
+    let in_frames = samples.len() / ch;
+    if in_frames == 0 {
+        return samples.to_vec();
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    let out_frames = (in_frames as u64 * to_rate as u64).div_ceil(from_rate as u64) as usize;
+    resample_by(samples, ch, ratio, out_frames)
+}
+
+/// Repitch interleaved PCM by `semitones` (positive = higher and shorter).
+///
+/// Plays the sample back faster or slower at the same rate, like a
+/// varispeed tape — duration changes along with pitch.
+pub fn pitch_shift(samples: &[f32], channels: u16, semitones: f32) -> Vec<f32> {
+    let ch = channels.max(1) as usize;
+    let in_frames = samples.len() / ch;
+    if semitones == 0.0 || in_frames == 0 {
+        return samples.to_vec();
+    }
+    let ratio = 2.0_f64.powf(-semitones as f64 / 12.0);
+    let out_frames = ((in_frames as f64 * ratio).ceil() as usize).max(1);
+    resample_by(samples, ch, ratio, out_frames)
+}
+
+/// Core sinc interpolator: `ratio` output frames per input frame.
+fn resample_by(samples: &[f32], ch: usize, ratio: f64, out_frames: usize) -> Vec<f32> {
+    let in_frames = samples.len() / ch;
+    let table = kernel_table();
+    // Lowpass at the lower Nyquist frequency, expressed relative to the input rate
+    let cutoff = ratio.min(1.0);
+    let half_width = RESAMPLE_HALF_TAPS as f64 / cutoff;
+
+    let mut out = vec![0.0_f32; out_frames * ch];
+    let mut acc = vec![0.0_f64; ch];
+    for n in 0..out_frames {
+        let t = n as f64 / ratio;
+        let first = (t - half_width).ceil().max(0.0) as usize;
+        let last = ((t + half_width).floor() as usize).min(in_frames - 1);
+
+        acc.fill(0.0);
+        for k in first..=last {
+            let w = cutoff * kernel_at(&table, (k as f64 - t) * cutoff);
+            for (c, a) in acc.iter_mut().enumerate() {
+                *a += samples[k * ch + c] as f64 * w;
+            }
+        }
+        for (c, a) in acc.iter().enumerate() {
+            out[n * ch + c] = *a as f32;
+        }
+    }
+    out
+}
+
 /// Load an audio file and decode it to interleaved f32 PCM samples.
 /// Supports all formats handled by Symphonia: WAV, MP3, FLAC, OGG Vorbis, AAC, ALAC, ADPCM.
 pub fn load_audio(path: &Path) -> Result<SampleData> {
@@ -187,4 +316,88 @@ mod tests {
         let result = load_audio(Path::new("/tmp/nonexistent_drumkit_test.wav"));
         assert!(result.is_err());
     }
+
+    #[test]
+    fn resample_same_rate_is_identity() {
+        let input = vec![0.1_f32, -0.2, 0.3, -0.4];
+        assert_eq!(resample(&input, 2, 48000, 48000), input);
+    }
+
+    #[test]
+    fn resample_output_length_tracks_ratio() {
+        let input = vec![0.0_f32; 44100 * 2];
+        let out = resample(&input, 2, 44100, 48000);
+        assert_eq!(out.len(), 48000 * 2);
+
+        let out = resample(&input, 2, 44100, 22050);
+        assert_eq!(out.len(), 22050 * 2);
+    }
+
+    #[test]
+    fn resample_preserves_sine() {
+        // 1 kHz sine at 44.1 kHz → 48 kHz should still be a 1 kHz sine
+        let freq = 1000.0_f64;
+        let input: Vec<f32> = (0..4410)
+            .map(|i| (2.0 * std::f64::consts::PI * freq * i as f64 / 44100.0).sin() as f32)
+            .collect();
+        let out = resample(&input, 1, 44100, 48000);
+        // Skip the kernel's edge transients
+        for (n, &s) in out.iter().enumerate().skip(200).take(4000) {
+            let expected = (2.0 * std::f64::consts::PI * freq * n as f64 / 48000.0).sin() as f32;
+            assert!((s - expected).abs() < 1e-3, "frame {}: {} vs {}", n, s, expected);
+        }
+    }
+
+    #[test]
+    fn resample_downsampling_removes_content_above_nyquist() {
+        // 20 kHz tone at 48 kHz can't be represented at 22.05 kHz and must be filtered out
+        let input: Vec<f32> = (0..4800)
+            .map(|i| (2.0 * std::f64::consts::PI * 20000.0 * i as f64 / 48000.0).sin() as f32)
+            .collect();
+        let out = resample(&input, 1, 48000, 22050);
+        let peak = out[200..out.len() - 200].iter().fold(0.0_f32, |m, s| m.max(s.abs()));
+        assert!(peak < 0.01, "aliased peak {}", peak);
+    }
+
+    #[test]
+    fn resample_keeps_channels_separate() {
+        // Left = DC 0.5, right = DC -0.25
+        let input: Vec<f32> = (0..2000).flat_map(|_| [0.5_f32, -0.25]).collect();
+        let out = resample(&input, 2, 44100, 48000);
+        let mid = (out.len() / 2) & !1;
+        assert!((out[mid] - 0.5).abs() < 1e-3);
+        assert!((out[mid + 1] + 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pitch_shift_changes_length_and_frequency() {
+        let input: Vec<f32> = (0..4800)
+            .map(|i| (2.0 * std::f32::consts::PI * 441.0 * i as f32 / 44100.0).sin())
+            .collect();
+        assert_eq!(pitch_shift(&input, 1, 0.0), input);
+
+        // One octave up halves the length; two cycles of the original now fit per cycle
+        let up = pitch_shift(&input, 1, 12.0);
+        assert_eq!(up.len(), 2400);
+        for (n, &s) in up.iter().enumerate().skip(100).take(2000) {
+            let expected = (2.0 * std::f32::consts::PI * 882.0 * n as f32 / 44100.0).sin();
+            assert!((s - expected).abs() < 1e-2, "frame {n}: {s} vs {expected}");
+        }
+
+        let down = pitch_shift(&input, 1, -12.0);
+        assert_eq!(down.len(), 9600);
+    }
+
+    #[test]
+    fn sample_data_resampled_updates_rate() {
+        let data = SampleData {
+            samples: vec![0.0; 882],
+            sample_rate: 44100,
+            channels: 2,
+        };
+        let out = data.resampled(48000);
+        assert_eq!(out.sample_rate, 48000);
+        assert_eq!(out.channels, 2);
+        assert_eq!(out.samples.len(), 960);
+    }
 }
@@ -1,6 +1,17 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::audio;
+use crate::convolution::ConvolutionSettings;
+use crate::kitswitch::KitSwitchSettings;
+use crate::master::MasterSettings;
+use crate::mix::{KitMix, KitMixSettings, PadMix};
+use crate::metronome::MetronomeSettings;
+use crate::reverb::ReverbSettings;
+use crate::suppress::SuppressionSettings;
+use crate::velocity::VelocitySettings;
 
 /// The default kit repository included out of the box.
 pub const DEFAULT_KIT_REPO: &str = "backmeupplz/drumkit-kits";
@@ -9,6 +20,140 @@ fn default_kit_repos() -> Vec<String> {
     vec![DEFAULT_KIT_REPO.to_string()]
 }
 
+fn default_max_voices() -> usize {
+    audio::DEFAULT_MAX_VOICES
+}
+
+/// Polyphony limits and voice-stealing policy.
+///
+/// ```toml
+/// [polyphony]
+/// max_voices = 32
+/// steal_policy = "same-note-first"   # or "oldest", "quietest"
+///
+/// [polyphony.note_limits]
+/// 51 = 4   # at most 4 simultaneous ride voices
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolyphonySettings {
+    #[serde(default = "default_max_voices")]
+    pub max_voices: usize,
+    #[serde(default)]
+    pub steal_policy: audio::StealPolicy,
+    /// Per-note voice limits. Keys are strings because TOML only supports string keys.
+    #[serde(default)]
+    pub note_limits: HashMap<String, usize>,
+}
+
+impl Default for PolyphonySettings {
+    fn default() -> Self {
+        Self {
+            max_voices: default_max_voices(),
+            steal_policy: audio::StealPolicy::default(),
+            note_limits: HashMap::new(),
+        }
+    }
+}
+
+impl PolyphonySettings {
+    /// Convert to the mixer's representation, skipping unparseable note keys.
+    pub fn to_polyphony(&self) -> audio::Polyphony {
+        audio::Polyphony {
+            max_voices: self.max_voices,
+            policy: self.steal_policy,
+            note_limits: self
+                .note_limits
+                .iter()
+                .filter_map(|(k, &v)| Some((k.parse().ok()?, v)))
+                .collect(),
+        }
+    }
+}
+
+fn default_midi_channels() -> Vec<u8> {
+    vec![10]
+}
+
+/// Which MIDI channels trigger drums, and kits played on channels of their own.
+///
+/// ```toml
+/// [midi]
+/// channels = [10]   # channels (1-16) that play the main kit; [] = all
+///
+/// [midi.channel_kits]
+/// 11 = "808"        # kit name from the kit picker, or a directory path
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MidiSettings {
+    #[serde(default = "default_midi_channels")]
+    pub channels: Vec<u8>,
+    /// Kits by channel. Keys are strings because TOML only supports string keys.
+    #[serde(default)]
+    pub channel_kits: BTreeMap<String, String>,
+}
+
+impl Default for MidiSettings {
+    fn default() -> Self {
+        Self {
+            channels: default_midi_channels(),
+            channel_kits: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A MIDI input port to connect, saved by port name, with optional overrides.
+///
+/// ```toml
+/// [[midi_inputs]]
+/// port = "nanoPAD2 MIDI 1"
+/// mapping = "General MIDI"   # instead of the kit's mapping
+/// channels = [1]             # instead of [midi].channels
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiInputSettings {
+    pub port: String,
+    /// Unticked in the MIDI input picker: remembered but not connected.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mapping: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<u8>>,
+}
+
+impl MidiInputSettings {
+    pub fn new(port: String) -> Self {
+        Self {
+            port,
+            enabled: true,
+            mapping: None,
+            channels: None,
+        }
+    }
+}
+
+/// Forwarding of incoming drum events to a MIDI output, e.g. a sound module or DAW.
+///
+/// ```toml
+/// [midi_thru]
+/// port = "SC-88 MIDI 1"   # output port from `drumkit devices`
+/// mode = "remapped"       # or "raw" to forward notes as played
+/// channel = 10            # send on this channel (1-16) instead of the incoming one
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MidiThruSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    #[serde(default)]
+    pub mode: crate::thru::ThruMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
+}
+
 /// Persisted user settings (last-used kit, audio device, MIDI device, extra directories).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
@@ -21,6 +166,126 @@ pub struct Settings {
     pub extra_mapping_dirs: Vec<PathBuf>,
     #[serde(default = "default_kit_repos")]
     pub kit_repos: Vec<String>,
+    #[serde(default)]
+    pub polyphony: PolyphonySettings,
+    /// Where play-mode recordings go (defaults to `record::default_recordings_dir`).
+    #[serde(default)]
+    pub recordings_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub metronome: MetronomeSettings,
+    #[serde(default)]
+    pub midi: MidiSettings,
+    /// MIDI inputs to connect alongside `midi_device` (the port picked at startup).
+    #[serde(default)]
+    pub midi_inputs: Vec<MidiInputSettings>,
+    #[serde(default)]
+    pub midi_thru: MidiThruSettings,
+    #[serde(default)]
+    pub velocity: VelocitySettings,
+    #[serde(default)]
+    pub suppression: SuppressionSettings,
+    #[serde(default)]
+    pub kit_switch: KitSwitchSettings,
+    #[serde(default)]
+    pub master: MasterSettings,
+    /// Pad mixes, one per kit.
+    #[serde(default)]
+    pub mixes: Vec<KitMixSettings>,
+}
+
+impl Settings {
+    /// The configured recordings directory, or the default under the data dir.
+    pub fn recordings_dir(&self) -> PathBuf {
+        self.recordings_dir
+            .clone()
+            .unwrap_or_else(crate::record::default_recordings_dir)
+    }
+
+    /// The inputs to connect when starting with `primary`: its saved entry (or a
+    /// fresh one) first, then the other enabled saved inputs.
+    pub fn midi_inputs_with(&self, primary: &str) -> Vec<MidiInputSettings> {
+        let mut inputs = vec![self
+            .midi_inputs
+            .iter()
+            .find(|i| i.port == primary)
+            .map(|i| MidiInputSettings { enabled: true, ..i.clone() })
+            .unwrap_or_else(|| MidiInputSettings::new(primary.to_string()))];
+        inputs.extend(
+            self.midi_inputs
+                .iter()
+                .filter(|i| i.enabled && i.port != primary)
+                .cloned(),
+        );
+        inputs
+    }
+
+    /// The saved mix of the kit at `kit` (empty if it has none).
+    pub fn kit_mix(&self, kit: &Path) -> KitMix {
+        self.mixes
+            .iter()
+            .find(|m| same_kit(&m.kit, kit))
+            .map(KitMixSettings::to_mix)
+            .unwrap_or_default()
+    }
+
+    /// Save one pad's mix for the kit at `kit`; pads back at the defaults (and kits
+    /// with nothing left to save) are dropped.
+    pub fn set_pad_mix(&mut self, kit: &Path, note: u8, mix: PadMix) {
+        self.update_kit_mix(kit, |saved| {
+            if mix == PadMix::default() {
+                saved.pads.remove(&note.to_string());
+            } else {
+                saved.pads.insert(note.to_string(), mix);
+            }
+        });
+    }
+
+    /// Save the reverb bus settings for the kit at `kit`.
+    pub fn set_reverb(&mut self, kit: &Path, reverb: ReverbSettings) {
+        self.update_kit_mix(kit, |saved| saved.reverb = reverb);
+    }
+
+    /// Save the IR bus settings for the kit at `kit`.
+    pub fn set_convolution(&mut self, kit: &Path, convolution: ConvolutionSettings) {
+        self.update_kit_mix(kit, |saved| saved.convolution = convolution);
+    }
+
+    fn update_kit_mix(&mut self, kit: &Path, change: impl FnOnce(&mut KitMixSettings)) {
+        let index = match self.mixes.iter().position(|m| same_kit(&m.kit, kit)) {
+            Some(index) => index,
+            None => {
+                self.mixes.push(KitMixSettings {
+                    kit: kit.to_path_buf(),
+                    reverb: ReverbSettings::default(),
+                    convolution: ConvolutionSettings::default(),
+                    pads: Default::default(),
+                });
+                self.mixes.len() - 1
+            }
+        };
+        change(&mut self.mixes[index]);
+        let saved = &self.mixes[index];
+        if saved.pads.is_empty()
+            && saved.reverb == ReverbSettings::default()
+            && saved.convolution == ConvolutionSettings::default()
+        {
+            self.mixes.remove(index);
+        }
+    }
+
+    /// Remember `port` as connected (or not), keeping any overrides it had.
+    pub fn set_midi_input_enabled(&mut self, port: &str, enabled: bool) {
+        match self.midi_inputs.iter_mut().find(|i| i.port == port) {
+            Some(input) => input.enabled = enabled,
+            None if enabled => self.midi_inputs.push(MidiInputSettings::new(port.to_string())),
+            None => {}
+        }
+    }
+}
+
+/// Whether two kit paths name the same directory (`./kit` and its absolute path do).
+fn same_kit(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
 }
 
 impl Default for Settings {
@@ -32,6 +297,17 @@ impl Default for Settings {
             extra_kit_dirs: Vec::new(),
             extra_mapping_dirs: Vec::new(),
             kit_repos: default_kit_repos(),
+            polyphony: PolyphonySettings::default(),
+            recordings_dir: None,
+            metronome: MetronomeSettings::default(),
+            midi: MidiSettings::default(),
+            midi_inputs: Vec::new(),
+            midi_thru: MidiThruSettings::default(),
+            velocity: VelocitySettings::default(),
+            suppression: SuppressionSettings::default(),
+            kit_switch: KitSwitchSettings::default(),
+            master: MasterSettings::default(),
+            mixes: Vec::new(),
         }
     }
 }
@@ -96,6 +372,17 @@ mod tests {
             extra_kit_dirs: vec![PathBuf::from("/extra/kits")],
             extra_mapping_dirs: vec![PathBuf::from("/extra/mappings")],
             kit_repos: default_kit_repos(),
+            polyphony: PolyphonySettings::default(),
+            recordings_dir: Some(PathBuf::from("/home/user/recordings")),
+            metronome: MetronomeSettings::default(),
+            midi: MidiSettings::default(),
+            midi_inputs: vec![MidiInputSettings::new("nanoPAD2 MIDI 1".to_string())],
+            midi_thru: MidiThruSettings::default(),
+            velocity: VelocitySettings::default(),
+            suppression: SuppressionSettings::default(),
+            kit_switch: KitSwitchSettings::default(),
+            master: MasterSettings::default(),
+            mixes: Vec::new(),
         };
 
         // Save manually to temp path
@@ -109,6 +396,8 @@ mod tests {
         assert_eq!(loaded.kit_path.unwrap(), PathBuf::from("/home/user/kits/linndrum"));
         assert_eq!(loaded.audio_device.unwrap(), "HDA Intel PCH");
         assert_eq!(loaded.midi_device.unwrap(), "Alesis Nitro Max MIDI 1");
+        assert_eq!(loaded.recordings_dir.unwrap(), PathBuf::from("/home/user/recordings"));
+        assert_eq!(loaded.midi_inputs, vec![MidiInputSettings::new("nanoPAD2 MIDI 1".to_string())]);
     }
 
     #[test]
@@ -125,4 +414,143 @@ mod tests {
         let settings: Settings = toml::from_str("not valid {{{}}}").unwrap_or_default();
         assert!(settings.kit_path.is_none());
     }
+
+    #[test]
+    fn parse_polyphony_settings() {
+        let toml = r#"
+[polyphony]
+max_voices = 24
+steal_policy = "same-note-first"
+
+[polyphony.note_limits]
+51 = 4
+bogus = 2
+"#;
+        let settings: Settings = toml::from_str(toml).unwrap();
+        let poly = settings.polyphony.to_polyphony();
+        assert_eq!(poly.max_voices, 24);
+        assert_eq!(poly.policy, audio::StealPolicy::SameNoteFirst);
+        assert_eq!(poly.note_limits.len(), 1);
+        assert_eq!(poly.note_limits[&51], 4);
+    }
+
+    #[test]
+    fn polyphony_defaults_when_missing() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert_eq!(settings.polyphony.max_voices, audio::DEFAULT_MAX_VOICES);
+        assert_eq!(settings.polyphony.steal_policy, audio::StealPolicy::Oldest);
+    }
+
+    #[test]
+    fn parse_midi_channel_settings() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert_eq!(settings.midi.channels, vec![10]);
+        assert!(settings.midi.channel_kits.is_empty());
+
+        let toml = r#"
+[midi]
+channels = [10, 12]
+
+[midi.channel_kits]
+11 = "808"
+"#;
+        let settings: Settings = toml::from_str(toml).unwrap();
+        assert_eq!(settings.midi.channels, vec![10, 12]);
+        assert_eq!(settings.midi.channel_kits["11"], "808");
+    }
+
+    #[test]
+    fn parse_midi_thru_settings() {
+        let settings: Settings = toml::from_str("").unwrap();
+        assert!(settings.midi_thru.port.is_none());
+        assert_eq!(settings.midi_thru.mode, crate::thru::ThruMode::Remapped);
+
+        let toml = r#"
+[midi_thru]
+port = "SC-88 MIDI 1"
+mode = "raw"
+channel = 1
+"#;
+        let settings: Settings = toml::from_str(toml).unwrap();
+        assert_eq!(settings.midi_thru.port.as_deref(), Some("SC-88 MIDI 1"));
+        assert_eq!(settings.midi_thru.mode, crate::thru::ThruMode::Raw);
+        assert_eq!(settings.midi_thru.channel, Some(1));
+    }
+
+    #[test]
+    fn midi_inputs_start_with_primary() {
+        let toml = r#"
+[[midi_inputs]]
+port = "Pads"
+mapping = "General MIDI"
+channels = [1]
+
+[[midi_inputs]]
+port = "Foot switch"
+enabled = false
+
+[[midi_inputs]]
+port = "Module"
+"#;
+        let settings: Settings = toml::from_str(toml).unwrap();
+        let ports = |inputs: Vec<MidiInputSettings>| -> Vec<String> {
+            inputs.into_iter().map(|i| i.port).collect()
+        };
+        assert_eq!(ports(settings.midi_inputs_with("Module")), vec!["Module", "Pads"]);
+        // An explicitly chosen port connects even if it was unticked
+        assert_eq!(ports(settings.midi_inputs_with("Foot switch")), vec!["Foot switch", "Pads", "Module"]);
+        assert_eq!(ports(settings.midi_inputs_with("New")), vec!["New", "Pads", "Module"]);
+        assert_eq!(settings.midi_inputs_with("Pads")[0].channels, Some(vec![1]));
+    }
+
+    #[test]
+    fn set_midi_input_enabled_keeps_overrides() {
+        let mut settings = Settings::default();
+        settings.set_midi_input_enabled("Module", true);
+        settings.set_midi_input_enabled("Pads", true);
+        settings.midi_inputs[1].mapping = Some("General MIDI".to_string());
+
+        settings.set_midi_input_enabled("Module", false);
+        settings.set_midi_input_enabled("Pads", false);
+        settings.set_midi_input_enabled("Pads", true);
+        assert_eq!(settings.midi_inputs.len(), 2);
+        assert_eq!(settings.midi_inputs[1].mapping.as_deref(), Some("General MIDI"));
+        assert!(!settings.midi_inputs[0].enabled);
+    }
+
+    #[test]
+    fn pad_mixes_are_kept_per_kit() {
+        let mut settings = Settings::default();
+        let (acoustic, electronic) = (Path::new("/kits/Acoustic"), Path::new("/kits/808"));
+        let quiet = PadMix { volume_db: -6.0, ..PadMix::default() };
+        settings.set_pad_mix(acoustic, 38, quiet);
+        settings.set_pad_mix(electronic, 36, PadMix { mute: true, ..PadMix::default() });
+        assert_eq!(settings.kit_mix(acoustic).for_note(38), quiet);
+        assert_eq!(settings.kit_mix(electronic).for_note(38), PadMix::default());
+
+        // Survives the round trip through settings.toml
+        let content = toml::to_string_pretty(&settings).unwrap();
+        let loaded: Settings = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.kit_mix(acoustic), settings.kit_mix(acoustic));
+        assert!(loaded.kit_mix(electronic).for_note(36).mute);
+
+        // A kit back at the defaults has no entry
+        settings.set_pad_mix(acoustic, 38, PadMix::default());
+        assert_eq!(settings.mixes.len(), 1);
+
+        // The reverb bus is kept per kit too
+        let hall = ReverbSettings { size: 0.9, ..ReverbSettings::default() };
+        settings.set_reverb(acoustic, hall);
+        assert_eq!(settings.kit_mix(acoustic).reverb, hall);
+        assert_eq!(settings.kit_mix(electronic).reverb, ReverbSettings::default());
+        settings.set_reverb(acoustic, ReverbSettings::default());
+        assert_eq!(settings.mixes.len(), 1);
+
+        // And so is the IR bus
+        let plate = ConvolutionSettings { ir: Some("plate.wav".to_string()), wet: 0.5 };
+        settings.set_convolution(acoustic, plate.clone());
+        assert_eq!(settings.kit_mix(acoustic).convolution, plate);
+        settings.set_convolution(acoustic, ConvolutionSettings::default());
+        assert_eq!(settings.mixes.len(), 1);
+    }
 }
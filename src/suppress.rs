@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+fn default_crosstalk_ratio() -> f32 {
+    0.5
+}
+
+/// Double-trigger and crosstalk thresholds for a pad. Zero times turn a rule off.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PadSuppression {
+    /// Ignore a repeat hit on the same pad this soon after the last one, in ms.
+    #[serde(default)]
+    pub retrigger_ms: f32,
+    /// Ignore a hit this soon after a louder hit on another pad, in ms...
+    #[serde(default)]
+    pub crosstalk_ms: f32,
+    /// ...if its velocity is at most this fraction of the louder hit's.
+    #[serde(default = "default_crosstalk_ratio")]
+    pub crosstalk_ratio: f32,
+}
+
+impl Default for PadSuppression {
+    fn default() -> Self {
+        Self {
+            retrigger_ms: 0.0,
+            crosstalk_ms: 0.0,
+            crosstalk_ratio: default_crosstalk_ratio(),
+        }
+    }
+}
+
+impl PadSuppression {
+    /// Clamp every field to its supported range.
+    pub fn sanitized(mut self) -> Self {
+        self.retrigger_ms = self.retrigger_ms.clamp(0.0, 500.0);
+        self.crosstalk_ms = self.crosstalk_ms.clamp(0.0, 100.0);
+        self.crosstalk_ratio = self.crosstalk_ratio.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// Double-trigger and crosstalk suppression, persisted under `[suppression]` in
+/// settings.toml.
+///
+/// ```toml
+/// [suppression]
+/// retrigger_ms = 20      # all pads
+///
+/// [suppression.pads.38]  # incoming MIDI note
+/// retrigger_ms = 30
+/// crosstalk_ms = 5       # ignore snare hits within 5 ms of a louder hit elsewhere
+/// crosstalk_ratio = 0.4  # ...when at most 40% as loud
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SuppressionSettings {
+    /// Thresholds for pads without their own.
+    #[serde(flatten)]
+    pub global: PadSuppression,
+    /// Per-pad thresholds. Keys are strings because TOML only supports string keys.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pads: BTreeMap<String, PadSuppression>,
+}
+
+impl SuppressionSettings {
+    /// Convert to the filter's representation, skipping unparseable note keys.
+    pub fn to_suppression(&self) -> Suppression {
+        Suppression {
+            global: self.global.sanitized(),
+            pads: self
+                .pads
+                .iter()
+                .filter_map(|(k, v)| Some((k.parse().ok()?, v.sanitized())))
+                .collect(),
+        }
+    }
+}
+
+/// The thresholds in effect, looked up by incoming note for every hit.
+#[derive(Debug, Clone, Default)]
+pub struct Suppression {
+    pub global: PadSuppression,
+    pub pads: HashMap<u8, PadSuppression>,
+}
+
+impl Suppression {
+    /// The pad's own thresholds, or the global ones.
+    pub fn for_note(&self, note: u8) -> &PadSuppression {
+        self.pads.get(&note).unwrap_or(&self.global)
+    }
+}
+
+/// What the filter decided about a note-on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Play,
+    /// Too soon after the same pad's last hit.
+    Retrigger,
+    /// Too soon after a much louder hit on another pad.
+    Crosstalk,
+}
+
+/// Drops double triggers and crosstalk among one input's note-ons, using the
+/// input's own timestamps (in µs).
+pub struct HitFilter {
+    /// Time and velocity of each note's last played hit.
+    last: [Option<(u64, u8)>; 128],
+}
+
+impl Default for HitFilter {
+    fn default() -> Self {
+        Self { last: [None; 128] }
+    }
+}
+
+impl HitFilter {
+    /// Judge a note-on, remembering it if it plays.
+    pub fn check(&mut self, timestamp_us: u64, note: u8, velocity: u8, suppression: &Suppression) -> Verdict {
+        let note = note & 0x7F;
+        let pad = suppression.for_note(note);
+        let within = |time: u64, ms: f32| ms > 0.0 && (timestamp_us.saturating_sub(time) as f32) < ms * 1000.0;
+
+        if let Some((time, _)) = self.last[note as usize]
+            && within(time, pad.retrigger_ms)
+        {
+            return Verdict::Retrigger;
+        }
+        let bleeds = self.last.iter().enumerate().any(|(other, last)| {
+            other != note as usize
+                && last.is_some_and(|(time, louder)| {
+                    within(time, pad.crosstalk_ms) && velocity as f32 <= louder as f32 * pad.crosstalk_ratio
+                })
+        });
+        if bleeds {
+            return Verdict::Crosstalk;
+        }
+
+        self.last[note as usize] = Some((timestamp_us, velocity));
+        Verdict::Play
+    }
+}
+
+/// Per-note counts of played and rejected hits, shared between the MIDI callbacks
+/// and the stats view.
+pub struct HitStats {
+    played: [AtomicU32; 128],
+    retriggers: [AtomicU32; 128],
+    crosstalk: [AtomicU32; 128],
+}
+
+impl Default for HitStats {
+    fn default() -> Self {
+        Self {
+            played: std::array::from_fn(|_| AtomicU32::new(0)),
+            retriggers: std::array::from_fn(|_| AtomicU32::new(0)),
+            crosstalk: std::array::from_fn(|_| AtomicU32::new(0)),
+        }
+    }
+}
+
+/// Counts for one note, as shown in the stats view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoteStats {
+    pub played: u32,
+    pub retriggers: u32,
+    pub crosstalk: u32,
+}
+
+impl HitStats {
+    pub fn record(&self, note: u8, verdict: Verdict) {
+        let counters = match verdict {
+            Verdict::Play => &self.played,
+            Verdict::Retrigger => &self.retriggers,
+            Verdict::Crosstalk => &self.crosstalk,
+        };
+        counters[(note & 0x7F) as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, note: u8) -> NoteStats {
+        let i = (note & 0x7F) as usize;
+        NoteStats {
+            played: self.played[i].load(Ordering::Relaxed),
+            retriggers: self.retriggers[i].load(Ordering::Relaxed),
+            crosstalk: self.crosstalk[i].load(Ordering::Relaxed),
+        }
+    }
+
+    /// Total rejected hits as (retriggers, crosstalk).
+    pub fn rejected(&self) -> (u32, u32) {
+        (0..128).fold((0, 0), |(r, c), note| {
+            let stats = self.get(note);
+            (r + stats.retriggers, c + stats.crosstalk)
+        })
+    }
+
+    pub fn reset(&self) {
+        for counters in [&self.played, &self.retriggers, &self.crosstalk] {
+            for counter in counters {
+                counter.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1000;
+
+    #[test]
+    fn retrigger_mask_drops_repeats_on_the_same_pad() {
+        let mut suppression = Suppression::default();
+        suppression.global.retrigger_ms = 30.0;
+        let mut filter = HitFilter::default();
+
+        assert_eq!(filter.check(0, 38, 100, &suppression), Verdict::Play);
+        assert_eq!(filter.check(10 * MS, 38, 60, &suppression), Verdict::Retrigger);
+        // The mask runs from the last hit that played, not the rejected one
+        assert_eq!(filter.check(31 * MS, 38, 90, &suppression), Verdict::Play);
+        // Other pads aren't masked
+        assert_eq!(filter.check(32 * MS, 36, 90, &suppression), Verdict::Play);
+    }
+
+    #[test]
+    fn crosstalk_drops_soft_hits_right_after_a_louder_one() {
+        let mut suppression = Suppression::default();
+        suppression.pads.insert(
+            45,
+            PadSuppression {
+                crosstalk_ms: 5.0,
+                crosstalk_ratio: 0.5,
+                ..PadSuppression::default()
+            },
+        );
+        let mut filter = HitFilter::default();
+
+        assert_eq!(filter.check(0, 38, 120, &suppression), Verdict::Play);
+        assert_eq!(filter.check(2 * MS, 45, 40, &suppression), Verdict::Crosstalk);
+        // Loud enough to be a real hit
+        assert_eq!(filter.check(3 * MS, 45, 80, &suppression), Verdict::Play);
+        // Only pad 45 has a crosstalk rule
+        assert_eq!(filter.check(4 * MS, 48, 10, &suppression), Verdict::Play);
+        // Outside the window
+        assert_eq!(filter.check(20 * MS, 45, 10, &suppression), Verdict::Play);
+    }
+
+    #[test]
+    fn stats_count_each_verdict() {
+        let stats = HitStats::default();
+        stats.record(38, Verdict::Play);
+        stats.record(38, Verdict::Retrigger);
+        stats.record(38, Verdict::Retrigger);
+        stats.record(45, Verdict::Crosstalk);
+        assert_eq!(stats.get(38), NoteStats { played: 1, retriggers: 2, crosstalk: 0 });
+        assert_eq!(stats.rejected(), (2, 1));
+
+        stats.reset();
+        assert_eq!(stats.get(38), NoteStats::default());
+    }
+
+    #[test]
+    fn parse_suppression_settings() {
+        let toml_str = r#"
+            [suppression]
+            retrigger_ms = 20
+
+            [suppression.pads.38]
+            crosstalk_ms = 5
+            crosstalk_ratio = 2.0
+        "#;
+        #[derive(Deserialize)]
+        struct Wrapper {
+            suppression: SuppressionSettings,
+        }
+        let settings = toml::from_str::<Wrapper>(toml_str).unwrap().suppression;
+        let suppression = settings.to_suppression();
+        assert_eq!(suppression.for_note(36).retrigger_ms, 20.0);
+        assert_eq!(suppression.for_note(36).crosstalk_ratio, 0.5);
+        // A pad's own entry replaces the global one; out-of-range values are clamped
+        assert_eq!(suppression.for_note(38).retrigger_ms, 0.0);
+        assert_eq!(suppression.for_note(38).crosstalk_ms, 5.0);
+        assert_eq!(suppression.for_note(38).crosstalk_ratio, 1.0);
+    }
+}
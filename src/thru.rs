@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use midir::{MidiOutput, MidiOutputConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::mapping::NoteMapping;
+use crate::settings::MidiThruSettings;
+
+/// Whether forwarded notes go out as played or after the mapping's note remapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThruMode {
+    /// Notes remapped with `NoteMapping::remap_note`, as the kit plays them.
+    #[default]
+    Remapped,
+    /// Notes exactly as received.
+    Raw,
+}
+
+/// A MIDI output that incoming drum events are forwarded to, so a sound module or
+/// DAW can be layered with the samples.
+pub struct MidiThru {
+    pub port: String,
+    connection: MidiOutputConnection,
+    mode: ThruMode,
+    /// Channel (1-16) to send on instead of the incoming one.
+    channel: Option<u8>,
+}
+
+impl MidiThru {
+    /// Open the output port named in `settings`, if there is one.
+    pub fn open(settings: &MidiThruSettings) -> Result<Option<Self>> {
+        let Some(port) = &settings.port else {
+            return Ok(None);
+        };
+        if let Some(channel) = settings.channel
+            && !(1..=16).contains(&channel)
+        {
+            anyhow::bail!("MIDI thru channel must be 1-16, got {}", channel);
+        }
+
+        let output = MidiOutput::new("drumkit-thru").context("Failed to create MIDI output")?;
+        let output_port = output
+            .ports()
+            .into_iter()
+            .find(|p| output.port_name(p).is_ok_and(|name| name == *port))
+            .with_context(|| format!("MIDI output \"{}\" not found", port))?;
+        let connection = output
+            .connect(&output_port, "drumkit-thru")
+            .map_err(|e| anyhow::anyhow!("Failed to connect to MIDI output {}: {}", port, e))?;
+
+        Ok(Some(Self {
+            port: port.clone(),
+            connection,
+            mode: settings.mode,
+            channel: settings.channel,
+        }))
+    }
+
+    /// Forward a raw MIDI message, remapping its note with `mapping` in remapped mode.
+    /// Only 3-byte channel messages (notes, aftertouch, CC) are sent on.
+    pub fn forward(&mut self, data: &[u8], mapping: &NoteMapping) {
+        if let Some(message) = thru_message(data, mapping, self.mode, self.channel) {
+            let _ = self.connection.send(&message);
+        }
+    }
+
+    /// Log line describing the output, e.g. "MIDI thru: SC-88 (remapped, ch 10)".
+    pub fn label(&self) -> String {
+        let mode = match self.mode {
+            ThruMode::Remapped => "remapped",
+            ThruMode::Raw => "raw",
+        };
+        match self.channel {
+            Some(channel) => format!("MIDI thru: {} ({}, ch {})", self.port, mode, channel),
+            None => format!("MIDI thru: {} ({})", self.port, mode),
+        }
+    }
+}
+
+/// The message sent on for `data`, or `None` if it isn't forwarded.
+fn thru_message(data: &[u8], mapping: &NoteMapping, mode: ThruMode, channel: Option<u8>) -> Option<[u8; 3]> {
+    let &[status, data1, data2] = data else {
+        return None;
+    };
+    if !(0x80..0xF0).contains(&status) {
+        return None;
+    }
+    let kind = status & 0xF0;
+    let data1 = match (mode, kind) {
+        // Note off, note on and poly aftertouch carry a note number
+        (ThruMode::Remapped, 0x80 | 0x90 | 0xA0) => mapping.remap_note(data1),
+        _ => data1,
+    };
+    let channel = channel.map_or(status & 0x0F, |c| c - 1);
+    Some([kind | channel, data1, data2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thru_message_remaps_notes_and_sets_channel() {
+        let mut mapping = crate::mapping::default_mapping();
+        mapping.remap.insert(48, 38);
+
+        // Remapped mode maps notes and aftertouch but leaves controllers alone
+        let remapped = |data: &[u8]| thru_message(data, &mapping, ThruMode::Remapped, None);
+        assert_eq!(remapped(&[0x99, 48, 100]), Some([0x99, 38, 100]));
+        assert_eq!(remapped(&[0x89, 48, 0]), Some([0x89, 38, 0]));
+        assert_eq!(remapped(&[0xA9, 48, 127]), Some([0xA9, 38, 127]));
+        assert_eq!(remapped(&[0xB9, 48, 64]), Some([0xB9, 48, 64]));
+
+        assert_eq!(thru_message(&[0x99, 48, 100], &mapping, ThruMode::Raw, None), Some([0x99, 48, 100]));
+
+        // Channel 1 out, whatever came in
+        assert_eq!(
+            thru_message(&[0x99, 48, 100], &mapping, ThruMode::Remapped, Some(1)),
+            Some([0x90, 38, 100])
+        );
+
+        // System and short messages aren't forwarded
+        assert_eq!(remapped(&[0xF8]), None);
+        assert_eq!(remapped(&[0xC9, 5]), None);
+        assert_eq!(remapped(&[0xF2, 0, 0]), None);
+    }
+}
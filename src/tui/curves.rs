@@ -0,0 +1,177 @@
+use crossterm::event::KeyCode;
+use std::sync::Arc;
+
+use super::{AppState, PlayResources, Popup};
+use crate::settings;
+use crate::velocity::{CurveShape, VelocityCurve, VelocityCurves};
+
+/// Rows of the curve editor; the point rows only show for custom curves.
+pub(super) const ROW_TARGET: usize = 0;
+pub(super) const ROW_CURVE: usize = 1;
+pub(super) const ROW_MIN: usize = 2;
+pub(super) const ROW_MAX: usize = 3;
+pub(super) const ROW_POINT: usize = 4;
+pub(super) const ROW_LEVEL: usize = 5;
+
+/// Open the editor on the most recently hit pad, or the global curve before any hit.
+pub(super) fn open(state: &mut AppState, resources: &PlayResources) {
+    state.popup = Some(Popup::VelocityCurve {
+        target: state.hit_log.first().map(|entry| entry.note),
+        row: ROW_TARGET,
+        point: 0,
+        curves: (**resources.velocity.load()).clone(),
+    });
+}
+
+/// The curve `target` plays with: its own, or the global one (`None` = global).
+pub(super) fn curve_for(curves: &VelocityCurves, target: Option<u8>) -> &VelocityCurve {
+    match target {
+        Some(note) => curves.for_note(note),
+        None => &curves.global,
+    }
+}
+
+pub(super) fn handle_key(state: &mut AppState, resources: &mut PlayResources, key: KeyCode) {
+    let Some(Popup::VelocityCurve { target, row, point, curves }) = state.popup.as_mut() else {
+        return;
+    };
+    let custom = curve_for(curves, *target).curve == CurveShape::Custom;
+    let rows = if custom { ROW_LEVEL + 1 } else { ROW_MAX + 1 };
+    // ←/→ step by one, PgUp/PgDn by ten (choices move one either way)
+    let delta: i32 = match key {
+        KeyCode::Esc | KeyCode::Char('v') => {
+            state.popup = None;
+            return;
+        }
+        KeyCode::Char('q') => {
+            state.popup = None;
+            state.should_quit = true;
+            return;
+        }
+        KeyCode::Up => {
+            *row = row.checked_sub(1).unwrap_or(rows - 1);
+            return;
+        }
+        KeyCode::Down => {
+            *row = (*row + 1) % rows;
+            return;
+        }
+        KeyCode::Right => 1,
+        KeyCode::Left => -1,
+        KeyCode::PageUp => 10,
+        KeyCode::PageDown => -10,
+        _ => return,
+    };
+
+    let changed = match *row {
+        ROW_TARGET => {
+            // Global first, then the kit's pads
+            let mut targets: Vec<Option<u8>> = vec![None];
+            targets.extend(state.pads.iter().map(|p| Some(p.note)));
+            if let Some(note) = *target
+                && !targets.contains(&Some(note))
+            {
+                targets.push(Some(note));
+            }
+            let idx = targets.iter().position(|t| t == target).unwrap_or(0);
+            let next = (idx as i32 + delta.signum()).rem_euclid(targets.len() as i32);
+            *target = targets[next as usize];
+            *point = 0;
+            false
+        }
+        ROW_CURVE => {
+            // A pad can also follow the global curve ("global" = no curve of its own)
+            let current = match *target {
+                Some(note) => curves.pads.get(&note).map(|c| c.curve),
+                None => Some(curves.global.curve),
+            };
+            let mut choices: Vec<Option<CurveShape>> = CurveShape::ALL.iter().copied().map(Some).collect();
+            if target.is_some() {
+                choices.insert(0, None);
+            }
+            let idx = choices.iter().position(|c| *c == current).unwrap_or(0);
+            let next = choices[(idx as i32 + delta.signum()).rem_euclid(choices.len() as i32) as usize];
+            match (*target, next) {
+                (Some(note), None) => {
+                    curves.pads.remove(&note);
+                }
+                (_, Some(shape)) => {
+                    let curve = edit(curves, *target);
+                    if shape == CurveShape::Custom {
+                        curve.make_custom();
+                    } else {
+                        curve.curve = shape;
+                    }
+                }
+                (None, None) => {}
+            }
+            *point = 0;
+            true
+        }
+        ROW_MIN => {
+            let curve = edit(curves, *target);
+            curve.min = (curve.min as i32 + delta).clamp(0, curve.max as i32) as u8;
+            true
+        }
+        ROW_MAX => {
+            let curve = edit(curves, *target);
+            curve.max = (curve.max as i32 + delta).clamp(curve.min as i32, 127) as u8;
+            true
+        }
+        ROW_POINT => {
+            let count = curve_for(curves, *target).points.len().max(1) as i32;
+            *point = (*point as i32 + delta.signum()).rem_euclid(count) as usize;
+            false
+        }
+        _ => {
+            let curve = edit(curves, *target);
+            if let Some(p) = curve.points.get_mut(*point) {
+                p[1] = (p[1] as i32 + delta).clamp(0, 127) as u8;
+            }
+            true
+        }
+    };
+
+    if changed {
+        let (target, curves) = (*target, curves.clone());
+        save(state, resources, target, curves);
+    }
+}
+
+/// Put the edited curves into effect and persist the one for `target`.
+fn save(state: &mut AppState, resources: &PlayResources, target: Option<u8>, curves: VelocityCurves) {
+    let pad_curve = target.and_then(|note| curves.pads.get(&note).cloned());
+    let status = match target {
+        Some(note) => match &pad_curve {
+            Some(curve) => format!("Velocity {} ({}): {}", state.mapping.drum_name(note), note, curve.label()),
+            None => format!("Velocity {} ({}): global curve", state.mapping.drum_name(note), note),
+        },
+        None => format!("Velocity global: {}", curves.global.label()),
+    };
+    state.set_status(status);
+
+    let mut s = settings::load_settings();
+    match (target, pad_curve) {
+        (Some(note), Some(curve)) => {
+            s.velocity.pads.insert(note.to_string(), curve);
+        }
+        (Some(note), None) => {
+            s.velocity.pads.remove(&note.to_string());
+        }
+        (None, _) => s.velocity.global = curves.global.clone(),
+    }
+    let _ = settings::save_settings(&s);
+    resources.velocity.store(Arc::new(curves));
+}
+
+/// The curve to change for `target`; a pad following the global curve gets a copy
+/// of it first.
+fn edit(curves: &mut VelocityCurves, target: Option<u8>) -> &mut VelocityCurve {
+    match target {
+        Some(note) => {
+            let global = &curves.global;
+            curves.pads.entry(note).or_insert_with(|| global.clone())
+        }
+        None => &mut curves.global,
+    }
+}
@@ -0,0 +1,63 @@
+use super::{AppState, PlayResources};
+use crate::hotplug::{self, DeviceChange};
+use crate::midi;
+
+/// Reconnect MIDI inputs and the audio output as their devices come back.
+pub(super) fn handle_change(state: &mut AppState, resources: &mut PlayResources, change: DeviceChange) {
+    match change {
+        DeviceChange::Midi(devices) => {
+            let targets = resources.midi_targets();
+            let lines = resources.missing_midi.sync(
+                &mut resources.midi_inputs,
+                &devices,
+                &targets,
+                &resources.extra_mapping_dirs,
+            );
+            for line in lines {
+                log(state, line);
+            }
+            state.midi_device = midi::inputs_label(&resources.midi_inputs);
+            state.midi_unplugged = resources.missing_midi.names();
+        }
+        DeviceChange::Audio(devices) => {
+            // Already back, e.g. picked by hand in the audio popup
+            if resources.stream.is_some() {
+                return;
+            }
+            let Some(device_index) = hotplug::find_audio_device(&devices, Some(&resources.audio_device_name)) else {
+                return;
+            };
+            // On failure it's tried again on the next poll
+            if let Ok(stream) = resources.stream_setup().open(&resources.producer, device_index) {
+                resources.stream = Some(stream);
+                if let Some(index) = device_index {
+                    resources.audio_device_index = index;
+                }
+                let line = format!("Audio output reconnected: {}", resources.audio_device_name);
+                log(state, line);
+                check_audio(state, resources);
+            }
+        }
+    }
+}
+
+/// Notice the output stream failing (e.g. its device was unplugged): release it so
+/// the watcher can look for the device coming back.
+pub(super) fn check_audio(state: &mut AppState, resources: &mut PlayResources) {
+    if resources.stream.as_ref().is_some_and(|s| s.has_failed()) {
+        *resources.producer.lock().unwrap() = None;
+        resources.stream = None;
+        let line = format!("Audio output lost: {}", resources.audio_device_name);
+        log(state, line);
+    }
+    let lost = resources.stream.is_none();
+    if lost != state.audio_lost {
+        state.audio_lost = lost;
+        resources.device_watcher.set_audio_lost(lost);
+    }
+}
+
+fn log(state: &mut AppState, line: String) {
+    state.log_lines.push(line.clone());
+    state.set_status(line);
+}
@@ -1,14 +1,15 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use notify::Watcher;
 use ratatui::{widgets::ListState, Terminal};
 use std::io;
+use std::path::PathBuf;
 use std::sync::{mpsc, Arc};
 use std::time::Duration;
 
 use super::list_nav::first_selectable;
-use super::{popups, render, AppState, PlayResources, Popup, TuiEvent};
-use crate::{audio, download, kit, mapping, midi, settings};
+use super::{curves, devices, midi_learn, mixer, popups, remote, render, stats, AppState, LoadedKit, PlayResources, Popup, TuiEvent};
+use crate::{audio, channels, control, download, kit, kitswitch, mapping, master, metronome, midi, midifile, pipeline, record, settings};
 
 pub(super) fn event_loop(
     terminal: &mut Terminal<ratatui::backend::CrosstermBackend<io::Stdout>>,
@@ -22,6 +23,14 @@ pub(super) fn event_loop(
             cap.drain_into(&mut state.log_lines);
         }
 
+        devices::check_audio(state, resources);
+        state.meter.update(&resources.master.meter);
+
+        // A stream rebuild drops the recording tap, which ends the recording
+        if resources.recording.as_ref().is_some_and(|r| r.is_finished()) {
+            let _ = finish_recording(state, resources);
+        }
+
         let extra_kit_dirs = &resources.extra_kits_dirs;
         let extra_mapping_dirs = &resources.extra_mapping_dirs;
         let kit_repos = &resources.kit_repos;
@@ -84,12 +93,13 @@ pub(super) fn event_loop(
                     }
                     KeyCode::Char('m') => {
                         if let Ok(devices) = midi::list_devices() {
+                            let connected = popups::midi_picker_connected(resources, &devices);
                             let mut list_state = ListState::default();
                             if !devices.is_empty() {
-                                let sel = devices.iter().position(|d| d.port_index == resources.midi_port_index).unwrap_or(0);
+                                let sel = connected.iter().position(|&c| c).unwrap_or(0);
                                 list_state.select(Some(sel));
                             }
-                            state.popup = Some(Popup::MidiPicker { devices, list_state });
+                            state.popup = Some(Popup::MidiPicker { devices, connected, list_state });
                         }
                     }
                     KeyCode::Char('n') => {
@@ -112,6 +122,30 @@ pub(super) fn event_loop(
                             });
                         }
                     }
+                    KeyCode::Char('v') => curves::open(state, resources),
+                    KeyCode::Char('h') => stats::open(state, resources),
+                    KeyCode::Char('L') => midi_learn::open(state),
+                    KeyCode::Char('x') => mixer::open(state, resources),
+                    KeyCode::Char('w') => toggle_recording(state, resources),
+                    KeyCode::Char('c') => toggle_midi_capture(state, resources),
+                    KeyCode::Char('t') => update_metronome(state, resources, |m| m.enabled = !m.enabled),
+                    KeyCode::Char('[') => update_metronome(state, resources, |m| m.bpm -= 1.0),
+                    KeyCode::Char(']') => update_metronome(state, resources, |m| m.bpm += 1.0),
+                    KeyCode::Char('{') => update_metronome(state, resources, |m| m.bpm -= 10.0),
+                    KeyCode::Char('}') => update_metronome(state, resources, |m| m.bpm += 10.0),
+                    KeyCode::Char('b') => update_metronome(state, resources, |m| m.next_time_signature()),
+                    KeyCode::Char('u') => update_metronome(state, resources, |m| {
+                        m.subdivision = m.subdivision % metronome::MAX_SUBDIVISION + 1;
+                    }),
+                    KeyCode::Char('e') => update_metronome(state, resources, |m| m.next_accent()),
+                    KeyCode::Char('-') => update_metronome(state, resources, |m| m.volume -= 0.1),
+                    KeyCode::Char('=') | KeyCode::Char('+') => update_metronome(state, resources, |m| m.volume += 0.1),
+                    KeyCode::Char(',') => update_master(state, resources, |m| m.gain_db -= 1.0),
+                    KeyCode::Char('.') => update_master(state, resources, |m| m.gain_db += 1.0),
+                    KeyCode::Char('z') => {
+                        resources.master.meter.reset_clips();
+                        state.set_status("Clip count reset".to_string());
+                    }
                     KeyCode::Char('s') => {
                         state.popup = Some(Popup::KitStoreFetching);
                         let tx = resources.tui_tx.clone();
@@ -132,6 +166,7 @@ pub(super) fn event_loop(
             match ev {
                 TuiEvent::Hit { note, velocity } => {
                     state.on_hit(note, velocity);
+                    midi_learn::on_hit(state, note);
                 }
                 TuiEvent::Choke { note } => state.on_choke(note),
                 TuiEvent::KitReloaded { note_keys, kit_path } => {
@@ -200,6 +235,11 @@ pub(super) fn event_loop(
                         }
                     }
                 }
+                TuiEvent::Control(request) => remote::handle_request(state, resources, request),
+                TuiEvent::Devices(change) => devices::handle_change(state, resources, change),
+                TuiEvent::KitSwitch(selection) => switch_kit_by_program(state, resources, selection),
+                TuiEvent::PadVolume { note, db } => mixer::set_volume(state, resources, note, db),
+                TuiEvent::OscError(msg) => state.log_lines.push(format!("OSC: {}", msg)),
                 TuiEvent::KitLoadComplete { result, path, name } => {
                     // Only process if we're still showing the Loading popup for this kit
                     let is_loading = matches!(
@@ -207,55 +247,42 @@ pub(super) fn event_loop(
                         Some(Popup::Loading { kit_name, .. }) if *kit_name == name
                     );
                     if !is_loading {
+                        reply_kit_load(resources, &name, control::ControlResponse::error("Kit load cancelled"));
                         continue; // Load was cancelled
                     }
                     match result {
-                        Ok(new_kit) => {
+                        Ok(LoadedKit { kit: new_kit, mix, impulse_error }) => {
                             // Rebuild audio stream if sample rate or channels changed
                             let need_stream_rebuild = new_kit.sample_rate != resources.sample_rate
                                 || new_kit.channels != resources.channels;
 
                             if need_stream_rebuild {
-                                // Pause audio: take the producer so MIDI callback drops events
-                                {
-                                    let mut guard = resources.producer.lock().unwrap();
-                                    *guard = None;
-                                }
-
-                                let (new_producer, new_consumer) = rtrb::RingBuffer::new(128);
-
-                                match audio::run_output_stream(
-                                    Some(resources.audio_device_index),
-                                    new_consumer,
-                                    new_kit.sample_rate,
-                                    new_kit.channels,
-                                ) {
+                                let index = Some(resources.audio_device_index);
+                                let setup = pipeline::StreamSetup {
+                                    sample_rate: new_kit.sample_rate,
+                                    channels: new_kit.channels,
+                                    mix: &mix,
+                                    ..resources.stream_setup()
+                                };
+                                match setup.open(&resources.producer, index) {
                                     Ok(new_stream) => {
-                                        resources.stream = new_stream;
+                                        resources.stream = Some(new_stream);
                                         resources.sample_rate = new_kit.sample_rate;
+                                        resources.shared_sample_rate.store(new_kit.sample_rate, std::sync::atomic::Ordering::Relaxed);
                                         resources.channels = new_kit.channels;
-                                        {
-                                            let mut guard = resources.producer.lock().unwrap();
-                                            *guard = Some(new_producer);
-                                        }
+                                        reload_channel_kits(resources);
                                     }
                                     Err(e) => {
-                                        // Try to restore old stream
-                                        let (restore_producer, restore_consumer) = rtrb::RingBuffer::new(128);
-                                        if let Ok(restored) = audio::run_output_stream(
-                                            Some(resources.audio_device_index),
-                                            restore_consumer,
-                                            resources.sample_rate,
-                                            resources.channels,
-                                        ) {
-                                            resources.stream = restored;
-                                            let mut guard = resources.producer.lock().unwrap();
-                                            *guard = Some(restore_producer);
-                                        }
+                                        // Back to the old stream; if that fails too, the
+                                        // device watcher picks it up again
+                                        resources.stream = resources.stream_setup().open(&resources.producer, index).ok();
                                         state.set_status(format!(
                                             "Stream rebuild failed: {}",
                                             e
                                         ));
+                                        reply_kit_load(resources, &name, control::ControlResponse::error(
+                                            format!("Stream rebuild failed: {}", e),
+                                        ));
                                         state.popup = None;
                                         continue;
                                     }
@@ -269,6 +296,9 @@ pub(super) fn event_loop(
                             resources.suppress_reload.store(true, std::sync::atomic::Ordering::Relaxed);
                             let _ = resources.watcher.unwatch(&resources.kit_path);
                             let _ = resources.watcher.watch(path.as_ref(), notify::RecursiveMode::NonRecursive);
+                            // Each kit keeps its own mix, loaded with the kit
+                            resources.mix = mix;
+                            resources.mix.send(&resources.producer);
                             resources.kit_path = path.clone();
                             resources.shared_kit_path.store(Arc::new(path.clone()));
 
@@ -279,12 +309,16 @@ pub(super) fn event_loop(
                             resources.shared_mapping.store(Arc::clone(&new_mapping));
                             state.mapping = new_mapping;
 
+                            reply_kit_load(resources, &name, control::ControlResponse::ok(format!("Kit: {}", name)));
                             state.kit_name = name;
                             state.sample_rate = resources.sample_rate;
                             state.channels = resources.channels;
                             state.rebuild_pads(&note_keys);
                             state.update_hit_log_names();
-                            state.set_status("Kit loaded".to_string());
+                            match impulse_error {
+                                Some(e) => state.set_status(format!("Kit loaded; IR bus: {}", e)),
+                                None => state.set_status("Kit loaded".to_string()),
+                            }
 
                             // Persist selected kit to settings
                             let mut s = settings::load_settings();
@@ -292,6 +326,9 @@ pub(super) fn event_loop(
                             let _ = settings::save_settings(&s);
                         }
                         Err(e) => {
+                            reply_kit_load(resources, &name, control::ControlResponse::error(
+                                format!("Kit load error: {}", e),
+                            ));
                             state.set_status(format!("Kit load error: {}", e));
                         }
                     }
@@ -302,3 +339,182 @@ pub(super) fn event_loop(
 
     }
 }
+
+/// Load the kit the drum module picked in the background, like the kit picker,
+/// unless it's already playing or loading.
+fn switch_kit_by_program(state: &mut AppState, resources: &PlayResources, selection: kitswitch::KitSelection) {
+    match control::resolve_kit(&selection.kit, &resources.extra_kits_dirs) {
+        Ok((path, name)) => {
+            let loading = matches!(&state.popup, Some(Popup::Loading { kit_name, .. }) if *kit_name == name);
+            if path == resources.kit_path || loading {
+                return;
+            }
+            state.set_status(format!("{}: {}", selection.label(), name));
+            let rate = resources.sample_rate;
+            popups::start_kit_load(state, resources, path, name, rate);
+        }
+        Err(e) => state.set_status(format!("{}: {:#}", selection.label(), e)),
+    }
+}
+
+/// Answer a control client waiting on the load of kit `name`, if any.
+fn reply_kit_load(resources: &mut PlayResources, name: &str, response: control::ControlResponse) {
+    if resources.pending_kit_reply.as_ref().is_some_and(|(pending, _)| pending == name)
+        && let Some((_, reply)) = resources.pending_kit_reply.take()
+    {
+        let _ = reply.send(response);
+    }
+}
+
+/// Reload the per-channel kits in the background after the stream's sample rate or
+/// channel count changed. Until they're ready, the old ones keep playing.
+fn reload_channel_kits(resources: &PlayResources) {
+    if resources.channel_routing.load().kits().is_empty() {
+        return;
+    }
+    let routing = Arc::clone(&resources.channel_routing);
+    let shared_sample_rate = Arc::clone(&resources.shared_sample_rate);
+    let kits_dirs = resources.extra_kits_dirs.clone();
+    let (rate, channels) = (resources.sample_rate, resources.channels);
+    std::thread::spawn(move || {
+        let midi_settings = settings::load_settings().midi;
+        let (new_routing, errors) = channels::load_routing(&midi_settings, &kits_dirs, rate, channels);
+        for e in errors {
+            eprintln!("{}", e);
+        }
+        // Another rebuild may have happened while loading
+        if shared_sample_rate.load(std::sync::atomic::Ordering::Relaxed) == rate {
+            routing.store(Arc::new(new_routing));
+        }
+    });
+}
+
+/// Start recording the master output, or stop the recording in progress.
+fn toggle_recording(state: &mut AppState, resources: &mut PlayResources) {
+    let result = if resources.recording.is_some() {
+        stop_recording(state, resources)
+    } else {
+        start_recording(state, resources)
+    };
+    if let Err(e) = result {
+        state.set_status(format!("Recording error: {}", e));
+    }
+}
+
+/// Start recording the master output to a new file; returns its path.
+pub(super) fn start_recording(state: &mut AppState, resources: &mut PlayResources) -> Result<PathBuf> {
+    if resources.recording.is_some() {
+        anyhow::bail!("Already recording");
+    }
+    let mut guard = resources.producer.lock().unwrap();
+    let Some(ref mut prod) = *guard else { anyhow::bail!("Audio stream is not running") };
+
+    let (recording, tap) = record::start(&resources.recordings_dir, resources.sample_rate, resources.channels)?;
+    if prod.push(audio::AudioCommand::StartRecording(tap)).is_err() {
+        anyhow::bail!("Failed to start recording (audio queue full)");
+    }
+    let path = recording.path.clone();
+    state.recording = Some(Arc::clone(&recording.stats));
+    state.set_status(format!("Recording to {}", path.display()));
+    resources.recording = Some(recording);
+    Ok(path)
+}
+
+/// Stop the recording in progress and wait for the file to be finalized.
+pub(super) fn stop_recording(state: &mut AppState, resources: &mut PlayResources) -> Result<PathBuf> {
+    if resources.recording.is_none() {
+        anyhow::bail!("Not recording");
+    }
+    {
+        let mut guard = resources.producer.lock().unwrap();
+        let Some(ref mut prod) = *guard else { anyhow::bail!("Audio stream is not running") };
+        if prod.push(audio::AudioCommand::StopRecording).is_err() {
+            anyhow::bail!("Failed to stop recording (audio queue full)");
+        }
+    }
+    finish_recording(state, resources)
+}
+
+/// Wait for the writer to finalize the current recording and report the result.
+fn finish_recording(state: &mut AppState, resources: &mut PlayResources) -> Result<PathBuf> {
+    state.recording = None;
+    let recording = resources.recording.take().context("Not recording")?;
+    let stats = Arc::clone(&recording.stats);
+    let result = recording.finish();
+    match &result {
+        Ok(path) => state.set_status(record::saved_message(path, &stats)),
+        Err(e) => state.set_status(format!("Recording error: {}", e)),
+    }
+    result
+}
+
+/// Start capturing MIDI input, or save the capture in progress as a `.mid` file.
+fn toggle_midi_capture(state: &mut AppState, resources: &mut PlayResources) {
+    let mut guard = resources.midi_capture.lock().unwrap();
+    match guard.take() {
+        Some(capture) => {
+            drop(guard);
+            state.midi_capture_started = None;
+            match capture.save(&resources.recordings_dir) {
+                Ok(path) => state.set_status(format!(
+                    "Saved {} ({} events)",
+                    path.display(),
+                    capture.events().len()
+                )),
+                Err(e) => state.set_status(format!("MIDI capture error: {}", e)),
+            }
+        }
+        None => {
+            let options = resources.capture_options;
+            *guard = Some(midifile::MidiCapture::new(options.bpm, options.ppq));
+            state.midi_capture_started = Some(std::time::Instant::now());
+            state.set_status(format!("Capturing MIDI at {} BPM", options.bpm));
+        }
+    }
+}
+
+/// Change the master stage's settings, apply them, and persist them.
+pub(super) fn update_master(state: &mut AppState, resources: &mut PlayResources, change: impl FnOnce(&mut master::MasterSettings)) {
+    let mut m = resources.master.settings;
+    change(&mut m);
+    let m = m.sanitized();
+    resources.master.settings = m;
+    resources.master.send(&resources.producer);
+    state.set_status(m.label());
+
+    let mut s = settings::load_settings();
+    s.master = m;
+    let _ = settings::save_settings(&s);
+}
+
+/// Change the metronome settings, send them to the audio thread and persist them.
+fn update_metronome(
+    state: &mut AppState,
+    resources: &mut PlayResources,
+    change: impl FnOnce(&mut metronome::MetronomeSettings),
+) {
+    let mut m = resources.metronome;
+    change(&mut m);
+    let m = m.sanitized();
+    resources.metronome = m;
+
+    if let Some(ref mut prod) = *resources.producer.lock().unwrap() {
+        let _ = prod.push(audio::AudioCommand::SetMetronome(m));
+    }
+
+    state.metronome_label = m.enabled.then(|| m.label());
+    if m.enabled {
+        state.set_status(format!(
+            "Metronome: {}  accent +{:.0} dB  volume {:.0}%",
+            m.label(),
+            m.accent_db,
+            m.volume * 100.0
+        ));
+    } else {
+        state.set_status("Metronome off".to_string());
+    }
+
+    let mut s = settings::load_settings();
+    s.metronome = m;
+    let _ = settings::save_settings(&s);
+}
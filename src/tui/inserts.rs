@@ -0,0 +1,231 @@
+use crossterm::event::KeyCode;
+
+use super::{mixer, AppState, PlayResources, Popup};
+use crate::inserts::{CompressorSettings, EqBand, EqSettings, PadInserts, TransientSettings};
+use crate::mix::PadMix;
+
+/// The three bands of the EQ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Band {
+    Low,
+    Mid,
+    High,
+}
+
+/// One row of the inserts view. The `*On` rows head each effect's section.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Param {
+    EqOn,
+    Freq(Band),
+    Gain(Band),
+    Q(Band),
+    CompressorOn,
+    Threshold,
+    Ratio,
+    Attack,
+    Release,
+    Makeup,
+    ShaperOn,
+    ShaperAttack,
+    ShaperSustain,
+}
+
+/// Which effect a row belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Section {
+    Eq,
+    Compressor,
+    Shaper,
+}
+
+pub(super) const PARAMS: [Param; 19] = [
+    Param::EqOn,
+    Param::Freq(Band::Low),
+    Param::Gain(Band::Low),
+    Param::Q(Band::Low),
+    Param::Freq(Band::Mid),
+    Param::Gain(Band::Mid),
+    Param::Q(Band::Mid),
+    Param::Freq(Band::High),
+    Param::Gain(Band::High),
+    Param::Q(Band::High),
+    Param::CompressorOn,
+    Param::Threshold,
+    Param::Ratio,
+    Param::Attack,
+    Param::Release,
+    Param::Makeup,
+    Param::ShaperOn,
+    Param::ShaperAttack,
+    Param::ShaperSustain,
+];
+
+impl Param {
+    pub(super) fn section(self) -> Section {
+        match self {
+            Param::EqOn | Param::Freq(_) | Param::Gain(_) | Param::Q(_) => Section::Eq,
+            Param::CompressorOn | Param::Threshold | Param::Ratio | Param::Attack | Param::Release | Param::Makeup => {
+                Section::Compressor
+            }
+            Param::ShaperOn | Param::ShaperAttack | Param::ShaperSustain => Section::Shaper,
+        }
+    }
+
+    pub(super) fn is_heading(self) -> bool {
+        matches!(self, Param::EqOn | Param::CompressorOn | Param::ShaperOn)
+    }
+
+    pub(super) fn label(self) -> String {
+        let band = |band: Band| match band {
+            Band::Low => "Low shelf",
+            Band::Mid => "Mid",
+            Band::High => "High shelf",
+        };
+        match self {
+            Param::EqOn => "EQ".to_string(),
+            Param::Freq(b) => format!("{} freq", band(b)),
+            Param::Gain(b) => format!("{} gain", band(b)),
+            Param::Q(b) => format!("{} Q", band(b)),
+            Param::CompressorOn => "Compressor".to_string(),
+            Param::Threshold => "Threshold".to_string(),
+            Param::Ratio => "Ratio".to_string(),
+            Param::Attack => "Attack".to_string(),
+            Param::Release => "Release".to_string(),
+            Param::Makeup => "Makeup".to_string(),
+            Param::ShaperOn => "Transient shaper".to_string(),
+            Param::ShaperAttack => "Attack".to_string(),
+            Param::ShaperSustain => "Sustain".to_string(),
+        }
+    }
+
+    /// e.g. "on", "1.2 kHz", "-3.0 dB" or "4.0:1".
+    pub(super) fn value(self, inserts: &PadInserts) -> String {
+        let on = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+        match self {
+            Param::EqOn => on(inserts.eq.enabled),
+            Param::Freq(b) => {
+                let freq = band(&inserts.eq, b).freq;
+                if freq < 1000.0 { format!("{:.0} Hz", freq) } else { format!("{:.1} kHz", freq / 1000.0) }
+            }
+            Param::Gain(b) => format!("{:+.1} dB", band(&inserts.eq, b).gain_db),
+            Param::Q(b) => format!("{:.2}", band(&inserts.eq, b).q),
+            Param::CompressorOn => on(inserts.compressor.enabled),
+            Param::Threshold => format!("{:.0} dB", inserts.compressor.threshold_db),
+            Param::Ratio => format!("{:.1}:1", inserts.compressor.ratio),
+            Param::Attack => format!("{:.1} ms", inserts.compressor.attack_ms),
+            Param::Release => format!("{:.0} ms", inserts.compressor.release_ms),
+            Param::Makeup => format!("{:+.1} dB", inserts.compressor.makeup_db),
+            Param::ShaperOn => on(inserts.transient.enabled),
+            Param::ShaperAttack => format!("{:+.0}%", inserts.transient.attack * 100.0),
+            Param::ShaperSustain => format!("{:+.0}%", inserts.transient.sustain * 100.0),
+        }
+    }
+
+    /// Move the value `delta` steps: 1/6 octave of frequency or Q, 0.5 dB of gain,
+    /// 1 dB of threshold, 0.5 of ratio, a quarter octave of time or 5% of shaping.
+    /// The headings flip their effect on or off.
+    fn adjust(self, inserts: &mut PadInserts, delta: f32) {
+        let octaves = |value: f32, per_octave: f32| value * 2.0_f32.powf(delta / per_octave);
+        let steps = |value: f32, size: f32| ((value / size).round() + delta) * size;
+        match self {
+            Param::EqOn => inserts.eq.enabled = !inserts.eq.enabled,
+            Param::Freq(b) => band_mut(&mut inserts.eq, b).freq = octaves(band(&inserts.eq, b).freq, 6.0),
+            Param::Gain(b) => band_mut(&mut inserts.eq, b).gain_db = steps(band(&inserts.eq, b).gain_db, 0.5),
+            Param::Q(b) => band_mut(&mut inserts.eq, b).q = octaves(band(&inserts.eq, b).q, 6.0),
+            Param::CompressorOn => inserts.compressor.enabled = !inserts.compressor.enabled,
+            Param::Threshold => inserts.compressor.threshold_db = steps(inserts.compressor.threshold_db, 1.0),
+            Param::Ratio => inserts.compressor.ratio = steps(inserts.compressor.ratio, 0.5),
+            Param::Attack => inserts.compressor.attack_ms = octaves(inserts.compressor.attack_ms, 4.0),
+            Param::Release => inserts.compressor.release_ms = octaves(inserts.compressor.release_ms, 4.0),
+            Param::Makeup => inserts.compressor.makeup_db = steps(inserts.compressor.makeup_db, 0.5),
+            Param::ShaperOn => inserts.transient.enabled = !inserts.transient.enabled,
+            Param::ShaperAttack => inserts.transient.attack = steps(inserts.transient.attack, 0.05),
+            Param::ShaperSustain => inserts.transient.sustain = steps(inserts.transient.sustain, 0.05),
+        }
+    }
+}
+
+fn band(eq: &EqSettings, band: Band) -> EqBand {
+    match band {
+        Band::Low => eq.low,
+        Band::Mid => eq.mid,
+        Band::High => eq.high,
+    }
+}
+
+fn band_mut(eq: &mut EqSettings, band: Band) -> &mut EqBand {
+    match band {
+        Band::Low => &mut eq.low,
+        Band::Mid => &mut eq.mid,
+        Band::High => &mut eq.high,
+    }
+}
+
+/// Open the inserts of the pad on mixer row `pad`.
+pub(super) fn open(state: &mut AppState, resources: &PlayResources, pad: usize) {
+    let Some(note) = state.pads.get(pad).map(|p| p.note) else {
+        return;
+    };
+    state.popup = Some(Popup::Inserts {
+        pad,
+        row: 0,
+        mix: resources.mix.for_note(note),
+    });
+}
+
+pub(super) fn handle_key(state: &mut AppState, resources: &mut PlayResources, key: KeyCode) {
+    let Some(Popup::Inserts { pad, row, mix }) = state.popup.as_mut() else {
+        return;
+    };
+    let Some(note) = state.pads.get(*pad).map(|p| p.note) else {
+        state.popup = None;
+        return;
+    };
+    let param = PARAMS[*row];
+    let mut inserts = mix.inserts;
+
+    // ←/→ step by one, PgUp/PgDn by ten; Enter and Space switch the effect on or off
+    match key {
+        KeyCode::Esc | KeyCode::Char('i') => {
+            let pad = *pad;
+            mixer::open_at(state, resources, pad);
+            return;
+        }
+        KeyCode::Char('q') => {
+            state.popup = None;
+            state.should_quit = true;
+            return;
+        }
+        KeyCode::Up => {
+            *row = row.checked_sub(1).unwrap_or(PARAMS.len() - 1);
+            return;
+        }
+        KeyCode::Down => {
+            *row = (*row + 1) % PARAMS.len();
+            return;
+        }
+        KeyCode::Enter | KeyCode::Char(' ') => {
+            let heading = PARAMS.iter().find(|p| p.is_heading() && p.section() == param.section());
+            if let Some(heading) = heading {
+                heading.adjust(&mut inserts, 0.0);
+            }
+        }
+        KeyCode::Delete | KeyCode::Backspace => match param.section() {
+            Section::Eq => inserts.eq = EqSettings::default(),
+            Section::Compressor => inserts.compressor = CompressorSettings::default(),
+            Section::Shaper => inserts.transient = TransientSettings::default(),
+        },
+        key => match mixer::step(key) {
+            Some(delta) => param.adjust(&mut inserts, delta),
+            None => return,
+        },
+    }
+
+    let pad = PadMix { inserts, ..*mix }.sanitized();
+    if pad == *mix {
+        return;
+    }
+    *mix = pad;
+    resources.mix.set(note, pad);
+    mixer::save(state, resources, note, pad);
+}
@@ -0,0 +1,94 @@
+use crossterm::event::KeyCode;
+
+use super::input::handle_text_input_key;
+use super::{popups, AppState, Popup};
+use crate::learn::{self, SLOTS};
+use crate::mapping::{self, MappingSource};
+
+pub(super) fn open(state: &mut AppState) {
+    state.popup = Some(Popup::MidiLearn {
+        step: 0,
+        learned: vec![None; SLOTS.len()],
+        input: String::new(),
+        cursor: 0,
+    });
+}
+
+/// Record a hit's incoming note for the slot being learned and move on to the next.
+pub(super) fn on_hit(state: &mut AppState, note: u8) {
+    let Some(Popup::MidiLearn { step, learned, input, cursor }) = state.popup.as_mut() else {
+        return;
+    };
+    if *step >= SLOTS.len() {
+        return;
+    }
+    // A note belongs to one slot: hitting a learned pad again moves it here
+    for other in learned.iter_mut() {
+        if *other == Some(note) {
+            *other = None;
+        }
+    }
+    learned[*step] = Some(note);
+    *step += 1;
+    if *step == SLOTS.len() {
+        start_naming(&state.midi_device, input, cursor);
+    }
+}
+
+fn start_naming(midi_device: &str, input: &mut String, cursor: &mut usize) {
+    if input.is_empty() {
+        *input = midi_device.to_string();
+    }
+    *cursor = input.len();
+}
+
+pub(super) fn handle_key(state: &mut AppState, resources: &super::PlayResources, key: KeyCode) {
+    let Some(Popup::MidiLearn { step, learned, input, cursor }) = state.popup.as_mut() else {
+        return;
+    };
+
+    // Naming the mapping once every slot has been visited
+    if *step >= SLOTS.len() {
+        match key {
+            KeyCode::Esc => *step = SLOTS.len() - 1,
+            KeyCode::Enter if !input.trim().is_empty() => {
+                let mut new_mapping = learn::build_mapping(input.trim().to_string(), learned);
+                match mapping::save_user_mapping(&new_mapping) {
+                    Ok(path) => {
+                        new_mapping.source = MappingSource::UserFile(path.clone());
+                        popups::apply_mapping(state, resources, new_mapping);
+                        state.set_status(format!("Saved mapping to {}", path.display()));
+                        state.popup = None;
+                    }
+                    Err(e) => state.set_status(format!("Failed to save mapping: {:#}", e)),
+                }
+            }
+            other => {
+                handle_text_input_key(input, cursor, None, other);
+            }
+        }
+        return;
+    }
+
+    match key {
+        KeyCode::Esc => state.popup = None,
+        KeyCode::Up | KeyCode::Left => *step = step.saturating_sub(1),
+        // Skip the slot, keeping whatever it had
+        KeyCode::Down | KeyCode::Right | KeyCode::Char(' ') => {
+            *step += 1;
+            if *step == SLOTS.len() {
+                start_naming(&state.midi_device, input, cursor);
+            }
+        }
+        KeyCode::Delete | KeyCode::Backspace => learned[*step] = None,
+        KeyCode::Enter => {
+            if learned.iter().all(Option::is_none) {
+                state.set_status("Hit a pad to learn its note first".to_string());
+            } else {
+                *step = SLOTS.len();
+                start_naming(&state.midi_device, input, cursor);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use std::path::Path;
+
+use super::{inserts, AppState, PlayResources, Popup};
+use crate::audio::AudioCommand;
+use crate::convolution::{self, ConvolutionSettings};
+use crate::mix::{self, KitMix, PadMix};
+use crate::reverb::ReverbSettings;
+use crate::settings;
+
+/// Editable columns of a pad's row.
+pub(super) const COL_VOLUME: usize = 0;
+pub(super) const COL_PAN: usize = 1;
+pub(super) const COL_SEND: usize = 2;
+pub(super) const COL_IR_SEND: usize = 3;
+pub(super) const COL_MUTE: usize = 4;
+pub(super) const COL_SOLO: usize = 5;
+const COLUMNS: usize = 6;
+
+/// Editable columns of the reverb row, below the pads.
+pub(super) const COL_SIZE: usize = 0;
+pub(super) const COL_DAMPING: usize = 1;
+pub(super) const COL_WET: usize = 2;
+const REVERB_COLUMNS: usize = 3;
+
+/// Editable columns of the IR row, below the reverb.
+pub(super) const COL_RESPONSE: usize = 0;
+pub(super) const COL_IR_WET: usize = 1;
+const IR_COLUMNS: usize = 2;
+
+/// Open the mixer on the most recently hit pad.
+pub(super) fn open(state: &mut AppState, resources: &PlayResources) {
+    let selected = state
+        .hit_log
+        .first()
+        .and_then(|entry| state.pad_index.get(&state.mapping.remap_note(entry.note)))
+        .copied()
+        .unwrap_or(0);
+    open_at(state, resources, selected);
+}
+
+/// Open the mixer on row `selected`.
+pub(super) fn open_at(state: &mut AppState, resources: &PlayResources, selected: usize) {
+    state.popup = Some(Popup::Mixer {
+        selected,
+        column: COL_VOLUME,
+        mix: resources.mix.clone(),
+    });
+}
+
+pub(super) fn handle_key(state: &mut AppState, resources: &mut PlayResources, key: KeyCode) {
+    let Some(Popup::Mixer { selected, column, mix }) = state.popup.as_mut() else {
+        return;
+    };
+    // A row per pad, then the reverb and IR buses
+    let rows = state.pads.len() + 2;
+    let columns = |row: usize| match row.cmp(&state.pads.len()) {
+        std::cmp::Ordering::Less => COLUMNS,
+        std::cmp::Ordering::Equal => REVERB_COLUMNS,
+        std::cmp::Ordering::Greater => IR_COLUMNS,
+    };
+
+    match key {
+        KeyCode::Esc | KeyCode::Char('x') => {
+            state.popup = None;
+            return;
+        }
+        KeyCode::Char('q') => {
+            state.popup = None;
+            state.should_quit = true;
+            return;
+        }
+        KeyCode::Up => *selected = selected.checked_sub(1).unwrap_or(rows - 1),
+        KeyCode::Down => *selected = (*selected + 1) % rows,
+        KeyCode::Char('i') if *selected < state.pads.len() => {
+            let pad = *selected;
+            inserts::open(state, resources, pad);
+            return;
+        }
+        KeyCode::Tab => *column = (*column + 1) % columns(*selected),
+        KeyCode::BackTab => *column = (*column + columns(*selected) - 1) % columns(*selected),
+        _ if *selected > state.pads.len() => {
+            let mut convolution = mix.convolution.clone();
+            match (key, step(key)) {
+                (KeyCode::Delete | KeyCode::Backspace, _) => convolution = ConvolutionSettings::default(),
+                (_, Some(delta)) if *column == COL_RESPONSE => {
+                    convolution.ir = next_impulse(&resources.kit_path, convolution.ir.as_deref(), delta);
+                }
+                (_, Some(delta)) => convolution.wet = ((convolution.wet * 20.0).round() + delta) / 20.0,
+                _ => return,
+            }
+            let convolution = convolution.sanitized();
+            if convolution != mix.convolution {
+                // Decoded here, before the audio thread gets it
+                let mut changed = KitMix { convolution, ..mix.clone() };
+                let loaded = changed.load_impulse(&resources.kit_path, resources.sample_rate);
+                if loaded.is_ok() {
+                    *mix = changed;
+                    resources.mix = mix.clone();
+                }
+                save_convolution(state, resources, loaded);
+            }
+            return;
+        }
+        _ if *selected == state.pads.len() => {
+            let reverb = match (key, step(key)) {
+                (KeyCode::Delete | KeyCode::Backspace, _) => ReverbSettings::default(),
+                (_, Some(delta)) => {
+                    let mut reverb = mix.reverb;
+                    let field = match *column {
+                        COL_SIZE => &mut reverb.size,
+                        COL_DAMPING => &mut reverb.damping,
+                        _ => &mut reverb.wet,
+                    };
+                    *field = ((*field * 20.0).round() + delta) / 20.0;
+                    reverb.sanitized()
+                }
+                _ => return,
+            };
+            if reverb != mix.reverb {
+                mix.reverb = reverb;
+                resources.mix = mix.clone();
+                save_reverb(state, resources, reverb);
+            }
+            return;
+        }
+        _ => {}
+    }
+    if matches!(key, KeyCode::Up | KeyCode::Down | KeyCode::Tab | KeyCode::BackTab) {
+        *column = (*column).min(columns(*selected) - 1);
+        return;
+    }
+
+    let note = state.pads[*selected].note;
+    let mut pad = mix.for_note(note);
+    // ←/→ step by one (1 dB, or 5% of pan or send), PgUp/PgDn by ten; they flip mute and solo
+    let delta = match key {
+        KeyCode::Char('m') => {
+            pad.mute = !pad.mute;
+            None
+        }
+        KeyCode::Char('s') => {
+            pad.solo = !pad.solo;
+            None
+        }
+        KeyCode::Delete | KeyCode::Backspace => {
+            // The inserts have their own reset
+            pad = PadMix { inserts: pad.inserts, ..PadMix::default() };
+            None
+        }
+        KeyCode::Enter | KeyCode::Char(' ') => Some(0.0),
+        key => match step(key) {
+            Some(delta) => Some(delta),
+            None => return,
+        },
+    };
+
+    if let Some(delta) = delta {
+        match *column {
+            COL_VOLUME => pad.volume_db = (pad.volume_db.round() + delta).clamp(mix::MIN_DB, mix::MAX_DB),
+            COL_PAN => pad.pan = ((pad.pan * 20.0).round() + delta) / 20.0,
+            COL_SEND => pad.reverb = ((pad.reverb * 20.0).round() + delta) / 20.0,
+            COL_IR_SEND => pad.convolution = ((pad.convolution * 20.0).round() + delta) / 20.0,
+            COL_MUTE => pad.mute = !pad.mute,
+            _ => pad.solo = !pad.solo,
+        }
+    }
+    let pad = pad.sanitized();
+    if pad == mix.for_note(note) {
+        return;
+    }
+    mix.set(note, pad);
+    resources.mix = mix.clone();
+    save(state, resources, note, pad);
+}
+
+/// Send a pad's new mix to the audio thread and persist it for the current kit.
+pub(super) fn save(state: &mut AppState, resources: &PlayResources, note: u8, pad: PadMix) {
+    state.set_status(format!("Mixer {} ({}): {}", state.mapping.drum_name(note), note, pad.label()));
+    if let Some(prod) = resources.producer.lock().unwrap().as_mut() {
+        let _ = prod.push(AudioCommand::SetPadMix { note, mix: pad });
+    }
+
+    let mut s = settings::load_settings();
+    s.set_pad_mix(&resources.kit_path, note, pad);
+    let _ = settings::save_settings(&s);
+}
+
+/// Steps taken by the adjusting keys.
+pub(super) fn step(key: KeyCode) -> Option<f32> {
+    match key {
+        KeyCode::Right => Some(1.0),
+        KeyCode::Left => Some(-1.0),
+        KeyCode::PageUp => Some(10.0),
+        KeyCode::PageDown => Some(-10.0),
+        _ => None,
+    }
+}
+
+/// Send the reverb bus's new settings to the audio thread and persist them for the current kit.
+fn save_reverb(state: &mut AppState, resources: &PlayResources, reverb: ReverbSettings) {
+    state.set_status(format!("Reverb: {}", reverb.label()));
+    if let Some(prod) = resources.producer.lock().unwrap().as_mut() {
+        let _ = prod.push(AudioCommand::SetReverb(reverb));
+    }
+
+    let mut s = settings::load_settings();
+    s.set_reverb(&resources.kit_path, reverb);
+    let _ = settings::save_settings(&s);
+}
+
+/// Put the IR bus's new response and wet level into effect and persist them for
+/// the current kit, unless the response failed to load.
+fn save_convolution(state: &mut AppState, resources: &PlayResources, loaded: Result<()>) {
+    if let Err(e) = loaded {
+        state.set_status(format!("IR bus: {:#}", e));
+        return;
+    }
+    state.set_status(resources.mix.impulse_label().unwrap_or_else(|| "IR bus: off".to_string()));
+    resources.mix.send_convolution(&resources.producer);
+
+    let mut s = settings::load_settings();
+    s.set_convolution(&resources.kit_path, resources.mix.convolution.clone());
+    let _ = settings::save_settings(&s);
+}
+
+/// The response one step from `current` (in the direction of `delta`) among those
+/// on offer for the kit, with none before the first.
+fn next_impulse(kit: &Path, current: Option<&str>, delta: f32) -> Option<String> {
+    let names: Vec<Option<String>> = std::iter::once(None)
+        .chain(convolution::list_impulses(kit).iter().map(|path| Some(convolution::impulse_name(path, kit))))
+        .collect();
+    let index = names.iter().position(|name| name.as_deref() == current).unwrap_or(0);
+    let next = if delta > 0.0 { index + 1 } else { index + names.len() - 1 };
+    names[next % names.len()].clone()
+}
+
+/// Set a pad's volume without saving it (OSC), keeping an open mixer in step.
+pub(super) fn set_volume(state: &mut AppState, resources: &mut PlayResources, note: u8, db: f32) {
+    let pad = PadMix { volume_db: db, ..resources.mix.for_note(note) }.sanitized();
+    resources.mix.set(note, pad);
+    if let Some(prod) = resources.producer.lock().unwrap().as_mut() {
+        let _ = prod.push(AudioCommand::SetPadMix { note, mix: pad });
+    }
+    if let Some(Popup::Mixer { mix, .. }) = state.popup.as_mut() {
+        mix.set(note, pad);
+    }
+}
@@ -1,9 +1,16 @@
+mod curves;
+mod devices;
 mod event_loop;
 pub(crate) mod input;
+mod inserts;
 pub(crate) mod list_nav;
+mod midi_learn;
+mod mixer;
 mod popups;
+mod remote;
 mod render;
 mod render_popups;
+mod stats;
 pub(crate) mod widgets;
 
 use anyhow::Result;
@@ -16,11 +23,11 @@ use ratatui::{widgets::ListState, Terminal};
 use std::collections::HashMap;
 use std::io::stdout;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::Instant;
 
-use crate::{audio, download, kit, mapping, midi, stderr};
+use crate::{audio, channels, control, download, hotplug, kit, kitswitch, mapping, master, metronome, midi, midifile, mix, osc, pipeline, record, stderr, suppress, thru, velocity};
 
 /// Events fed into the TUI from various sources.
 pub enum TuiEvent {
@@ -29,7 +36,7 @@ pub enum TuiEvent {
     KitReloaded { note_keys: Vec<u8>, kit_path: PathBuf },
     KitReloadError(String),
     KitLoadComplete {
-        result: Result<kit::Kit, String>,
+        result: Result<LoadedKit, String>,
         path: PathBuf,
         name: String,
     },
@@ -41,6 +48,16 @@ pub enum TuiEvent {
         result: Result<std::path::PathBuf, String>,
         kit_name: String,
     },
+    /// A command from the control socket, answered via the request's reply channel.
+    Control(control::ControlRequest),
+    /// MIDI ports or audio devices came or went (see `hotplug::DeviceWatcher`).
+    Devices(hotplug::DeviceChange),
+    /// The drum module picked a kit with Program Change (see `kitswitch`).
+    KitSwitch(kitswitch::KitSelection),
+    /// Set a pad's mixer volume (by sample note), e.g. from OSC. Not saved.
+    PadVolume { note: u8, db: f32 },
+    /// An OSC packet that failed to decode or named an unknown address.
+    OscError(String),
 }
 
 /// Mode for the library directory popup.
@@ -55,7 +72,8 @@ pub enum Popup {
     Log { scroll: usize },
     KitPicker { kits: Vec<kit::DiscoveredKit>, list_state: ListState },
     AudioPicker { devices: Vec<audio::AudioDevice>, list_state: ListState },
-    MidiPicker { devices: Vec<midi::MidiDevice>, list_state: ListState },
+    /// Ports with a tick are connected; several can be at once.
+    MidiPicker { devices: Vec<midi::MidiDevice>, connected: Vec<bool>, list_state: ListState },
     LibraryDir {
         mode: DirPopupMode,
         selected: usize,
@@ -86,30 +104,147 @@ pub enum Popup {
         error: Option<String>,
         confirm_delete: bool,
     },
+    /// Velocity curve editor for one pad (by incoming note) or the global curve
+    /// (`target: None`); `curves` is the working copy, applied on every change.
+    VelocityCurve {
+        target: Option<u8>,
+        row: usize,
+        point: usize,
+        curves: velocity::VelocityCurves,
+    },
+    /// Building a mapping by hitting each of `learn::SLOTS` in turn: `learned` holds
+    /// the incoming note per slot; once `step` passes the last slot, `input` names it.
+    MidiLearn {
+        step: usize,
+        learned: Vec<Option<u8>>,
+        input: String,
+        cursor: usize,
+    },
+    /// Played and rejected hits per pad, with the suppression thresholds (a working
+    /// copy, applied on every change) for the selected row and column.
+    HitStats {
+        selected: usize,
+        column: usize,
+        suppression: suppress::Suppression,
+        stats: Arc<suppress::HitStats>,
+    },
+    /// Volume, pan, mute and solo per pad (by sample note); `mix` is the working
+    /// copy, applied on every change.
+    Mixer {
+        selected: usize,
+        column: usize,
+        mix: mix::KitMix,
+    },
+    /// EQ, compressor and transient shaper of the pad on mixer row `pad`; `mix` is
+    /// the working copy of its mix, applied on every change.
+    Inserts {
+        pad: usize,
+        row: usize,
+        mix: mix::PadMix,
+    },
+}
+
+/// A kit loaded in the background, with its mix and the mix's impulse response,
+/// so nothing slow is left for the event loop.
+pub struct LoadedKit {
+    pub kit: kit::Kit,
+    pub mix: mix::KitMix,
+    /// Why the IR bus has no response, if it names one that couldn't be loaded.
+    pub impulse_error: Option<String>,
 }
 
 /// Swappable resources owned by the TUI event loop during play mode.
 pub struct PlayResources {
-    pub stream: cpal::Stream,
-    pub connection: midir::MidiInputConnection<()>,
+    /// The output stream; `None` while its device is unplugged.
+    pub stream: Option<audio::OutputStream>,
+    /// Connected MIDI inputs (dropping one disconnects it).
+    pub midi_inputs: Vec<midi::InputPort>,
+    /// Inputs to reconnect when their port is plugged back in.
+    pub missing_midi: hotplug::MissingInputs,
+    /// Polls for unplugged MIDI ports and the audio device coming back.
+    pub device_watcher: hotplug::DeviceWatcher,
     pub producer: Arc<Mutex<Option<rtrb::Producer<audio::AudioCommand>>>>,
     pub shared_notes: Arc<ArcSwap<HashMap<u8, Arc<kit::NoteGroup>>>>,
     pub kit_path: PathBuf,
     pub shared_kit_path: Arc<ArcSwap<PathBuf>>,
     pub suppress_reload: Arc<AtomicBool>,
     pub sample_rate: u32,
+    /// Stream rate shared with the hot-reload thread so reloads resample to it.
+    pub shared_sample_rate: Arc<AtomicU32>,
     pub channels: u16,
     pub audio_device_index: usize,
-    pub midi_port_index: usize,
+    /// Name of the output device, used to find it again after it's replugged.
+    pub audio_device_name: String,
     pub tui_tx: mpsc::Sender<TuiEvent>,
-    pub choke_fade: usize,
-    pub aftertouch_fade: usize,
+    /// Routes control-socket triggers; kept so the hi-hat pedal and chokes carry over.
+    pub control_router: midi::MidiRouter,
     pub watcher: notify::RecommendedWatcher,
     pub stderr_capture: Option<stderr::StderrCapture>,
     pub extra_kits_dirs: Vec<PathBuf>,
     pub extra_mapping_dirs: Vec<PathBuf>,
     pub shared_mapping: Arc<ArcSwap<mapping::NoteMapping>>,
+    /// MIDI channel filter and per-channel kits, shared with the MIDI callback.
+    pub channel_routing: Arc<ArcSwap<channels::ChannelRouting>>,
+    /// MIDI output that the inputs forward to, if any.
+    pub midi_thru: Option<Arc<Mutex<thru::MidiThru>>>,
+    /// Velocity curves, shared with the MIDI callback and edited in the curve popup.
+    pub velocity: Arc<ArcSwap<velocity::VelocityCurves>>,
+    /// Double-trigger and crosstalk thresholds, edited in the stats popup.
+    pub suppression: Arc<ArcSwap<suppress::Suppression>>,
+    pub hit_stats: Arc<suppress::HitStats>,
+    /// Programs from the drum module that switch the kit (`[kit_switch]`).
+    pub kit_switch: Arc<kitswitch::KitSwitchTable>,
+    /// The current kit's pad mix, resent to the audio thread whenever the stream
+    /// is rebuilt.
+    pub mix: mix::KitMix,
     pub kit_repos: Vec<String>,
+    pub polyphony: audio::Polyphony,
+    /// Master output recording in progress, if any.
+    pub recording: Option<record::Recording>,
+    pub recordings_dir: PathBuf,
+    /// MIDI capture shared with the MIDI callback (`None` when not capturing).
+    pub midi_capture: Arc<Mutex<Option<midifile::MidiCapture>>>,
+    pub capture_options: midifile::CaptureOptions,
+    pub metronome: metronome::MetronomeSettings,
+    /// Output gain, limiter and meter, resent whenever the stream is rebuilt.
+    pub master: master::MasterBus,
+    /// Control socket listener, if enabled (removes the socket file when dropped).
+    pub control_socket: Option<control::ControlSocket>,
+    /// OSC listener, if enabled (stops its thread when dropped).
+    pub osc_server: Option<osc::OscServer>,
+    /// Control client waiting for a kit load to finish: (kit name, reply channel).
+    pub pending_kit_reply: Option<(String, mpsc::Sender<control::ControlResponse>)>,
+}
+
+impl PlayResources {
+    /// How the output stream is opened, at the current rate and channel count.
+    pub(crate) fn stream_setup(&self) -> pipeline::StreamSetup<'_> {
+        pipeline::StreamSetup {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            polyphony: &self.polyphony,
+            metronome: &self.metronome,
+            mix: &self.mix,
+            master: &self.master,
+        }
+    }
+
+    /// What a newly connected MIDI input plays into.
+    pub(crate) fn midi_targets(&self) -> midi::MidiTargets {
+        midi::MidiTargets {
+            producer: Arc::clone(&self.producer),
+            shared_notes: Arc::clone(&self.shared_notes),
+            shared_mapping: Arc::clone(&self.shared_mapping),
+            routing: Arc::clone(&self.channel_routing),
+            velocity: Arc::clone(&self.velocity),
+            suppression: Arc::clone(&self.suppression),
+            hit_stats: Arc::clone(&self.hit_stats),
+            kit_switch: Arc::clone(&self.kit_switch),
+            tui_tx: self.tui_tx.clone(),
+            midi_capture: Arc::clone(&self.midi_capture),
+            thru: self.midi_thru.clone(),
+        }
+    }
 }
 
 /// Visual state for a single pad in the grid.
@@ -133,6 +268,7 @@ pub(crate) const FLASH_DURATION_MS: u128 = 300;
 /// Complete application state for the TUI.
 pub struct AppState {
     pub kit_name: String,
+    /// Connected MIDI inputs, for the header (see `midi::inputs_label`).
     pub midi_device: String,
     pub sample_rate: u32,
     pub channels: u16,
@@ -145,6 +281,18 @@ pub struct AppState {
     pub(crate) should_quit: bool,
     pub(crate) popup: Option<Popup>,
     pub(crate) log_lines: Vec<String>,
+    /// Live stats of the current recording, shown in the header.
+    pub(crate) recording: Option<Arc<record::RecordingStats>>,
+    /// When the current MIDI capture started, shown in the header.
+    pub(crate) midi_capture_started: Option<Instant>,
+    /// Metronome summary for the header while it is running.
+    pub(crate) metronome_label: Option<String>,
+    /// MIDI inputs waiting to be plugged back in, shown in the header.
+    pub(crate) midi_unplugged: Vec<String>,
+    /// Whether the audio output device is gone, shown in the header.
+    pub(crate) audio_lost: bool,
+    /// Output level and clip count, shown in the header.
+    pub(crate) meter: master::MeterDisplay,
 }
 
 impl AppState {
@@ -182,6 +330,12 @@ impl AppState {
             should_quit: false,
             popup: None,
             log_lines: initial_log,
+            recording: None,
+            midi_capture_started: None,
+            metronome_label: None,
+            midi_unplugged: Vec::new(),
+            audio_lost: false,
+            meter: master::MeterDisplay::default(),
         }
     }
 
@@ -276,10 +430,31 @@ pub fn run(
 
     restore_terminal();
 
+    // Stop accepting commands before tearing down what they act on
+    drop(resources.control_socket.take());
+    drop(resources.osc_server.take());
+
     // Restore stderr
     if let Some(cap) = resources.stderr_capture.take() {
         cap.restore();
     }
 
+    if let Some(capture) = resources.midi_capture.lock().unwrap().take() {
+        match capture.save(&resources.recordings_dir) {
+            Ok(path) => println!("MIDI capture saved to {}", path.display()),
+            Err(e) => eprintln!("MIDI capture failed: {}", e),
+        }
+    }
+
+    // Dropping the stream drops the recording tap, which lets the writer finalize
+    let recording = resources.recording.take();
+    drop(resources);
+    if let Some(rec) = recording {
+        match rec.finish() {
+            Ok(path) => println!("Recording saved to {}", path.display()),
+            Err(e) => eprintln!("Recording failed: {}", e),
+        }
+    }
+
     result
 }
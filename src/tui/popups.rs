@@ -6,7 +6,7 @@ use std::sync::Arc;
 
 use super::input::handle_text_input_key;
 use super::list_nav::{index_down, index_up, list_down, list_down_skip, list_up, list_up_skip};
-use super::{AppState, DirPopupMode, PlayResources, Popup, TuiEvent};
+use super::{curves, inserts, midi_learn, mixer, stats, AppState, DirPopupMode, LoadedKit, PlayResources, Popup, TuiEvent};
 use crate::{audio, download, kit, mapping, midi, settings};
 
 pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResources, key: KeyCode) {
@@ -44,30 +44,8 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
                 if let Some(idx) = list_state.selected() {
                     let selected_path = kits[idx].path.clone();
                     let selected_name = kits[idx].name.clone();
-
-                    let progress = Arc::new(AtomicUsize::new(0));
-                    let total = Arc::new(AtomicUsize::new(0));
-
-                    let tx = resources.tui_tx.clone();
-                    let path_clone = selected_path.clone();
-                    let name_clone = selected_name.clone();
-                    let prog = Arc::clone(&progress);
-                    let tot = Arc::clone(&total);
-                    std::thread::spawn(move || {
-                        let result = kit::load_kit_with_progress(&path_clone, &prog, &tot)
-                            .map_err(|e| e.to_string());
-                        let _ = tx.send(TuiEvent::KitLoadComplete {
-                            result,
-                            path: path_clone,
-                            name: name_clone,
-                        });
-                    });
-
-                    state.popup = Some(Popup::Loading {
-                        kit_name: selected_name,
-                        progress,
-                        total,
-                    });
+                    let rate = resources.sample_rate;
+                    start_kit_load(state, resources, selected_path, selected_name, rate);
                 }
             }
             _ => {}
@@ -83,49 +61,32 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
                     let new_device_index = devices[idx].index;
                     let new_device_name = devices[idx].name.clone();
 
-                    // 1. Lock producer, take it (MIDI callback silently drops events)
-                    {
-                        let mut guard = resources.producer.lock().unwrap();
-                        *guard = None;
-                    }
-
-                    // 2. Create new ring buffer
-                    let (new_producer, new_consumer) = rtrb::RingBuffer::new(128);
-
-                    // 3. Try to create new stream
-                    match audio::run_output_stream(
-                        Some(new_device_index),
-                        new_consumer,
-                        resources.sample_rate,
-                        resources.channels,
-                    ) {
+                    match resources.stream_setup().open(&resources.producer, Some(new_device_index)) {
                         Ok(new_stream) => {
-                            resources.stream = new_stream;
+                            resources.stream = Some(new_stream);
                             resources.audio_device_index = new_device_index;
-                            {
-                                let mut guard = resources.producer.lock().unwrap();
-                                *guard = Some(new_producer);
-                            }
+                            resources.audio_device_name = new_device_name.clone();
                             state.set_status(format!("Audio: {}", new_device_name));
                             let mut s = settings::load_settings();
                             s.audio_device = Some(new_device_name);
                             let _ = settings::save_settings(&s);
+
+                            // Re-render the kit at the new device's native rate if it differs;
+                            // the stream is rebuilt at that rate once loading completes
+                            if let Ok(rate) = audio::preferred_sample_rate(Some(new_device_index))
+                                && rate != resources.sample_rate
+                            {
+                                let path = resources.kit_path.clone();
+                                let name = state.kit_name.clone();
+                                start_kit_load(state, resources, path, name, rate);
+                                return;
+                            }
                         }
                         Err(e) => {
-                            let (restore_producer, restore_consumer) = rtrb::RingBuffer::new(128);
-                            match audio::run_output_stream(
-                                Some(resources.audio_device_index),
-                                restore_consumer,
-                                resources.sample_rate,
-                                resources.channels,
-                            ) {
-                                Ok(restored_stream) => {
-                                    resources.stream = restored_stream;
-                                    let mut guard = resources.producer.lock().unwrap();
-                                    *guard = Some(restore_producer);
-                                }
-                                Err(_) => {}
-                            }
+                            // Back to the previous device; if that fails too, the
+                            // device watcher picks it up again
+                            let index = Some(resources.audio_device_index);
+                            resources.stream = resources.stream_setup().open(&resources.producer, index).ok();
                             state.set_status(format!("Audio switch failed: {}", e));
                         }
                     }
@@ -134,41 +95,19 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
             }
             _ => {}
         },
-        Popup::MidiPicker { devices, list_state } => match key {
+        Popup::MidiPicker { devices, list_state, .. } => match key {
             KeyCode::Char('m') | KeyCode::Esc => { state.popup = None; }
             KeyCode::Char('q') => { state.popup = None; state.should_quit = true; }
             KeyCode::Up => list_up(list_state, devices.len()),
             KeyCode::Down => list_down(list_state, devices.len()),
-            KeyCode::Enter => {
+            KeyCode::Enter | KeyCode::Char(' ') => {
                 if devices.is_empty() { return; }
                 if let Some(idx) = list_state.selected() {
-                    let new_port_index = devices[idx].port_index;
-                    let new_port_name = devices[idx].name.clone();
-
-                    let callback = midi::build_midi_callback(
-                        Arc::clone(&resources.producer),
-                        Arc::clone(&resources.shared_notes),
-                        Arc::clone(&resources.shared_mapping),
-                        resources.tui_tx.clone(),
-                        resources.choke_fade,
-                        resources.aftertouch_fade,
-                    );
-
-                    match midi::connect_callback(new_port_index, callback) {
-                        Ok(new_connection) => {
-                            resources.connection = new_connection;
-                            resources.midi_port_index = new_port_index;
-                            state.midi_device = new_port_name.clone();
-                            state.set_status(format!("MIDI: {}", new_port_name));
-                            let mut s = settings::load_settings();
-                            s.midi_device = Some(new_port_name);
-                            let _ = settings::save_settings(&s);
-                        }
-                        Err(e) => {
-                            state.set_status(format!("MIDI switch failed: {}", e));
-                        }
+                    let device = devices[idx].clone();
+                    toggle_midi_input(state, resources, &device);
+                    if let Some(Popup::MidiPicker { devices, connected, .. }) = &mut state.popup {
+                        *connected = midi_picker_connected(resources, devices);
                     }
-                    state.popup = None;
                 }
             }
             _ => {}
@@ -187,11 +126,7 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
                 if mappings.is_empty() { return; }
                 if let Some(idx) = list_state.selected() {
                     let selected = mappings[idx].clone();
-                    let new_mapping = Arc::new(selected);
-                    resources.shared_mapping.store(Arc::clone(&new_mapping));
-                    state.mapping = new_mapping;
-                    state.update_hit_log_names();
-                    state.set_status(format!("Mapping: {}", state.mapping.name));
+                    apply_mapping(state, resources, selected);
                     state.popup = None;
                 }
             }
@@ -265,6 +200,21 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
         Popup::LibraryDir { .. } => {
             handle_library_dir_key(state, resources, key);
         }
+        Popup::VelocityCurve { .. } => {
+            curves::handle_key(state, resources, key);
+        }
+        Popup::HitStats { .. } => {
+            stats::handle_key(state, resources, key);
+        }
+        Popup::MidiLearn { .. } => {
+            midi_learn::handle_key(state, resources, key);
+        }
+        Popup::Mixer { .. } => {
+            mixer::handle_key(state, resources, key);
+        }
+        Popup::Inserts { .. } => {
+            inserts::handle_key(state, resources, key);
+        }
         Popup::KitStoreFetching => match key {
             KeyCode::Esc | KeyCode::Char('s') => { state.popup = None; }
             KeyCode::Char('q') => { state.popup = None; state.should_quit = true; }
@@ -331,6 +281,94 @@ pub(super) fn handle_popup_key(state: &mut AppState, resources: &mut PlayResourc
     }
 }
 
+/// Load a kit in the background at `target_rate`, showing the Loading popup.
+/// The result arrives as `TuiEvent::KitLoadComplete`.
+pub(super) fn start_kit_load(state: &mut AppState, resources: &PlayResources, path: PathBuf, name: String, target_rate: u32) {
+    let progress = Arc::new(AtomicUsize::new(0));
+    let total = Arc::new(AtomicUsize::new(0));
+
+    let tx = resources.tui_tx.clone();
+    // Reloading the same kit keeps its mix, and its response if the rate still matches
+    let current_mix = (path == resources.kit_path).then(|| resources.mix.clone());
+    let path_clone = path;
+    let name_clone = name.clone();
+    let prog = Arc::clone(&progress);
+    let tot = Arc::clone(&total);
+    std::thread::spawn(move || {
+        let result = kit::load_kit_with_progress(&path_clone, Some(target_rate), &prog, &tot)
+            .map(|kit| {
+                let mut mix = current_mix.unwrap_or_else(|| settings::load_settings().kit_mix(&path_clone));
+                let impulse_error = mix.load_impulse(&path_clone, kit.sample_rate).err().map(|e| format!("{:#}", e));
+                LoadedKit { kit, mix, impulse_error }
+            })
+            .map_err(|e| e.to_string());
+        let _ = tx.send(TuiEvent::KitLoadComplete {
+            result,
+            path: path_clone,
+            name: name_clone,
+        });
+    });
+
+    state.popup = Some(Popup::Loading {
+        kit_name: name,
+        progress,
+        total,
+    });
+}
+
+/// Which of `devices` are connected inputs, for the MIDI picker's ticks.
+pub(super) fn midi_picker_connected(resources: &PlayResources, devices: &[midi::MidiDevice]) -> Vec<bool> {
+    devices
+        .iter()
+        .map(|d| resources.midi_inputs.iter().any(|i| i.name == d.name))
+        .collect()
+}
+
+/// Connect `device` as another MIDI input, or disconnect it if it already is one,
+/// and remember the choice.
+fn toggle_midi_input(state: &mut AppState, resources: &mut PlayResources, device: &midi::MidiDevice) {
+    let mut s = settings::load_settings();
+    if let Some(pos) = resources.midi_inputs.iter().position(|i| i.name == device.name) {
+        resources.midi_inputs.remove(pos);
+        s.set_midi_input_enabled(&device.name, false);
+        state.set_status(format!("MIDI disconnected: {}", device.name));
+    } else {
+        // Keep any mapping / channel overrides saved for this port
+        let config = s
+            .midi_inputs
+            .iter()
+            .find(|i| i.port == device.name)
+            .cloned()
+            .unwrap_or_else(|| settings::MidiInputSettings::new(device.name.clone()));
+        let targets = resources.midi_targets();
+        match midi::connect_input(device.port_index, config, &targets, &resources.extra_mapping_dirs) {
+            Ok(input) => {
+                resources.midi_inputs.push(input);
+                resources.missing_midi.remove(&device.name);
+                state.midi_unplugged = resources.missing_midi.names();
+                s.set_midi_input_enabled(&device.name, true);
+                s.midi_device.get_or_insert_with(|| device.name.clone());
+                state.set_status(format!("MIDI connected: {}", device.name));
+            }
+            Err(e) => {
+                state.set_status(format!("MIDI connect failed: {:#}", e));
+                return;
+            }
+        }
+    }
+    state.midi_device = midi::inputs_label(&resources.midi_inputs);
+    let _ = settings::save_settings(&s);
+}
+
+/// Make `new_mapping` the active note mapping for the MIDI callback and the display.
+pub(super) fn apply_mapping(state: &mut AppState, resources: &PlayResources, new_mapping: mapping::NoteMapping) {
+    let new_mapping = Arc::new(new_mapping);
+    resources.shared_mapping.store(Arc::clone(&new_mapping));
+    state.mapping = new_mapping;
+    state.update_hit_log_names();
+    state.set_status(format!("Mapping: {}", state.mapping.name));
+}
+
 fn save_repo_settings(resources: &PlayResources) {
     let mut s = settings::load_settings();
     s.kit_repos = resources.kit_repos.clone();
@@ -0,0 +1,92 @@
+use super::{event_loop, popups, AppState, PlayResources};
+use crate::control::{self, ControlCommand, ControlRequest, ControlResponse, PlayerState};
+
+/// Carry out a control socket command with the same operations as the keys and popups.
+pub(super) fn handle_request(state: &mut AppState, resources: &mut PlayResources, request: ControlRequest) {
+    let response = match &request.command {
+        ControlCommand::Kit { kit } => {
+            match control::resolve_kit(kit, &resources.extra_kits_dirs) {
+                Ok((path, name)) => {
+                    // Answered once the load completes (see `KitLoadComplete`)
+                    if let Some((_, previous)) = resources.pending_kit_reply.take() {
+                        let _ = previous.send(ControlResponse::error("Superseded by another kit switch"));
+                    }
+                    resources.pending_kit_reply = Some((name.clone(), request.reply));
+                    let rate = resources.sample_rate;
+                    popups::start_kit_load(state, resources, path, name, rate);
+                    return;
+                }
+                Err(e) => ControlResponse::error(e),
+            }
+        }
+        ControlCommand::Mapping { mapping } => {
+            match control::resolve_mapping(mapping, &resources.extra_mapping_dirs) {
+                Ok(found) => {
+                    popups::apply_mapping(state, resources, found);
+                    ControlResponse::ok(format!("Mapping: {}", state.mapping.name))
+                }
+                Err(e) => ControlResponse::error(e),
+            }
+        }
+        ControlCommand::Volume { volume } => set_volume(state, resources, *volume),
+        ControlCommand::Trigger { note, velocity } => trigger(resources, *note, *velocity),
+        ControlCommand::StartRecording => event_loop::start_recording(state, resources)
+            .map(|path| format!("Recording to {}", path.display()))
+            .into(),
+        ControlCommand::StopRecording => event_loop::stop_recording(state, resources)
+            .map(|path| format!("Saved {}", path.display()))
+            .into(),
+        ControlCommand::State => ControlResponse::state(player_state(state, resources)),
+    };
+    request.respond(response);
+}
+
+/// Set the master output gain from a linear volume, as `,`/`.` would.
+fn set_volume(state: &mut AppState, resources: &mut PlayResources, volume: f32) -> ControlResponse {
+    if !volume.is_finite() || volume < 0.0 {
+        return ControlResponse::error("Volume must be 0 or more");
+    }
+    event_loop::update_master(state, resources, |m| m.set_volume(volume));
+    ControlResponse::ok(resources.master.settings.label())
+}
+
+/// Play a note through the same routing as the MIDI input (remapping, chokes).
+fn trigger(resources: &mut PlayResources, note: u8, velocity: u8) -> ControlResponse {
+    let data = match control::trigger_message(note, velocity) {
+        Ok(data) => data,
+        Err(e) => return ControlResponse::error(e),
+    };
+    let mut guard = resources.producer.lock().unwrap();
+    let Some(ref mut prod) = *guard else {
+        return ControlResponse::error("Audio stream is not running");
+    };
+    resources.control_router.route(
+        &data,
+        &resources.shared_mapping.load(),
+        &resources.shared_notes.load(),
+        &resources.velocity.load(),
+        |cmd| {
+            let _ = prod.push(cmd);
+        },
+        |event| {
+            let _ = resources.tui_tx.send(event);
+        },
+    );
+    ControlResponse::ok(format!("Triggered note {} at velocity {}", note, velocity))
+}
+
+fn player_state(state: &AppState, resources: &PlayResources) -> PlayerState {
+    PlayerState {
+        kit: state.kit_name.clone(),
+        kit_path: resources.kit_path.clone(),
+        mapping: state.mapping.name.clone(),
+        midi_device: state.midi_device.clone(),
+        sample_rate: resources.sample_rate,
+        channels: resources.channels,
+        volume: resources.master.settings.volume(),
+        recording: resources.recording.as_ref().map(|r| r.path.clone()),
+        midi_capture: resources.midi_capture.lock().unwrap().is_some(),
+        metronome: state.metronome_label.clone(),
+        clips: resources.master.meter.clips(),
+    }
+}
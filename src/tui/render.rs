@@ -9,6 +9,13 @@ use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 use super::{render_popups, AppState, PadState, FLASH_DURATION_MS};
+use crate::{master, record};
+
+/// Width of the output meter in the header.
+const METER_WIDTH: usize = 10;
+
+/// Bottom of the output meter's scale.
+const METER_FLOOR_DB: f32 = -48.0;
 
 pub(super) fn ui(frame: &mut Frame, state: &AppState, extra_kit_dirs: &[PathBuf], extra_mapping_dirs: &[PathBuf], kit_repos: &[String]) {
     let area = frame.area();
@@ -20,7 +27,8 @@ pub(super) fn ui(frame: &mut Frame, state: &AppState, extra_kit_dirs: &[PathBuf]
         return;
     }
 
-    let header_height = if area.height < 8 { 1 } else { 3 };
+    // Title row, two lines of status and the bottom border
+    let header_height = if area.height < 8 { 1 } else { 4 };
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -47,7 +55,7 @@ fn render_header(frame: &mut Frame, area: Rect, state: &AppState) {
     }
 
     if area.height == 1 {
-        let line = Line::from(vec![
+        let mut line = Line::from(vec![
             Span::styled(
                 " drumkit ",
                 Style::default()
@@ -61,18 +69,39 @@ fn render_header(frame: &mut Frame, area: Rect, state: &AppState) {
                 Style::default().fg(Color::Yellow),
             ),
         ]);
+        if let Some(rec) = recording_span(state) {
+            line.spans.push(Span::raw("  "));
+            line.spans.push(rec);
+        }
+        if state.meter.clips > 0 {
+            line.spans.push(Span::raw("  "));
+            line.spans.push(clips_span(state));
+        }
         frame.render_widget(Paragraph::new(line), area);
         return;
     }
 
-    let line1 = Line::from(vec![
+    let mut line1 = Line::from(vec![
         Span::styled(" Kit: ", Style::default().fg(Color::DarkGray)),
         Span::styled(&state.kit_name, Style::default().fg(Color::Cyan)),
         Span::raw("   "),
         Span::styled("MIDI: ", Style::default().fg(Color::DarkGray)),
         Span::styled(&state.midi_device, Style::default().fg(Color::Green)),
     ]);
-    let line2 = Line::from(vec![
+    if !state.midi_unplugged.is_empty() {
+        line1.spans.push(Span::styled(
+            format!("  (unplugged: {})", state.midi_unplugged.join(", ")),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    if state.audio_lost {
+        line1.spans.push(Span::raw("   "));
+        line1.spans.push(Span::styled(
+            "Audio disconnected",
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let mut line2 = Line::from(vec![
         Span::styled(
             format!(" {} Hz / {} ch", state.sample_rate, state.channels),
             Style::default().fg(Color::DarkGray),
@@ -83,7 +112,24 @@ fn render_header(frame: &mut Frame, area: Rect, state: &AppState) {
             state.total_hits.to_string(),
             Style::default().fg(Color::Yellow),
         ),
+        Span::raw("    "),
     ]);
+    line2.spans.extend(meter_spans(state));
+    if let Some(rec) = recording_span(state) {
+        line2.spans.push(Span::raw("    "));
+        line2.spans.push(rec);
+    }
+    if let Some(capture) = midi_capture_span(state) {
+        line2.spans.push(Span::raw("    "));
+        line2.spans.push(capture);
+    }
+    if let Some(ref label) = state.metronome_label {
+        line2.spans.push(Span::raw("    "));
+        line2.spans.push(Span::styled(
+            format!("\u{2669} {}", label),
+            Style::default().fg(Color::Cyan),
+        ));
+    }
 
     let block = Block::default()
         .borders(Borders::BOTTOM)
@@ -99,6 +145,90 @@ fn render_header(frame: &mut Frame, area: Rect, state: &AppState) {
     frame.render_widget(paragraph, area);
 }
 
+/// Output meter: RMS as a solid bar with the peak beyond it, the peak level,
+/// the limiter's gain reduction while it works, and the clip count.
+fn meter_spans(state: &AppState) -> Vec<Span<'static>> {
+    let levels = state.meter.levels;
+    let peak_db = master::level_db(levels.peak);
+    let cells = |db: f32| {
+        let fill = ((db - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0);
+        (fill * METER_WIDTH as f32).round() as usize
+    };
+    let rms_cells = cells(master::level_db(levels.rms));
+    let peak_cells = cells(peak_db).max(rms_cells);
+    let color = if peak_db > -1.0 {
+        Color::Red
+    } else if peak_db > -6.0 {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+    let peak_label = if peak_db <= master::FLOOR_DB {
+        format!("{:>5} dB", "-inf")
+    } else {
+        format!("{:>5.1} dB", peak_db)
+    };
+
+    let mut spans = vec![
+        Span::styled("Out ", Style::default().fg(Color::DarkGray)),
+        Span::styled(
+            format!("{}{}", "\u{2588}".repeat(rms_cells), "\u{2592}".repeat(peak_cells - rms_cells)),
+            Style::default().fg(color),
+        ),
+        Span::styled("\u{00B7}".repeat(METER_WIDTH - peak_cells), Style::default().fg(Color::DarkGray)),
+        Span::styled(format!(" {}", peak_label), Style::default().fg(color)),
+    ];
+    if levels.reduction_db >= 0.1 {
+        spans.push(Span::styled(
+            format!("  limit -{:.1} dB", levels.reduction_db),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    spans.push(Span::raw("  "));
+    spans.push(clips_span(state));
+    spans
+}
+
+/// Clip count, red once anything has clipped.
+fn clips_span(state: &AppState) -> Span<'static> {
+    match state.meter.clips {
+        0 => Span::styled("Clips 0", Style::default().fg(Color::DarkGray)),
+        n => Span::styled(
+            format!("CLIP {} (z resets)", n),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+    }
+}
+
+/// Elapsed time of the MIDI capture in progress.
+fn midi_capture_span(state: &AppState) -> Option<Span<'static>> {
+    let secs = state.midi_capture_started?.elapsed().as_secs();
+    Some(Span::styled(
+        format!("\u{25CF} MIDI {:02}:{:02}", secs / 60, secs % 60),
+        Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+    ))
+}
+
+/// Elapsed time and file size of the recording in progress.
+fn recording_span(state: &AppState) -> Option<Span<'static>> {
+    let stats = state.recording.as_ref()?;
+    let secs = stats.elapsed().as_secs();
+    let dropped = match stats.dropped_buffers() {
+        0 => String::new(),
+        n => format!("  {} dropped", n),
+    };
+    Some(Span::styled(
+        format!(
+            "\u{25CF} REC {:02}:{:02}  {}{}",
+            secs / 60,
+            secs % 60,
+            record::format_size(stats.bytes()),
+            dropped
+        ),
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    ))
+}
+
 fn render_body(frame: &mut Frame, area: Rect, state: &AppState) {
     if area.height == 0 || area.width == 0 {
         return;
@@ -421,7 +551,7 @@ fn render_shortcuts(frame: &mut Frame, area: Rect) {
     if area.width == 0 || area.height == 0 {
         return;
     }
-    let hints = " l log  k kit  s store  n mapping  r rename  d dirs  a audio  m midi  q quit";
+    let hints = " l log  k kit  s store  n mapping  L learn  r rename  v velocity  h stats  x mixer  d dirs  a audio  m midi  w rec  c capture  t click  q quit";
     let hint_style = Style::default().fg(Color::DarkGray);
     let line = Line::from(Span::styled(hints, hint_style));
     frame.render_widget(Paragraph::new(line), area);
@@ -1,11 +1,13 @@
 use ratatui::{
     layout::Rect,
     style::{Color, Modifier, Style},
+    symbols::Marker,
     text::{Line, Span},
+    widgets::canvas::{Canvas, Line as CanvasLine},
     widgets::{Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame,
 };
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
@@ -13,15 +15,17 @@ use super::widgets::{
     content_footer_split, popup_area_fixed, popup_area_percent, render_footer_hint,
     render_progress_popup, render_text_input, styled_block,
 };
-use super::{AppState, DirPopupMode, Popup};
-use crate::{audio, download, kit, mapping, midi};
+use super::{curves, inserts, mixer, stats, AppState, DirPopupMode, Popup};
+use crate::suppress::{HitStats, Suppression};
+use crate::velocity::{CurveShape, VelocityCurves};
+use crate::{audio, convolution, download, kit, learn, mapping, midi, mix};
 
 pub(super) fn render_popup(frame: &mut Frame, area: Rect, popup: &Popup, state: &AppState, extra_kit_dirs: &[PathBuf], extra_mapping_dirs: &[PathBuf], kit_repos: &[String]) {
     match popup {
         Popup::Log { scroll } => render_log_popup(frame, area, state, *scroll),
         Popup::KitPicker { kits, list_state } => render_kit_popup(frame, area, kits, list_state),
         Popup::AudioPicker { devices, list_state } => render_audio_popup(frame, area, devices, list_state),
-        Popup::MidiPicker { devices, list_state } => render_midi_popup(frame, area, devices, list_state),
+        Popup::MidiPicker { devices, connected, list_state } => render_midi_popup(frame, area, devices, connected, list_state),
         Popup::LibraryDir { mode, selected, input, cursor, error } => render_library_dir_popup(frame, area, mode, *selected, input, *cursor, error.as_deref(), extra_kit_dirs, extra_mapping_dirs),
         Popup::Loading { kit_name, progress, total } => render_loading_popup(frame, area, kit_name, progress, total),
         Popup::MappingPicker { mappings, list_state } => render_mapping_popup(frame, area, mappings, list_state, state),
@@ -31,6 +35,11 @@ pub(super) fn render_popup(frame: &mut Frame, area: Rect, popup: &Popup, state:
         Popup::KitStore { kits, rows, list_state } => render_kit_store(frame, area, kits, rows, list_state),
         Popup::KitDownloading { kit_name, progress, total } => render_kit_downloading(frame, area, kit_name, progress, total),
         Popup::KitStoreRepos { selected, adding, input, cursor, error, confirm_delete } => render_kit_store_repos(frame, area, kit_repos, *selected, *adding, input, *cursor, error.as_deref(), *confirm_delete),
+        Popup::VelocityCurve { target, row, point, curves } => render_velocity_popup(frame, area, state, *target, *row, *point, curves),
+        Popup::HitStats { selected, column, suppression, stats } => render_stats_popup(frame, area, state, *selected, *column, suppression, stats),
+        Popup::MidiLearn { step, learned, input, cursor } => render_midi_learn_popup(frame, area, *step, learned, input, *cursor),
+        Popup::Mixer { selected, column, mix } => render_mixer_popup(frame, area, state, *selected, *column, mix),
+        Popup::Inserts { pad, row, mix } => render_inserts_popup(frame, area, state, *pad, *row, mix),
     }
 }
 
@@ -155,11 +164,11 @@ fn render_audio_popup(frame: &mut Frame, area: Rect, devices: &[audio::AudioDevi
     render_footer_hint(frame, footer_area, " \u{2191}\u{2193} navigate  Enter select  Esc/a close  q quit");
 }
 
-fn render_midi_popup(frame: &mut Frame, area: Rect, devices: &[midi::MidiDevice], list_state: &ListState) {
+fn render_midi_popup(frame: &mut Frame, area: Rect, devices: &[midi::MidiDevice], connected: &[bool], list_state: &ListState) {
     let popup = popup_area_percent(area);
     frame.render_widget(Clear, popup);
 
-    let block = styled_block(" Select MIDI Input ", Color::Magenta);
+    let block = styled_block(" MIDI Inputs ", Color::Magenta);
     let inner = block.inner(popup);
     frame.render_widget(block, popup);
 
@@ -178,7 +187,15 @@ fn render_midi_popup(frame: &mut Frame, area: Rect, devices: &[midi::MidiDevice]
     } else {
         let items: Vec<ListItem> = devices
             .iter()
-            .map(|dev| ListItem::new(Line::from(vec![Span::raw(" "), Span::raw(&dev.name)])))
+            .zip(connected)
+            .map(|(dev, &on)| {
+                let (mark, style) = if on {
+                    ("[x] ", Style::default().fg(Color::Green))
+                } else {
+                    ("[ ] ", Style::default().fg(Color::DarkGray))
+                };
+                ListItem::new(Line::from(vec![Span::raw(" "), Span::styled(mark, style), Span::raw(&dev.name)]))
+            })
             .collect();
 
         let list = List::new(items)
@@ -189,7 +206,7 @@ fn render_midi_popup(frame: &mut Frame, area: Rect, devices: &[midi::MidiDevice]
         frame.render_stateful_widget(list, content_area, &mut ls);
     }
 
-    render_footer_hint(frame, footer_area, " \u{2191}\u{2193} navigate  Enter select  Esc/m close  q quit");
+    render_footer_hint(frame, footer_area, " \u{2191}\u{2193} navigate  Enter/Space connect/disconnect  Esc/m close  q quit");
 }
 
 fn render_mapping_popup(
@@ -698,3 +715,494 @@ fn render_kit_downloading(
         Color::Cyan, "Esc cancel  q quit",
     );
 }
+
+fn render_velocity_popup(
+    frame: &mut Frame,
+    area: Rect,
+    state: &AppState,
+    target: Option<u8>,
+    row: usize,
+    point: usize,
+    curves: &VelocityCurves,
+) {
+    let popup = popup_area_fixed(area, 60, 28);
+    frame.render_widget(Clear, popup);
+
+    let block = styled_block(" Velocity Curve ", Color::Cyan);
+    let inner = block.inner(popup);
+    frame.render_widget(block, popup);
+
+    if inner.height < 12 || inner.width < 20 {
+        return;
+    }
+    let (content_area, footer_area) = content_footer_split(inner);
+
+    let curve = curves::curve_for(curves, target);
+    let custom = curve.curve == CurveShape::Custom;
+    let (target_label, curve_label) = match target {
+        Some(note) if !curves.pads.contains_key(&note) => (
+            format!("{} ({})", state.mapping.drum_name(note), note),
+            format!("global ({})", curve.curve.label()),
+        ),
+        Some(note) => (format!("{} ({})", state.mapping.drum_name(note), note), curve.curve.label().to_string()),
+        None => ("Global".to_string(), curve.curve.label().to_string()),
+    };
+    let mut rows = vec![
+        ("Target", target_label),
+        ("Curve", curve_label),
+        ("Min", curve.min.to_string()),
+        ("Max", curve.max.to_string()),
+    ];
+    if custom {
+        let [x, y] = curve.points.get(point).copied().unwrap_or_default();
+        rows.push(("Point", format!("{} of {} (at {})", point + 1, curve.points.len(), x)));
+        rows.push(("Level", y.to_string()));
+    }
+
+    let mut lines: Vec<Line> = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, value))| {
+            let selected = i == row;
+            let prefix = if selected { " \u{25b8} " } else { "   " };
+            let style = if selected {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            Line::from(vec![
+                Span::styled(format!("{}{:<7}", prefix, name), Style::default().fg(Color::DarkGray)),
+                Span::styled(value, style),
+            ])
+        })
+        .collect();
+
+    // The last hit played with this curve: the pad's own, or any pad following the global one
+    let last_hit = state
+        .hit_log
+        .iter()
+        .find(|entry| match target {
+            Some(note) => entry.note == note,
+            None => !curves.pads.contains_key(&entry.note),
+        })
+        .map(|entry| entry.velocity);
+    let hit_text = match last_hit.map(|v| (v, curve.apply(v))) {
+        Some((v, Some(out))) => format!("{} \u{2192} {}", v, out),
+        Some((v, None)) => format!("{} (ignored, below min)", v),
+        None => "none yet".to_string(),
+    };
+    lines.push(Line::from(vec![
+        Span::styled("   Last hit ", Style::default().fg(Color::DarkGray)),
+        Span::styled(hit_text, Style::default().fg(Color::Yellow)),
+    ]));
+
+    let text_height = (lines.len() as u16 + 1).min(content_area.height);
+    frame.render_widget(Paragraph::new(lines), Rect::new(content_area.x, content_area.y, content_area.width, text_height));
+
+    // Incoming velocity across, played velocity up
+    let plot = Rect::new(
+        content_area.x + 1,
+        content_area.y + text_height,
+        content_area.width.saturating_sub(2),
+        content_area.height.saturating_sub(text_height),
+    );
+    let canvas = Canvas::default()
+        .marker(Marker::Braille)
+        .x_bounds([0.0, 127.0])
+        .y_bounds([0.0, 127.0])
+        .paint(|ctx| {
+            for v in [curve.min, curve.max] {
+                ctx.draw(&CanvasLine::new(v as f64, 0.0, v as f64, 127.0, Color::DarkGray));
+            }
+            let mut prev: Option<(f64, f64)> = None;
+            for v in curve.min.max(1)..=127 {
+                let (x, y) = (v as f64, curve.apply(v).unwrap_or(0) as f64);
+                if let Some((px, py)) = prev {
+                    ctx.draw(&CanvasLine::new(px, py, x, y, Color::Cyan));
+                }
+                prev = Some((x, y));
+            }
+            ctx.layer();
+            if custom {
+                // Points are placed along min..max like the curve itself
+                let span = curve.max.saturating_sub(curve.min) as f64;
+                for (i, [x, y]) in curve.points.iter().enumerate() {
+                    let color = if i == point { Color::Cyan } else { Color::White };
+                    let at = curve.min as f64 + *x as f64 / 127.0 * span;
+                    ctx.print(at, *y as f64, Span::styled("o", Style::default().fg(color)));
+                }
+            }
+            if let Some(v) = last_hit {
+                let y = curve.apply(v).unwrap_or(0) as f64;
+                ctx.print(
+                    v as f64,
+                    y,
+                    Span::styled("\u{25cf}", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                );
+            }
+        });
+    frame.render_widget(canvas, plot);
+
+    render_footer_hint(frame, footer_area, " \u{2191}\u{2193} select  \u{2190}\u{2192} change  PgUp/PgDn \u{00b1}10  Esc close");
+}
+
+fn render_stats_popup(
+    frame: &mut Frame,
+    area: Rect,
+    state: &AppState,
+    selected: usize,
+    column: usize,
+    suppression: &Suppression,
+    hit_stats: &HitStats,
+) {
+    let popup = popup_area_percent(area);
+    frame.render_widget(Clear, popup);
+
+    let block = styled_block(" Hit Stats ", Color::Cyan);
+    let inner = block.inner(popup);
+    frame.render_widget(block, popup);
+
+    if inner.height < 5 || inner.width < 20 {
+        return;
+    }
+    let (content_area, footer_area) = content_footer_split(inner);
+
+    let (retriggers, crosstalk) = hit_stats.rejected();
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!(" Rejected: {} double triggers, {} crosstalk", retriggers, crosstalk),
+            Style::default().fg(Color::White),
+        )),
+        Line::from(Span::styled(
+            format!(
+                "   {:<22}{:>7}{:>8}{:>7}  {:>7}{:>7}{:>7}",
+                "Pad", "Hits", "Retrig", "Xtalk", "Mask", "Xtalk", "Ratio"
+            ),
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD),
+        )),
+    ];
+
+    let rows = stats::rows(state, suppression, hit_stats);
+    let visible = (content_area.height as usize).saturating_sub(lines.len()).max(1);
+    let skip = selected.saturating_sub(visible - 1);
+    for (i, &row) in rows.iter().enumerate().skip(skip).take(visible) {
+        let is_selected = i == selected;
+        let prefix = if is_selected { " \u{25b8} " } else { "   " };
+        let (name, counts) = match row {
+            Some(note) => (
+                format!("{} ({})", state.mapping.drum_name(note), note),
+                hit_stats.get(note),
+            ),
+            None => ("All pads".to_string(), Default::default()),
+        };
+        let counts = match row {
+            Some(_) => format!("{:>7}{:>8}{:>7}", counts.played, counts.retriggers, counts.crosstalk),
+            None => format!("{:>22}", ""),
+        };
+        let pad = stats::thresholds(suppression, row);
+        // Pads following the global thresholds show them dimmed
+        let own = row.is_none_or(|note| suppression.pads.contains_key(&note));
+        let value_style = |col: usize| {
+            let style = Style::default().fg(if own { Color::White } else { Color::DarkGray });
+            if is_selected && col == column {
+                style.fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+            } else {
+                style
+            }
+        };
+        let name_style = if is_selected {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let name: String = name.chars().take(21).collect();
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}{:<22}", prefix, name), name_style),
+            Span::styled(counts, Style::default().fg(Color::Yellow)),
+            Span::raw("  "),
+            Span::styled(format!("{:>5}ms", pad.retrigger_ms), value_style(stats::COL_RETRIGGER)),
+            Span::styled(format!("{:>5}ms", pad.crosstalk_ms), value_style(stats::COL_CROSSTALK)),
+            Span::raw(" "),
+            Span::styled(format!("{:>5.0}%", pad.crosstalk_ratio * 100.0), value_style(stats::COL_RATIO)),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines), content_area);
+
+    render_footer_hint(
+        frame,
+        footer_area,
+        " \u{2191}\u{2193} pad  Tab column  \u{2190}\u{2192} \u{00b1}1  PgUp/PgDn \u{00b1}10  Del pad uses all-pads  r clear counts  Esc close",
+    );
+}
+
+/// Width of the volume bar in the mixer, 3 dB per cell.
+const MIXER_BAR_WIDTH: usize = 12;
+
+/// Volume at the left end of the mixer's bar; the right end is `mix::MAX_DB`.
+const MIXER_BAR_FLOOR_DB: f32 = -24.0;
+
+fn render_mixer_popup(frame: &mut Frame, area: Rect, state: &AppState, selected: usize, column: usize, mix: &mix::KitMix) {
+    let popup = popup_area_percent(area);
+    frame.render_widget(Clear, popup);
+
+    let block = styled_block(" Mixer ", Color::Cyan);
+    let inner = block.inner(popup);
+    frame.render_widget(block, popup);
+
+    if inner.height < 5 || inner.width < 20 {
+        return;
+    }
+    let (content_area, footer_area) = content_footer_split(inner);
+
+    let summary = if mix.any_solo() {
+        format!(" {} \u{2014} solo: only soloed pads play", state.kit_name)
+    } else {
+        format!(" {}", state.kit_name)
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(summary, Style::default().fg(Color::White))),
+        Line::from(Span::styled(
+            format!(
+                "   {:<22}{:>9} {:<width$}{:>6} {:>7} {:>5}  {:<4} {:<4} {}",
+                "Pad", "Volume", "", "Pan", "Reverb", "IR", "Mute", "Solo", "FX",
+                width = MIXER_BAR_WIDTH
+            ),
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::BOLD),
+        )),
+    ];
+    if state.pads.is_empty() {
+        lines.push(Line::from(Span::styled("   No pads in this kit", Style::default().fg(Color::DarkGray))));
+    }
+
+    // The bus rows stay at the bottom, under a blank line
+    let visible = (content_area.height as usize).saturating_sub(lines.len() + 3).max(1);
+    let skip = selected.min(state.pads.len().saturating_sub(1)).saturating_sub(visible - 1);
+    for (i, pad_state) in state.pads.iter().enumerate().skip(skip).take(visible) {
+        let is_selected = i == selected;
+        let pad = mix.for_note(pad_state.note);
+        let audible = mix.is_audible(pad_state.note);
+        let prefix = if is_selected { " \u{25b8} " } else { "   " };
+        let value_style = |col: usize| {
+            let style = Style::default().fg(if audible { Color::White } else { Color::DarkGray });
+            if is_selected && col == column {
+                style.fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+            } else {
+                style
+            }
+        };
+        let name_style = if is_selected {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+        } else if audible {
+            Style::default().fg(Color::White)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        let name: String = format!("{} ({})", pad_state.name, pad_state.note).chars().take(21).collect();
+        let filled = ((pad.volume_db - MIXER_BAR_FLOOR_DB) / (mix::MAX_DB - MIXER_BAR_FLOOR_DB) * MIXER_BAR_WIDTH as f32)
+            .round()
+            .max(0.0) as usize;
+        let bar = format!("{:<width$}", "\u{2588}".repeat(filled.min(MIXER_BAR_WIDTH)), width = MIXER_BAR_WIDTH);
+        let flag = |on: bool, label: &str| if on { format!("[{}]", label) } else { "[ ]".to_string() };
+        // Enabled inserts: E(Q), C(ompressor), T(ransient shaper)
+        let fx: String = [(pad.inserts.eq.enabled, 'E'), (pad.inserts.compressor.enabled, 'C'), (pad.inserts.transient.enabled, 'T')]
+            .iter()
+            .map(|&(on, letter)| if on { letter } else { '\u{00b7}' })
+            .collect();
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}{:<22}", prefix, name), name_style),
+            Span::styled(format!("{:>9}", mix::volume_label(pad.volume_db)), value_style(mixer::COL_VOLUME)),
+            Span::raw(" "),
+            Span::styled(bar, Style::default().fg(if audible { Color::Green } else { Color::DarkGray })),
+            Span::styled(format!("{:>6}", mix::pan_label(pad.pan)), value_style(mixer::COL_PAN)),
+            Span::raw(" "),
+            Span::styled(format!("{:>7}", mix::send_label(pad.reverb)), value_style(mixer::COL_SEND)),
+            Span::raw(" "),
+            Span::styled(format!("{:>5}", mix::send_label(pad.convolution)), value_style(mixer::COL_IR_SEND)),
+            Span::raw("  "),
+            Span::styled(flag(pad.mute, "M"), value_style(mixer::COL_MUTE).fg(if pad.mute { Color::Red } else { Color::DarkGray })),
+            Span::raw("  "),
+            Span::styled(flag(pad.solo, "S"), value_style(mixer::COL_SOLO).fg(if pad.solo { Color::Yellow } else { Color::DarkGray })),
+            Span::styled(format!("  {}", fx), Style::default().fg(Color::Magenta)),
+        ]));
+    }
+
+    let on_reverb = selected == state.pads.len();
+    let reverb_style = |col: usize| {
+        if on_reverb && col == column {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        } else {
+            Style::default().fg(Color::White)
+        }
+    };
+    let percent = |value: f32| format!("{:.0}%", value * 100.0);
+    lines.push(Line::default());
+    lines.push(Line::from(vec![
+        Span::styled(
+            format!("{}{:<22}", if on_reverb { " \u{25b8} " } else { "   " }, "Reverb bus"),
+            if on_reverb {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            },
+        ),
+        Span::styled("size ", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{:>4}", percent(mix.reverb.size)), reverb_style(mixer::COL_SIZE)),
+        Span::styled("   damping ", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{:>4}", percent(mix.reverb.damping)), reverb_style(mixer::COL_DAMPING)),
+        Span::styled("   wet ", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{:>4}", percent(mix.reverb.wet)), reverb_style(mixer::COL_WET)),
+    ]));
+
+    let on_ir = selected > state.pads.len();
+    let ir_style = |col: usize| {
+        if on_ir && col == column {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        } else {
+            Style::default().fg(Color::White)
+        }
+    };
+    let response = match (&mix.impulse, &mix.convolution.ir) {
+        (Some(impulse), _) => convolution::display_name(&impulse.path),
+        (None, Some(name)) => format!("{} (not loaded)", convolution::display_name(Path::new(name))),
+        (None, None) => "none".to_string(),
+    };
+    lines.push(Line::from(vec![
+        Span::styled(
+            format!("{}{:<22}", if on_ir { " \u{25b8} " } else { "   " }, "IR bus"),
+            if on_ir {
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::White)
+            },
+        ),
+        Span::styled("response ", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{:<16}", response), ir_style(mixer::COL_RESPONSE)),
+        Span::styled("   wet ", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("{:>4}", percent(mix.convolution.wet)), ir_style(mixer::COL_IR_WET)),
+    ]));
+    frame.render_widget(Paragraph::new(lines), content_area);
+
+    render_footer_hint(
+        frame,
+        footer_area,
+        " \u{2191}\u{2193} row  Tab column  \u{2190}\u{2192}/PgUp/PgDn adjust  m mute  s solo  i inserts  Del reset  Esc close",
+    );
+}
+
+fn render_inserts_popup(frame: &mut Frame, area: Rect, state: &AppState, pad: usize, row: usize, mix: &mix::PadMix) {
+    let popup = popup_area_percent(area);
+    frame.render_widget(Clear, popup);
+
+    let block = styled_block(" Inserts ", Color::Magenta);
+    let inner = block.inner(popup);
+    frame.render_widget(block, popup);
+
+    if inner.height < 5 || inner.width < 20 {
+        return;
+    }
+    let (content_area, footer_area) = content_footer_split(inner);
+
+    let name = state.pads.get(pad).map(|p| format!("{} ({})", p.name, p.note)).unwrap_or_default();
+    let mut lines = vec![Line::from(Span::styled(
+        format!(" {} \u{2014} EQ, then compressor, then transient shaper", name),
+        Style::default().fg(Color::White),
+    ))];
+
+    let visible = (content_area.height as usize).saturating_sub(lines.len()).max(1);
+    let skip = row.saturating_sub(visible - 1);
+    for (i, param) in inserts::PARAMS.iter().enumerate().skip(skip).take(visible) {
+        let is_selected = i == row;
+        let enabled = match param.section() {
+            inserts::Section::Eq => mix.inserts.eq.enabled,
+            inserts::Section::Compressor => mix.inserts.compressor.enabled,
+            inserts::Section::Shaper => mix.inserts.transient.enabled,
+        };
+        let prefix = if is_selected { " \u{25b8} " } else { "   " };
+        let indent = if param.is_heading() { "" } else { "  " };
+        let label_style = match (is_selected, param.is_heading()) {
+            (true, _) => Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            (false, true) => Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            (false, false) => Style::default().fg(if enabled { Color::White } else { Color::DarkGray }),
+        };
+        let value_style = if is_selected {
+            Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        } else if param.is_heading() {
+            Style::default().fg(if enabled { Color::Green } else { Color::DarkGray })
+        } else {
+            Style::default().fg(if enabled { Color::White } else { Color::DarkGray })
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}{}{:<width$}", prefix, indent, param.label(), width = 24 - indent.len()), label_style),
+            Span::styled(format!("{:>10}", param.value(&mix.inserts)), value_style),
+        ]));
+    }
+    frame.render_widget(Paragraph::new(lines), content_area);
+
+    render_footer_hint(
+        frame,
+        footer_area,
+        " \u{2191}\u{2193} row  \u{2190}\u{2192}/PgUp/PgDn adjust  Enter on/off  Del reset effect  Esc back to mixer",
+    );
+}
+
+fn render_midi_learn_popup(
+    frame: &mut Frame,
+    area: Rect,
+    step: usize,
+    learned: &[Option<u8>],
+    input: &str,
+    cursor: usize,
+) {
+    let naming = step >= learn::SLOTS.len();
+    let popup = popup_area_fixed(area, 58, learn::SLOTS.len() as u16 + 6);
+    frame.render_widget(Clear, popup);
+
+    let block = styled_block(" MIDI Learn ", Color::Yellow);
+    let inner = block.inner(popup);
+    frame.render_widget(block, popup);
+
+    if inner.height < 4 || inner.width < 20 {
+        return;
+    }
+    let (content_area, footer_area) = content_footer_split(inner);
+
+    let mut lines: Vec<Line> = vec![Line::from(Span::styled(
+        if naming { " Name the new mapping:" } else { " Hit the pad for each drum:" },
+        Style::default().fg(Color::White),
+    ))];
+    for (i, (slot, note)) in learn::SLOTS.iter().zip(learned).enumerate() {
+        let current = i == step;
+        let prefix = if current { " \u{25b8} " } else { "   " };
+        let (value, value_style) = match note {
+            Some(note) => (format!("note {}", note), Style::default().fg(Color::Green)),
+            None if current => ("hit the pad...".to_string(), Style::default().fg(Color::Yellow)),
+            None => ("\u{2014}".to_string(), Style::default().fg(Color::DarkGray)),
+        };
+        let name_style = if current {
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}{:<16}", prefix, slot.name), name_style),
+            Span::styled(value, value_style),
+        ]));
+    }
+    let list_height = (lines.len() as u16).min(content_area.height);
+    frame.render_widget(Paragraph::new(lines), Rect::new(content_area.x, content_area.y, content_area.width, list_height));
+
+    if naming {
+        if content_area.height > list_height + 1 {
+            render_text_input(
+                frame,
+                Rect::new(content_area.x, content_area.y + list_height + 1, content_area.width, 1),
+                input,
+                cursor,
+                Color::Yellow,
+            );
+        }
+        render_footer_hint(frame, footer_area, " Enter save  Esc back to the pads");
+    } else {
+        render_footer_hint(frame, footer_area, " \u{2191}\u{2193} slot  \u{2192} skip  Del clear  Enter finish  Esc cancel");
+    }
+}
@@ -0,0 +1,147 @@
+use crossterm::event::KeyCode;
+use std::sync::Arc;
+
+use super::{AppState, PlayResources, Popup};
+use crate::settings;
+use crate::suppress::{HitStats, PadSuppression, Suppression};
+
+/// Editable columns of the stats view.
+pub(super) const COL_RETRIGGER: usize = 0;
+pub(super) const COL_CROSSTALK: usize = 1;
+pub(super) const COL_RATIO: usize = 2;
+const COLUMNS: usize = 3;
+
+pub(super) fn open(state: &mut AppState, resources: &PlayResources) {
+    state.popup = Some(Popup::HitStats {
+        selected: 0,
+        column: COL_RETRIGGER,
+        suppression: (**resources.suppression.load()).clone(),
+        stats: Arc::clone(&resources.hit_stats),
+    });
+}
+
+/// Rows of the view: all pads (`None`), then every note that is a pad, was hit or
+/// has thresholds of its own.
+pub(super) fn rows(state: &AppState, suppression: &Suppression, stats: &HitStats) -> Vec<Option<u8>> {
+    let mut notes: Vec<u8> = state.pads.iter().map(|p| p.note).collect();
+    notes.extend(suppression.pads.keys().copied());
+    notes.extend((0..128).filter(|&note| stats.get(note) != Default::default()));
+    notes.sort();
+    notes.dedup();
+    std::iter::once(None).chain(notes.into_iter().map(Some)).collect()
+}
+
+/// The thresholds a row shows: the pad's own, or the global ones.
+pub(super) fn thresholds(suppression: &Suppression, row: Option<u8>) -> &PadSuppression {
+    match row {
+        Some(note) => suppression.for_note(note),
+        None => &suppression.global,
+    }
+}
+
+pub(super) fn handle_key(state: &mut AppState, resources: &mut PlayResources, key: KeyCode) {
+    let Some(Popup::HitStats { selected, column, suppression, stats }) = state.popup.as_ref() else {
+        return;
+    };
+    let rows = rows(state, suppression, stats);
+    let (mut selected, mut column) = (*selected, *column);
+    let mut suppression = suppression.clone();
+    let row = rows.get(selected).copied().flatten();
+
+    // ←/→ step by one (1 ms or 5%), PgUp/PgDn by ten
+    let delta = match key {
+        KeyCode::Esc | KeyCode::Char('h') => {
+            state.popup = None;
+            return;
+        }
+        KeyCode::Char('q') => {
+            state.popup = None;
+            state.should_quit = true;
+            return;
+        }
+        KeyCode::Up => {
+            selected = selected.checked_sub(1).unwrap_or(rows.len() - 1);
+            None
+        }
+        KeyCode::Down => {
+            selected = (selected + 1) % rows.len();
+            None
+        }
+        KeyCode::Tab => {
+            column = (column + 1) % COLUMNS;
+            None
+        }
+        KeyCode::BackTab => {
+            column = (column + COLUMNS - 1) % COLUMNS;
+            None
+        }
+        KeyCode::Char('r') => {
+            resources.hit_stats.reset();
+            state.set_status("Hit counts reset".to_string());
+            None
+        }
+        KeyCode::Delete | KeyCode::Backspace => {
+            // The pad goes back to the global thresholds
+            if let Some(note) = row
+                && suppression.pads.remove(&note).is_some()
+            {
+                save(state, resources, row, &suppression);
+            }
+            None
+        }
+        KeyCode::Right => Some(1.0),
+        KeyCode::Left => Some(-1.0),
+        KeyCode::PageUp => Some(10.0),
+        KeyCode::PageDown => Some(-10.0),
+        _ => None,
+    };
+
+    if let Some(delta) = delta {
+        let pad = match row {
+            Some(note) => suppression.pads.entry(note).or_insert(suppression.global),
+            None => &mut suppression.global,
+        };
+        match column {
+            COL_RETRIGGER => pad.retrigger_ms += delta,
+            COL_CROSSTALK => pad.crosstalk_ms += delta,
+            _ => pad.crosstalk_ratio = ((pad.crosstalk_ratio * 20.0).round() + delta) / 20.0,
+        }
+        *pad = pad.sanitized();
+        save(state, resources, row, &suppression);
+    }
+
+    if let Some(Popup::HitStats { selected: s, column: c, suppression: current, .. }) = state.popup.as_mut() {
+        (*s, *c, *current) = (selected, column, suppression);
+    }
+}
+
+/// Put the edited thresholds into effect and persist the ones for `row`.
+fn save(state: &mut AppState, resources: &PlayResources, row: Option<u8>, suppression: &Suppression) {
+    let pad = thresholds(suppression, row);
+    let name = match row {
+        Some(note) => format!("{} ({})", state.mapping.drum_name(note), note),
+        None => "all pads".to_string(),
+    };
+    state.set_status(format!(
+        "Suppression {}: retrigger {:.0} ms, crosstalk {:.0} ms at {:.0}%",
+        name,
+        pad.retrigger_ms,
+        pad.crosstalk_ms,
+        pad.crosstalk_ratio * 100.0
+    ));
+
+    let mut s = settings::load_settings();
+    match row {
+        Some(note) => match suppression.pads.get(&note) {
+            Some(pad) => {
+                s.suppression.pads.insert(note.to_string(), *pad);
+            }
+            None => {
+                s.suppression.pads.remove(&note.to_string());
+            }
+        },
+        None => s.suppression.global = suppression.global,
+    }
+    let _ = settings::save_settings(&s);
+    resources.suppression.store(Arc::new(suppression.clone()));
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Shape of a velocity response curve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CurveShape {
+    #[default]
+    Linear,
+    /// Soft hits come out louder (easier to play loud).
+    Log,
+    /// Soft hits come out quieter (more room for ghost notes).
+    Exp,
+    /// Compressed at both ends, steep in the middle.
+    SCurve,
+    /// Straight lines between `VelocityCurve::points`.
+    Custom,
+}
+
+impl CurveShape {
+    /// Shapes in the order the curve editor cycles through them.
+    pub const ALL: [CurveShape; 5] = [
+        CurveShape::Linear,
+        CurveShape::Log,
+        CurveShape::Exp,
+        CurveShape::SCurve,
+        CurveShape::Custom,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CurveShape::Linear => "linear",
+            CurveShape::Log => "log",
+            CurveShape::Exp => "exp",
+            CurveShape::SCurve => "s-curve",
+            CurveShape::Custom => "custom",
+        }
+    }
+}
+
+fn default_min() -> u8 {
+    0
+}
+
+fn default_max() -> u8 {
+    127
+}
+
+/// How incoming velocities map to played ones, for layer selection and gain.
+///
+/// Hits below `min` are ignored; `min..=max` is stretched over the whole curve, so
+/// `max` and above play at full velocity. The default is the identity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VelocityCurve {
+    #[serde(default)]
+    pub curve: CurveShape,
+    #[serde(default = "default_min")]
+    pub min: u8,
+    #[serde(default = "default_max")]
+    pub max: u8,
+    /// `[in, out]` pairs on the 0-127 scale for the custom curve, sorted by `in`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<[u8; 2]>,
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        Self {
+            curve: CurveShape::Linear,
+            min: default_min(),
+            max: default_max(),
+            points: Vec::new(),
+        }
+    }
+}
+
+impl VelocityCurve {
+    /// The velocity to play for an incoming `velocity`, or `None` below `min`.
+    pub fn apply(&self, velocity: u8) -> Option<u8> {
+        if velocity < self.min {
+            return None;
+        }
+        let y = self.shape(self.position(velocity));
+        Some(((y * 127.0).round() as u8).max(1))
+    }
+
+    /// Where `velocity` falls between `min` and `max`, from 0.0 to 1.0.
+    pub fn position(&self, velocity: u8) -> f32 {
+        if self.max <= self.min {
+            return if velocity >= self.min { 1.0 } else { 0.0 };
+        }
+        ((velocity as f32 - self.min as f32) / (self.max - self.min) as f32).clamp(0.0, 1.0)
+    }
+
+    /// The curve itself: 0.0-1.0 in, 0.0-1.0 out.
+    pub fn shape(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self.curve {
+            CurveShape::Linear => x,
+            CurveShape::Log => (1.0 + 9.0 * x).log10(),
+            CurveShape::Exp => (10.0_f32.powf(x) - 1.0) / 9.0,
+            CurveShape::SCurve => x * x * (3.0 - 2.0 * x),
+            CurveShape::Custom => interpolate(&self.points, x * 127.0) / 127.0,
+        }
+    }
+
+    /// Switch to the custom curve, starting from points sampled off the current
+    /// shape so the curve doesn't jump.
+    pub fn make_custom(&mut self) {
+        if self.curve == CurveShape::Custom && !self.points.is_empty() {
+            return;
+        }
+        self.points = [0u8, 32, 64, 96, 127]
+            .iter()
+            .map(|&x| [x, (self.shape(x as f32 / 127.0) * 127.0).round() as u8])
+            .collect();
+        self.curve = CurveShape::Custom;
+    }
+
+    /// Short description, e.g. "s-curve 20-110".
+    pub fn label(&self) -> String {
+        if self.min == default_min() && self.max == default_max() {
+            self.curve.label().to_string()
+        } else {
+            format!("{} {}-{}", self.curve.label(), self.min, self.max)
+        }
+    }
+}
+
+/// Piecewise-linear lookup of `x` among sorted `[in, out]` points; linear with none.
+fn interpolate(points: &[[u8; 2]], x: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return x;
+    };
+    if x <= first[0] as f32 {
+        return first[1] as f32;
+    }
+    for pair in points.windows(2) {
+        let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+        if x <= x1 as f32 {
+            if x1 == x0 {
+                return y1 as f32;
+            }
+            let t = (x - x0 as f32) / (x1 - x0) as f32;
+            return y0 as f32 + t * (y1 as f32 - y0 as f32);
+        }
+    }
+    last[1] as f32
+}
+
+/// Velocity curves, persisted under `[velocity]` in settings.toml.
+///
+/// ```toml
+/// [velocity]
+/// curve = "log"     # "linear", "log", "exp", "s-curve" or "custom"
+/// min = 5           # ignore hits softer than this
+/// max = 120         # play this and harder at full velocity
+///
+/// [velocity.pads.38]   # incoming MIDI note
+/// curve = "custom"
+/// points = [[0, 0], [64, 40], [127, 127]]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VelocitySettings {
+    /// The curve for pads without one of their own.
+    #[serde(flatten)]
+    pub global: VelocityCurve,
+    /// Per-pad curves. Keys are strings because TOML only supports string keys.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pads: BTreeMap<String, VelocityCurve>,
+}
+
+impl VelocitySettings {
+    /// Convert to the router's representation, skipping unparseable note keys.
+    pub fn to_curves(&self) -> VelocityCurves {
+        VelocityCurves {
+            global: self.global.clone(),
+            pads: self
+                .pads
+                .iter()
+                .filter_map(|(k, v)| Some((k.parse().ok()?, v.clone())))
+                .collect(),
+        }
+    }
+}
+
+/// The curves in effect, looked up by incoming note for every hit.
+#[derive(Debug, Clone, Default)]
+pub struct VelocityCurves {
+    pub global: VelocityCurve,
+    pub pads: HashMap<u8, VelocityCurve>,
+}
+
+impl VelocityCurves {
+    /// The pad's own curve, or the global one.
+    pub fn for_note(&self, note: u8) -> &VelocityCurve {
+        self.pads.get(&note).unwrap_or(&self.global)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(shape: CurveShape) -> VelocityCurve {
+        VelocityCurve {
+            curve: shape,
+            ..VelocityCurve::default()
+        }
+    }
+
+    #[test]
+    fn shapes_span_the_whole_range() {
+        for shape in CurveShape::ALL {
+            assert_eq!(curve(shape).apply(127), Some(127), "{:?}", shape);
+        }
+        // Linear with no thresholds leaves velocities as they are
+        for v in 1..=127 {
+            assert_eq!(curve(CurveShape::Linear).apply(v), Some(v));
+        }
+        // Log lifts soft hits, exp lowers them, the S-curve is symmetric
+        assert!(curve(CurveShape::Log).apply(32).unwrap() > 32);
+        assert!(curve(CurveShape::Exp).apply(32).unwrap() < 32);
+        assert!(curve(CurveShape::SCurve).apply(32).unwrap() < 32);
+        assert!(curve(CurveShape::SCurve).apply(96).unwrap() > 96);
+    }
+
+    #[test]
+    fn thresholds_ignore_soft_hits_and_saturate() {
+        let c = VelocityCurve {
+            min: 20,
+            max: 100,
+            ..VelocityCurve::default()
+        };
+        assert_eq!(c.apply(19), None);
+        assert_eq!(c.apply(20), Some(1));
+        assert_eq!(c.apply(60), Some(64));
+        assert_eq!(c.apply(100), Some(127));
+        assert_eq!(c.apply(120), Some(127));
+    }
+
+    #[test]
+    fn custom_curve_interpolates_points() {
+        let mut c = VelocityCurve {
+            curve: CurveShape::Custom,
+            points: vec![[0, 0], [64, 100], [127, 127]],
+            ..VelocityCurve::default()
+        };
+        assert_eq!(c.apply(64), Some(100));
+        assert_eq!(c.apply(32), Some(50));
+
+        // Switching to custom keeps the existing shape
+        c = curve(CurveShape::SCurve);
+        let before = c.apply(96);
+        c.make_custom();
+        assert_eq!(c.curve, CurveShape::Custom);
+        assert_eq!(c.points.len(), 5);
+        assert_eq!(c.apply(96), before);
+    }
+
+    #[test]
+    fn parse_velocity_settings() {
+        let toml_str = r#"
+            [velocity]
+            curve = "s-curve"
+            min = 5
+
+            [velocity.pads.38]
+            curve = "custom"
+            points = [[0, 0], [127, 127]]
+        "#;
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            velocity: VelocitySettings,
+        }
+        let settings = toml::from_str::<Wrapper>(toml_str).unwrap().velocity;
+        assert_eq!(settings.global.curve, CurveShape::SCurve);
+        assert_eq!((settings.global.min, settings.global.max), (5, 127));
+
+        let curves = settings.to_curves();
+        assert_eq!(curves.for_note(38).curve, CurveShape::Custom);
+        assert_eq!(curves.for_note(36).curve, CurveShape::SCurve);
+
+        let saved = toml::to_string(&Wrapper { velocity: settings.clone() }).unwrap();
+        assert_eq!(toml::from_str::<Wrapper>(&saved).unwrap().velocity, settings);
+    }
+}